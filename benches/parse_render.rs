@@ -0,0 +1,102 @@
+// パース・描画のスループット計測
+// `cargo bench` で実行する。入力の種類ごとに bytes/s で比較できるようにしている
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use bbcode_parser::{
+    ast_to_html, ast_to_markdown, ast_to_plain_text, parse_bbcode_to_ast, BbCodeOptions,
+};
+
+/// 計測対象の入力（名前, 本文）
+fn corpora() -> Vec<(&'static str, String)> {
+    let ascii = "[quote=Alice]Hi [b]there[/b], see [url=https://example.com]this[/url].[/quote]\n\
+                 Plain reply with [i]some[/i] [color=red]color[/color] and a list:\n\
+                 [list][*]one[*]two[*]three[/list]\n";
+    let cjk = "[quote=太郎]こんにちは、[b]世界[/b]。[url=https://example.jp]リンク[/url][/quote]\n\
+               返信です。[color=#f00]赤字[/color]と[i]斜体[/i]の混在した日本語の本文。\n";
+    let tag_dense = "[b]a[/b][i]b[/i][u]c[/u][s]d[/s][color=red]e[/color][size=3]f[/size]";
+    let text_dense = "Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod \
+                      tempor incididunt ut labore et dolore magna aliqua. <escaped> & \"quoted\"\n";
+
+    vec![
+        ("ascii", ascii.repeat(40)),
+        ("cjk", cjk.repeat(40)),
+        ("tag_dense", tag_dense.repeat(100)),
+        ("text_dense", text_dense.repeat(100)),
+    ]
+}
+
+/// 二次以上の時間がかかりやすい入力。サイズを変えて伸び方を見る
+fn adversarial(n: usize) -> Vec<(&'static str, String)> {
+    vec![
+        ("unmatched_open", "[b".repeat(n)),
+        ("unclosed_tags", "[b]x".repeat(n)),
+        ("stray_close", "x[/b]".repeat(n)),
+        ("brackets", "[[]]".repeat(n)),
+    ]
+}
+
+fn options() -> BbCodeOptions {
+    BbCodeOptions::default()
+        .with_max_depth(16)
+        .with_max_tags(100_000)
+        .with_max_input_size(1 << 20)
+        .with_max_text_len(1 << 20)
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let opts = options();
+    let mut group = c.benchmark_group("parse");
+    for (name, input) in corpora() {
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &input, |b, input| {
+            b.iter(|| parse_bbcode_to_ast(black_box(input), &opts))
+        });
+        // `--features fast-parser` のときは手書きの字句解析器とも比べる
+        #[cfg(feature = "fast-parser")]
+        {
+            let fast = options().with_backend(bbcode_parser::ParserBackend::Fast);
+            group.bench_with_input(BenchmarkId::new("fast", name), &input, |b, input| {
+                b.iter(|| parse_bbcode_to_ast(black_box(input), &fast))
+            });
+        }
+    }
+    group.finish();
+}
+
+fn bench_render(c: &mut Criterion) {
+    let opts = options();
+    let mut group = c.benchmark_group("render");
+    for (name, input) in corpora() {
+        let ast = parse_bbcode_to_ast(&input, &opts).expect("corpus must parse");
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_with_input(BenchmarkId::new("html", name), &ast, |b, ast| {
+            b.iter(|| ast_to_html(black_box(ast)))
+        });
+        group.bench_with_input(BenchmarkId::new("markdown", name), &ast, |b, ast| {
+            b.iter(|| ast_to_markdown(black_box(ast)))
+        });
+        group.bench_with_input(BenchmarkId::new("plain", name), &ast, |b, ast| {
+            b.iter(|| ast_to_plain_text(black_box(ast)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_adversarial(c: &mut Criterion) {
+    let opts = options();
+    let mut group = c.benchmark_group("adversarial");
+    // 線形なら 4 倍の入力で時間もおよそ 4 倍になる
+    for n in [256, 1024] {
+        for (name, input) in adversarial(n) {
+            group.throughput(Throughput::Bytes(input.len() as u64));
+            group.bench_with_input(BenchmarkId::new(name, n), &input, |b, input| {
+                b.iter(|| parse_bbcode_to_ast(black_box(input), &opts))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse, bench_render, bench_adversarial);
+criterion_main!(benches);
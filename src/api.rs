@@ -0,0 +1,18 @@
+// 内部の構成を変えても変わらない、最小限の公開 API
+//
+// ここにある名前と関数の形は互換性を保って維持する。削除・変更する場合は変更履歴に書いた上で
+// メジャーバージョンでのみ行う。細かい設定・描画が要る場合はクレート直下の API を使う。
+
+pub use crate::ast::{Element, Node, Span};
+pub use crate::error::BbCodeError as Error;
+pub use crate::options::BbCodeOptions as Options;
+
+/// input を opts に従って AST にする
+pub fn parse(input: &str, opts: &Options) -> Result<Vec<Node>, Error> {
+    crate::parser::parse_bbcode_to_ast(input, opts)
+}
+
+/// AST を既定の設定で HTML にする
+pub fn render_html(nodes: &[Node]) -> String {
+    crate::render::ast_to_html(nodes)
+}
@@ -1,25 +1,314 @@
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::{Deref, Range};
+use std::sync::{Arc, PoisonError, RwLock};
+
+use once_cell::sync::Lazy;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::color::Color;
+
+/// 入力上の範囲（バイト位置）。プログラムから組み立てたノードは既定値の 0..0 を使う
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub struct Span {
     pub start: usize,
     pub end: usize,
 }
 
+// Span はバイト位置なので、文字単位で位置を扱う側（JS の UTF-16、DB の文字数）へ渡すときは
+// 以下で変換する。どれも範囲外・文字の途中を指す場合は None を返し、panic しない
+impl Span {
+    /// input の該当部分
+    pub fn slice<'a>(&self, input: &'a str) -> Option<&'a str> {
+        input.get(self.start..self.end)
+    }
+
+    /// Unicode スカラー値（Rust の char）単位の範囲
+    pub fn to_char_range(&self, input: &str) -> Option<Range<usize>> {
+        self.convert(input, |s| s.chars().count())
+    }
+
+    /// UTF-16 のコード単位での範囲（JavaScript の文字列の添字）
+    pub fn to_utf16_range(&self, input: &str) -> Option<Range<usize>> {
+        self.convert(input, |s| s.encode_utf16().count())
+    }
+
+    /// 書記素クラスタ単位の範囲
+    /// 書記素の途中を指す場合は、その書記素全体を含むように外側へ広げる
+    pub fn to_grapheme_range(&self, input: &str) -> Option<Range<usize>> {
+        if self.start > self.end || input.get(self.start..self.end).is_none() {
+            return None;
+        }
+        let mut start = None;
+        let mut count = 0;
+        for (offset, g) in input.grapheme_indices(true) {
+            if start.is_none() && offset + g.len() > self.start {
+                start = Some(count);
+            }
+            if offset >= self.end && start.is_some() {
+                break;
+            }
+            count += 1;
+        }
+        Some(start.unwrap_or(count)..count)
+    }
+
+    /// to_char_range の逆。文字単位の範囲をバイト位置に戻す
+    pub fn from_char_range(input: &str, range: Range<usize>) -> Option<Span> {
+        let offset = |n: usize| {
+            input
+                .char_indices()
+                .map(|(i, _)| i)
+                .chain(std::iter::once(input.len()))
+                .nth(n)
+        };
+        Self::checked(offset(range.start)?, offset(range.end)?)
+    }
+
+    /// to_utf16_range の逆。サロゲートペアの途中を指す場合は None
+    pub fn from_utf16_range(input: &str, range: Range<usize>) -> Option<Span> {
+        let offset = |n: usize| {
+            let mut units = 0;
+            for (i, c) in input.char_indices() {
+                if units == n {
+                    return Some(i);
+                }
+                if units > n {
+                    return None;
+                }
+                units += c.len_utf16();
+            }
+            (units == n).then_some(input.len())
+        };
+        Self::checked(offset(range.start)?, offset(range.end)?)
+    }
+
+    fn checked(start: usize, end: usize) -> Option<Span> {
+        (start <= end).then_some(Span { start, end })
+    }
+
+    /// 先頭から start / end までの部分を measure で数える
+    fn convert(&self, input: &str, measure: impl Fn(&str) -> usize) -> Option<Range<usize>> {
+        let before = input.get(..self.start)?;
+        let inner = input.get(self.start..self.end)?;
+        let start = measure(before);
+        Some(start..start + measure(inner))
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum Node {
-    Text { span: Span, text: String },
+    Text {
+        span: Span,
+        text: String,
+    },
     Element(Element),
+    /// このバージョンでは解釈できないノード（新しいバージョンで保存された AST を読み込んだ場合）
+    /// 描画では何も出力せず、書き出すときは読み込んだ内容をそのまま戻す
+    Unsupported(UnsupportedNode),
+}
+
+/// 保存された AST にあった未知の種類のノード
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsupportedNode {
+    /// 保存形式での種類名（JSON の `type`）
+    pub kind: String,
+    pub span: Span,
+    /// 保存形式でのノードそのもの（JSON のオブジェクト）
+    pub raw: String,
+}
+
+impl Node {
+    /// 入力上の位置を持たないテキストノード
+    pub fn text(text: impl Into<String>) -> Self {
+        Node::Text {
+            span: Span::default(),
+            text: text.into(),
+        }
+    }
+}
+
+impl From<Element> for Node {
+    fn from(el: Element) -> Self {
+        Node::Element(el)
+    }
+}
+
+impl From<&str> for Node {
+    fn from(text: &str) -> Self {
+        Node::text(text)
+    }
+}
+
+impl From<String> for Node {
+    fn from(text: String) -> Self {
+        Node::text(text)
+    }
+}
+
+/// 組み込みタグの名前なら、それを指す静的な文字列
+fn builtin_tag_name(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "b" => "b",
+        "i" => "i",
+        "u" => "u",
+        "s" => "s",
+        "quote" => "quote",
+        "left" => "left",
+        "center" => "center",
+        "right" => "right",
+        "color" => "color",
+        "highlight" => "highlight",
+        "kbd" => "kbd",
+        "tt" => "tt",
+        "size" => "size",
+        "big" => "big",
+        "small" => "small",
+        "font" => "font",
+        "url" => "url",
+        "img" => "img",
+        "code" => "code",
+        "pre" => "pre",
+        "anchor" => "anchor",
+        "goto" => "goto",
+        "tag" => "tag",
+        "list" => "list",
+        "*" => "*",
+        _ => return None,
+    })
+}
+
+/// 組み込み以外のタグ名の共有表。同じ名前の要素は同じ文字列を指す
+static INTERNED_TAG_NAMES: Lazy<RwLock<HashSet<Arc<str>>>> = Lazy::new(Default::default);
+
+/// 共有表に入れる名前の数の上限。保存した AST の読み込みなどで任意の名前が来ても表が際限なく
+/// 大きくならないよう、超えた分は共有せずにその場で確保する
+const MAX_INTERNED_TAG_NAMES: usize = 1024;
+
+fn intern_tag_name(name: &str) -> Arc<str> {
+    let names = INTERNED_TAG_NAMES
+        .read()
+        .unwrap_or_else(PoisonError::into_inner);
+    if let Some(interned) = names.get(name) {
+        return interned.clone();
+    }
+    drop(names);
+    let mut names = INTERNED_TAG_NAMES
+        .write()
+        .unwrap_or_else(PoisonError::into_inner);
+    if let Some(interned) = names.get(name) {
+        return interned.clone();
+    }
+    let interned: Arc<str> = Arc::from(name);
+    if names.len() < MAX_INTERNED_TAG_NAMES {
+        names.insert(interned.clone());
+    }
+    interned
+}
+
+/// 要素のタグ名
+/// 組み込みタグは静的文字列、それ以外は共有表の文字列にして、要素ごとの確保と複製を減らす
+#[derive(Clone, Eq)]
+pub enum TagName {
+    Builtin(&'static str),
+    Other(Arc<str>),
+}
+
+impl TagName {
+    pub fn new(name: &str) -> Self {
+        match builtin_tag_name(name) {
+            Some(n) => TagName::Builtin(n),
+            None => TagName::Other(intern_tag_name(name)),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            TagName::Builtin(n) => n,
+            TagName::Other(n) => n,
+        }
+    }
+}
+
+impl Deref for TagName {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl PartialEq for TagName {
+    fn eq(&self, other: &Self) -> bool {
+        // 同じ名前は同じ文字列を指すので、大抵ポインタ比較で済む
+        let same = match (self, other) {
+            (TagName::Builtin(a), TagName::Builtin(b)) => std::ptr::eq(*a, *b),
+            (TagName::Other(a), TagName::Other(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        };
+        same || self.as_str() == other.as_str()
+    }
+}
+
+impl PartialEq<str> for TagName {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for TagName {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for TagName {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl std::hash::Hash for TagName {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state);
+    }
+}
+
+impl fmt::Debug for TagName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for TagName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<&str> for TagName {
+    fn from(name: &str) -> Self {
+        TagName::new(name)
+    }
+}
+
+impl From<String> for TagName {
+    fn from(name: String) -> Self {
+        TagName::new(&name)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Element {
     pub span: Span,
-    pub name: String,
+    pub name: TagName,
     pub attrs: Vec<(String, String)>,
     pub children: Vec<Node>,
 }
 
 impl Element {
-    pub fn new(name: impl Into<String>, span: Span) -> Self {
+    pub fn new(name: impl Into<TagName>, span: Span) -> Self {
         Self {
             span,
             name: name.into(),
@@ -37,4 +326,135 @@ impl Element {
         self.children = children;
         self
     }
+
+    /// 値属性（`[color=red]` の `red`）を色として読んだもの。色として読めなければ None
+    pub fn color_value(&self) -> Option<Color> {
+        let (_, value) = self.attrs.iter().find(|(k, _)| k == "value")?;
+        Color::parse(value)
+    }
+
+    /// 入力上の位置を持たない要素（AST をプログラムから組み立てる場合）
+    pub fn tag(name: impl Into<TagName>) -> Self {
+        Self::new(name, Span::default())
+    }
+
+    /// `[name=value]` の値属性を付ける
+    pub fn with_value(self, value: impl Into<String>) -> Self {
+        self.with_attr("value", value)
+    }
+
+    /// 子ノードを1つ追加する
+    pub fn child(mut self, node: impl Into<Node>) -> Self {
+        self.children.push(node.into());
+        self
+    }
+
+    pub fn bold(children: Vec<Node>) -> Self {
+        Self::tag("b").with_children(children)
+    }
+
+    pub fn italic(children: Vec<Node>) -> Self {
+        Self::tag("i").with_children(children)
+    }
+
+    pub fn underline(children: Vec<Node>) -> Self {
+        Self::tag("u").with_children(children)
+    }
+
+    pub fn strike(children: Vec<Node>) -> Self {
+        Self::tag("s").with_children(children)
+    }
+
+    pub fn quote(children: Vec<Node>) -> Self {
+        Self::tag("quote").with_children(children)
+    }
+
+    pub fn color(color: impl Into<String>, children: Vec<Node>) -> Self {
+        Self::tag("color").with_value(color).with_children(children)
+    }
+
+    pub fn size(size: impl Into<String>, children: Vec<Node>) -> Self {
+        Self::tag("size").with_value(size).with_children(children)
+    }
+
+    /// `[url=href]children[/url]`
+    pub fn url(href: impl Into<String>, children: Vec<Node>) -> Self {
+        Self::tag("url").with_value(href).with_children(children)
+    }
+
+    /// `[img]src[/img]`
+    pub fn img(src: impl Into<String>) -> Self {
+        Self::tag("img").child(Node::text(src))
+    }
+
+    /// `[code]text[/code]`（中身は verbatim なテキスト1つ）
+    pub fn code(text: impl Into<String>) -> Self {
+        Self::tag("code").child(Node::text(text))
+    }
+
+    /// `[list]` の中身は `Element::item` を並べる
+    pub fn list(items: Vec<Node>) -> Self {
+        Self::tag("list").with_children(items)
+    }
+
+    /// リスト項目 `[*]`
+    pub fn item(children: Vec<Node>) -> Self {
+        Self::tag("*").with_children(children)
+    }
+}
+
+/// AST を宣言的に組み立てる。`Vec<Node>` を返す
+///
+/// - `"text"`: テキストノード
+/// - `b { ... }`: 要素。タグ名は識別子をそのまま使う
+/// - `color("red") { ... }`: 値属性 `[color=red]`
+/// - `quote[author = "Alice", date = "1"] { ... }`: 名前付き属性
+/// - `"*" { ... }`: 識別子にできないタグ名は文字列で書く
+/// - `(expr)`: `Into<Node>` な式（変数の文字列や組み立て済みの要素）
+#[macro_export]
+macro_rules! bbcode {
+    (@nodes [$($out:expr,)*]) => {
+        ::std::vec![$($out,)*] as ::std::vec::Vec<$crate::ast::Node>
+    };
+    (@nodes [$($out:expr,)*] $name:literal { $($inner:tt)* } $($rest:tt)*) => {
+        $crate::bbcode!(@nodes [$($out,)*
+            $crate::ast::Node::Element(
+                $crate::ast::Element::tag($name).with_children($crate::bbcode!($($inner)*))
+            ),
+        ] $($rest)*)
+    };
+    (@nodes [$($out:expr,)*] $text:literal $($rest:tt)*) => {
+        $crate::bbcode!(@nodes [$($out,)* $crate::ast::Node::text($text),] $($rest)*)
+    };
+    (@nodes [$($out:expr,)*] ($node:expr) $($rest:tt)*) => {
+        $crate::bbcode!(@nodes [$($out,)* $crate::ast::Node::from($node),] $($rest)*)
+    };
+    (@nodes [$($out:expr,)*] $tag:ident ($value:expr) { $($inner:tt)* } $($rest:tt)*) => {
+        $crate::bbcode!(@nodes [$($out,)*
+            $crate::ast::Node::Element(
+                $crate::ast::Element::tag(stringify!($tag))
+                    .with_value($value)
+                    .with_children($crate::bbcode!($($inner)*))
+            ),
+        ] $($rest)*)
+    };
+    (@nodes [$($out:expr,)*] $tag:ident [$($key:ident = $val:expr),* $(,)?] { $($inner:tt)* } $($rest:tt)*) => {
+        $crate::bbcode!(@nodes [$($out,)*
+            $crate::ast::Node::Element(
+                $crate::ast::Element::tag(stringify!($tag))
+                    $(.with_attr(stringify!($key), $val))*
+                    .with_children($crate::bbcode!($($inner)*))
+            ),
+        ] $($rest)*)
+    };
+    (@nodes [$($out:expr,)*] $tag:ident { $($inner:tt)* } $($rest:tt)*) => {
+        $crate::bbcode!(@nodes [$($out,)*
+            $crate::ast::Node::Element(
+                $crate::ast::Element::tag(stringify!($tag)).with_children($crate::bbcode!($($inner)*))
+            ),
+        ] $($rest)*)
+    };
+    ($($body:tt)*) => {
+        $crate::bbcode!(@nodes [] $($body)*)
+    };
 }
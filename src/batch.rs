@@ -0,0 +1,172 @@
+// スレッドのページなど、多数の投稿をまとめて HTML にする
+//
+// 投稿ごとに parse + render を呼ぶ代わりに、設定とキャッシュを共有して描画し、
+// 投稿ごとの所要時間とキャッシュの利用を返す（遅い投稿の調査用）。
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::BbCodeError;
+use crate::options::BbCodeOptions;
+use crate::parser::parse_bbcode_to_ast;
+use crate::render::html::options_hash;
+use crate::render::{ast_to_html_cached, ast_to_html_with, HtmlRenderOptions, RenderCache};
+
+/// render_many の設定
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct RenderManyOptions {
+    pub parse: BbCodeOptions,
+    pub html: HtmlRenderOptions,
+    /// 描画に使うスレッドの数。1 以下なら呼び出したスレッドだけで描画する
+    pub threads: usize,
+}
+
+impl Default for RenderManyOptions {
+    fn default() -> Self {
+        Self {
+            parse: BbCodeOptions::default(),
+            html: HtmlRenderOptions::default(),
+            threads: 1,
+        }
+    }
+}
+
+impl RenderManyOptions {
+    pub fn with_parse(mut self, parse: BbCodeOptions) -> Self {
+        self.parse = parse;
+        self
+    }
+
+    pub fn with_html(mut self, html: HtmlRenderOptions) -> Self {
+        self.html = html;
+        self
+    }
+
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+}
+
+/// 1つの投稿の描画結果
+#[derive(Debug)]
+pub struct RenderedPost {
+    /// HTML。パースできなければそのエラー
+    pub html: Result<String, BbCodeError>,
+    /// パースと描画（キャッシュにあれば取り出し）にかかった時間
+    pub duration: Duration,
+    /// 投稿全体の HTML をキャッシュから取り出した
+    pub cache_hit: bool,
+}
+
+/// inputs の投稿をすべて HTML にする。結果は inputs と同じ順
+///
+/// cache は投稿全体の HTML と、1スレッドで描画する場合は部分木（同じ引用など）の HTML に使う。
+/// 部分木のキャッシュは祖先による出力の違いも区別するので、スレッドの数で HTML は変わらない。
+/// キーには HTML の設定を混ぜるが、パースの設定は混ぜないので、パースの設定が違う呼び出しで
+/// 同じ cache を共有しないこと。複数のスレッドで描画する場合も cache の読み書きは呼び出したスレッドで行う
+pub fn render_many<I>(
+    inputs: I,
+    opts: &RenderManyOptions,
+    cache: &mut dyn RenderCache,
+) -> Vec<RenderedPost>
+where
+    I: IntoIterator,
+    I::Item: AsRef<str> + Sync,
+{
+    let inputs: Vec<I::Item> = inputs.into_iter().collect();
+    let salt = options_hash(&opts.html);
+
+    if opts.threads <= 1 {
+        return inputs
+            .iter()
+            .map(|input| {
+                let start = Instant::now();
+                let key = post_key(input.as_ref(), salt);
+                if let Some(html) = cache.get(key) {
+                    return hit(html, start);
+                }
+                let html = parse_bbcode_to_ast(input.as_ref(), &opts.parse)
+                    .map(|ast| ast_to_html_cached(&ast, &opts.html, cache));
+                if let Ok(html) = &html {
+                    cache.put(key, html.clone());
+                }
+                RenderedPost {
+                    html,
+                    duration: start.elapsed(),
+                    cache_hit: false,
+                }
+            })
+            .collect();
+    }
+
+    // キャッシュに無い投稿だけをスレッドに分けて描画する
+    let mut posts = Vec::with_capacity(inputs.len());
+    let mut misses = vec![];
+    for (i, input) in inputs.iter().enumerate() {
+        let start = Instant::now();
+        match cache.get(post_key(input.as_ref(), salt)) {
+            Some(html) => posts.push(Some(hit(html, start))),
+            None => {
+                posts.push(None);
+                misses.push(i);
+            }
+        }
+    }
+    let chunk_len = misses.len().div_ceil(opts.threads).max(1);
+    let rendered: Vec<(usize, RenderedPost)> = thread::scope(|s| {
+        let handles: Vec<_> = misses
+            .chunks(chunk_len)
+            .map(|chunk| {
+                let inputs = &inputs;
+                s.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|&i| (i, render_post(inputs[i].as_ref(), opts)))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+            .collect()
+    });
+    for (i, post) in rendered {
+        if let Ok(html) = &post.html {
+            cache.put(post_key(inputs[i].as_ref(), salt), html.clone());
+        }
+        posts[i] = Some(post);
+    }
+    posts.into_iter().flatten().collect()
+}
+
+fn render_post(input: &str, opts: &RenderManyOptions) -> RenderedPost {
+    let start = Instant::now();
+    let html =
+        parse_bbcode_to_ast(input, &opts.parse).map(|ast| ast_to_html_with(&ast, &opts.html));
+    RenderedPost {
+        html,
+        duration: start.elapsed(),
+        cache_hit: false,
+    }
+}
+
+fn hit(html: String, start: Instant) -> RenderedPost {
+    RenderedPost {
+        html: Ok(html),
+        duration: start.elapsed(),
+        cache_hit: true,
+    }
+}
+
+/// 投稿全体の HTML のキー（部分木のキーと区別するため印を混ぜる）
+fn post_key(input: &str, salt: u64) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    "post".hash(&mut hasher);
+    input.hash(&mut hasher);
+    hasher.finish() ^ salt
+}
@@ -0,0 +1,415 @@
+// `[color]` / `[highlight]` などの色の値
+//
+// 受け付ける形式は CSS の色名・`#RGB` / `#RRGGBB`（・`#RRGGBBAA`）・`rgb()` / `hsl()`。
+// どの形式を許可するかは BbCodeOptions::color_formats で選ぶ。読めた値は文字・記号が
+// 限られているので、そのまま CSS の宣言に置ける。
+
+/// 読み取った色（sRGB と不透明度）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    /// 不透明度（255 で不透明）
+    pub a: u8,
+}
+
+impl Color {
+    /// 不透明な色
+    pub fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b, a: 255 }
+    }
+
+    pub fn with_alpha(mut self, a: u8) -> Self {
+        self.a = a;
+        self
+    }
+
+    /// どの形式でも読む
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::parse_with(value, ColorFormats::all())
+    }
+
+    /// formats で許可した形式だけを読む。前後の空白は無視し、色名・関数名の大文字小文字は区別しない
+    pub fn parse_with(value: &str, formats: ColorFormats) -> Option<Self> {
+        let value = value.trim();
+        if value.is_empty() || value.len() > 64 {
+            return None;
+        }
+        if let Some(hex) = value.strip_prefix('#') {
+            return parse_hex(hex, formats);
+        }
+        if value.ends_with(')') {
+            return parse_function(value, formats);
+        }
+        if !formats.named {
+            return None;
+        }
+        let name = value.to_ascii_lowercase();
+        if name == "transparent" {
+            return Some(Self::new(0, 0, 0).with_alpha(0));
+        }
+        let index = NAMED_COLORS
+            .binary_search_by_key(&name.as_str(), |(n, _)| n)
+            .ok()?;
+        let [_, r, g, b] = NAMED_COLORS[index].1.to_be_bytes();
+        Some(Self::new(r, g, b))
+    }
+
+    pub fn is_opaque(&self) -> bool {
+        self.a == 255
+    }
+
+    /// `#rrggbb`。不透明でなければ `#rrggbbaa`
+    pub fn to_hex(&self) -> String {
+        let mut hex = format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b);
+        if !self.is_opaque() {
+            hex.push_str(&format!("{:02x}", self.a));
+        }
+        hex
+    }
+}
+
+/// 受け付ける色の書き方
+/// 既定は色名と `#RGB` / `#RRGGBB`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct ColorFormats {
+    /// CSS の色名（`red` / `rebeccapurple` / `transparent` など）
+    pub named: bool,
+    /// `#RGB` / `#RRGGBB`
+    pub hex: bool,
+    /// `#RGBA` / `#RRGGBBAA`
+    pub hex_alpha: bool,
+    /// `rgb(255, 0, 0)` / `rgba(255, 0, 0, 0.5)` / `rgb(100% 0% 0% / 50%)`
+    pub rgb: bool,
+    /// `hsl(120, 100%, 50%)` / `hsla(120deg, 100%, 50%, 0.5)` / `hsl(120 100% 50% / 50%)`
+    pub hsl: bool,
+}
+
+impl Default for ColorFormats {
+    fn default() -> Self {
+        Self {
+            named: true,
+            hex: true,
+            hex_alpha: false,
+            rgb: false,
+            hsl: false,
+        }
+    }
+}
+
+impl ColorFormats {
+    /// すべての形式
+    pub fn all() -> Self {
+        Self {
+            named: true,
+            hex: true,
+            hex_alpha: true,
+            rgb: true,
+            hsl: true,
+        }
+    }
+
+    pub fn with_named(mut self, enabled: bool) -> Self {
+        self.named = enabled;
+        self
+    }
+
+    pub fn with_hex(mut self, enabled: bool) -> Self {
+        self.hex = enabled;
+        self
+    }
+
+    pub fn with_hex_alpha(mut self, enabled: bool) -> Self {
+        self.hex_alpha = enabled;
+        self
+    }
+
+    pub fn with_rgb(mut self, enabled: bool) -> Self {
+        self.rgb = enabled;
+        self
+    }
+
+    pub fn with_hsl(mut self, enabled: bool) -> Self {
+        self.hsl = enabled;
+        self
+    }
+
+    /// value がこの形式のどれかで読めるか
+    pub fn accepts(&self, value: &str) -> bool {
+        Color::parse_with(value, *self).is_some()
+    }
+}
+
+fn parse_hex(hex: &str, formats: ColorFormats) -> Option<Color> {
+    let digits: Vec<u8> = hex
+        .chars()
+        .map(|c| c.to_digit(16).map(|d| d as u8))
+        .collect::<Option<_>>()?;
+    match digits.as_slice() {
+        [r, g, b] if formats.hex => Some(Color::new(r * 17, g * 17, b * 17)),
+        [r, g, b, a] if formats.hex_alpha => {
+            Some(Color::new(r * 17, g * 17, b * 17).with_alpha(a * 17))
+        }
+        [r1, r2, g1, g2, b1, b2] if formats.hex => {
+            Some(Color::new(r1 * 16 + r2, g1 * 16 + g2, b1 * 16 + b2))
+        }
+        [r1, r2, g1, g2, b1, b2, a1, a2] if formats.hex_alpha => {
+            Some(Color::new(r1 * 16 + r2, g1 * 16 + g2, b1 * 16 + b2).with_alpha(a1 * 16 + a2))
+        }
+        _ => None,
+    }
+}
+
+/// `rgb(..)` / `rgba(..)` / `hsl(..)` / `hsla(..)`
+fn parse_function(value: &str, formats: ColorFormats) -> Option<Color> {
+    let (name, args) = value.strip_suffix(')')?.split_once('(')?;
+    let ([x, y, z], alpha) = split_args(args)?;
+    let a = match alpha {
+        Some(alpha) => to_channel(ratio(alpha)?),
+        None => 255,
+    };
+    let color = match name.trim().to_ascii_lowercase().as_str() {
+        "rgb" | "rgba" if formats.rgb => {
+            Color::new(rgb_channel(x)?, rgb_channel(y)?, rgb_channel(z)?)
+        }
+        "hsl" | "hsla" if formats.hsl => {
+            let hue = number(x.strip_suffix("deg").unwrap_or(x))?;
+            hsl_to_rgb(hue, percentage(y)?, percentage(z)?)
+        }
+        _ => return None,
+    };
+    Some(color.with_alpha(a))
+}
+
+/// 引数を3つの成分と不透明度に分ける（`a, b, c[, d]` と `a b c[ / d]` の両方）
+fn split_args(args: &str) -> Option<([&str; 3], Option<&str>)> {
+    let parts: Vec<&str> = if args.contains(',') {
+        args.split(',').map(str::trim).collect()
+    } else {
+        let (components, alpha) = match args.split_once('/') {
+            Some((components, alpha)) => (components, Some(alpha.trim())),
+            None => (args, None),
+        };
+        let mut parts: Vec<&str> = components.split_whitespace().collect();
+        parts.extend(alpha);
+        parts
+    };
+    match parts.as_slice() {
+        [x, y, z] => Some(([x, y, z], None)),
+        [x, y, z, a] => Some(([x, y, z], Some(a))),
+        _ => None,
+    }
+}
+
+/// 符号・指数を使わない10進数
+fn number(s: &str) -> Option<f32> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit() || b == b'.') {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// 0〜100 の `%` 付きの値を 0.0〜1.0 にする
+fn percentage(s: &str) -> Option<f32> {
+    let p = number(s.strip_suffix('%')?)?;
+    (p <= 100.0).then_some(p / 100.0)
+}
+
+/// 0〜1 の数か 0〜100 の `%` を 0.0〜1.0 にする
+fn ratio(s: &str) -> Option<f32> {
+    if s.ends_with('%') {
+        return percentage(s);
+    }
+    number(s).filter(|n| *n <= 1.0)
+}
+
+/// 0〜255 の数か 0〜100 の `%`
+fn rgb_channel(s: &str) -> Option<u8> {
+    if s.ends_with('%') {
+        return percentage(s).map(to_channel);
+    }
+    number(s).filter(|n| *n <= 255.0).map(|n| n.round() as u8)
+}
+
+fn to_channel(ratio: f32) -> u8 {
+    (ratio * 255.0).round() as u8
+}
+
+fn hsl_to_rgb(hue: f32, saturation: f32, lightness: f32) -> Color {
+    let h = hue.rem_euclid(360.0) / 360.0;
+    let (s, l) = (saturation, lightness);
+    let q = if l < 0.5 {
+        l * (1.0 + s)
+    } else {
+        l + s - l * s
+    };
+    let p = 2.0 * l - q;
+    let channel = |t: f32| {
+        let t = t.rem_euclid(1.0);
+        let v = if t < 1.0 / 6.0 {
+            p + (q - p) * 6.0 * t
+        } else if t < 0.5 {
+            q
+        } else if t < 2.0 / 3.0 {
+            p + (q - p) * (2.0 / 3.0 - t) * 6.0
+        } else {
+            p
+        };
+        to_channel(v)
+    };
+    Color::new(channel(h + 1.0 / 3.0), channel(h), channel(h - 1.0 / 3.0))
+}
+
+/// CSS の色名（名前順）と `0xRRGGBB`
+const NAMED_COLORS: [(&str, u32); 148] = [
+    ("aliceblue", 0xF0F8FF),
+    ("antiquewhite", 0xFAEBD7),
+    ("aqua", 0x00FFFF),
+    ("aquamarine", 0x7FFFD4),
+    ("azure", 0xF0FFFF),
+    ("beige", 0xF5F5DC),
+    ("bisque", 0xFFE4C4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xFFEBCD),
+    ("blue", 0x0000FF),
+    ("blueviolet", 0x8A2BE2),
+    ("brown", 0xA52A2A),
+    ("burlywood", 0xDEB887),
+    ("cadetblue", 0x5F9EA0),
+    ("chartreuse", 0x7FFF00),
+    ("chocolate", 0xD2691E),
+    ("coral", 0xFF7F50),
+    ("cornflowerblue", 0x6495ED),
+    ("cornsilk", 0xFFF8DC),
+    ("crimson", 0xDC143C),
+    ("cyan", 0x00FFFF),
+    ("darkblue", 0x00008B),
+    ("darkcyan", 0x008B8B),
+    ("darkgoldenrod", 0xB8860B),
+    ("darkgray", 0xA9A9A9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xA9A9A9),
+    ("darkkhaki", 0xBDB76B),
+    ("darkmagenta", 0x8B008B),
+    ("darkolivegreen", 0x556B2F),
+    ("darkorange", 0xFF8C00),
+    ("darkorchid", 0x9932CC),
+    ("darkred", 0x8B0000),
+    ("darksalmon", 0xE9967A),
+    ("darkseagreen", 0x8FBC8F),
+    ("darkslateblue", 0x483D8B),
+    ("darkslategray", 0x2F4F4F),
+    ("darkslategrey", 0x2F4F4F),
+    ("darkturquoise", 0x00CED1),
+    ("darkviolet", 0x9400D3),
+    ("deeppink", 0xFF1493),
+    ("deepskyblue", 0x00BFFF),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1E90FF),
+    ("firebrick", 0xB22222),
+    ("floralwhite", 0xFFFAF0),
+    ("forestgreen", 0x228B22),
+    ("fuchsia", 0xFF00FF),
+    ("gainsboro", 0xDCDCDC),
+    ("ghostwhite", 0xF8F8FF),
+    ("gold", 0xFFD700),
+    ("goldenrod", 0xDAA520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xADFF2F),
+    ("grey", 0x808080),
+    ("honeydew", 0xF0FFF0),
+    ("hotpink", 0xFF69B4),
+    ("indianred", 0xCD5C5C),
+    ("indigo", 0x4B0082),
+    ("ivory", 0xFFFFF0),
+    ("khaki", 0xF0E68C),
+    ("lavender", 0xE6E6FA),
+    ("lavenderblush", 0xFFF0F5),
+    ("lawngreen", 0x7CFC00),
+    ("lemonchiffon", 0xFFFACD),
+    ("lightblue", 0xADD8E6),
+    ("lightcoral", 0xF08080),
+    ("lightcyan", 0xE0FFFF),
+    ("lightgoldenrodyellow", 0xFAFAD2),
+    ("lightgray", 0xD3D3D3),
+    ("lightgreen", 0x90EE90),
+    ("lightgrey", 0xD3D3D3),
+    ("lightpink", 0xFFB6C1),
+    ("lightsalmon", 0xFFA07A),
+    ("lightseagreen", 0x20B2AA),
+    ("lightskyblue", 0x87CEFA),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xB0C4DE),
+    ("lightyellow", 0xFFFFE0),
+    ("lime", 0x00FF00),
+    ("limegreen", 0x32CD32),
+    ("linen", 0xFAF0E6),
+    ("magenta", 0xFF00FF),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66CDAA),
+    ("mediumblue", 0x0000CD),
+    ("mediumorchid", 0xBA55D3),
+    ("mediumpurple", 0x9370DB),
+    ("mediumseagreen", 0x3CB371),
+    ("mediumslateblue", 0x7B68EE),
+    ("mediumspringgreen", 0x00FA9A),
+    ("mediumturquoise", 0x48D1CC),
+    ("mediumvioletred", 0xC71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xF5FFFA),
+    ("mistyrose", 0xFFE4E1),
+    ("moccasin", 0xFFE4B5),
+    ("navajowhite", 0xFFDEAD),
+    ("navy", 0x000080),
+    ("oldlace", 0xFDF5E6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6B8E23),
+    ("orange", 0xFFA500),
+    ("orangered", 0xFF4500),
+    ("orchid", 0xDA70D6),
+    ("palegoldenrod", 0xEEE8AA),
+    ("palegreen", 0x98FB98),
+    ("paleturquoise", 0xAFEEEE),
+    ("palevioletred", 0xDB7093),
+    ("papayawhip", 0xFFEFD5),
+    ("peachpuff", 0xFFDAB9),
+    ("peru", 0xCD853F),
+    ("pink", 0xFFC0CB),
+    ("plum", 0xDDA0DD),
+    ("powderblue", 0xB0E0E6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xFF0000),
+    ("rosybrown", 0xBC8F8F),
+    ("royalblue", 0x4169E1),
+    ("saddlebrown", 0x8B4513),
+    ("salmon", 0xFA8072),
+    ("sandybrown", 0xF4A460),
+    ("seagreen", 0x2E8B57),
+    ("seashell", 0xFFF5EE),
+    ("sienna", 0xA0522D),
+    ("silver", 0xC0C0C0),
+    ("skyblue", 0x87CEEB),
+    ("slateblue", 0x6A5ACD),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xFFFAFA),
+    ("springgreen", 0x00FF7F),
+    ("steelblue", 0x4682B4),
+    ("tan", 0xD2B48C),
+    ("teal", 0x008080),
+    ("thistle", 0xD8BFD8),
+    ("tomato", 0xFF6347),
+    ("turquoise", 0x40E0D0),
+    ("violet", 0xEE82EE),
+    ("wheat", 0xF5DEB3),
+    ("white", 0xFFFFFF),
+    ("whitesmoke", 0xF5F5F5),
+    ("yellow", 0xFFFF00),
+    ("yellowgreen", 0x9ACD32),
+];
@@ -0,0 +1,152 @@
+// 入力と期待する出力を並べたファイル群（コーパス）による適合性テスト
+//
+// `名前.bbcode` の入力ごとに、同じ名前の `名前.html`（HTML の出力）・`名前.json`
+// （export::ast_to_json_v1 の出力）と一致するかを確かめる。方言の変換や独自の
+// レジストリが基準の挙動から外れていないかを、同じコーパスで確認できるようにする。
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::export::ast_to_json_v1;
+use crate::options::BbCodeOptions;
+use crate::parser::parse_bbcode_to_ast;
+use crate::render::{ast_to_html_with, HtmlRenderOptions};
+
+/// 比べた出力の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConformanceOutput {
+    /// `名前.html`
+    Html,
+    /// `名前.json`
+    Json,
+}
+
+/// 期待と違った出力
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConformanceFailure {
+    /// 入力ファイル
+    pub case: PathBuf,
+    pub output: ConformanceOutput,
+    pub expected: String,
+    pub actual: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConformanceReport {
+    /// すべての出力が期待どおりだった入力の数
+    pub passed: usize,
+    pub failures: Vec<ConformanceFailure>,
+}
+
+impl ConformanceReport {
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+impl fmt::Display for ConformanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} passed, {} failed", self.passed, self.failures.len())?;
+        for failure in &self.failures {
+            writeln!(
+                f,
+                "{} ({:?})\n  expected: {:?}\n  actual:   {:?}",
+                failure.case.display(),
+                failure.output,
+                failure.expected,
+                failure.actual
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// 既定のオプションで dir 以下のコーパスを確かめる
+pub fn run_corpus(dir: impl AsRef<Path>) -> io::Result<ConformanceReport> {
+    run_corpus_with(
+        dir,
+        &BbCodeOptions::default(),
+        &HtmlRenderOptions::default(),
+    )
+}
+
+/// opts でパースし、html で描画した結果をコーパスと比べる（サブディレクトリも含む）
+/// ファイル末尾の改行1つは無視する。パースに失敗した入力の出力は `error: メッセージ` とみなす。
+/// 期待する出力が1つも無い入力があれば InvalidData のエラーにする
+pub fn run_corpus_with(
+    dir: impl AsRef<Path>,
+    opts: &BbCodeOptions,
+    html: &HtmlRenderOptions,
+) -> io::Result<ConformanceReport> {
+    let mut cases = vec![];
+    collect_cases(dir.as_ref(), &mut cases)?;
+    cases.sort();
+
+    let mut report = ConformanceReport::default();
+    for case in cases {
+        let input = read_trimmed(&case)?;
+        let expectations = [
+            (ConformanceOutput::Html, case.with_extension("html")),
+            (ConformanceOutput::Json, case.with_extension("json")),
+        ];
+        let expectations: Vec<_> = expectations
+            .into_iter()
+            .filter(|(_, path)| path.is_file())
+            .collect();
+        if expectations.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("no expected output for {}", case.display()),
+            ));
+        }
+
+        let ast = parse_bbcode_to_ast(&input, opts);
+        let mut ok = true;
+        for (output, path) in expectations {
+            let expected = read_trimmed(&path)?;
+            let actual = match (&ast, output) {
+                (Ok(ast), ConformanceOutput::Html) => ast_to_html_with(ast, html),
+                (Ok(ast), ConformanceOutput::Json) => ast_to_json_v1(ast),
+                (Err(e), _) => format!("error: {e}"),
+            };
+            if actual != expected {
+                ok = false;
+                report.failures.push(ConformanceFailure {
+                    case: case.clone(),
+                    output,
+                    expected,
+                    actual,
+                });
+            }
+        }
+        if ok {
+            report.passed += 1;
+        }
+    }
+    Ok(report)
+}
+
+fn collect_cases(dir: &Path, cases: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_cases(&path, cases)?;
+        } else if path.extension().is_some_and(|e| e == "bbcode") {
+            cases.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn read_trimmed(path: &Path) -> io::Result<String> {
+    let mut text = fs::read_to_string(path)?;
+    if text.ends_with('\n') {
+        text.pop();
+        if text.ends_with('\r') {
+            text.pop();
+        }
+    }
+    Ok(text)
+}
@@ -0,0 +1,99 @@
+// 投稿の集まり（コーパス）全体でのタグの使われ方の集計
+//
+// パーサーの移行やタグ・制限の方針を厳しくする前に、既存の投稿でどのタグ・属性が
+// どれだけ使われ、どれだけテキストに戻り、どの投稿が制限を超えるかを確かめるためのもの。
+
+use std::collections::BTreeMap;
+
+use crate::ast::Node;
+use crate::diagnostic::Diagnostic;
+use crate::error::{BbCodeError, LimitError};
+use crate::options::BbCodeOptions;
+use crate::parser::parse_counting_fallbacks;
+
+/// コーパスの集計結果。投稿の番号は scan に渡した順（0 始まり）
+#[derive(Debug, Default)]
+pub struct CorpusReport {
+    /// 調べた投稿の数
+    pub posts: usize,
+    /// エラーにならずにパースできた投稿の数
+    pub parsed: usize,
+    /// タグ（別名は正規名）ごとの要素の数
+    pub tags: BTreeMap<String, usize>,
+    /// `(タグ, 属性名)` ごとの数。`[tag=..]` の値は属性名 "value"
+    pub attrs: BTreeMap<(String, String), usize>,
+    /// 未知のタグ（小文字にした名前）ごとの数
+    pub unknown_tags: BTreeMap<String, usize>,
+    /// テキストに戻したタグの数（未知のタグ・不正な値・閉じていないタグなど）
+    pub fallbacks: usize,
+    /// タグを1つ以上テキストに戻した投稿
+    pub posts_with_fallbacks: Vec<usize>,
+    /// 制限を超えてパースできなかった投稿
+    pub limit_exceeded: Vec<(usize, LimitError)>,
+    /// 制限以外の理由でパースできなかった投稿
+    pub failed: Vec<(usize, BbCodeError)>,
+}
+
+impl CorpusReport {
+    /// テキストに戻したタグのある投稿の割合（投稿が無ければ 0）
+    pub fn fallback_rate(&self) -> f64 {
+        if self.posts == 0 {
+            return 0.0;
+        }
+        self.posts_with_fallbacks.len() as f64 / self.posts as f64
+    }
+
+    fn add_nodes(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            let Node::Element(el) = node else {
+                continue;
+            };
+            *self.tags.entry(el.name.to_string()).or_default() += 1;
+            for (key, _) in &el.attrs {
+                *self
+                    .attrs
+                    .entry((el.name.to_string(), key.clone()))
+                    .or_default() += 1;
+            }
+            self.add_nodes(&el.children);
+        }
+    }
+}
+
+/// inputs の投稿をすべて opts でパースし、集計する
+pub fn scan<I>(inputs: I, opts: &BbCodeOptions) -> CorpusReport
+where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    let mut report = CorpusReport::default();
+    for (index, input) in inputs.into_iter().enumerate() {
+        report.posts += 1;
+        let parsed = match parse_counting_fallbacks(input.as_ref(), opts) {
+            Ok(parsed) => parsed,
+            Err(BbCodeError::Limit(e)) => {
+                report.limit_exceeded.push((index, e));
+                continue;
+            }
+            Err(e) => {
+                report.failed.push((index, e));
+                continue;
+            }
+        };
+        report.parsed += 1;
+        report.add_nodes(&parsed.nodes);
+        for diagnostic in &parsed.diagnostics {
+            if let Diagnostic::UnknownTag { tag, .. } = diagnostic {
+                *report
+                    .unknown_tags
+                    .entry(tag.to_ascii_lowercase())
+                    .or_default() += 1;
+            }
+        }
+        if !parsed.fallbacks.is_empty() {
+            report.fallbacks += parsed.fallbacks.len();
+            report.posts_with_fallbacks.push(index);
+        }
+    }
+    report
+}
@@ -0,0 +1,154 @@
+use std::fmt;
+
+use crate::ast::Span;
+use crate::lint::LintCode;
+use crate::registry::PermissionLevel;
+
+/// パース自体は成功したが、利用者に知らせたい事柄
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Diagnostic {
+    /// `[/]` が想定外のタグ（未知のタグなど、テキストに戻るもの）を閉じた
+    UniversalCloseMismatch { closed: String, span: Span },
+    /// インライン要素 `parent` の中にブロック要素 `child` がある。span は子要素の範囲
+    BlockInInline {
+        parent: String,
+        child: String,
+        span: Span,
+    },
+    /// 未知のタグ（テキストとして扱った）。suggestion は近い名前の登録済みタグ
+    UnknownTag {
+        tag: String,
+        suggestion: Option<String>,
+        span: Span,
+    },
+    /// 廃止予定のタグが使われた。replacement は代わりに使うべきもの
+    Deprecated {
+        tag: String,
+        replacement: Option<String>,
+        span: Span,
+    },
+    /// `max_links` を超えたリンク
+    TooManyLinks { max_links: usize, span: Span },
+    /// `link_domains` で許可されていないドメインへのリンク
+    LinkDomainNotAllowed { domain: String, span: Span },
+    /// 投稿者の権限では使えないタグ（テキストとして扱った）。required は必要な権限
+    PermissionDenied {
+        tag: String,
+        required: PermissionLevel,
+        span: Span,
+    },
+    /// 生の HTML を含む `[html]` が使われた。描画の設定によってはそのまま出力される
+    RawHtml { span: Span },
+    /// `[color]` の色が背景色に対してコントラスト不足（color_contrast）
+    LowContrast {
+        color: String,
+        background: String,
+        span: Span,
+    },
+    /// `[url=..]` の表示が別のリンク先に見える（lookalike::deceptive_link）
+    /// lookalike は見た目の似た文字で同じホストに見せかけている場合
+    DeceptiveLink {
+        shown: String,
+        actual: String,
+        lookalike: bool,
+        span: Span,
+    },
+}
+
+impl Diagnostic {
+    /// 該当箇所の入力上の範囲
+    pub fn span(&self) -> Span {
+        match self {
+            Diagnostic::UniversalCloseMismatch { span, .. } => *span,
+            Diagnostic::BlockInInline { span, .. } => *span,
+            Diagnostic::UnknownTag { span, .. } => *span,
+            Diagnostic::Deprecated { span, .. } => *span,
+            Diagnostic::TooManyLinks { span, .. } => *span,
+            Diagnostic::LinkDomainNotAllowed { span, .. } => *span,
+            Diagnostic::PermissionDenied { span, .. } => *span,
+            Diagnostic::RawHtml { span } => *span,
+            Diagnostic::LowContrast { span, .. } => *span,
+            Diagnostic::DeceptiveLink { span, .. } => *span,
+        }
+    }
+
+    /// lint で報告するときのコード
+    pub fn code(&self) -> LintCode {
+        match self {
+            // `[/]` で閉じたのは未知のタグ
+            Diagnostic::UniversalCloseMismatch { .. } => LintCode::UnknownTag,
+            Diagnostic::BlockInInline { .. } => LintCode::BlockInInline,
+            Diagnostic::UnknownTag { .. } => LintCode::UnknownTag,
+            Diagnostic::Deprecated { .. } => LintCode::Deprecated,
+            Diagnostic::TooManyLinks { .. } => LintCode::TooManyLinks,
+            Diagnostic::LinkDomainNotAllowed { .. } => LintCode::LinkDomainNotAllowed,
+            Diagnostic::PermissionDenied { .. } => LintCode::PermissionDenied,
+            Diagnostic::RawHtml { .. } => LintCode::RawHtml,
+            Diagnostic::LowContrast { .. } => LintCode::LowContrast,
+            Diagnostic::DeceptiveLink { .. } => LintCode::DeceptiveLink,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Diagnostic::UniversalCloseMismatch { closed, .. } => {
+                write!(f, "[/] closes unknown tag [{closed}]")
+            }
+            Diagnostic::BlockInInline { parent, child, .. } => {
+                write!(
+                    f,
+                    "Block element [{child}] inside inline element [{parent}]"
+                )
+            }
+            Diagnostic::UnknownTag {
+                tag, suggestion, ..
+            } => {
+                write!(f, "Unknown tag [{tag}]")?;
+                match suggestion {
+                    Some(s) => write!(f, "; did you mean [{s}]?"),
+                    None => Ok(()),
+                }
+            }
+            Diagnostic::Deprecated {
+                tag, replacement, ..
+            } => {
+                write!(f, "Tag [{tag}] is deprecated")?;
+                match replacement {
+                    Some(r) => write!(f, "; use {r} instead"),
+                    None => Ok(()),
+                }
+            }
+            Diagnostic::TooManyLinks { max_links, .. } => {
+                write!(f, "Too many links (max {max_links})")
+            }
+            Diagnostic::LinkDomainNotAllowed { domain, .. } => {
+                write!(f, "Links to {domain} are not allowed")
+            }
+            Diagnostic::PermissionDenied { tag, required, .. } => {
+                write!(f, "Tag [{tag}] requires {required:?} permission")
+            }
+            Diagnostic::RawHtml { .. } => f.write_str("Raw HTML in [html]"),
+            Diagnostic::LowContrast {
+                color, background, ..
+            } => write!(
+                f,
+                "Color {color} has low contrast against background {background}"
+            ),
+            Diagnostic::DeceptiveLink {
+                shown,
+                actual,
+                lookalike,
+                ..
+            } => {
+                write!(f, "Link text {shown} points to {actual}")?;
+                if *lookalike {
+                    f.write_str(" (lookalike characters)")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
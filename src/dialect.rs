@@ -0,0 +1,92 @@
+use crate::options::BbCodeOptions;
+use crate::registry::{
+    is_valid_email, split_author_post_id, split_xenforo_quote, validate_email_element, TagRegistry,
+    TagSpec,
+};
+
+/// 主要フォーラムエンジンの BBCode 方言
+/// 組み込みタグをベースに、タグの別名・属性の書式・`[*]` の扱いなどを合わせる
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum Dialect {
+    /// このクレートの標準
+    #[default]
+    Standard,
+    /// `[quote="Alice" post_id=1 time=2 user_id=3]`、`[*]..[/*]` も許可。閉じタグは表記まで一致させる
+    PhpBB,
+    /// `[quote=Alice;123]`、`[strike]`
+    VBulletin,
+    /// `[QUOTE="Alice, post: 123, member: 45"]`
+    XenForo,
+    /// `[quote author=Alice link=... date=...]`
+    Smf,
+}
+
+impl Dialect {
+    /// 方言に合わせて構成したタグレジストリ
+    pub fn registry(self) -> TagRegistry {
+        let mut r = TagRegistry::builtin();
+        match self {
+            Dialect::Standard => {}
+            Dialect::PhpBB => {
+                r.insert(
+                    "quote",
+                    TagSpec::with_value(None)
+                        .with_splitter(split_author_post_id)
+                        .with_named_attrs(&["post_id", "time", "user_id"]),
+                );
+            }
+            Dialect::VBulletin => {
+                r.insert(
+                    "quote",
+                    TagSpec::with_value(None).with_splitter(split_author_post_id),
+                );
+                r.alias("strike", "s");
+                // 項目は閉じタグ無しの `[*]` のみ（`[*]..[/*]` はテキスト扱い）
+                r.remove("*");
+            }
+            Dialect::XenForo => {
+                r.insert(
+                    "quote",
+                    TagSpec::with_value(None).with_splitter(split_xenforo_quote),
+                );
+                // `[email]a@b.example[/email]` / `[email=a@b.example]名前[/email]`
+                // AST 上は `mailto:` を付けた `[url]` になる
+                r.insert(
+                    "email",
+                    TagSpec::with_value(Some(is_valid_email))
+                        .with_element_validator(validate_email_element),
+                );
+                r.remove("*");
+            }
+            Dialect::Smf => {
+                r.insert(
+                    "quote",
+                    TagSpec::with_value(None).with_named_attrs(&["author", "link", "date"]),
+                );
+                r.alias("iurl", "url");
+                r.remove("*");
+            }
+        }
+        r
+    }
+
+    /// 方言に合わせてオプションを書き換える（制限値などはそのまま）
+    pub fn apply(self, opts: &mut BbCodeOptions) {
+        opts.registry = self.registry();
+        // phpBB は保存時に解釈したタグの名前を小文字に揃えるので、書き出した本文で
+        // `[B]..[/b]` のように表記の違う組は元の投稿でもタグではなかったもの
+        opts.case_sensitive_tags = self == Dialect::PhpBB;
+        // `[/]` はどのエンジンでも文字のまま表示される
+        opts.universal_close = false;
+    }
+}
+
+impl BbCodeOptions {
+    /// 既定の制限値と、方言に合わせたレジストリを持つオプション
+    pub fn for_dialect(dialect: Dialect) -> Self {
+        let mut opts = Self::default();
+        dialect.apply(&mut opts);
+        opts
+    }
+}
@@ -0,0 +1,169 @@
+use crate::ast::Node;
+use crate::diagnostic::Diagnostic;
+use crate::error::BbCodeError;
+use crate::iter::{Cursor, DepthFirst};
+use crate::options::BbCodeOptions;
+use crate::parser::{parse_bbcode_with_diagnostics, parse_plain_text};
+use crate::registry::{is_valid_url, single_text_child};
+use crate::render::{
+    ast_to_html, ast_to_html_with, ast_to_markdown, ast_to_plain_text, attr_value,
+    HtmlRenderOptions,
+};
+
+/// パース結果の統計
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParseMetrics {
+    /// 要素の数
+    pub element_count: usize,
+    /// テキストノードの数
+    pub text_node_count: usize,
+    /// テキストの合計長（バイト）
+    pub text_len: usize,
+    /// 要素の入れ子の最大の深さ（要素が無ければ 0）
+    pub max_depth: usize,
+}
+
+impl ParseMetrics {
+    pub(crate) fn of(nodes: &[Node]) -> Self {
+        let mut metrics = Self::default();
+        metrics.visit(nodes, 0);
+        metrics
+    }
+
+    fn visit(&mut self, nodes: &[Node], depth: usize) {
+        for node in nodes {
+            match node {
+                Node::Text { text, .. } => {
+                    self.text_node_count += 1;
+                    self.text_len += text.len();
+                }
+                Node::Element(el) => {
+                    self.element_count += 1;
+                    self.max_depth = self.max_depth.max(depth + 1);
+                    self.visit(&el.children, depth + 1);
+                }
+                Node::Unsupported(_) => {}
+            }
+        }
+    }
+}
+
+/// 入力の記法
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Format {
+    BbCode,
+    /// CommonMark。BBCode に変換してからパースする
+    #[cfg(feature = "markdown")]
+    Markdown,
+    /// 装飾の無いテキスト。`[` もそのまま文字として扱う
+    Plain,
+}
+
+/// パース結果。AST に診断情報と統計を添えたもの
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BbCodeDocument {
+    pub nodes: Vec<Node>,
+    /// パースは成功したが利用者に知らせたい事柄
+    pub warnings: Vec<Diagnostic>,
+    /// 元の入力の長さ（バイト）
+    pub source_len: usize,
+    pub metrics: ParseMetrics,
+}
+
+impl BbCodeDocument {
+    pub fn parse(input: &str, opts: &BbCodeOptions) -> Result<Self, BbCodeError> {
+        let (nodes, warnings) = parse_bbcode_with_diagnostics(input, opts)?;
+        Ok(Self::from_parts(nodes, warnings, input.len()))
+    }
+
+    /// format の記法で書かれた入力をパースする
+    /// どの記法でも opts の制限（入力サイズ・テキスト長・改行・ハッシュタグなど）は同じく適用される
+    pub fn parse_any(
+        input: &str,
+        format: Format,
+        opts: &BbCodeOptions,
+    ) -> Result<Self, BbCodeError> {
+        let (nodes, warnings) = match format {
+            Format::BbCode => parse_bbcode_with_diagnostics(input, opts)?,
+            #[cfg(feature = "markdown")]
+            Format::Markdown => crate::import::parse_markdown_with_diagnostics(input, opts)?,
+            Format::Plain => (parse_plain_text(input, opts)?, vec![]),
+        };
+        Ok(Self::from_parts(nodes, warnings, input.len()))
+    }
+
+    /// パース済みの AST から組み立てる
+    pub fn from_parts(nodes: Vec<Node>, warnings: Vec<Diagnostic>, source_len: usize) -> Self {
+        let metrics = ParseMetrics::of(&nodes);
+        Self {
+            nodes,
+            warnings,
+            source_len,
+            metrics,
+        }
+    }
+
+    pub fn to_html(&self) -> String {
+        ast_to_html(&self.nodes)
+    }
+
+    pub fn to_html_with(&self, opts: &HtmlRenderOptions) -> String {
+        ast_to_html_with(&self.nodes, opts)
+    }
+
+    pub fn to_markdown(&self) -> String {
+        ast_to_markdown(&self.nodes)
+    }
+
+    pub fn to_plain_text(&self) -> String {
+        ast_to_plain_text(&self.nodes)
+    }
+
+    /// すべてのノードを深さ優先で `(深さ, ノード)` として返す
+    pub fn iter(&self) -> DepthFirst<'_> {
+        DepthFirst::new(&self.nodes)
+    }
+
+    /// 最初のノードを指すカーソル
+    pub fn cursor(&self) -> Cursor<'_> {
+        Cursor::new(&self.nodes)
+    }
+
+    /// `[url]` のリンク先を文書順に返す（HTML でリンクにならない不正な URL は除く）
+    pub fn links(&self) -> Vec<&str> {
+        let mut out = vec![];
+        collect_links(&self.nodes, &mut out);
+        out
+    }
+}
+
+fn collect_links<'a>(nodes: &'a [Node], out: &mut Vec<&'a str>) {
+    for node in nodes {
+        let Node::Element(el) = node else {
+            continue;
+        };
+        if el.name == "url" {
+            // 値属性が無ければ中身がそのままリンク先
+            let href = attr_value(el).or_else(|| single_text_child(el));
+            if let Some(href) = href.filter(|h| is_valid_url(h)) {
+                out.push(href.trim());
+            }
+        }
+        collect_links(&el.children, out);
+    }
+}
+
+/// 公開API：入力文字列をパースして BbCodeDocument を返す
+pub fn parse(input: &str, opts: &BbCodeOptions) -> Result<BbCodeDocument, BbCodeError> {
+    BbCodeDocument::parse(input, opts)
+}
+
+/// 公開API：BBCode・Markdown・プレーンテキストのいずれかをパースして BbCodeDocument を返す
+pub fn parse_any(
+    input: &str,
+    format: Format,
+    opts: &BbCodeOptions,
+) -> Result<BbCodeDocument, BbCodeError> {
+    BbCodeDocument::parse_any(input, format, opts)
+}
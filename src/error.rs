@@ -1,11 +1,89 @@
-use crate::ast::Span;
+use std::fmt;
+
 use thiserror::Error;
 
+use crate::ast::Span;
+use crate::lint::LintCode;
+
+/// パース・変換の失敗
+/// 入力が制限を超えた（利用者の入力の問題）のか、文法として解釈できなかったのかで分かれる。
+/// 種類だけを知りたいなら `kind()` を使う
 #[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum BbCodeError {
+    #[error(transparent)]
+    Limit(#[from] LimitError),
+
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+
+    /// パーサ内部の不整合（文法の変更と AST の組み立てが食い違ったなど）。入力ではなくクレートの不具合
+    #[error("Internal parser error: {0}")]
+    Internal(String),
+}
+
+/// `BbCodeError` の分類（HTTP のステータスなどへの対応付け用）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// `BbCodeOptions` の制限を超えた
+    Limit,
+    /// 文法として解釈できなかった
+    Parse,
+    /// クレートの不具合（HTTP 500 相当）
+    Internal,
+}
+
+impl BbCodeError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Limit(_) => ErrorKind::Limit,
+            Self::Parse(_) => ErrorKind::Parse,
+            Self::Internal(_) => ErrorKind::Internal,
+        }
+    }
+
+    /// lint で報告するときのコード
+    pub fn code(&self) -> LintCode {
+        match self {
+            Self::Limit(LimitError::NestDepthExceeded { .. }) => LintCode::NestDepth,
+            Self::Limit(_) => LintCode::LimitExceeded,
+            Self::Parse(ParseError::Syntax(_)) => LintCode::Syntax,
+            Self::Parse(ParseError::InvalidTag { reason, .. }) => reason.code(),
+            Self::Parse(ParseError::DuplicateAttribute { .. }) => LintCode::DuplicateAttribute,
+            Self::Internal(_) => LintCode::Internal,
+        }
+    }
+
+    /// 入力上の位置。入力全体に対するもの（入力サイズの超過など）は None
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::Limit(LimitError::NestDepthExceeded { span, .. })
+            | Self::Limit(LimitError::ChildCountExceeded { span, .. })
+            | Self::Parse(ParseError::InvalidTag { span, .. })
+            | Self::Parse(ParseError::DuplicateAttribute { span, .. }) => Some(*span),
+            Self::Parse(ParseError::Syntax(e)) => Some(e.span),
+            Self::Limit(_) | Self::Internal(_) => None,
+        }
+    }
+}
+
+impl From<SyntaxError> for BbCodeError {
+    fn from(e: SyntaxError) -> Self {
+        Self::Parse(e.into())
+    }
+}
+
+/// `BbCodeOptions` の制限（max_input_size / max_depth など）を超えた
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum LimitError {
     #[error("Input size exceeded limit (max {max_size} bytes)")]
     InputSizeExceeded { max_size: usize, actual_size: usize },
 
+    #[error("Total text length exceeded limit (max {max_len} bytes)")]
+    TextLengthExceeded { max_len: usize, actual_len: usize },
+
     #[error("Parsed tag count exceeded limit (max {max_tags})")]
     TagCountExceeded { max_tags: usize },
 
@@ -20,6 +98,129 @@ pub enum BbCodeError {
         column: usize,
     },
 
+    /// 1つの要素の直下の子要素が多すぎる。span は親の要素
+    #[error(
+        "Child element count exceeded limit (max {max_children}) at line {line}, col {column}"
+    )]
+    ChildCountExceeded {
+        max_children: usize,
+        span: Span,
+        line: usize,
+        column: usize,
+    },
+}
+
+/// 入力を BBCode として解釈できなかった
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ParseError {
     #[error("Failed to parse input: {0}")]
-    PestError(#[from] pest::error::Error<crate::parser::Rule>),
+    Syntax(#[from] SyntaxError),
+
+    /// テキストに戻すはずのタグ（BbCodeOptions::strict の場合）。near はタグの入力どおりの範囲
+    #[error("Invalid tag ({reason}) at line {line}, col {column}. Near: \"{near}\"")]
+    InvalidTag {
+        reason: InvalidTagReason,
+        near: String,
+        span: Span,
+        line: usize,
+        column: usize,
+    },
+
+    /// 同じ名前付き属性が繰り返された（BbCodeOptions::duplicate_attrs が Error の場合）
+    #[error("Duplicate attribute \"{attr}\" in [{tag}] at line {line}, col {column}")]
+    DuplicateAttribute {
+        tag: String,
+        attr: String,
+        span: Span,
+        line: usize,
+        column: usize,
+    },
+}
+
+/// 文法として解釈できなかった位置（何も閉じない閉じタグなど）
+/// 字句解析の実装（pest）の型に依存しないよう、必要な情報だけを持つ
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{message} at line {line}, col {column}")]
+pub struct SyntaxError {
+    pub message: String,
+    /// 入力上の位置。1点を指す場合は空の範囲
+    pub span: Span,
+    pub line: usize,
+    pub column: usize,
+    /// その位置にあるべきだった文法の要素（`open_tag` など）。分からなければ空
+    pub expected: Vec<String>,
+}
+
+/// タグを要素にできなかった理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum InvalidTagReason {
+    /// 閉じタグの名前が開始タグと合わない
+    MismatchedClose,
+    /// 閉じタグが無い
+    Unclosed,
+    /// 登録されていないタグ
+    UnknownTag,
+    /// 値属性が無効（許可されていない・検証に失敗した）
+    InvalidValue,
+    /// 許可されていない名前付き属性
+    InvalidAttribute,
+    /// 中身が無効（`[url]` / `[img]` の URL など）
+    InvalidContent,
+    /// その位置に置けない（親子関係の制約・インライン要素の中のブロック要素）
+    NotAllowedHere,
+    /// 投稿者の権限では使えない
+    PermissionDenied,
+}
+
+impl fmt::Display for InvalidTagReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::MismatchedClose => "mismatched closing tag",
+            Self::Unclosed => "unclosed tag",
+            Self::UnknownTag => "unknown tag",
+            Self::InvalidValue => "invalid value",
+            Self::InvalidAttribute => "invalid attribute",
+            Self::InvalidContent => "invalid content",
+            Self::NotAllowedHere => "not allowed here",
+            Self::PermissionDenied => "permission denied",
+        })
+    }
+}
+
+impl InvalidTagReason {
+    /// lint で報告するときのコード
+    pub fn code(self) -> LintCode {
+        match self {
+            Self::MismatchedClose => LintCode::MismatchedClose,
+            Self::Unclosed => LintCode::Unclosed,
+            Self::UnknownTag => LintCode::UnknownTag,
+            Self::InvalidValue => LintCode::InvalidValue,
+            Self::InvalidAttribute => LintCode::InvalidAttribute,
+            Self::InvalidContent => LintCode::InvalidContent,
+            Self::NotAllowedHere => LintCode::NotAllowedHere,
+            Self::PermissionDenied => LintCode::PermissionDenied,
+        }
+    }
+}
+
+/// `TagTemplate::parse` / `TagRegistry::insert_template` のエラー
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[non_exhaustive]
+pub enum TemplateError {
+    #[error("Unknown template placeholder: {{{0}}}")]
+    UnknownPlaceholder(String),
+
+    #[error("Unclosed template placeholder (use {{{{ for a literal brace)")]
+    UnclosedPlaceholder,
+
+    #[error("Template placeholder {{{0}}} must be inside a quoted attribute value")]
+    UnquotedAttribute(String),
+
+    #[error("Template placeholder {{{0}}} cannot be escaped safely in this position")]
+    UnsafeContext(String),
+
+    #[error("Template placeholder {{content}} must appear at most once, in element content")]
+    MisplacedContent,
 }
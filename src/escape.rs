@@ -0,0 +1,63 @@
+// 出力先の文脈ごとのエスケープ
+//
+// 同じ値でも、要素の中身・引用符で囲んだ属性値・style 属性の CSS・URL の一部とで
+// 特別な意味を持つ文字が違う。置く位置に合った関数を使うこと。
+
+/// 要素の中身に置くテキスト
+pub fn escape_text(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// 引用符（`"` / `'`）で囲んだ属性値。XML の属性値・中身にも使える
+pub fn escape_attr(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// style 属性の宣言の値（`color:値`）
+/// 英数字と `#` `.` `%` `,` `-` `_` 空白以外は CSS のエスケープ（`\3b `）にするので、
+/// 宣言や属性値の外に出られない。結果はそのまま引用符で囲んだ style 属性に置ける
+pub fn escape_css_value(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    for c in input.chars() {
+        if c.is_ascii_alphanumeric() || matches!(c, '#' | '.' | '%' | ',' | '-' | '_' | ' ') {
+            out.push(c);
+        } else {
+            out.push_str(&format!("\\{:x} ", u32::from(c)));
+        }
+    }
+    out
+}
+
+/// URL のパス・クエリの一部（`/search?q=値` の値など）
+/// RFC 3986 の非予約文字以外を UTF-8 のパーセントエンコードにする
+pub fn escape_url_component(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    for b in input.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~') {
+            out.push(char::from(b));
+        } else {
+            out.push_str(&format!("%{b:02X}"));
+        }
+    }
+    out
+}
@@ -0,0 +1,235 @@
+// 保存用の AST 書き出し形式
+//
+// 内部の `Node` 型とは独立したスキーマ（v1）を定義し、クレートの更新で
+// `Node` が変わっても保存済みの AST を読み戻せるようにする。
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::ast::{Element, Node, Span, UnsupportedNode};
+use crate::escape::escape_attr;
+
+/// 現在の書き出し形式のバージョン
+pub const AST_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ExportError {
+    #[error("Invalid AST JSON: {0}")]
+    InvalidJson(String),
+
+    #[error("Unsupported AST schema version {found} (expected {expected})")]
+    UnsupportedVersion { found: u32, expected: u32 },
+}
+
+/// v1 の文書。`version` は常に 1
+#[derive(Debug, Serialize, Deserialize)]
+struct DocumentV1 {
+    version: u32,
+    nodes: Vec<NodeV1>,
+}
+
+/// v1 のノード。このバージョンが知らない種類（`type`）のノードは JSON のまま保持する
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum NodeV1 {
+    Known(KnownNodeV1),
+    Unknown(serde_json::Value),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum KnownNodeV1 {
+    Text {
+        text: String,
+        span: [usize; 2],
+    },
+    Element {
+        name: String,
+        #[serde(default)]
+        attrs: Vec<[String; 2]>,
+        span: [usize; 2],
+        #[serde(default)]
+        children: Vec<NodeV1>,
+    },
+}
+
+impl From<&Node> for NodeV1 {
+    fn from(node: &Node) -> Self {
+        match node {
+            Node::Text { span, text } => NodeV1::Known(KnownNodeV1::Text {
+                text: text.clone(),
+                span: [span.start, span.end],
+            }),
+            Node::Element(el) => NodeV1::Known(KnownNodeV1::Element {
+                name: el.name.to_string(),
+                attrs: el
+                    .attrs
+                    .iter()
+                    .map(|(k, v)| [k.clone(), v.clone()])
+                    .collect(),
+                span: [el.span.start, el.span.end],
+                children: el.children.iter().map(NodeV1::from).collect(),
+            }),
+            // 読み込んだときの JSON を戻す。プログラムから作った壊れた raw なら種類と範囲だけ残す
+            Node::Unsupported(node) => {
+                NodeV1::Unknown(serde_json::from_str(&node.raw).unwrap_or_else(|_| {
+                    serde_json::json!({
+                        "type": node.kind,
+                        "span": [node.span.start, node.span.end],
+                    })
+                }))
+            }
+        }
+    }
+}
+
+impl TryFrom<NodeV1> for Node {
+    type Error = ExportError;
+
+    fn try_from(node: NodeV1) -> Result<Self, ExportError> {
+        match node {
+            NodeV1::Known(KnownNodeV1::Text { text, span }) => Ok(Node::Text {
+                span: Span {
+                    start: span[0],
+                    end: span[1],
+                },
+                text,
+            }),
+            NodeV1::Known(KnownNodeV1::Element {
+                name,
+                attrs,
+                span,
+                children,
+            }) => {
+                let children = children
+                    .into_iter()
+                    .map(Node::try_from)
+                    .collect::<Result<_, _>>()?;
+                let mut el = Element::new(
+                    name,
+                    Span {
+                        start: span[0],
+                        end: span[1],
+                    },
+                )
+                .with_children(children);
+                el.attrs = attrs.into_iter().map(|[k, v]| (k, v)).collect();
+                Ok(Node::Element(el))
+            }
+            NodeV1::Unknown(value) => unsupported_node(value),
+        }
+    }
+}
+
+/// 未知の種類のノード。既知の種類（text / element）なのに形が合わないものはエラーにする
+fn unsupported_node(value: serde_json::Value) -> Result<Node, ExportError> {
+    let kind = match value.get("type").and_then(|t| t.as_str()) {
+        Some("text" | "element") => {
+            return Err(ExportError::InvalidJson(format!("malformed node: {value}")));
+        }
+        Some(kind) => kind.to_string(),
+        None => {
+            return Err(ExportError::InvalidJson(format!(
+                "node without type: {value}"
+            )))
+        }
+    };
+    let bound = |i: usize| {
+        value
+            .get("span")
+            .and_then(|s| s.get(i))
+            .and_then(|n| n.as_u64())
+            .map_or(0, |n| n as usize)
+    };
+    Ok(Node::Unsupported(UnsupportedNode {
+        kind,
+        span: Span {
+            start: bound(0),
+            end: bound(1),
+        },
+        raw: value.to_string(),
+    }))
+}
+
+/// AST を v1 形式の JSON 文字列にする
+pub fn ast_to_json_v1(nodes: &[Node]) -> String {
+    let doc = DocumentV1 {
+        version: AST_SCHEMA_VERSION,
+        nodes: nodes.iter().map(NodeV1::from).collect(),
+    };
+    // 文字列キーと基本型のみなので失敗しない
+    serde_json::to_string(&doc).unwrap_or_default()
+}
+
+/// v1 形式の JSON 文字列から AST を復元する
+pub fn ast_from_json_v1(json: &str) -> Result<Vec<Node>, ExportError> {
+    #[derive(Deserialize)]
+    struct VersionOnly {
+        version: u32,
+    }
+
+    // 先にバージョンだけ確認して、未知の形式は明確なエラーにする
+    let header: VersionOnly =
+        serde_json::from_str(json).map_err(|e| ExportError::InvalidJson(e.to_string()))?;
+    if header.version != AST_SCHEMA_VERSION {
+        return Err(ExportError::UnsupportedVersion {
+            found: header.version,
+            expected: AST_SCHEMA_VERSION,
+        });
+    }
+
+    let doc: DocumentV1 =
+        serde_json::from_str(json).map_err(|e| ExportError::InvalidJson(e.to_string()))?;
+    doc.nodes.into_iter().map(Node::try_from).collect()
+}
+
+/// AST を v1 形式の XML 文字列にする（書き出し専用）
+pub fn ast_to_xml_v1(nodes: &[Node]) -> String {
+    let mut out = format!("<bbcode version=\"{AST_SCHEMA_VERSION}\">");
+    for n in nodes {
+        write_xml_node(n, &mut out);
+    }
+    out.push_str("</bbcode>");
+    out
+}
+
+fn write_xml_node(node: &Node, out: &mut String) {
+    match node {
+        Node::Text { span, text } => {
+            out.push_str(&format!(
+                "<text start=\"{}\" end=\"{}\">{}</text>",
+                span.start,
+                span.end,
+                escape_attr(text)
+            ));
+        }
+        Node::Element(el) => {
+            out.push_str(&format!(
+                "<element name=\"{}\" start=\"{}\" end=\"{}\">",
+                escape_attr(&el.name),
+                el.span.start,
+                el.span.end
+            ));
+            for (k, v) in &el.attrs {
+                out.push_str(&format!(
+                    "<attr name=\"{}\">{}</attr>",
+                    escape_attr(k),
+                    escape_attr(v)
+                ));
+            }
+            for c in &el.children {
+                write_xml_node(c, out);
+            }
+            out.push_str("</element>");
+        }
+        Node::Unsupported(node) => {
+            out.push_str(&format!(
+                "<unsupported type=\"{}\" start=\"{}\" end=\"{}\"/>",
+                escape_attr(&node.kind),
+                node.span.start,
+                node.span.end
+            ));
+        }
+    }
+}
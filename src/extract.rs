@@ -0,0 +1,243 @@
+// パース済みの AST から構造化された情報を取り出す
+//
+// モデレーションやリンクプレビューなど、描画とは別に中身を調べたい用途向け。
+// 生のテキストを正規表現で探し直さずに済むよう、span 付きで返す。
+
+use crate::ast::{Element, Node, Span};
+use crate::registry::{
+    image_size, is_valid_image_url, is_valid_url, single_text_child, split_author_post_id,
+};
+use crate::render::{ast_to_plain_text, attr_value};
+use crate::transform::strip_quotes;
+
+/// `[url]` によるリンク
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkRef {
+    /// リンク先（前後の空白は除去済み）
+    pub url: String,
+    /// リンクの表示テキスト（装飾を除いたもの）
+    pub label_text: String,
+    pub span: Span,
+    /// `[quote]` の中にあるか（他人の投稿の引用か）
+    pub inside_quote: bool,
+}
+
+/// `[url]` のリンクを文書順に取り出す（HTML でリンクにならない不正な URL は除く）
+pub fn extract_links(nodes: &[Node]) -> Vec<LinkRef> {
+    let mut out = vec![];
+    visit(nodes, 0, &mut |el, quote_depth| {
+        if el.name != "url" {
+            return;
+        }
+        // 値属性が無ければ中身がそのままリンク先
+        let href = attr_value(el).or_else(|| single_text_child(el));
+        if let Some(href) = href.filter(|h| is_valid_url(h)) {
+            out.push(LinkRef {
+                url: href.trim().to_string(),
+                label_text: ast_to_plain_text(&el.children),
+                span: el.span,
+                inside_quote: quote_depth > 0,
+            });
+        }
+    });
+    out
+}
+
+/// `[img]` による画像
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageRef {
+    /// 画像の URL（前後の空白は除去済み）
+    pub src: String,
+    /// 代替テキスト（`alt` 属性を受け付けるタグ仕様の場合のみ）
+    pub alt: Option<String>,
+    /// `[img=WxH]` で指定された表示サイズ（幅, 高さ）
+    pub declared_size: Option<(u32, u32)>,
+    pub span: Span,
+}
+
+/// `[img]` の画像を文書順に取り出す（HTML で画像にならない不正な URL は除く）
+pub fn extract_images(nodes: &[Node]) -> Vec<ImageRef> {
+    let mut out = vec![];
+    visit(nodes, 0, &mut |el, _| {
+        if el.name != "img" {
+            return;
+        }
+        let Some(src) = single_text_child(el).filter(|s| is_valid_image_url(s)) else {
+            return;
+        };
+        let declared_size = image_size(el);
+        let alt = el
+            .attrs
+            .iter()
+            .find(|(k, _)| k == "alt")
+            .map(|(_, v)| v.clone());
+        out.push(ImageRef {
+            src: src.trim().to_string(),
+            alt,
+            declared_size,
+            span: el.span,
+        });
+    });
+    out
+}
+
+/// `[user]` によるメンション
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MentionRef {
+    /// `[user=123]` の値。無ければ None
+    pub user_id: Option<String>,
+    /// 表示名（中身から装飾を除いたもの）
+    pub name: String,
+    pub span: Span,
+}
+
+/// `[user=123]Alice[/user]` / `[user]Alice[/user]` のメンションを文書順に取り出す
+/// 組み込みのタグ仕様には `[user]` が無いので、レジストリに登録した場合のみ要素になる
+pub fn extract_mentions(nodes: &[Node]) -> Vec<MentionRef> {
+    let mut out = vec![];
+    visit(nodes, 0, &mut |el, _| {
+        if el.name != "user" {
+            return;
+        }
+        let name = ast_to_plain_text(&el.children).trim().to_string();
+        let user_id = attr_value(el)
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::to_string);
+        if name.is_empty() && user_id.is_none() {
+            return;
+        }
+        out.push(MentionRef {
+            user_id,
+            name,
+            span: el.span,
+        });
+    });
+    out
+}
+
+/// `[quote]` による引用
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuoteRef {
+    pub author: Option<String>,
+    /// `[quote=Alice;123]` / `[quote post_id=123]` の投稿 ID
+    pub post_id: Option<String>,
+    /// 入れ子の深さ（最も外側の引用が 1）
+    pub depth: usize,
+    pub span: Span,
+}
+
+/// `[quote]` を文書順（外側が先）に取り出す
+/// 値属性が分解されていなくても `名前;投稿ID` の形なら author / post_id に分ける
+pub fn extract_quotes(nodes: &[Node]) -> Vec<QuoteRef> {
+    let mut out = vec![];
+    visit(nodes, 0, &mut |el, quote_depth| {
+        if el.name != "quote" {
+            return;
+        }
+        let (author, post_id) = quote_source(el);
+        out.push(QuoteRef {
+            author,
+            post_id,
+            depth: quote_depth + 1,
+            span: el.span,
+        });
+    });
+    out
+}
+
+/// `[quote]` の引用元の (名前, 投稿 ID)
+/// 分解済みの author / post_id 属性を優先し、無ければ値属性を `名前;投稿ID` として分解する
+pub(crate) fn quote_source(el: &Element) -> (Option<String>, Option<String>) {
+    let attr = |key: &str| {
+        el.attrs
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.trim().to_string())
+    };
+    let split = attr_value(el).and_then(split_author_post_id);
+    let from_value = |key: &str| {
+        split
+            .iter()
+            .flatten()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.clone())
+    };
+    let author = attr("author")
+        .or_else(|| from_value("author"))
+        .filter(|a| !a.is_empty());
+    let post_id = attr("post_id").or_else(|| from_value("post_id"));
+    (author, post_id)
+}
+
+/// SNS の埋め込み向けのメタデータ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PostPreview {
+    /// 冒頭の1行が `[b]` / `[u]` / `[size]` / `[center]` だけならその文字列
+    pub title_guess: Option<String>,
+    /// 引用を除いた本文の最初の段落（空白を詰め、長ければ省略する）
+    pub description: Option<String>,
+    pub first_image: Option<ImageRef>,
+}
+
+/// description の最大文字数
+const PREVIEW_DESCRIPTION_CHARS: usize = 200;
+
+/// 見出しの代わりによく使われるタグ
+const TITLE_LIKE_TAGS: &[&str] = &["b", "u", "size", "center"];
+
+pub fn extract_preview(nodes: &[Node]) -> PostPreview {
+    // 引用は他人の文章なので説明文・タイトルには使わない
+    let body = strip_quotes(nodes);
+    let mut rest = &body[..];
+    while let [Node::Text { text, .. }, tail @ ..] = rest {
+        if !text.trim().is_empty() {
+            break;
+        }
+        rest = tail;
+    }
+
+    let mut title_guess = None;
+    if let [Node::Element(el), tail @ ..] = rest {
+        let own_line = match tail.first() {
+            None => true,
+            Some(Node::Text { text, .. }) => {
+                text.trim_start_matches([' ', '\t', '\r']).starts_with('\n')
+            }
+            Some(_) => false,
+        };
+        let title = ast_to_plain_text(&el.children).trim().to_string();
+        if own_line && TITLE_LIKE_TAGS.contains(&el.name.as_str()) && !title.is_empty() {
+            title_guess = Some(title);
+            rest = tail;
+        }
+    }
+
+    let text = ast_to_plain_text(rest);
+    let description = text
+        .split("\n\n")
+        .map(|p| p.split_whitespace().collect::<Vec<_>>().join(" "))
+        .find(|p| !p.is_empty())
+        .map(|p| match p.char_indices().nth(PREVIEW_DESCRIPTION_CHARS) {
+            Some((cut, _)) => format!("{}…", p[..cut].trim_end()),
+            None => p,
+        });
+
+    PostPreview {
+        title_guess,
+        description,
+        first_image: extract_images(nodes).into_iter().next(),
+    }
+}
+
+/// 要素を行きがけ順にたどる。f には要素とそれを囲む `[quote]` の数を渡す
+fn visit(nodes: &[Node], quote_depth: usize, f: &mut impl FnMut(&Element, usize)) {
+    for node in nodes {
+        let Node::Element(el) = node else {
+            continue;
+        };
+        f(el, quote_depth);
+        let depth = quote_depth + usize::from(el.name == "quote");
+        visit(&el.children, depth, f);
+    }
+}
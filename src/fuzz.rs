@@ -0,0 +1,159 @@
+// ファジング用の入口（`fuzz` フィーチャ）
+//
+// 信頼できない入力をそのまま受け取るクレートなので、どの入力でも守るべき性質を
+// 1つの関数にまとめておく。cargo-fuzz などのターゲットからは
+// `fuzz_target!(|input: &str| bbcode_parser::fuzz::check_invariants(input));` のように呼ぶ。
+
+use crate::ast::{Node, Span};
+use crate::error::{BbCodeError, ErrorKind, LimitError};
+use crate::options::BbCodeOptions;
+use crate::parser::parse_bbcode_with_diagnostics;
+use crate::render::{
+    ast_to_asciidoc, ast_to_html_with, ast_to_markdown, ast_to_plain_text, ast_to_rst, ast_to_rtf,
+    HtmlRenderOptions, HtmlTarget,
+};
+
+/// 中身も閉じタグも持たない要素
+const VOID_ELEMENTS: &[&str] = &["br", "hr", "img", "input", "meta", "link", "source", "wbr"];
+
+/// input について以下を確かめ、破っていれば panic する
+/// - パース・各形式への描画で panic しない
+/// - 出力した HTML の開始タグと終了タグが対応し、テキスト中に生の `<` が無い
+/// - 制限（max_input_size / max_depth / max_tags / max_text_len）を超えた AST を返さない
+/// - AST の span が入力の範囲内で、文字の途中を指さない
+/// - （`fast-parser` フィーチャ）字句解析の実装によらず同じ結果になる
+pub fn check_invariants(input: &str) {
+    let opts = BbCodeOptions::default();
+    let result = parse_bbcode_with_diagnostics(input, &opts);
+
+    #[cfg(feature = "fast-parser")]
+    {
+        let fast_opts = opts
+            .clone()
+            .with_backend(crate::options::ParserBackend::Fast);
+        let fast = parse_bbcode_with_diagnostics(input, &fast_opts);
+        match (&result, &fast) {
+            (Ok(pest), Ok(fast)) => assert_eq!(pest, fast, "backends disagree on {input:?}"),
+            (Err(pest), Err(fast)) => {
+                assert_eq!(pest.kind(), fast.kind(), "backends disagree on {input:?}")
+            }
+            _ => panic!("backends disagree on {input:?}: {result:?} / {fast:?}"),
+        }
+    }
+
+    let ast = match result {
+        Ok((ast, _)) => ast,
+        Err(BbCodeError::Limit(LimitError::InputSizeExceeded { .. })) => {
+            assert!(input.len() > opts.max_input_size);
+            return;
+        }
+        Err(e) if e.kind() == ErrorKind::Internal => panic!("internal error for {input:?}: {e}"),
+        Err(_) => return,
+    };
+    assert!(
+        input.len() <= opts.max_input_size,
+        "input size limit not enforced"
+    );
+
+    let mut stats = Stats::default();
+    stats.visit(input, &ast, 0);
+    assert!(stats.depth <= opts.max_depth, "depth limit not enforced");
+    assert!(stats.tags <= opts.max_tags, "tag count limit not enforced");
+    assert!(
+        stats.text_len <= opts.max_text_len,
+        "text length limit not enforced"
+    );
+
+    let mut targets = vec![HtmlTarget::Web, HtmlTarget::Email, HtmlTarget::Feed];
+    #[cfg(feature = "amp")]
+    targets.push(HtmlTarget::Amp);
+    for target in targets {
+        let html = ast_to_html_with(&ast, &HtmlRenderOptions::default().with_target(target));
+        if let Err(e) = check_balanced_html(&html) {
+            panic!("unbalanced {target:?} HTML for {input:?}: {e}\n{html}");
+        }
+    }
+    ast_to_plain_text(&ast);
+    ast_to_markdown(&ast);
+    ast_to_rst(&ast);
+    ast_to_asciidoc(&ast);
+    ast_to_rtf(&ast);
+}
+
+/// HTML の開始タグと終了タグが対応しているか。対応していなければ理由を返す
+/// 出力した HTML を確かめるためのもので、属性値の `>` やコメントなどは扱わない
+pub fn check_balanced_html(html: &str) -> Result<(), String> {
+    let mut stack: Vec<&str> = vec![];
+    let mut rest = html;
+    while let Some(pos) = rest.find('<') {
+        let tag = &rest[pos + 1..];
+        let end = tag
+            .find('>')
+            .ok_or_else(|| format!("unterminated tag at {:?}", head(tag)))?;
+        let body = &tag[..end];
+        rest = &tag[end + 1..];
+
+        let (closing, body) = match body.strip_prefix('/') {
+            Some(b) => (true, b),
+            None => (false, body),
+        };
+        let name_len = body
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-'))
+            .unwrap_or(body.len());
+        let name = &body[..name_len];
+        if name.is_empty() || !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            return Err(format!("raw '<' in text before {:?}", head(body)));
+        }
+        if closing {
+            match stack.pop() {
+                Some(open) if open.eq_ignore_ascii_case(name) => {}
+                open => return Err(format!("</{name}> closes {open:?}")),
+            }
+        } else if !body.ends_with('/') && !VOID_ELEMENTS.contains(&name) {
+            stack.push(name);
+        }
+    }
+    match stack.last() {
+        Some(open) => Err(format!("<{open}> is never closed")),
+        None => Ok(()),
+    }
+}
+
+/// エラーメッセージ用の先頭部分
+fn head(s: &str) -> String {
+    s.chars().take(20).collect()
+}
+
+#[derive(Default)]
+struct Stats {
+    depth: usize,
+    tags: usize,
+    text_len: usize,
+}
+
+impl Stats {
+    fn visit(&mut self, input: &str, nodes: &[Node], depth: usize) {
+        for node in nodes {
+            match node {
+                Node::Text { span, text } => {
+                    check_span(input, *span);
+                    self.text_len += text.len();
+                }
+                Node::Element(el) => {
+                    check_span(input, el.span);
+                    self.tags += 1;
+                    self.depth = self.depth.max(depth + 1);
+                    self.visit(input, &el.children, depth + 1);
+                }
+                Node::Unsupported(_) => panic!("parser produced an unsupported node"),
+            }
+        }
+    }
+}
+
+fn check_span(input: &str, span: Span) {
+    assert!(
+        span.slice(input).is_some(),
+        "span {span:?} is outside {input:?} or splits a character"
+    );
+}
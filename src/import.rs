@@ -0,0 +1,248 @@
+// Markdown（CommonMark）で書かれた投稿の取り込み
+//
+// Markdown を直接 AST にせず、いったん BBCode に変換してから通常のパーサーに通す。
+// 保存形式を BBCode にそろえられ、タグの許可・深さ・長さなどの制限も BBCode の投稿と
+// まったく同じように適用される。
+
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+
+use crate::ast::Node;
+use crate::diagnostic::Diagnostic;
+use crate::error::{BbCodeError, LimitError};
+use crate::options::BbCodeOptions;
+use crate::parser::parse_bbcode_with_diagnostics;
+
+/// Markdown を同じ意味の BBCode に変換する
+/// 生の HTML は文字列として残し、BBCode で表せない装飾（表など）は中身だけ出力する
+pub fn markdown_to_bbcode(markdown: &str) -> String {
+    let mut conv = Converter::default();
+    for event in Parser::new_ext(markdown, Options::ENABLE_STRIKETHROUGH) {
+        conv.event(event);
+    }
+    conv.out.truncate(conv.out.trim_end().len());
+    conv.out
+}
+
+/// Markdown を BBCode に変換してから opts に従ってパースする
+/// AST の span は変換後の BBCode 上の位置になる
+pub fn parse_markdown(markdown: &str, opts: &BbCodeOptions) -> Result<Vec<Node>, BbCodeError> {
+    parse_markdown_with_diagnostics(markdown, opts).map(|(nodes, _)| nodes)
+}
+
+/// Markdown をパースし、診断情報もあわせて返す
+pub fn parse_markdown_with_diagnostics(
+    markdown: &str,
+    opts: &BbCodeOptions,
+) -> Result<(Vec<Node>, Vec<Diagnostic>), BbCodeError> {
+    // 変換で長くなる前に元の入力の大きさでも制限する
+    if markdown.len() > opts.max_input_size {
+        return Err(LimitError::InputSizeExceeded {
+            max_size: opts.max_input_size,
+            actual_size: markdown.len(),
+        }
+        .into());
+    }
+    parse_bbcode_with_diagnostics(&markdown_to_bbcode(markdown), opts)
+}
+
+#[derive(Default)]
+struct Converter {
+    out: String,
+    /// 開いているタグの閉じタグ
+    closes: Vec<&'static str>,
+    /// 開いている `[*]` の数（項目内の段落は空行で区切らない）
+    item_depth: usize,
+    /// `![alt](src)` の中（代替テキストは BBCode で表せないので捨てる）
+    image_depth: usize,
+    /// `[code]` の中。中身はエスケープしない
+    in_code_block: bool,
+    /// 次のブロックの前に段落の区切りが要る
+    pending_break: bool,
+}
+
+impl Converter {
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) if self.in_code_block => self.out.push_str(&text),
+            Event::Text(text) | Event::Html(text) | Event::InlineHtml(text)
+                if self.image_depth == 0 =>
+            {
+                self.text(&text)
+            }
+            Event::Code(code) => {
+                self.text("");
+                self.push_tag("[tt]");
+                self.out.push_str(&escape_bbcode(&code));
+                self.push_tag("[/tt]");
+            }
+            // BBCode の改行はそのまま表示上の改行になる
+            Event::SoftBreak | Event::HardBreak => self.text("\n"),
+            Event::Rule => {
+                self.block_break();
+                self.out.push_str("----");
+                self.pending_break = true;
+            }
+            _ => {}
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        if self.image_depth > 0 {
+            self.image_depth += usize::from(matches!(tag, Tag::Image { .. }));
+            return;
+        }
+        match tag {
+            Tag::Paragraph | Tag::HtmlBlock => self.block_break(),
+            Tag::Heading { level, .. } => {
+                self.block_break();
+                // 見出しは大きい太字で表す
+                let size = match level {
+                    HeadingLevel::H1 => Some(6),
+                    HeadingLevel::H2 => Some(5),
+                    HeadingLevel::H3 => Some(4),
+                    _ => None,
+                };
+                match size {
+                    Some(size) => {
+                        self.push_tag(&format!("[size={size}][b]"));
+                        self.closes.push("[/b][/size]");
+                    }
+                    None => self.open("[b]", "[/b]"),
+                }
+            }
+            Tag::BlockQuote(_) => {
+                self.block_break();
+                self.open("[quote]", "[/quote]");
+            }
+            Tag::CodeBlock(kind) => {
+                self.block_break();
+                self.push_tag("[code");
+                if let CodeBlockKind::Fenced(info) = kind {
+                    // 情報文字列の先頭の単語が言語名
+                    if let Some(lang) = info.split_whitespace().next() {
+                        self.out.push('=');
+                        self.out.push_str(&escape_attr(lang));
+                    }
+                }
+                self.out.push(']');
+                self.in_code_block = true;
+            }
+            Tag::List(start) => {
+                self.block_break();
+                if self.item_depth > 0 && !self.out.ends_with('\n') {
+                    self.out.push('\n');
+                }
+                match start {
+                    Some(_) => self.open("[list=1]", "[/list]"),
+                    None => self.open("[list]", "[/list]"),
+                }
+                self.out.push('\n');
+            }
+            Tag::Item => {
+                self.push_tag("[*]");
+                self.item_depth += 1;
+                self.pending_break = false;
+            }
+            Tag::Emphasis => self.open("[i]", "[/i]"),
+            Tag::Strong => self.open("[b]", "[/b]"),
+            Tag::Strikethrough => self.open("[s]", "[/s]"),
+            Tag::Link { dest_url, .. } => {
+                self.text("");
+                self.push_tag("[url=");
+                self.out.push_str(&escape_attr(&dest_url));
+                self.out.push(']');
+                self.closes.push("[/url]");
+            }
+            Tag::Image { dest_url, .. } => {
+                self.text("");
+                self.push_tag("[img]");
+                self.out.push_str(&escape_bbcode(&dest_url));
+                self.push_tag("[/img]");
+                self.image_depth += 1;
+            }
+            // 表・脚注などは有効にしていないので来ない
+            _ => self.closes.push(""),
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        if self.image_depth > 0 {
+            self.image_depth -= usize::from(tag == TagEnd::Image);
+            return;
+        }
+        match tag {
+            TagEnd::Paragraph | TagEnd::HtmlBlock => self.pending_break = true,
+            TagEnd::CodeBlock => {
+                if self.out.ends_with('\n') {
+                    self.out.pop();
+                }
+                self.push_tag("[/code]");
+                self.in_code_block = false;
+                self.pending_break = true;
+            }
+            TagEnd::Item => {
+                self.item_depth -= 1;
+                self.pending_break = false;
+                self.out.push('\n');
+            }
+            _ => {
+                let close = self.closes.pop().unwrap_or_default();
+                self.push_tag(close);
+                if matches!(
+                    tag,
+                    TagEnd::Heading(_) | TagEnd::BlockQuote(_) | TagEnd::List(_)
+                ) {
+                    self.pending_break = true;
+                }
+            }
+        }
+    }
+
+    fn open(&mut self, open: &str, close: &'static str) {
+        self.text("");
+        self.push_tag(open);
+        self.closes.push(close);
+    }
+
+    /// 直前の文字が `\` だとタグが `\[` のエスケープとして読まれるので、
+    /// 間に幅の無い空白を挟む
+    fn push_tag(&mut self, tag: &str) {
+        if self.out.ends_with('\\') && tag.starts_with('[') {
+            self.out.push('\u{200B}');
+        }
+        self.out.push_str(tag);
+    }
+
+    /// ブロックの前に区切りを入れる（項目の中では改行1つ）
+    fn block_break(&mut self) {
+        if !std::mem::take(&mut self.pending_break) || self.out.is_empty() {
+            return;
+        }
+        let newline = if self.item_depth > 0 { "\n" } else { "\n\n" };
+        self.out.truncate(self.out.trim_end_matches('\n').len());
+        self.out.push_str(newline);
+    }
+
+    fn text(&mut self, text: &str) {
+        if self.pending_break {
+            self.block_break();
+        }
+        self.out.push_str(&escape_bbcode(text));
+    }
+}
+
+/// 文字列の `[` をエスケープしてタグとして解釈されないようにする
+fn escape_bbcode(text: &str) -> String {
+    text.replace('[', "\\[")
+}
+
+/// `]` や引用符を含む値は引用符で囲む
+fn escape_attr(value: &str) -> String {
+    if value.contains([']', '"', '\'', '\\']) {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        value.to_string()
+    }
+}
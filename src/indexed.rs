@@ -0,0 +1,167 @@
+// 添字で親子をたどれる AST 表現
+//
+// `Vec<Node>` は子から親へ戻れないので、エディタ向けにノードを1本の配列に並べ、
+// 親・子を添字で持つ形に変換できるようにする。部分木の置き換えは新しいノードを
+// 末尾に追加して親の子リストを差し替えるだけなので、木全体を作り直さずに済む。
+
+use crate::ast::{Element, Node, Span, TagName, UnsupportedNode};
+
+/// IndexedAst 内のノードを指す添字
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum NodeData {
+    Text(String),
+    Element {
+        name: TagName,
+        attrs: Vec<(String, String)>,
+    },
+    Unsupported(UnsupportedNode),
+}
+
+#[derive(Debug, Clone)]
+pub struct IndexedNode {
+    pub span: Span,
+    pub data: NodeData,
+    pub parent: Option<NodeId>,
+    pub children: Vec<NodeId>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct IndexedAst {
+    nodes: Vec<IndexedNode>,
+    roots: Vec<NodeId>,
+}
+
+impl IndexedAst {
+    pub fn new(nodes: &[Node]) -> Self {
+        let mut ast = Self::default();
+        ast.roots = nodes.iter().map(|n| ast.insert(n, None)).collect();
+        ast
+    }
+
+    /// 最上位のノード
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    pub fn get(&self, id: NodeId) -> &IndexedNode {
+        &self.nodes[id.0]
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.get(id).parent
+    }
+
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        &self.get(id).children
+    }
+
+    /// id の親から根までを近い順に返す
+    pub fn ancestors(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        std::iter::successors(self.parent(id), |&p| self.parent(p))
+    }
+
+    pub fn next_sibling(&self, id: NodeId) -> Option<NodeId> {
+        let siblings = self.siblings(id);
+        let i = siblings.iter().position(|&s| s == id)?;
+        siblings.get(i + 1).copied()
+    }
+
+    pub fn prev_sibling(&self, id: NodeId) -> Option<NodeId> {
+        let siblings = self.siblings(id);
+        let i = siblings.iter().position(|&s| s == id)?;
+        i.checked_sub(1).map(|i| siblings[i])
+    }
+
+    /// id の部分木を node に置き換え、新しい部分木の根を返す
+    /// 置き換えられた古いノードは配列に残るが、どこからも参照されなくなる
+    pub fn replace(&mut self, id: NodeId, node: &Node) -> NodeId {
+        let parent = self.parent(id);
+        let new_id = self.insert(node, parent);
+        let siblings = match parent {
+            Some(p) => &mut self.nodes[p.0].children,
+            None => &mut self.roots,
+        };
+        if let Some(slot) = siblings.iter_mut().find(|s| **s == id) {
+            *slot = new_id;
+        }
+        self.nodes[id.0].parent = None;
+        new_id
+    }
+
+    /// id の部分木を `Node` に戻す
+    pub fn to_node(&self, id: NodeId) -> Node {
+        let n = self.get(id);
+        match &n.data {
+            NodeData::Text(text) => Node::Text {
+                span: n.span,
+                text: text.clone(),
+            },
+            NodeData::Element { name, attrs } => Node::Element(Element {
+                span: n.span,
+                name: name.clone(),
+                attrs: attrs.clone(),
+                children: n.children.iter().map(|&c| self.to_node(c)).collect(),
+            }),
+            NodeData::Unsupported(node) => Node::Unsupported(node.clone()),
+        }
+    }
+
+    /// 木全体を `Vec<Node>` に戻す
+    pub fn to_nodes(&self) -> Vec<Node> {
+        self.roots.iter().map(|&r| self.to_node(r)).collect()
+    }
+
+    fn siblings(&self, id: NodeId) -> &[NodeId] {
+        match self.parent(id) {
+            Some(p) => self.children(p),
+            None => &self.roots,
+        }
+    }
+
+    fn insert(&mut self, node: &Node, parent: Option<NodeId>) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        let (span, data, children) = match node {
+            Node::Text { span, text } => (*span, NodeData::Text(text.clone()), &[][..]),
+            Node::Element(el) => (
+                el.span,
+                NodeData::Element {
+                    name: el.name.clone(),
+                    attrs: el.attrs.clone(),
+                },
+                &el.children[..],
+            ),
+            Node::Unsupported(node) => (node.span, NodeData::Unsupported(node.clone()), &[][..]),
+        };
+        self.nodes.push(IndexedNode {
+            span,
+            data,
+            parent,
+            children: vec![],
+        });
+        let children = children.iter().map(|c| self.insert(c, Some(id))).collect();
+        self.nodes[id.0].children = children;
+        id
+    }
+}
+
+impl From<&[Node]> for IndexedAst {
+    fn from(nodes: &[Node]) -> Self {
+        Self::new(nodes)
+    }
+}
+
+impl From<Vec<Node>> for IndexedAst {
+    fn from(nodes: Vec<Node>) -> Self {
+        Self::new(&nodes)
+    }
+}
+
+impl From<&IndexedAst> for Vec<Node> {
+    fn from(ast: &IndexedAst) -> Self {
+        ast.to_nodes()
+    }
+}
@@ -0,0 +1,162 @@
+use std::slice;
+
+use crate::ast::Node;
+
+/// AST を深さ優先（行きがけ順）にたどるイテレータ。`(深さ, ノード)` を返す
+/// 最上位のノードの深さは 0
+pub struct DepthFirst<'a> {
+    stack: Vec<slice::Iter<'a, Node>>,
+}
+
+impl<'a> DepthFirst<'a> {
+    pub fn new(nodes: &'a [Node]) -> Self {
+        Self {
+            stack: vec![nodes.iter()],
+        }
+    }
+}
+
+impl<'a> Iterator for DepthFirst<'a> {
+    type Item = (usize, &'a Node);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let depth = self.stack.len().checked_sub(1)?;
+            match self.stack[depth].next() {
+                Some(node) => {
+                    if let Node::Element(el) = node {
+                        self.stack.push(el.children.iter());
+                    }
+                    return Some((depth, node));
+                }
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
+
+/// AST 上の位置を指すカーソル
+/// 位置は最上位からの添字の列で持ち、移動に失敗したときは位置を変えない
+#[derive(Debug, Clone)]
+pub struct Cursor<'a> {
+    root: &'a [Node],
+    path: Vec<usize>,
+}
+
+impl<'a> Cursor<'a> {
+    /// 最初のノードを指すカーソル。nodes が空なら node() は None
+    pub fn new(root: &'a [Node]) -> Self {
+        Self {
+            root,
+            path: vec![0],
+        }
+    }
+
+    /// 現在のノード
+    pub fn node(&self) -> Option<&'a Node> {
+        self.resolve(&self.path)
+    }
+
+    /// 現在のノードの深さ（最上位が 0）
+    pub fn depth(&self) -> usize {
+        self.path.len().saturating_sub(1)
+    }
+
+    /// 最上位から現在のノードまでの添字の列
+    pub fn path(&self) -> &[usize] {
+        &self.path
+    }
+
+    /// 行きがけ順で次のノードへ移動する
+    pub fn move_next(&mut self) -> Option<&'a Node> {
+        let mut path = self.path.clone();
+        if matches!(self.node()?, Node::Element(el) if !el.children.is_empty()) {
+            path.push(0);
+        } else {
+            loop {
+                let (&last, parent) = path.split_last()?;
+                if last + 1 < self.children_at(parent)?.len() {
+                    *path.last_mut()? += 1;
+                    break;
+                }
+                path.pop();
+            }
+        }
+        self.move_to(path)
+    }
+
+    /// 行きがけ順で前のノードへ移動する
+    pub fn move_prev(&mut self) -> Option<&'a Node> {
+        let mut path = self.path.clone();
+        match path.last_mut() {
+            Some(last) if *last > 0 => {
+                *last -= 1;
+                // 前の兄弟の最後の子孫
+                while let Some(Node::Element(el)) = self.resolve(&path) {
+                    if el.children.is_empty() {
+                        break;
+                    }
+                    path.push(el.children.len() - 1);
+                }
+            }
+            _ => {
+                path.pop();
+            }
+        }
+        self.move_to(path)
+    }
+
+    /// 親の要素へ移動する
+    pub fn move_parent(&mut self) -> Option<&'a Node> {
+        let mut path = self.path.clone();
+        path.pop();
+        self.move_to(path)
+    }
+
+    /// 最初の子へ移動する
+    pub fn move_first_child(&mut self) -> Option<&'a Node> {
+        let mut path = self.path.clone();
+        path.push(0);
+        self.move_to(path)
+    }
+
+    /// 次の兄弟へ移動する
+    pub fn move_next_sibling(&mut self) -> Option<&'a Node> {
+        let mut path = self.path.clone();
+        *path.last_mut()? += 1;
+        self.move_to(path)
+    }
+
+    /// 前の兄弟へ移動する
+    pub fn move_prev_sibling(&mut self) -> Option<&'a Node> {
+        let mut path = self.path.clone();
+        let last = path.last_mut()?;
+        *last = last.checked_sub(1)?;
+        self.move_to(path)
+    }
+
+    fn move_to(&mut self, path: Vec<usize>) -> Option<&'a Node> {
+        let node = self.resolve(&path)?;
+        self.path = path;
+        Some(node)
+    }
+
+    fn resolve(&self, path: &[usize]) -> Option<&'a Node> {
+        let (&last, parent) = path.split_last()?;
+        self.children_at(parent)?.get(last)
+    }
+
+    /// path が指す要素の子（空の path なら最上位）
+    fn children_at(&self, path: &[usize]) -> Option<&'a [Node]> {
+        let mut nodes = self.root;
+        for &i in path {
+            match nodes.get(i)? {
+                Node::Element(el) => nodes = &el.children,
+                _ => return None,
+            }
+        }
+        Some(nodes)
+    }
+}
@@ -0,0 +1,176 @@
+// `[size]` の文字の大きさ・`[img=WxH]` の表示サイズなどの長さの値
+//
+// 数値と決まった単位（px / em / % / pt）だけを受け付けるので、読めた値はそのまま
+// CSS の宣言に置ける。単位と範囲はタグごとに LengthRule で決める。
+
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// 長さの単位
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LengthUnit {
+    Px,
+    Em,
+    Percent,
+    Pt,
+}
+
+impl LengthUnit {
+    /// CSS での表記（`px` / `em` / `%` / `pt`）
+    pub fn suffix(self) -> &'static str {
+        match self {
+            LengthUnit::Px => "px",
+            LengthUnit::Em => "em",
+            LengthUnit::Percent => "%",
+            LengthUnit::Pt => "pt",
+        }
+    }
+}
+
+/// 読み取った長さ
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Length {
+    pub value: f32,
+    /// None なら単位を省略した数値（意味はタグによる。`[size=5]` は段階、`[img=100x50]` はピクセル）
+    pub unit: Option<LengthUnit>,
+}
+
+impl Length {
+    /// `12` / `12px` / `1.5em` / `150%` / `10pt` を読む（単位の大文字小文字は区別しない）
+    /// 整数部は4桁、小数部は2桁まで。符号・指数は受け付けない
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let end = value
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(value.len());
+        let (number, unit) = value.split_at(end);
+        let unit = match unit.to_ascii_lowercase().as_str() {
+            "" => None,
+            "px" => Some(LengthUnit::Px),
+            "em" => Some(LengthUnit::Em),
+            "%" => Some(LengthUnit::Percent),
+            "pt" => Some(LengthUnit::Pt),
+            _ => return None,
+        };
+        let (integer, fraction) = match number.split_once('.') {
+            Some((integer, fraction)) if !fraction.is_empty() => (integer, fraction),
+            Some(_) => return None,
+            None => (number, ""),
+        };
+        let digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+        if !(1..=4).contains(&integer.len())
+            || fraction.len() > 2
+            || !digits(integer)
+            || !digits(fraction)
+        {
+            return None;
+        }
+        Some(Self {
+            value: number.parse().ok()?,
+            unit,
+        })
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.value.fract() == 0.0
+    }
+
+    /// CSS の値（`12px` / `1.5em`）。単位を省略した数値には unitless を付ける
+    pub fn to_css(&self, unitless: LengthUnit) -> String {
+        Self {
+            unit: Some(self.unit.unwrap_or(unitless)),
+            ..*self
+        }
+        .to_string()
+    }
+
+    /// 単位なし・px の整数ならピクセル数
+    pub fn pixels(&self) -> Option<u32> {
+        matches!(self.unit, None | Some(LengthUnit::Px))
+            .then_some(self.value)
+            .filter(|_| self.is_integer())
+            .map(|v| v as u32)
+    }
+}
+
+impl fmt::Display for Length {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // 小数部は2桁までなので丸めて余分な 0 を除く
+        let number = format!("{:.2}", self.value);
+        let number = number.trim_end_matches('0').trim_end_matches('.');
+        write!(f, "{number}{}", self.unit.map_or("", LengthUnit::suffix))
+    }
+}
+
+/// タグごとに受け付ける長さ（単位と範囲）
+/// 許可していない単位の値は受け付けない。単位を省略した数値は整数に限る
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LengthRule {
+    ranges: Vec<(Option<LengthUnit>, RangeInclusive<f32>)>,
+    dimensions: bool,
+}
+
+impl LengthRule {
+    /// unit の長さを range の範囲で許可する。unit が None なら単位を省略した数値
+    pub fn allow(mut self, unit: Option<LengthUnit>, range: RangeInclusive<f32>) -> Self {
+        self.ranges.push((unit, range));
+        self
+    }
+
+    /// 値を `WxH`（幅と高さ）として読む
+    pub fn with_dimensions(mut self) -> Self {
+        self.dimensions = true;
+        self
+    }
+
+    pub fn is_dimensions(&self) -> bool {
+        self.dimensions
+    }
+
+    /// 1つの長さとして読む。許可していない単位・範囲外なら None
+    pub fn parse(&self, value: &str) -> Option<Length> {
+        let length = Length::parse(value)?;
+        if length.unit.is_none() && !length.is_integer() {
+            return None;
+        }
+        self.ranges
+            .iter()
+            .any(|(unit, range)| *unit == length.unit && range.contains(&length.value))
+            .then_some(length)
+    }
+
+    /// `WxH` を（幅, 高さ）として読む
+    pub fn parse_dimensions(&self, value: &str) -> Option<(Length, Length)> {
+        let value = value.trim();
+        // `100pxx50px` のように単位にも `x` が含まれるので、読める区切りを探す
+        value
+            .match_indices('x')
+            .find_map(|(i, _)| Some((self.parse(&value[..i])?, self.parse(&value[i + 1..])?)))
+    }
+
+    /// 値属性として受け付けるか（with_dimensions なら `WxH`）
+    pub fn accepts(&self, value: &str) -> bool {
+        if self.dimensions {
+            self.parse_dimensions(value).is_some()
+        } else {
+            self.parse(value).is_some()
+        }
+    }
+
+    /// `[size]` の既定
+    /// 単位なしの 1〜7 は HTML の font size 相当の段階、8〜200 はパーセント。
+    /// ほかに 6〜72px・5〜54pt・0.5〜4.5em・8〜200%
+    pub fn font_size() -> Self {
+        Self::default()
+            .allow(None, 1.0..=200.0)
+            .allow(Some(LengthUnit::Px), 6.0..=72.0)
+            .allow(Some(LengthUnit::Pt), 5.0..=54.0)
+            .allow(Some(LengthUnit::Em), 0.5..=4.5)
+            .allow(Some(LengthUnit::Percent), 8.0..=200.0)
+    }
+
+    /// `[img=WxH]` の既定。各辺 0〜9999 の単位なしの整数（ピクセル）
+    pub fn image_size() -> Self {
+        Self::default().allow(None, 0.0..=9999.0).with_dimensions()
+    }
+}
@@ -1,17 +1,80 @@
+pub mod api;
 pub mod ast;
+pub mod batch;
+pub mod color;
+pub mod conformance;
+pub mod corpus;
+pub mod diagnostic;
+pub mod dialect;
+pub mod document;
 pub mod error;
+pub mod escape;
+pub mod export;
+pub mod extract;
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
+#[cfg(feature = "markdown")]
+pub mod import;
+pub mod indexed;
+pub mod iter;
+pub mod length;
+#[cfg(feature = "url")]
+pub mod link;
+pub mod lint;
+pub mod lookalike;
 pub mod options;
+pub mod pass;
+pub mod prelude;
 pub mod registry;
+pub mod repair;
+pub mod spam;
+pub mod style;
+pub mod summary;
+pub mod template;
+pub mod transform;
+pub mod validate;
 
 pub mod parser;
 pub mod render;
 
-pub use ast::{Element, Node};
-pub use error::BbCodeError;
-pub use options::BbCodeOptions;
+pub use ast::{Element, Node, UnsupportedNode};
+pub use batch::{render_many, RenderManyOptions, RenderedPost};
+pub use color::{Color, ColorFormats};
+pub use diagnostic::Diagnostic;
+pub use dialect::Dialect;
+pub use document::{parse, parse_any, BbCodeDocument, Format, ParseMetrics};
+pub use error::{
+    BbCodeError, ErrorKind, InvalidTagReason, LimitError, ParseError, SyntaxError, TemplateError,
+};
+pub use extract::{
+    extract_images, extract_links, extract_mentions, extract_preview, extract_quotes, ImageRef,
+    LinkRef, MentionRef, PostPreview, QuoteRef,
+};
+pub use indexed::{IndexedAst, NodeId};
+pub use iter::{Cursor, DepthFirst};
+pub use length::{Length, LengthRule, LengthUnit};
+pub use lint::{lint, Lint, LintCode, Severity, TextEdit};
+pub use options::{
+    AttrWhitespace, BbCodeOptions, ContrastAction, ContrastCheck, ControlChars, DomainPolicy,
+    DuplicateAttrs, EmptyElements, FragmentContext, LinkPolicyAction, NestingStrictness,
+    ParserBackend, SyntaxChars,
+};
+pub use pass::AstPass;
+pub use registry::{DisplayKind, PermissionLevel, SameTagNesting, TagRegistry, TagSpec};
+pub use repair::{repair, AppliedFix};
+pub use spam::{spam_score, SpamHeuristics};
+pub use style::{resolve_styles, StyledRun, TextStyle};
+pub use summary::{summarize, Summary, SummaryOptions};
+pub use template::TagTemplate;
+pub use transform::{append_signature, strip_quotes, truncate, SignaturePolicy, TruncateOptions};
+pub use validate::{validate, ValidationReport, ValidationStats};
 
-pub use parser::parse_bbcode_to_ast;
-pub use render::ast_to_html;
+pub use parser::{
+    parse_bbcode_to_ast, parse_bbcode_with_diagnostics, raw_parse, validate_fragment, Rule,
+};
+pub use render::{
+    ast_to_asciidoc, ast_to_html, ast_to_markdown, ast_to_plain_text, ast_to_rst, ast_to_rtf,
+};
 
 pub fn bbcode_to_html(input: &str, opts: &BbCodeOptions) -> Result<String, BbCodeError> {
     let ast = parse_bbcode_to_ast(input, opts)?;
@@ -0,0 +1,121 @@
+// リンク先 URL の正規化（`url` フィーチャ）
+//
+// 表示と違う場所へ飛ばす細工（見た目の似た IDN・制御文字・おかしな文字を含む URL）を
+// 防ぐため、リンク先には url クレートで正規化した形を使い、表示には人が読める形を使う。
+
+use url::{Position, Url};
+
+use crate::registry::is_valid_url;
+
+/// サイト内パスを解釈するための仮の基準 URL（出力には含めない）
+const RELATIVE_BASE: &str = "https://relative.invalid/";
+
+/// href を正規化する
+/// 制御文字を取り除き、URL に使えない文字はパーセントエンコードし、IDN のホストは punycode にする。
+/// リンクにできない URL（http(s) / mailto / サイト内パス / ページ内リンク以外）なら None
+pub fn normalize_url(href: &str) -> Option<String> {
+    let href: String = href.trim().chars().filter(|c| !c.is_control()).collect();
+    let normalized = if href.starts_with('#') {
+        let url = Url::parse(RELATIVE_BASE).ok()?.join(&href).ok()?;
+        url[Position::AfterQuery..].to_string()
+    } else if href.starts_with('/') && !href.starts_with("//") {
+        let url = Url::parse(RELATIVE_BASE).ok()?.join(&href).ok()?;
+        url[Position::BeforePath..].to_string()
+    } else {
+        let url = Url::parse(&href).ok()?;
+        match url.scheme() {
+            "http" | "https" if url.host_str().is_some() => url.to_string(),
+            "mailto" => url.to_string(),
+            _ => return None,
+        }
+    };
+    // url クレートはパスの `'` をエンコードしないが、属性値に置くので揃えてエンコードする
+    let normalized = normalized.replace('\'', "%27");
+    is_valid_url(&normalized).then_some(normalized)
+}
+
+/// 正規化した URL の読みやすい形（表示用）
+/// ホストは Unicode に戻し、パーセントエンコードされた非 ASCII 文字は復元する。
+/// 復元すると見えなくなる文字（空白・制御文字・書字方向の制御など）はエンコードのまま残す
+pub fn display_url(normalized: &str) -> String {
+    let Ok(url) = Url::parse(normalized) else {
+        return decode_non_ascii(normalized);
+    };
+    let Some(host) = url.host_str().filter(|_| url.scheme() != "mailto") else {
+        return decode_non_ascii(normalized);
+    };
+    let (host, result) = idna::domain_to_unicode(host);
+    let host = if result.is_ok() {
+        host
+    } else {
+        url.host_str().unwrap_or_default().to_string()
+    };
+    let mut out = format!("{}://", url.scheme());
+    out.push_str(&host);
+    if let Some(port) = url.port() {
+        out.push_str(&format!(":{port}"));
+    }
+    out.push_str(&decode_non_ascii(&url[Position::BeforePath..]));
+    out
+}
+
+/// `%XX` のうち非 ASCII 文字を表すものだけを復元する（`%2F` などの意味は変えない）
+fn decode_non_ascii(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut out = String::with_capacity(input.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        // 非 ASCII の `%XX` が続く範囲をまとめて UTF-8 として復元する
+        let mut decoded = vec![];
+        let mut j = i;
+        while let Some(b) = percent_byte(bytes, j).filter(|b| *b >= 0x80) {
+            decoded.push(b);
+            j += 3;
+        }
+        if decoded.is_empty() {
+            let c = input[i..].chars().next().unwrap_or_default();
+            out.push(c);
+            i += c.len_utf8();
+            continue;
+        }
+        match String::from_utf8(decoded) {
+            Ok(s) if !s.chars().any(is_invisible) => out.push_str(&s),
+            _ => out.push_str(&input[i..j]),
+        }
+        i = j;
+    }
+    out
+}
+
+fn percent_byte(bytes: &[u8], i: usize) -> Option<u8> {
+    if bytes.get(i) != Some(&b'%') {
+        return None;
+    }
+    let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+    u8::from_str_radix(hex, 16).ok()
+}
+
+/// 表示すると見えない・表示を乱す文字
+fn is_invisible(c: char) -> bool {
+    c.is_whitespace()
+        || c.is_control()
+        || matches!(
+            c,
+            '\u{00AD}'
+                | '\u{034F}'
+                | '\u{061C}'
+                | '\u{115F}'
+                | '\u{1160}'
+                | '\u{17B4}'
+                | '\u{17B5}'
+                | '\u{180E}'
+                | '\u{200B}'..='\u{200F}'
+                | '\u{202A}'..='\u{202E}'
+                | '\u{2060}'..='\u{206F}'
+                | '\u{3164}'
+                | '\u{FE00}'..='\u{FE0F}'
+                | '\u{FEFF}'
+                | '\u{FFA0}'
+                | '\u{FFF0}'..='\u{FFF8}'
+        )
+}
@@ -0,0 +1,267 @@
+// 投稿の問題点を番号付きのコード（BB001 など）で一覧にする
+//
+// エディタの問題パネルや、投稿前の内容チェックで使う。パースのエラー・診断・テキストに戻したタグを
+// 同じ形にまとめ、コードごとの重大度は BbCodeOptions::lint_severities で変えられる。
+// コードの番号は互換性のため変えない（廃止しても欠番にする）。
+
+use std::fmt;
+
+use crate::ast::Span;
+use crate::options::BbCodeOptions;
+use crate::parser::parse_lenient;
+
+/// 問題の種類ごとの固定のコード
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum LintCode {
+    /// BB001 閉じタグが開始タグと合わない
+    MismatchedClose,
+    /// BB002 未知のタグ
+    UnknownTag,
+    /// BB003 入れ子が max_depth を超えた
+    NestDepth,
+    /// BB004 閉じていないタグ
+    Unclosed,
+    /// BB005 無効な値属性
+    InvalidValue,
+    /// BB006 許可されていない名前付き属性
+    InvalidAttribute,
+    /// BB007 無効な中身（`[url]` / `[img]` の URL など）
+    InvalidContent,
+    /// BB008 その位置に置けないタグ
+    NotAllowedHere,
+    /// BB009 インライン要素の中のブロック要素（block_in_inline が Warn の場合）
+    BlockInInline,
+    /// BB010 投稿者の権限では使えないタグ
+    PermissionDenied,
+    /// BB011 同じ名前付き属性の繰り返し
+    DuplicateAttribute,
+    /// BB012 廃止予定のタグ
+    Deprecated,
+    /// BB013 max_links を超えたリンク
+    TooManyLinks,
+    /// BB014 許可されていないドメインへのリンク
+    LinkDomainNotAllowed,
+    /// BB015 生の HTML を含む `[html]`
+    RawHtml,
+    /// BB016 背景色に対してコントラスト不足の色
+    LowContrast,
+    /// BB017 表示と別のリンク先に見えるリンク
+    DeceptiveLink,
+    /// BB018 文法として解釈できない入力
+    Syntax,
+    /// BB019 入力の大きさ・タグの数などの制限を超えた
+    LimitExceeded,
+    /// BB020 パーサ内部の不整合
+    Internal,
+}
+
+impl LintCode {
+    pub const ALL: [LintCode; 20] = [
+        LintCode::MismatchedClose,
+        LintCode::UnknownTag,
+        LintCode::NestDepth,
+        LintCode::Unclosed,
+        LintCode::InvalidValue,
+        LintCode::InvalidAttribute,
+        LintCode::InvalidContent,
+        LintCode::NotAllowedHere,
+        LintCode::BlockInInline,
+        LintCode::PermissionDenied,
+        LintCode::DuplicateAttribute,
+        LintCode::Deprecated,
+        LintCode::TooManyLinks,
+        LintCode::LinkDomainNotAllowed,
+        LintCode::RawHtml,
+        LintCode::LowContrast,
+        LintCode::DeceptiveLink,
+        LintCode::Syntax,
+        LintCode::LimitExceeded,
+        LintCode::Internal,
+    ];
+
+    /// `BB001` の形のコード
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LintCode::MismatchedClose => "BB001",
+            LintCode::UnknownTag => "BB002",
+            LintCode::NestDepth => "BB003",
+            LintCode::Unclosed => "BB004",
+            LintCode::InvalidValue => "BB005",
+            LintCode::InvalidAttribute => "BB006",
+            LintCode::InvalidContent => "BB007",
+            LintCode::NotAllowedHere => "BB008",
+            LintCode::BlockInInline => "BB009",
+            LintCode::PermissionDenied => "BB010",
+            LintCode::DuplicateAttribute => "BB011",
+            LintCode::Deprecated => "BB012",
+            LintCode::TooManyLinks => "BB013",
+            LintCode::LinkDomainNotAllowed => "BB014",
+            LintCode::RawHtml => "BB015",
+            LintCode::LowContrast => "BB016",
+            LintCode::DeceptiveLink => "BB017",
+            LintCode::Syntax => "BB018",
+            LintCode::LimitExceeded => "BB019",
+            LintCode::Internal => "BB020",
+        }
+    }
+
+    /// `BB001` の形のコードから（大文字小文字は区別しない）
+    pub fn from_code(code: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|c| c.as_str().eq_ignore_ascii_case(code.trim()))
+    }
+
+    /// lint_severities で指定しない場合の重大度
+    /// パースできないものはエラー、テキストに戻したタグやリンクの問題は警告
+    pub fn default_severity(self) -> Severity {
+        match self {
+            LintCode::NestDepth
+            | LintCode::DuplicateAttribute
+            | LintCode::Syntax
+            | LintCode::LimitExceeded
+            | LintCode::Internal => Severity::Error,
+            LintCode::Deprecated => Severity::Info,
+            _ => Severity::Warning,
+        }
+    }
+
+    /// BbCodeOptions::strict でエラーになる（テキストに戻す）種類
+    fn is_fallback(self) -> bool {
+        matches!(
+            self,
+            LintCode::MismatchedClose
+                | LintCode::UnknownTag
+                | LintCode::Unclosed
+                | LintCode::InvalidValue
+                | LintCode::InvalidAttribute
+                | LintCode::InvalidContent
+                | LintCode::NotAllowedHere
+                | LintCode::PermissionDenied
+        )
+    }
+}
+
+impl fmt::Display for LintCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// 問題の重大度。Allow のコードは報告しない
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Allow,
+    Info,
+    Warning,
+    Error,
+}
+
+/// 報告する問題1つ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    pub code: LintCode,
+    pub severity: Severity,
+    /// 入力上の範囲。位置の無いエラー（入力サイズの超過など）は 0..0
+    pub span: Span,
+    pub message: String,
+    /// 問題を直す書き換えの案（閉じていないタグに閉じタグを補う・閉じタグの名前を直すなど）
+    pub suggested_fix: Option<TextEdit>,
+}
+
+/// input を opts でパースし、見つかった問題を入力上の位置の順に返す
+/// strict に関わらず最後までパースして、テキストに戻したタグをすべて報告する
+/// （strict なら、それらの既定の重大度をエラーにする）
+pub fn lint(input: &str, opts: &BbCodeOptions) -> Vec<Lint> {
+    let severity = |code: LintCode| {
+        opts.lint_severities
+            .get(&code)
+            .copied()
+            .unwrap_or(if opts.strict && code.is_fallback() {
+                Severity::Error
+            } else {
+                code.default_severity()
+            })
+    };
+    let lint = |code: LintCode, span: Span, message: String| Lint {
+        code,
+        severity: severity(code),
+        span,
+        message,
+        suggested_fix: None,
+    };
+
+    let mut lints = vec![];
+    match parse_lenient(input, opts) {
+        Ok(parsed) => {
+            for d in &parsed.diagnostics {
+                lints.push(lint(d.code(), d.span(), d.to_string()));
+            }
+            for fallback in parsed.fallbacks {
+                let code = fallback.reason.code();
+                // 未知のタグ・権限の不足は診断で知らせ済みなので、書き換えの案だけを付ける
+                match lints
+                    .iter_mut()
+                    .find(|l| l.code == code && l.span == fallback.span)
+                {
+                    Some(reported) => reported.suggested_fix = fallback.fix,
+                    None => lints.push(Lint {
+                        suggested_fix: fallback.fix,
+                        ..lint(
+                            code,
+                            fallback.span,
+                            format!("Tag left as text ({})", fallback.reason),
+                        )
+                    }),
+                }
+            }
+        }
+        Err(e) => {
+            let span = e.span().unwrap_or_default();
+            lints.push(Lint {
+                suggested_fix: stray_close_removal(input, span, opts),
+                ..lint(e.code(), span, e.to_string())
+            });
+        }
+    }
+    lints.retain(|l| l.severity != Severity::Allow);
+    lints.sort_by_key(|l| (l.span.start, l.span.end));
+    lints
+}
+
+/// 入力の書き換え1つ。span の部分を replacement に置き換える（span が空なら挿入）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub span: Span,
+    pub replacement: String,
+}
+
+impl TextEdit {
+    /// input に適用した結果。span が input の範囲外・文字の途中なら None
+    pub fn apply(&self, input: &str) -> Option<String> {
+        let head = input.get(..self.span.start)?;
+        let tail = input.get(self.span.end..)?;
+        Some(format!("{head}{}{tail}", self.replacement))
+    }
+}
+
+/// 文法エラーの位置 span が何も閉じない閉じタグ（`[/b]` / `[/*]`）なら、それを消す書き換え
+fn stray_close_removal(input: &str, span: Span, opts: &BbCodeOptions) -> Option<TextEdit> {
+    let chars = opts.syntax_chars;
+    let rest = input.get(span.start..)?;
+    let tag = rest.strip_prefix(chars.open())?.strip_prefix('/')?;
+    let end = tag.find(chars.close())?;
+    let name = &tag[..end];
+    if name.contains(|c: char| c.is_whitespace() || c == chars.open()) {
+        return None;
+    }
+    // 区切りの文字はどれも ASCII なので、`[` `/` 名前 `]` の長さ
+    Some(TextEdit {
+        span: Span {
+            start: span.start,
+            end: span.start + end + 3,
+        },
+        replacement: String::new(),
+    })
+}
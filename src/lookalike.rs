@@ -0,0 +1,154 @@
+// 表示とリンク先が食い違うリンクの検出
+//
+// `[url=https://evil.example]https://paypal.com[/url]` のように、表示が URL に見えるのに
+// 実際は別のホストへ飛ぶリンクはフィッシングの典型的な手口。見た目の似た文字
+// （キリル文字の `а` など）で同じホストに見せかけたものも見分けられるようにする。
+
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::ast::{Element, Node};
+use crate::registry::{is_valid_url, url_host};
+use crate::render::attr_value;
+
+/// 表示が別のリンク先に見えるリンク
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeceptiveLink {
+    /// 表示から読み取れるホスト
+    pub shown: String,
+    /// 実際のリンク先のホスト。ホストの無いサイト内パスならリンク先そのもの
+    pub actual: String,
+    /// 見た目の似た文字で同じホストに見せかけている
+    pub lookalike: bool,
+}
+
+/// 表示 label が href とは別のリンク先に見えるか
+/// label が URL に見える（`http://` / `https://` / `//` / `www.` で始まる）場合だけ調べる。
+/// 同じホスト（大文字小文字・末尾の `.`・先頭の `www.` は区別しない）とそのサブドメインは食い違いとみなさない
+pub fn deceptive_link(href: &str, label: &str) -> Option<DeceptiveLink> {
+    let shown = shown_host(label)?;
+    let actual = url_host(href).unwrap_or_else(|| href.trim().to_string());
+    let (shown_key, actual_key) = (host_key(&shown), host_key(&actual));
+    if actual_key == shown_key || actual_key.ends_with(&format!(".{shown_key}")) {
+        return None;
+    }
+    let lookalike = skeleton(&shown_key) == skeleton(&actual_key);
+    Some(DeceptiveLink {
+        shown,
+        actual,
+        lookalike,
+    })
+}
+
+/// 値属性でリンク先を指定した `[url]` 要素の食い違い
+pub(crate) fn deceptive_link_of(el: &Element) -> Option<DeceptiveLink> {
+    if el.name != "url" {
+        return None;
+    }
+    let href = attr_value(el).filter(|h| is_valid_url(h))?;
+    let mut label = String::new();
+    push_text(&el.children, &mut label);
+    deceptive_link(href, &label)
+}
+
+/// 見た目の似た文字を代表の文字に揃えた形（比較用）
+/// 互換分解して結合文字を除き、紛らわしい文字（キリル文字・ギリシャ文字の一部、`0` と `o`、
+/// `1` / `I` と `l`、`rn` と `m` など）を同じ文字にする
+pub fn skeleton(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.nfkd().filter(|c| !is_combining_mark(*c)) {
+        out.push(confusable(c));
+    }
+    out.to_lowercase().replace("rn", "m").replace("vv", "w")
+}
+
+/// 利用者に示すためのホストの表記
+/// 非 ASCII 文字を含むホストは見た目で区別できないので、`url` フィーチャなら punycode、
+/// そうでなければ `\uXXXX` の形にする
+pub fn display_host(host: &str) -> String {
+    if host.is_ascii() {
+        return host.to_string();
+    }
+    #[cfg(feature = "url")]
+    if let Ok(ascii) = idna::domain_to_ascii(host) {
+        return ascii;
+    }
+    let mut out = String::with_capacity(host.len());
+    for c in host.chars() {
+        if c.is_ascii() {
+            out.push(c);
+        } else {
+            out.push_str(&format!("\\u{:04X}", u32::from(c)));
+        }
+    }
+    out
+}
+
+/// 表示が URL に見えれば、そのホスト
+fn shown_host(label: &str) -> Option<String> {
+    let label = label.trim();
+    if label.contains(char::is_whitespace) {
+        return None;
+    }
+    let lower = label.to_ascii_lowercase();
+    if lower.starts_with("http://") || lower.starts_with("https://") {
+        url_host(label)
+    } else if let Some(rest) = label.strip_prefix("//") {
+        url_host(&format!("http://{rest}"))
+    } else if lower.starts_with("www.") {
+        url_host(&format!("http://{label}"))
+    } else {
+        None
+    }
+}
+
+/// ホストを比べるための形
+fn host_key(host: &str) -> String {
+    let host = host.trim_end_matches('.').to_lowercase();
+    #[cfg(feature = "url")]
+    let host = match idna::domain_to_unicode(&host) {
+        (unicode, Ok(())) => unicode,
+        _ => host,
+    };
+    match host.strip_prefix("www.") {
+        Some(rest) => rest.to_string(),
+        None => host,
+    }
+}
+
+fn confusable(c: char) -> char {
+    match c {
+        'а' | 'α' | 'ɑ' => 'a',
+        'ь' | 'в' => 'b',
+        'с' | 'ϲ' => 'c',
+        'ԁ' => 'd',
+        'е' | 'ε' => 'e',
+        'ɡ' => 'g',
+        'һ' => 'h',
+        'і' | 'ι' | 'ı' | 'ӏ' => 'i',
+        'ј' => 'j',
+        'κ' | 'к' => 'k',
+        '1' | 'I' | 'ǀ' => 'l',
+        'о' | 'ο' | '0' => 'o',
+        'р' | 'ρ' => 'p',
+        'ԛ' => 'q',
+        'ѕ' => 's',
+        'τ' | 'т' => 't',
+        'υ' => 'u',
+        'ν' | 'ѵ' => 'v',
+        'ԝ' | 'ω' => 'w',
+        'х' | 'χ' => 'x',
+        'у' | 'γ' => 'y',
+        _ => c,
+    }
+}
+
+fn push_text(nodes: &[Node], out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text { text, .. } => out.push_str(text),
+            Node::Element(el) => push_text(&el.children, out),
+            Node::Unsupported(_) => {}
+        }
+    }
+}
@@ -1,8 +1,368 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::color::ColorFormats;
+use crate::lint::{LintCode, Severity};
+use crate::pass::AstPass;
+use crate::registry::{PermissionLevel, TagRegistry};
+
+/// インライン要素の中にブロック要素がある場合（`[b][quote]..[/quote][/b]`）の扱い
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum NestingStrictness {
+    /// そのまま受け入れる
+    #[default]
+    Allow,
+    /// 受け入れた上で Diagnostic::BlockInInline を出す
+    Warn,
+    /// 外側のインライン要素を丸ごとテキストへ
+    Fallback,
+}
+
+/// 字句解析に使う実装
+/// どちらも同じ文法・同じ結果になる（pest 版を基準実装として差分テストしている）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ParserBackend {
+    /// bbcode.pest から生成したパーサ
+    #[default]
+    Pest,
+    /// 手書きの字句解析器（`fast-parser` フィーチャ）
+    #[cfg(feature = "fast-parser")]
+    Fast,
+}
+
+/// リンク先のドメインの制限
+/// 列挙したドメインはサブドメインにも一致する（`example.com` は `www.example.com` にも一致）。
+/// サイト内パス・ページ内リンクは常に許可する
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DomainPolicy {
+    #[default]
+    AllowAll,
+    /// 列挙したドメインへのリンクだけを許可する
+    AllowList(Vec<String>),
+    /// 列挙したドメインへのリンクを禁止する
+    DenyList(Vec<String>),
+}
+
+impl DomainPolicy {
+    /// host（小文字）へのリンクを許可するか
+    pub fn allows(&self, host: &str) -> bool {
+        let matches = |domains: &[String]| {
+            domains.iter().any(|d| {
+                let d = d.trim().trim_start_matches('.').to_ascii_lowercase();
+                host == d || host.strip_suffix(&d).is_some_and(|p| p.ends_with('.'))
+            })
+        };
+        match self {
+            DomainPolicy::AllowAll => true,
+            DomainPolicy::AllowList(domains) => matches(domains),
+            DomainPolicy::DenyList(domains) => !matches(domains),
+        }
+    }
+}
+
+/// リンクの制限（max_links / link_domains）に反したリンクの扱い
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LinkPolicyAction {
+    /// リンクのまま残し、診断だけを出す
+    #[default]
+    Warn,
+    /// `[url]` を外して表示テキストだけを残し、診断を出す
+    Strip,
+}
+
+/// `[color]` の文字色と背景色のコントラストの確認
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct ContrastCheck {
+    /// 投稿が表示される背景色（`#ffffff` / `white` など）
+    pub background: String,
+    /// 必要なコントラスト比（WCAG AA の本文は 4.5）
+    pub min_ratio: f32,
+    /// 足りない場合の扱い
+    pub action: ContrastAction,
+}
+
+impl Default for ContrastCheck {
+    fn default() -> Self {
+        Self {
+            background: "#ffffff".to_string(),
+            min_ratio: 4.5,
+            action: ContrastAction::Warn,
+        }
+    }
+}
+
+impl ContrastCheck {
+    pub fn with_background(mut self, background: impl Into<String>) -> Self {
+        self.background = background.into();
+        self
+    }
+
+    pub fn with_min_ratio(mut self, min_ratio: f32) -> Self {
+        self.min_ratio = min_ratio;
+        self
+    }
+
+    pub fn with_action(mut self, action: ContrastAction) -> Self {
+        self.action = action;
+        self
+    }
+}
+
+/// コントラストの足りない `[color]` の扱い
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ContrastAction {
+    /// 色はそのまま残し、診断だけを出す
+    #[default]
+    Warn,
+    /// `[color]` を外して中身だけを残し、診断を出す
+    Strip,
+}
+
+/// 入力中の制御文字（`\n` / `\t` / 改行の `\r\n` 以外の C0 制御文字と U+2028 / U+2029）の扱い
+/// JavaScript への埋め込みやログの処理を壊すので、パースの前に取り除くかエスケープできる。
+/// Keep 以外で入力が変わった場合、AST の span は変換後の入力上の位置になる
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ControlChars {
+    /// そのまま残す
+    #[default]
+    Keep,
+    /// 取り除く
+    Strip,
+    /// `\u0000` / `\u2028` のような文字列に置き換える
+    Escape,
+}
+
+/// 中身の無い要素（`[b][/b]`）の扱い
+/// `[anchor=x][/anchor]` のように空で使うタグ（TagSpec::keep_empty）は常に残す
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum EmptyElements {
+    /// 要素として残す
+    #[default]
+    Keep,
+    /// 取り除く
+    Drop,
+    /// 入力どおりのテキストにする
+    Text,
+}
+
+/// 同じ名前付き属性が繰り返された場合（`[quote author=a author=b]`）の扱い
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DuplicateAttrs {
+    /// 最初の値を使い、残りは捨てる
+    #[default]
+    FirstWins,
+    /// 最後の値を使い、残りは捨てる
+    LastWins,
+    /// ParseError::DuplicateAttribute のエラーにする
+    Error,
+}
+
+/// 属性値（`[tag=..]` の値と名前付き属性）の空白の扱い
+/// AST を組み立てるときに適用するので、検証・レンダラーはどれも同じ値を見る。
+/// 引用符で囲まない値の前後の空白は区切りなので、どの場合も含まれない
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AttrWhitespace {
+    /// 引用符で囲んだ値の空白もそのまま残す
+    Keep,
+    /// 前後の空白を除く
+    #[default]
+    Trim,
+    /// 前後の空白を除き、途中の連続する空白（改行を含む）を1つの半角空白にする
+    Collapse,
+}
+
+impl AttrWhitespace {
+    pub(crate) fn apply(self, value: String) -> String {
+        match self {
+            AttrWhitespace::Keep => value,
+            AttrWhitespace::Trim => value.trim().to_string(),
+            AttrWhitespace::Collapse => value.split_whitespace().collect::<Vec<_>>().join(" "),
+        }
+    }
+}
+
+/// タグの区切りとエスケープに使う文字。既定は `[` `]` `\`
+/// `{b}..{/b}` や `<b>..</b>` で書かれた古いアーカイブを、書き換えずにパースするためのもの。
+/// パースの前に既定の文字と入れ替え、AST のテキスト・属性値では元の文字に戻す
+/// （span は変わらない）。raw_parse は対象外
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SyntaxChars {
+    open: char,
+    close: char,
+    escape: char,
+}
+
+impl Default for SyntaxChars {
+    fn default() -> Self {
+        Self {
+            open: '[',
+            close: ']',
+            escape: '\\',
+        }
+    }
+}
+
+impl SyntaxChars {
+    /// 使えない組み合わせなら None
+    /// どれも ASCII の記号で互いに異なり、タグの文法に使う `=` `/` `*` `"` `'` でないこと
+    pub fn new(open: char, close: char, escape: char) -> Option<Self> {
+        let usable =
+            |c: char| c.is_ascii_punctuation() && !matches!(c, '=' | '/' | '*' | '"' | '\'');
+        let distinct = open != close && open != escape && close != escape;
+        (usable(open) && usable(close) && usable(escape) && distinct).then_some(Self {
+            open,
+            close,
+            escape,
+        })
+    }
+
+    /// `{` `}` で囲む（エスケープは `\`）
+    pub fn braces() -> Self {
+        Self {
+            open: '{',
+            close: '}',
+            escape: '\\',
+        }
+    }
+
+    /// `<` `>` で囲む（エスケープは `\`）
+    pub fn angle_brackets() -> Self {
+        Self {
+            open: '<',
+            close: '>',
+            escape: '\\',
+        }
+    }
+
+    pub fn open(&self) -> char {
+        self.open
+    }
+
+    pub fn close(&self) -> char {
+        self.close
+    }
+
+    pub fn escape(&self) -> char {
+        self.escape
+    }
+
+    /// 入力の文字を既定の文字に置き換える対応（置き換え元, 置き換え先）
+    /// 既定の文字が入力に現れた場合は空いた文字に逃がすので、逆向きに置き換えれば元に戻る
+    pub(crate) fn translation(&self) -> Vec<(char, char)> {
+        let custom = [self.open, self.close, self.escape];
+        let default = ['[', ']', '\\'];
+        let mut pairs: Vec<_> = custom
+            .into_iter()
+            .zip(default)
+            .filter(|(from, to)| from != to)
+            .collect();
+        let freed = custom.into_iter().filter(|c| !default.contains(c));
+        let displaced = default.into_iter().filter(|c| !custom.contains(c));
+        pairs.extend(displaced.zip(freed));
+        pairs
+    }
+}
+
+/// 部分的な検証（`validate_fragment`）で、断片が置かれる位置
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FragmentContext {
+    /// 断片を直接囲むタグ名（別名も可）。None なら投稿の最上位
+    pub parent: Option<String>,
+    /// 断片を囲んでいる要素の数（max_depth の判定に使う）
+    pub depth: usize,
+}
+
+impl FragmentContext {
+    /// 最上位にある `[parent]` の中
+    pub fn inside(parent: &str) -> Self {
+        Self {
+            parent: Some(parent.to_string()),
+            depth: 1,
+        }
+    }
+}
+
+/// パースの設定
+/// 今後のバージョンで項目を増やせるよう `#[non_exhaustive]` にしている。
+/// クレートの外では `BbCodeOptions::default().with_max_depth(5)` のように組み立てる
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub struct BbCodeOptions {
     pub max_depth: usize,
     pub max_tags: usize,
     pub max_input_size: usize,
+    /// 1つの要素の直下に置ける子要素の数の上限（テキストは数えない）。最上位は対象外
+    pub max_children_per_element: usize,
+    /// 最終ASTのテキストノード長の合計上限（バイト）
+    pub max_text_len: usize,
+    /// パース時に参照するタグ仕様
+    pub registry: TagRegistry,
+    /// 開始タグと閉じタグの大文字・小文字まで一致を要求する（`[B]..[/b]` を不一致とする）
+    pub case_sensitive_tags: bool,
+    /// `[/]` で直前に開いたタグを閉じられるようにする
+    pub universal_close: bool,
+    /// 連続する改行をこの数までに詰める（`[code]` の中身は対象外）。None なら詰めない
+    /// Text の span は詰める前の入力上の範囲のまま（詰めた分だけ text より長くなる）
+    pub max_consecutive_newlines: Option<usize>,
+    /// テキスト中の `#topic` を `[tag=topic]` 要素として取り出す
+    pub detect_hashtags: bool,
+    /// インライン要素がブロック要素を含む場合の扱い
+    pub block_in_inline: NestingStrictness,
+    /// 字句解析の実装
+    pub backend: ParserBackend,
+    /// 隣り合う Text ノードを1つにまとめる
+    /// false なら字句単位の細かいノードと元の span をそのまま残す（`\[` は独立した "[" になる）
+    pub merge_adjacent_text: bool,
+    /// 投稿に含められる `[url]` リンクの数。None なら制限しない
+    pub max_links: Option<usize>,
+    /// リンク先のドメインの制限
+    pub link_domains: DomainPolicy,
+    /// max_links / link_domains に反したリンクの扱い
+    pub link_policy_action: LinkPolicyAction,
+    /// 投稿者の権限。TagSpec::required_level に満たないタグはテキストとして扱う
+    pub author_level: PermissionLevel,
+    /// `[color]` の色と背景色のコントラストを確認する。None なら確認しない
+    /// 不透明度は無視して比べる
+    pub color_contrast: Option<ContrastCheck>,
+    /// 色の値（TagSpec::color_value のタグ）として受け付ける書き方
+    pub color_formats: ColorFormats,
+    /// 入力中の制御文字の扱い（パースの前に適用する）
+    pub control_chars: ControlChars,
+    /// 入力の先頭の BOM（U+FEFF）を取り除く（パースの前に適用し、span はその後の位置になる）
+    pub strip_bom: bool,
+    /// テキストノードを Unicode の NFC に正規化する
+    /// 編集環境によって合成済み・結合文字の違う同じ文字列を、保存や検索で同じものとして扱える
+    pub normalize_nfc: bool,
+    /// タグの区切りとエスケープに使う文字
+    pub syntax_chars: SyntaxChars,
+    /// 中身の無い要素の扱い
+    pub empty_elements: EmptyElements,
+    /// 同じ名前付き属性が繰り返された場合の扱い
+    pub duplicate_attrs: DuplicateAttrs,
+    /// 属性値の空白の扱い
+    pub attr_whitespace: AttrWhitespace,
+    /// テキストに戻すはずのタグ（閉じタグの不一致・未知のタグ・無効な属性など）を
+    /// ParseError::InvalidTag のエラーにする。崩れた投稿を保存せずに拒否したい場合に使う
+    pub strict: bool,
+    /// パース後に登録した順に実行する処理（禁止語の伏せ字・自動リンクなど）
+    pub ast_passes: Vec<Arc<dyn AstPass>>,
+    /// 字句解析の前（入力サイズの確認の後）に入力を書き換える関数。`<br>` の残骸や
+    /// 古い顔文字の記法を直すなどの後始末に使う。span は書き換えた後の入力での位置になる。
+    /// 書き換えた結果も max_input_size の対象
+    pub preprocess: Option<fn(&str) -> Cow<'_, str>>,
+    /// lint で報告する重大度をコードごとに変える（Allow なら報告しない）。無いコードは既定の重大度
+    pub lint_severities: HashMap<LintCode, Severity>,
 }
 
 impl Default for BbCodeOptions {
@@ -11,6 +371,185 @@ impl Default for BbCodeOptions {
             max_depth: 3,
             max_tags: 500,
             max_input_size: 50 * 1024,
+            max_children_per_element: 100,
+            max_text_len: 50 * 1024,
+            registry: TagRegistry::builtin(),
+            case_sensitive_tags: false,
+            universal_close: false,
+            max_consecutive_newlines: None,
+            detect_hashtags: false,
+            block_in_inline: NestingStrictness::Allow,
+            backend: ParserBackend::Pest,
+            merge_adjacent_text: true,
+            max_links: None,
+            link_domains: DomainPolicy::AllowAll,
+            link_policy_action: LinkPolicyAction::Warn,
+            author_level: PermissionLevel::Member,
+            color_contrast: None,
+            color_formats: ColorFormats::default(),
+            control_chars: ControlChars::Keep,
+            strip_bom: false,
+            normalize_nfc: false,
+            syntax_chars: SyntaxChars::default(),
+            empty_elements: EmptyElements::Keep,
+            duplicate_attrs: DuplicateAttrs::FirstWins,
+            attr_whitespace: AttrWhitespace::Trim,
+            strict: false,
+            ast_passes: vec![],
+            preprocess: None,
+            lint_severities: HashMap::new(),
         }
     }
 }
+
+impl BbCodeOptions {
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn with_max_tags(mut self, max_tags: usize) -> Self {
+        self.max_tags = max_tags;
+        self
+    }
+
+    pub fn with_max_input_size(mut self, max_input_size: usize) -> Self {
+        self.max_input_size = max_input_size;
+        self
+    }
+
+    pub fn with_max_children_per_element(mut self, max_children: usize) -> Self {
+        self.max_children_per_element = max_children;
+        self
+    }
+
+    pub fn with_max_text_len(mut self, max_text_len: usize) -> Self {
+        self.max_text_len = max_text_len;
+        self
+    }
+
+    pub fn with_registry(mut self, registry: TagRegistry) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    pub fn with_case_sensitive_tags(mut self, enabled: bool) -> Self {
+        self.case_sensitive_tags = enabled;
+        self
+    }
+
+    pub fn with_universal_close(mut self, enabled: bool) -> Self {
+        self.universal_close = enabled;
+        self
+    }
+
+    pub fn with_max_consecutive_newlines(mut self, max: usize) -> Self {
+        self.max_consecutive_newlines = Some(max);
+        self
+    }
+
+    pub fn with_detect_hashtags(mut self, enabled: bool) -> Self {
+        self.detect_hashtags = enabled;
+        self
+    }
+
+    pub fn with_block_in_inline(mut self, strictness: NestingStrictness) -> Self {
+        self.block_in_inline = strictness;
+        self
+    }
+
+    pub fn with_backend(mut self, backend: ParserBackend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    pub fn with_merge_adjacent_text(mut self, enabled: bool) -> Self {
+        self.merge_adjacent_text = enabled;
+        self
+    }
+
+    pub fn with_max_links(mut self, max_links: usize) -> Self {
+        self.max_links = Some(max_links);
+        self
+    }
+
+    pub fn with_link_domains(mut self, domains: DomainPolicy) -> Self {
+        self.link_domains = domains;
+        self
+    }
+
+    pub fn with_link_policy_action(mut self, action: LinkPolicyAction) -> Self {
+        self.link_policy_action = action;
+        self
+    }
+
+    pub fn with_author_level(mut self, level: PermissionLevel) -> Self {
+        self.author_level = level;
+        self
+    }
+
+    pub fn with_color_contrast(mut self, check: ContrastCheck) -> Self {
+        self.color_contrast = Some(check);
+        self
+    }
+
+    pub fn with_color_formats(mut self, formats: ColorFormats) -> Self {
+        self.color_formats = formats;
+        self
+    }
+
+    pub fn with_control_chars(mut self, policy: ControlChars) -> Self {
+        self.control_chars = policy;
+        self
+    }
+
+    pub fn with_strip_bom(mut self, enabled: bool) -> Self {
+        self.strip_bom = enabled;
+        self
+    }
+
+    pub fn with_normalize_nfc(mut self, enabled: bool) -> Self {
+        self.normalize_nfc = enabled;
+        self
+    }
+
+    pub fn with_syntax_chars(mut self, chars: SyntaxChars) -> Self {
+        self.syntax_chars = chars;
+        self
+    }
+
+    pub fn with_empty_elements(mut self, policy: EmptyElements) -> Self {
+        self.empty_elements = policy;
+        self
+    }
+
+    pub fn with_duplicate_attrs(mut self, policy: DuplicateAttrs) -> Self {
+        self.duplicate_attrs = policy;
+        self
+    }
+
+    pub fn with_attr_whitespace(mut self, policy: AttrWhitespace) -> Self {
+        self.attr_whitespace = policy;
+        self
+    }
+
+    pub fn with_strict(mut self, enabled: bool) -> Self {
+        self.strict = enabled;
+        self
+    }
+
+    pub fn with_preprocess(mut self, preprocess: fn(&str) -> Cow<'_, str>) -> Self {
+        self.preprocess = Some(preprocess);
+        self
+    }
+
+    pub fn with_ast_pass(mut self, pass: impl AstPass + 'static) -> Self {
+        self.ast_passes.push(Arc::new(pass));
+        self
+    }
+
+    pub fn with_lint_severity(mut self, code: LintCode, severity: Severity) -> Self {
+        self.lint_severities.insert(code, severity);
+        self
+    }
+}
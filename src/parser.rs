@@ -1,2 +1,11 @@
+mod build;
+#[cfg(feature = "fast-parser")]
+mod fast;
+mod normalize;
 pub mod pest_parser;
-pub use pest_parser::{parse_bbcode_to_ast, Rule};
+mod tree;
+
+pub use build::{parse_bbcode_to_ast, parse_bbcode_with_diagnostics, validate_fragment};
+pub(crate) use build::{parse_counting_fallbacks, parse_lenient, parse_plain_text};
+pub use pest::iterators::{Pair, Pairs};
+pub use pest_parser::{raw_parse, Rule};
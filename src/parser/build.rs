@@ -0,0 +1,1380 @@
+use crate::ast::{Element, Node, Span, TagName};
+use crate::diagnostic::Diagnostic;
+use crate::error::{BbCodeError, InvalidTagReason, LimitError, ParseError};
+use crate::lint::TextEdit;
+use crate::lookalike::deceptive_link_of;
+use crate::options::{
+    BbCodeOptions, ContrastAction, DomainPolicy, DuplicateAttrs, EmptyElements, FragmentContext,
+    LinkPolicyAction, NestingStrictness, ParserBackend,
+};
+use crate::parser::normalize::{
+    normalize_input, normalize_nfc_in, restore_syntax_chars, restore_syntax_chars_in_tokens,
+};
+use crate::parser::pest_parser::{syntax_error, tokenize};
+use crate::parser::tree::{build_tree, OpenTag, Raw};
+use crate::registry::{
+    color_to_rgb, contrast_ratio, is_valid_url, single_text_child, url_host, DisplayKind,
+    SameTagNesting, TagRegistry, TagSpec,
+};
+use crate::render::attr_value;
+
+/// AST構築時のコンテキスト
+struct BuildAstContext<'a> {
+    opts: &'a BbCodeOptions,
+    /// 範囲からテキストを取り出すための元の入力
+    input: &'a str,
+    tag_count: usize,
+    diagnostics: Vec<Diagnostic>,
+    /// テキストに戻したタグ
+    fallbacks: Vec<Fallback>,
+    /// テキストに戻すはずのタグをエラーにする（opts.strict。lint では常に false）
+    strict: bool,
+}
+
+impl<'a> BuildAstContext<'a> {
+    fn new(opts: &'a BbCodeOptions, input: &'a str) -> Self {
+        Self {
+            opts,
+            input,
+            tag_count: 0,
+            diagnostics: vec![],
+            fallbacks: vec![],
+            strict: opts.strict,
+        }
+    }
+
+    /// 入力の span の部分。字句解析の結果と入力が食い違っていれば Internal エラー
+    fn slice(&self, span: Span) -> Result<&'a str, BbCodeError> {
+        span.slice(self.input).ok_or_else(|| {
+            BbCodeError::Internal(format!(
+                "span {}..{} is outside the input ({} bytes)",
+                span.start,
+                span.end,
+                self.input.len()
+            ))
+        })
+    }
+
+    /// テキストに戻す範囲 span の中身。中の `\[` はテキストとして組み立てたときと同じく `[` にする
+    /// （入力のまま残すとエスケープの `\` が表示に残る）
+    fn fallback_text(&self, span: Span, content: &[Raw]) -> Result<String, BbCodeError> {
+        let mut escapes = vec![];
+        collect_escapes(content, &mut escapes);
+        self.splice(span, escapes.into_iter().map(|s| (s, "[")))
+    }
+
+    /// 組み立て済みの要素 el をテキストに戻すときの中身。子のテキストが入力と違う部分
+    /// （`\[` やテキストに戻したタグ）は子のテキストを使う
+    fn fallback_text_of(&self, el: &Element) -> Result<String, BbCodeError> {
+        let mut pieces = vec![];
+        self.rewritten_texts(&el.children, &mut pieces)?;
+        self.splice(el.span, pieces)
+    }
+
+    fn rewritten_texts<'n>(
+        &self,
+        nodes: &'n [Node],
+        out: &mut Vec<(Span, &'n str)>,
+    ) -> Result<(), BbCodeError> {
+        for node in nodes {
+            match node {
+                Node::Text { span, text } => {
+                    if self.slice(*span)? != text {
+                        out.push((*span, text));
+                    }
+                }
+                Node::Element(el) => self.rewritten_texts(&el.children, out)?,
+                Node::Unsupported(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// span の入力のうち、pieces の各範囲を対応するテキストに置き換えたもの（pieces は位置順）
+    fn splice<'s>(
+        &self,
+        span: Span,
+        pieces: impl IntoIterator<Item = (Span, &'s str)>,
+    ) -> Result<String, BbCodeError> {
+        let mut out = String::with_capacity(span.end.saturating_sub(span.start));
+        let mut pos = span.start;
+        for (piece, text) in pieces {
+            out.push_str(self.slice(Span {
+                start: pos,
+                end: piece.start,
+            })?);
+            out.push_str(text);
+            pos = piece.end;
+        }
+        out.push_str(self.slice(Span {
+            start: pos,
+            end: span.end,
+        })?);
+        Ok(out)
+    }
+
+    /// name を閉じるタグ（syntax_chars の文字で書く）
+    fn close_tag(&self, name: &str) -> String {
+        let chars = self.opts.syntax_chars;
+        format!("{}/{name}{}", chars.open(), chars.close())
+    }
+
+    /// pos にある開始タグの区切りをエスケープする書き換え
+    fn escape_at(&self, pos: usize) -> TextEdit {
+        TextEdit {
+            span: Span {
+                start: pos,
+                end: pos,
+            },
+            replacement: self.opts.syntax_chars.escape().to_string(),
+        }
+    }
+
+    /// `[b][i]x[/b][/i]` のように交差した閉じタグを、開いた順の逆に閉じ直す書き換えと、
+    /// 外側の閉じタグ（open_key のもの）で閉じてしまった内側のタグの範囲
+    fn crossed_close_fix(
+        &self,
+        open_name: &str,
+        open_key: &str,
+        content: &[Raw],
+        close_span: Span,
+    ) -> Result<Option<(TextEdit, Span)>, BbCodeError> {
+        // 外側から、open_key の閉じタグで閉じた内側のタグまでの Block の並び
+        fn path<'r>(
+            content: &'r [Raw],
+            open_key: &str,
+            registry: &TagRegistry,
+        ) -> Option<Vec<(&'r OpenTag, Span, Span)>> {
+            content.iter().rev().find_map(|raw| {
+                let Raw::Block {
+                    span,
+                    open,
+                    children,
+                    close_name,
+                    close_span,
+                } = raw
+                else {
+                    return None;
+                };
+                if registry.canonical_name(close_name) == open_key {
+                    return Some(vec![(open, *span, *close_span)]);
+                }
+                let mut inner = path(children, open_key, registry)?;
+                inner.insert(0, (open, *span, *close_span));
+                Some(inner)
+            })
+        }
+        let Some(blocks) = path(content, open_key, &self.opts.registry) else {
+            return Ok(None);
+        };
+        let Some(&(_, inner_span, first_close)) = blocks.last() else {
+            return Ok(None);
+        };
+        let closers: String = blocks
+            .iter()
+            .rev()
+            .map(|(tag, _, _)| self.close_tag(&tag.name))
+            .chain([self.close_tag(open_name)])
+            .collect();
+        // 最初の閉じタグの位置に並べ直した閉じタグを置き、それより後の閉じタグは消す
+        let pieces = std::iter::once((first_close, closers.as_str()))
+            .chain(
+                blocks
+                    .iter()
+                    .rev()
+                    .skip(1)
+                    .map(|&(_, _, close)| (close, "")),
+            )
+            .chain([(close_span, "")]);
+        let region = Span {
+            start: first_close.start,
+            end: close_span.end,
+        };
+        let replacement = self.splice(region, pieces)?;
+        Ok(Some((
+            TextEdit {
+                span: region,
+                replacement,
+            },
+            inner_span,
+        )))
+    }
+
+    /// 要素 span の直下の子要素の数が max_children_per_element 以下か
+    fn check_children(&self, children: &[Node], span: Span) -> Result<(), BbCodeError> {
+        let max_children = self.opts.max_children_per_element;
+        let count = children
+            .iter()
+            .filter(|n| matches!(n, Node::Element(_)))
+            .count();
+        if count > max_children {
+            let (line, column) = self.line_col(span.start);
+            return Err(LimitError::ChildCountExceeded {
+                max_children,
+                span,
+                line,
+                column,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    fn on_tag(&mut self) -> Result<(), BbCodeError> {
+        self.tag_count += 1;
+        if self.tag_count > self.opts.max_tags {
+            return Err(LimitError::TagCountExceeded {
+                max_tags: self.opts.max_tags,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// 入力上の位置の行・列（1 始まり）
+    fn line_col(&self, pos: usize) -> (usize, usize) {
+        pest::Position::new(self.input, pos)
+            .map(|p| p.line_col())
+            .unwrap_or((1, 1))
+    }
+
+    fn check_depth(&self, depth: usize, span: Span) -> Result<(), BbCodeError> {
+        let level = depth.saturating_add(1);
+        if level > self.opts.max_depth {
+            let (line, column) = self.line_col(span.start);
+            return Err(LimitError::NestDepthExceeded {
+                max_depth: self.opts.max_depth,
+                near: self.slice(span)?.to_string(),
+                span,
+                line,
+                column,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// インライン要素がブロック要素を直接含んでいないか確認する
+    /// false ならフォールバックさせる
+    fn check_content_model(&mut self, spec: &TagSpec, elem: &Element) -> bool {
+        let strictness = self.opts.block_in_inline;
+        if strictness == NestingStrictness::Allow || spec.display == DisplayKind::Block {
+            return true;
+        }
+        for child in &elem.children {
+            let Node::Element(child) = child else {
+                continue;
+            };
+            let is_block = self
+                .opts
+                .registry
+                .get(&child.name)
+                .is_some_and(|s| s.display == DisplayKind::Block);
+            if !is_block {
+                continue;
+            }
+            if strictness == NestingStrictness::Fallback {
+                return false;
+            }
+            self.diagnostics.push(Diagnostic::BlockInInline {
+                parent: elem.name.to_string(),
+                child: child.name.to_string(),
+                span: child.span,
+            });
+        }
+        true
+    }
+
+    /// 断片の外にある親タグ（インライン）に対して、ブロック要素の子を知らせる
+    fn check_fragment_content_model(&mut self, parent: &str, spec: &TagSpec, nodes: &[Node]) {
+        if self.opts.block_in_inline == NestingStrictness::Allow
+            || spec.display == DisplayKind::Block
+        {
+            return;
+        }
+        for node in nodes {
+            let Node::Element(child) = node else {
+                continue;
+            };
+            let is_block = self
+                .opts
+                .registry
+                .get(&child.name)
+                .is_some_and(|s| s.display == DisplayKind::Block);
+            if is_block {
+                self.diagnostics.push(Diagnostic::BlockInInline {
+                    parent: parent.to_string(),
+                    child: child.name.to_string(),
+                    span: child.span,
+                });
+            }
+        }
+    }
+
+    /// 親子関係の制約（allowed_children / required_parent）に反する子要素を元のテキストへ戻す
+    fn enforce_parent_constraints(
+        &mut self,
+        parent: Option<&str>,
+        children: Vec<Node>,
+    ) -> Result<Vec<Node>, BbCodeError> {
+        let registry = &self.opts.registry;
+        let allowed = parent
+            .and_then(|p| registry.get(p))
+            .and_then(|s| s.allowed_children.as_ref());
+        let mut out = Vec::with_capacity(children.len());
+        for n in children {
+            let Node::Element(el) = n else {
+                out.push(n);
+                continue;
+            };
+            let parent_ok = registry
+                .get(&el.name)
+                .and_then(|s| s.required_parent.as_deref())
+                .is_none_or(|required| parent == Some(required));
+            let allowed_ok = allowed.is_none_or(|a| a.contains(el.name.as_str()));
+            if parent_ok && allowed_ok {
+                out.push(Node::Element(el));
+            } else {
+                let original = self.fallback_text_of(&el)?;
+                out.extend(self.fallback(el.span, original, InvalidTagReason::NotAllowedHere)?);
+            }
+        }
+        Ok(out)
+    }
+
+    /// 子孫のうち outer と同じ名前の要素を policy に従って扱う
+    /// 同じ名前の要素より内側は、その要素を組み立てたときに扱い済みなので見ない
+    fn apply_same_tag_nesting(
+        &mut self,
+        policy: SameTagNesting,
+        outer: &Element,
+        children: Vec<Node>,
+    ) -> Result<Vec<Node>, BbCodeError> {
+        let mut out = Vec::with_capacity(children.len());
+        for node in children {
+            let Node::Element(mut el) = node else {
+                out.push(node);
+                continue;
+            };
+            if el.name != outer.name {
+                let inner = std::mem::take(&mut el.children);
+                el.children = self.apply_same_tag_nesting(policy, outer, inner)?;
+                out.push(Node::Element(el));
+                continue;
+            }
+            match policy {
+                SameTagNesting::Collapse if el.attrs == outer.attrs => out.extend(el.children),
+                SameTagNesting::Reject => {
+                    let original = self.fallback_text_of(&el)?;
+                    out.extend(self.fallback(
+                        el.span,
+                        original,
+                        InvalidTagReason::NotAllowedHere,
+                    )?);
+                }
+                _ => out.push(Node::Element(el)),
+            }
+        }
+        Ok(out)
+    }
+
+    /// 繰り返された名前付き属性を duplicate_attrs に従って1つにする
+    fn dedupe_attrs(
+        &self,
+        tag: &str,
+        attrs: Vec<(String, String)>,
+        span: Span,
+    ) -> Result<Vec<(String, String)>, BbCodeError> {
+        let mut out: Vec<(String, String)> = Vec::with_capacity(attrs.len());
+        for (key, value) in attrs {
+            let Some(pos) = out.iter().position(|(k, _)| *k == key) else {
+                out.push((key, value));
+                continue;
+            };
+            match self.opts.duplicate_attrs {
+                DuplicateAttrs::FirstWins => {}
+                DuplicateAttrs::LastWins => out[pos].1 = value,
+                DuplicateAttrs::Error => {
+                    let (line, column) = self.line_col(span.start);
+                    return Err(ParseError::DuplicateAttribute {
+                        tag: tag.to_string(),
+                        attr: key,
+                        span,
+                        line,
+                        column,
+                    }
+                    .into());
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    /// 中身の無い要素を empty_elements に従って扱う。そのまま残すなら None
+    fn apply_empty_policy(
+        &self,
+        spec: &TagSpec,
+        elem: &Element,
+        original: &str,
+    ) -> Option<Vec<Node>> {
+        if !elem.children.is_empty() || spec.keep_empty {
+            return None;
+        }
+        match self.opts.empty_elements {
+            EmptyElements::Keep => None,
+            EmptyElements::Drop => Some(vec![]),
+            EmptyElements::Text => Some(vec![Node::Text {
+                span: elem.span,
+                text: original.to_string(),
+            }]),
+        }
+    }
+
+    /// タグを元のテキストに戻す。strict なら reason のエラーにする
+    fn fallback(
+        &mut self,
+        span: Span,
+        original: String,
+        reason: InvalidTagReason,
+    ) -> Result<Vec<Node>, BbCodeError> {
+        self.fallback_with_fix(span, original, reason, None)
+    }
+
+    /// fallback と同じ。fix はタグとして読めるようにする書き換えの案（lint で示す）
+    fn fallback_with_fix(
+        &mut self,
+        span: Span,
+        original: String,
+        reason: InvalidTagReason,
+        fix: Option<TextEdit>,
+    ) -> Result<Vec<Node>, BbCodeError> {
+        if self.strict {
+            let (line, column) = self.line_col(span.start);
+            return Err(ParseError::InvalidTag {
+                reason,
+                near: original,
+                span,
+                line,
+                column,
+            }
+            .into());
+        }
+        self.fallbacks.push(Fallback { reason, span, fix });
+        Ok(vec![Node::Text {
+            span,
+            text: original,
+        }])
+    }
+
+    /// max_links / link_domains に反する `[url]` に診断を出し、Strip なら中身だけにする
+    /// links はここまでに残したリンクの数
+    fn apply_link_policy(&mut self, nodes: Vec<Node>, links: &mut usize) -> Vec<Node> {
+        let mut out = Vec::with_capacity(nodes.len());
+        for node in nodes {
+            let Node::Element(mut el) = node else {
+                out.push(node);
+                continue;
+            };
+            el.children = self.apply_link_policy(el.children, links);
+            // HTML でリンクにならない `[url]` は数えない
+            let href = (el.name == "url")
+                .then(|| attr_value(&el).or_else(|| single_text_child(&el)))
+                .flatten()
+                .filter(|h| is_valid_url(h));
+            let Some(href) = href else {
+                out.push(Node::Element(el));
+                continue;
+            };
+
+            let denied = url_host(href).filter(|host| !self.opts.link_domains.allows(host));
+            let violation = match denied {
+                Some(domain) => Some(Diagnostic::LinkDomainNotAllowed {
+                    domain,
+                    span: el.span,
+                }),
+                None => self
+                    .opts
+                    .max_links
+                    .filter(|&max| *links >= max)
+                    .map(|max_links| Diagnostic::TooManyLinks {
+                        max_links,
+                        span: el.span,
+                    }),
+            };
+            let strip =
+                violation.is_some() && self.opts.link_policy_action == LinkPolicyAction::Strip;
+            if let Some(diagnostic) = violation {
+                self.diagnostics.push(diagnostic);
+            }
+            if strip {
+                out.extend(el.children);
+            } else {
+                *links += 1;
+                out.push(Node::Element(el));
+            }
+        }
+        out
+    }
+
+    /// 表示が別のリンク先に見える `[url=..]` を知らせる
+    fn check_deceptive_links(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            let Node::Element(el) = node else {
+                continue;
+            };
+            if let Some(link) = deceptive_link_of(el) {
+                self.diagnostics.push(Diagnostic::DeceptiveLink {
+                    shown: link.shown,
+                    actual: link.actual,
+                    lookalike: link.lookalike,
+                    span: el.span,
+                });
+            }
+            self.check_deceptive_links(&el.children);
+        }
+    }
+
+    /// 値属性を spec が受け入れるか。色の値は color_formats で許可した書き方に限る
+    fn accepts_value(&self, spec: &TagSpec, value: Option<&str>) -> bool {
+        spec.accepts_value(value)
+            && (!spec.color_value || value.is_none_or(|v| self.opts.color_formats.accepts(v)))
+    }
+
+    /// 投稿者の権限で使えるタグか確認し、使えなければ知らせる
+    /// false ならフォールバックさせる
+    fn check_permission(&mut self, spec: &TagSpec, name: &str, span: Span) -> bool {
+        if self.opts.author_level >= spec.required_level {
+            return true;
+        }
+        self.diagnostics.push(Diagnostic::PermissionDenied {
+            tag: name.to_string(),
+            required: spec.required_level,
+            span,
+        });
+        false
+    }
+
+    /// `[color]` の色が背景色に対して読みやすいか確認し、足りなければ知らせる
+    /// false なら `[color]` を外させる
+    fn check_contrast(&mut self, elem: &Element) -> bool {
+        let Some(check) = self.opts.color_contrast.as_ref() else {
+            return true;
+        };
+        let color = attr_value(elem).unwrap_or_default();
+        let (Some(fg), Some(bg)) = (color_to_rgb(color), color_to_rgb(&check.background)) else {
+            return true;
+        };
+        if contrast_ratio(fg, bg) >= check.min_ratio {
+            return true;
+        }
+        self.diagnostics.push(Diagnostic::LowContrast {
+            color: color.trim().to_string(),
+            background: check.background.clone(),
+            span: elem.span,
+        });
+        check.action == ContrastAction::Warn
+    }
+
+    /// 廃止予定のタグなら知らせる
+    fn check_deprecated(&mut self, spec: &TagSpec, elem: &Element) {
+        if spec.deprecated {
+            self.diagnostics.push(Diagnostic::Deprecated {
+                tag: elem.name.to_string(),
+                replacement: spec.replacement.clone(),
+                span: elem.span,
+            });
+        }
+    }
+
+    fn build_nodes(&mut self, raw: Raw, depth: usize) -> Result<Vec<Node>, BbCodeError> {
+        match raw {
+            Raw::Code {
+                span,
+                open_name,
+                close_name,
+                value,
+                body,
+            } => {
+                self.check_depth(depth, span)?;
+                self.on_tag()?;
+
+                let original = self.slice(span)?.to_string(); // フォールバック用
+
+                if self.opts.case_sensitive_tags && open_name != close_name {
+                    let fix = TextEdit {
+                        span: Span {
+                            start: body.end,
+                            end: span.end,
+                        },
+                        replacement: self.close_tag(&open_name),
+                    };
+                    return self.fallback_with_fix(
+                        span,
+                        original,
+                        InvalidTagReason::MismatchedClose,
+                        Some(fix),
+                    );
+                }
+
+                // 方言などで [code] が無効化されていれば丸ごとテキストへ
+                let Some(spec) = self.opts.registry.get("code") else {
+                    return self.fallback(span, original, InvalidTagReason::UnknownTag);
+                };
+                let value = value.map(|v| self.opts.attr_whitespace.apply(v));
+                if !self.accepts_value(spec, value.as_deref()) {
+                    return self.fallback(span, original, InvalidTagReason::InvalidValue);
+                }
+                if !self.check_permission(spec, &open_name, span) {
+                    return self.fallback(span, original, InvalidTagReason::PermissionDenied);
+                }
+
+                // 中身は解釈せずそのまま 1つの Text にする
+                let mut elem = Element::new("code", span);
+                if body.start < body.end {
+                    elem.children.push(Node::Text {
+                        span: body,
+                        text: self.slice(body)?.to_string(),
+                    });
+                }
+                if let Some(val) = value {
+                    elem.attrs.push(("value".to_string(), val));
+                }
+                if let Some(nodes) = self.apply_empty_policy(spec, &elem, &original) {
+                    return Ok(nodes);
+                }
+                self.check_deprecated(spec, &elem);
+
+                Ok(vec![Node::Element(elem)])
+            }
+
+            Raw::Block {
+                span,
+                open,
+                children: content,
+                close_name,
+                close_span,
+            } => {
+                self.check_depth(depth, span)?;
+                self.on_tag()?;
+
+                let original = self.fallback_text(span, &content)?; // フォールバック用
+
+                let open_name = open.name;
+                let open_name_lc = open_name.to_ascii_lowercase();
+                let ws = self.opts.attr_whitespace;
+                let value_attr = open.value.map(|v| ws.apply(v));
+                let named_attrs: Vec<_> = open
+                    .named
+                    .into_iter()
+                    .map(|(k, v)| (k, ws.apply(v)))
+                    .collect();
+                let open_span = open.span;
+
+                let close_name_lc = close_name.to_ascii_lowercase();
+                // `[/]` の場合は閉じタグ名が無い
+                let universal = close_name.is_empty();
+
+                // 別名は正規名に解決してから比較する（[strike]..[/s] なども一致扱い）
+                let registry = &self.opts.registry;
+                let open_key = registry.canonical_name(&open_name_lc);
+                let close_key = if universal && self.opts.universal_close {
+                    open_key.clone()
+                } else {
+                    registry.canonical_name(&close_name_lc)
+                };
+
+                // `[/]` が想定外のタグを閉じた場合は知らせる
+                if universal && self.opts.universal_close && registry.get(&open_key).is_none() {
+                    self.diagnostics.push(Diagnostic::UniversalCloseMismatch {
+                        closed: open_name.clone(),
+                        span,
+                    });
+                }
+
+                // case_sensitive_tags なら表記まで一致していること
+                let case_mismatch = self.opts.case_sensitive_tags
+                    && open_name != close_name
+                    && open_name_lc == close_name_lc;
+
+                // タグ不整合は「その部分を丸ごとテキストへ」(構造を壊さない方針)
+                if open_key != close_key || case_mismatch {
+                    // 開始タグが登録済みのタグなら閉じタグをそれに合わせ、
+                    // `[1]` のような未知の名前なら閉じタグを横取りしないよう開始タグをエスケープする案を出す
+                    // 内側のタグがこのタグの閉じタグで閉じていれば（交差）、内側から順に閉じ直す
+                    let crossed = match registry.get(&open_key) {
+                        Some(_) if open_key != close_key => {
+                            self.crossed_close_fix(&open_name, &open_key, &content, close_span)?
+                        }
+                        _ => None,
+                    };
+                    let fix = match (crossed, registry.get(&open_key)) {
+                        (Some((fix, inner)), _) => {
+                            // 閉じ損ねた内側のタグも、それ自体の問題として知らせる
+                            self.fallbacks.push(Fallback {
+                                reason: InvalidTagReason::MismatchedClose,
+                                span: inner,
+                                fix: None,
+                            });
+                            fix
+                        }
+                        (None, Some(_)) => TextEdit {
+                            span: close_span,
+                            replacement: self.close_tag(&open_name),
+                        },
+                        (None, None) => self.escape_at(open_span.start),
+                    };
+                    return self.fallback_with_fix(
+                        span,
+                        original,
+                        InvalidTagReason::MismatchedClose,
+                        Some(fix),
+                    );
+                }
+
+                // TagSpec に従って属性を許可・検証する
+                // unknown tag は BBCode として扱わない
+                let spec = match registry.get(&open_key) {
+                    Some(s) => s.clone(),
+                    None => {
+                        let mut fix = None;
+                        // `[/]` で閉じたものは UniversalCloseMismatch で知らせ済み
+                        if !(universal && self.opts.universal_close) {
+                            let suggestion = registry.suggest(&open_name).map(str::to_string);
+                            // 近い名前があれば開始タグ・閉じタグの名前をそれに置き換える案を出す
+                            if let Some(to) = &suggestion {
+                                let name_start = open_span.start + 1;
+                                let pieces = [
+                                    (name_start, name_start + open_name.len()),
+                                    (close_span.start + 2, close_span.end - 1),
+                                ];
+                                let pieces =
+                                    pieces.map(|(start, end)| (Span { start, end }, to.as_str()));
+                                let replacement = self.splice(span, pieces)?;
+                                fix = Some(TextEdit { span, replacement });
+                            }
+                            self.diagnostics.push(Diagnostic::UnknownTag {
+                                suggestion,
+                                tag: open_name,
+                                span,
+                            });
+                        }
+                        // unknown tag は丸ごとテキストへ（中身も含めて構造化しない）
+                        return self.fallback_with_fix(
+                            span,
+                            original,
+                            InvalidTagReason::UnknownTag,
+                            fix,
+                        );
+                    }
+                };
+
+                // 権限の足りないタグは中身も含めて丸ごとテキストへ
+                if !self.check_permission(&spec, &open_name, span) {
+                    return self.fallback(span, original, InvalidTagReason::PermissionDenied);
+                }
+
+                // 子要素を再帰で構築
+                // `[list]` のように項目タグを持つものは `[*]` ごとに子をまとめる
+                let children = if spec.raw_content {
+                    // 中身は解釈せず、開始タグの直後から最後の子の終わりまでを1つの Text にする
+                    let body = Span {
+                        start: open_span.end,
+                        end: content.last().map_or(open_span.end, |r| r.span().end),
+                    };
+                    if body.start < body.end {
+                        vec![Node::Text {
+                            span: body,
+                            text: self.slice(body)?.to_string(),
+                        }]
+                    } else {
+                        vec![]
+                    }
+                } else if spec.implicit_items {
+                    self.build_items(content, depth + 1)?
+                } else {
+                    let mut children = vec![];
+                    for raw in content {
+                        children.extend(self.build_nodes(raw, depth + 1)?);
+                    }
+                    children
+                };
+                let children = self.enforce_parent_constraints(Some(&open_key), children)?;
+                self.check_children(&children, span)?;
+
+                // 値属性が許可されていない / 検証に失敗 -> フォールバック
+                if !self.accepts_value(&spec, value_attr.as_deref()) {
+                    return self.fallback(span, original, InvalidTagReason::InvalidValue);
+                }
+
+                let named_attrs = self.dedupe_attrs(&open_name, named_attrs, open_span)?;
+
+                // 許可されていない名前付き属性 -> フォールバック
+                if named_attrs
+                    .iter()
+                    .any(|(k, _)| !spec.named_attrs.iter().any(|a| a == k))
+                {
+                    return self.fallback(span, original, InvalidTagReason::InvalidAttribute);
+                }
+
+                let mut elem = Element::new(open_key, span).with_children(children);
+
+                if let Some(val) = value_attr {
+                    match spec.split_value_attr {
+                        // `[quote=Alice;123]` のような複合値は分解して格納
+                        Some(splitter) => match splitter(&val) {
+                            Some(attrs) => elem.attrs.extend(attrs),
+                            None => {
+                                return self.fallback(
+                                    span,
+                                    original,
+                                    InvalidTagReason::InvalidValue,
+                                );
+                            }
+                        },
+                        // `[color=red]` を attrs=[("value","red")] に正規化
+                        None => elem.attrs.push(("value".to_string(), val)),
+                    }
+                }
+                elem.attrs.extend(named_attrs);
+
+                // 要素全体の検証（[url] / [img] の中身など）
+                if let Some(validate) = spec.validate_element {
+                    if !validate(&elem) {
+                        return self.fallback(span, original, InvalidTagReason::InvalidContent);
+                    }
+                }
+
+                // `[email]` はリンクの数・ドメインの制限や出力を `[url]` と共通にする
+                if elem.name == "email" {
+                    elem = email_to_url(elem);
+                }
+
+                // インライン要素の中のブロック要素
+                if !self.check_content_model(&spec, &elem) {
+                    return self.fallback(span, original, InvalidTagReason::NotAllowedHere);
+                }
+                if spec.same_tag_nesting != SameTagNesting::Allow {
+                    let children = std::mem::take(&mut elem.children);
+                    elem.children =
+                        self.apply_same_tag_nesting(spec.same_tag_nesting, &elem, children)?;
+                }
+                if let Some(nodes) = self.apply_empty_policy(&spec, &elem, &original) {
+                    return Ok(nodes);
+                }
+                self.check_deprecated(&spec, &elem);
+                if elem.name == "html" {
+                    self.diagnostics.push(Diagnostic::RawHtml { span });
+                }
+                if elem.name == "color" && !self.check_contrast(&elem) {
+                    return Ok(elem.children);
+                }
+
+                Ok(vec![Node::Element(elem)])
+            }
+
+            Raw::Item {
+                span,
+                children: content,
+            } => {
+                self.check_depth(depth, span)?;
+                self.on_tag()?;
+
+                // 方言などで `[*]..[/*]` が無効なら丸ごとテキストへ
+                if self.opts.registry.get("*").is_none() {
+                    let original = self.fallback_text(span, &content)?;
+                    return self.fallback(span, original, InvalidTagReason::UnknownTag);
+                }
+
+                let mut children = vec![];
+                for raw in content {
+                    children.extend(self.build_nodes(raw, depth + 1)?);
+                }
+                let children = self.enforce_parent_constraints(Some("*"), children)?;
+                self.check_children(&children, span)?;
+                Ok(vec![Node::Element(
+                    Element::new("*", span).with_children(children),
+                )])
+            }
+
+            Raw::Unclosed {
+                span,
+                name,
+                close_at,
+            } => {
+                // 開始タグのみで閉じタグがないケースはその部分を丸ごとテキストへ
+                // DoS耐性としてタグ数制限の対象に含める
+                self.on_tag()?;
+                let original = self.slice(span)?.to_string();
+                // 登録済みのタグなら閉じタグを補う案を出す（入れ子の外側のタグが閉じる位置の手前）
+                // `[1]` のような本文中の括弧はタグにせず、後から補う閉じタグを横取りしないよう
+                // エスケープする案を出す（表示は変わらない）
+                let registry = &self.opts.registry;
+                let fix = match registry.get(&registry.canonical_name(&name)) {
+                    Some(_) => {
+                        let at = close_at.unwrap_or(self.input.len());
+                        TextEdit {
+                            span: Span { start: at, end: at },
+                            replacement: self.close_tag(&name),
+                        }
+                    }
+                    None => self.escape_at(span.start),
+                };
+                self.fallback_with_fix(span, original, InvalidTagReason::Unclosed, Some(fix))
+            }
+
+            Raw::Marker { span } => {
+                self.on_tag()?;
+                let original = self.slice(span)?.to_string();
+                self.fallback(span, original, InvalidTagReason::Unclosed)
+            }
+
+            Raw::Escaped { span } => Ok(vec![Node::Text {
+                span,
+                text: "[".to_string(),
+            }]),
+
+            Raw::Text { span } => Ok(vec![Node::Text {
+                span,
+                text: self.slice(span)?.to_string(),
+            }]),
+        }
+    }
+
+    /// 閉じタグの無い `[*]` を区切りとして、後続の内容を項目要素の子にまとめる
+    fn build_items(&mut self, content: Vec<Raw>, depth: usize) -> Result<Vec<Node>, BbCodeError> {
+        let mut out = vec![];
+        let mut current: Option<Element> = None;
+
+        for raw in content {
+            if let Raw::Marker { span } = raw {
+                self.check_depth(depth, span)?;
+                self.on_tag()?;
+                if let Some(item) = current.take() {
+                    self.check_children(&item.children, item.span)?;
+                    out.push(Node::Element(item));
+                }
+                current = Some(Element::new("*", span));
+                continue;
+            }
+
+            let end = raw.span().end;
+            let nodes = self.build_nodes(raw, depth + 1)?;
+            match current.as_mut() {
+                Some(item) => {
+                    item.children.extend(nodes);
+                    item.span.end = end;
+                }
+                // 最初の `[*]` より前の内容はそのまま残す
+                None => out.extend(nodes),
+            }
+        }
+        if let Some(item) = current {
+            self.check_children(&item.children, item.span)?;
+            out.push(Node::Element(item));
+        }
+
+        Ok(out)
+    }
+}
+
+/// 公開API：入力文字列をASTにパース
+pub fn parse_bbcode_to_ast(input: &str, opts: &BbCodeOptions) -> Result<Vec<Node>, BbCodeError> {
+    parse_bbcode_with_diagnostics(input, opts).map(|(nodes, _)| nodes)
+}
+
+/// 公開API：入力文字列をASTにパースし、診断情報もあわせて返す
+/// 字句解析・タグの対応付け・AST 構築のいずれも入力長に対して線形時間
+pub fn parse_bbcode_with_diagnostics(
+    input: &str,
+    opts: &BbCodeOptions,
+) -> Result<(Vec<Node>, Vec<Diagnostic>), BbCodeError> {
+    parse_in_context(input, opts, &FragmentContext::default(), opts.strict).map(Parsed::into_parts)
+}
+
+/// 公開API：投稿の一部分を、context の親タグの中にあるものとして検証する
+/// 親タグの制約（`[list]` の項目、allowed_children / required_parent、深さ）も適用する。
+/// 親タグ自体は断片の外にあるので、インライン要素の中のブロック要素は
+/// block_in_inline が Allow 以外なら診断として知らせるだけにする
+pub fn validate_fragment(
+    input: &str,
+    opts: &BbCodeOptions,
+    context: FragmentContext,
+) -> Result<(Vec<Node>, Vec<Diagnostic>), BbCodeError> {
+    parse_in_context(input, opts, &context, opts.strict).map(Parsed::into_parts)
+}
+
+/// パースの結果と、その途中で数えたもの（集計用）
+pub(crate) struct Parsed {
+    pub nodes: Vec<Node>,
+    pub diagnostics: Vec<Diagnostic>,
+    /// テキストに戻したタグ（未知のタグ・不正な値・閉じていないタグなど）
+    pub fallbacks: Vec<Fallback>,
+}
+
+/// テキストに戻したタグ1つ
+pub(crate) struct Fallback {
+    pub reason: InvalidTagReason,
+    pub span: Span,
+    pub fix: Option<TextEdit>,
+}
+
+impl Parsed {
+    fn into_parts(self) -> (Vec<Node>, Vec<Diagnostic>) {
+        (self.nodes, self.diagnostics)
+    }
+}
+
+/// parse_bbcode_with_diagnostics と同じくパースし、テキストに戻したタグの数も返す
+pub(crate) fn parse_counting_fallbacks(
+    input: &str,
+    opts: &BbCodeOptions,
+) -> Result<Parsed, BbCodeError> {
+    parse_in_context(input, opts, &FragmentContext::default(), opts.strict)
+}
+
+/// opts.strict に関わらず、テキストに戻すタグをエラーにせず最後までパースする（lint 用）
+pub(crate) fn parse_lenient(input: &str, opts: &BbCodeOptions) -> Result<Parsed, BbCodeError> {
+    parse_in_context(input, opts, &FragmentContext::default(), false)
+}
+
+fn parse_in_context(
+    input: &str,
+    opts: &BbCodeOptions,
+    context: &FragmentContext,
+    strict: bool,
+) -> Result<Parsed, BbCodeError> {
+    check_input_size(input, opts)?;
+    let preprocessed = opts.preprocess.map(|f| f(input));
+    let input = match preprocessed.as_deref() {
+        Some(preprocessed) => {
+            check_input_size(preprocessed, opts)?;
+            preprocessed
+        }
+        None => input,
+    };
+    let input = normalize_input(input, opts);
+    let input = input.as_ref();
+
+    let mut tokens = match opts.backend {
+        ParserBackend::Pest => tokenize(input)?,
+        #[cfg(feature = "fast-parser")]
+        ParserBackend::Fast => crate::parser::fast::tokenize(input)
+            .map_err(|pos| syntax_error(input, pos, "Unexpected opening bracket"))?,
+    };
+    restore_syntax_chars_in_tokens(&mut tokens, opts);
+    let tree = build_tree(tokens)
+        .map_err(|pos| syntax_error(input, pos, "Closing tag does not close any open tag"))?;
+    let mut ctx = BuildAstContext::new(opts, input);
+    ctx.strict = strict;
+
+    let parent = context
+        .parent
+        .as_deref()
+        .map(|p| opts.registry.canonical_name(&p.to_ascii_lowercase()));
+    let parent_spec = parent.as_deref().and_then(|p| opts.registry.get(p));
+
+    let nodes = if parent_spec.is_some_and(|s| s.implicit_items) {
+        ctx.build_items(tree, context.depth)?
+    } else {
+        let mut nodes = vec![];
+        for raw in tree {
+            nodes.extend(ctx.build_nodes(raw, context.depth)?);
+        }
+        nodes
+    };
+    let mut nodes = ctx.enforce_parent_constraints(parent.as_deref(), nodes)?;
+    restore_syntax_chars(&mut nodes, opts);
+    if let (Some(parent), Some(spec)) = (parent.as_deref(), parent_spec) {
+        ctx.check_fragment_content_model(parent, spec, &nodes);
+    }
+
+    let nodes = if opts.max_links.is_some() || opts.link_domains != DomainPolicy::AllowAll {
+        ctx.apply_link_policy(nodes, &mut 0)
+    } else {
+        nodes
+    };
+    ctx.check_deceptive_links(&nodes);
+    let mut nodes = if opts.merge_adjacent_text {
+        normalize_text_nodes(nodes)
+    } else {
+        nodes
+    };
+    for pass in &opts.ast_passes {
+        nodes = pass.run(nodes, &mut ctx.diagnostics);
+    }
+    Ok(Parsed {
+        nodes: finish(nodes, opts)?,
+        diagnostics: ctx.diagnostics,
+        fallbacks: ctx.fallbacks,
+    })
+}
+
+/// 装飾の無いテキストとして1つの Text ノードにする
+/// `[` もそのまま文字として扱い、ハッシュタグ・改行・長さの制限は BBCode と同じく適用する
+pub(crate) fn parse_plain_text(
+    input: &str,
+    opts: &BbCodeOptions,
+) -> Result<Vec<Node>, BbCodeError> {
+    check_input_size(input, opts)?;
+    let input = normalize_input(input, opts);
+    let mut nodes = if input.is_empty() {
+        vec![]
+    } else {
+        vec![Node::Text {
+            span: Span {
+                start: 0,
+                end: input.len(),
+            },
+            text: input.into_owned(),
+        }]
+    };
+    restore_syntax_chars(&mut nodes, opts);
+    finish(nodes, opts)
+}
+
+fn check_input_size(input: &str, opts: &BbCodeOptions) -> Result<(), BbCodeError> {
+    if input.len() > opts.max_input_size {
+        return Err(LimitError::InputSizeExceeded {
+            max_size: opts.max_input_size,
+            actual_size: input.len(),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// content の中の `\[` の位置（位置順）
+fn collect_escapes(content: &[Raw], out: &mut Vec<Span>) {
+    for raw in content {
+        match raw {
+            Raw::Escaped { span } => out.push(*span),
+            Raw::Block { children, .. } | Raw::Item { children, .. } => {
+                collect_escapes(children, out)
+            }
+            _ => {}
+        }
+    }
+}
+
+/// AST 構築後の共通の後処理と、テキスト長の制限
+fn finish(mut nodes: Vec<Node>, opts: &BbCodeOptions) -> Result<Vec<Node>, BbCodeError> {
+    if opts.detect_hashtags && opts.registry.get("tag").is_some() {
+        nodes = detect_hashtags_in(nodes);
+    }
+    if let Some(max) = opts.max_consecutive_newlines {
+        squash_newlines_in(&mut nodes, max);
+    }
+    if opts.normalize_nfc {
+        normalize_nfc_in(&mut nodes);
+    }
+
+    // 入力サイズとは別に、展開後の論理テキスト長を制限する
+    let text_len = total_text_len(&nodes);
+    if text_len > opts.max_text_len {
+        return Err(LimitError::TextLengthExceeded {
+            max_len: opts.max_text_len,
+            actual_len: text_len,
+        }
+        .into());
+    }
+    Ok(nodes)
+}
+
+/// 検証済みの `[email]` を宛先に `mailto:` を付けた `[url]` にする
+fn email_to_url(mut el: Element) -> Element {
+    let address = attr_value(&el)
+        .or_else(|| single_text_child(&el))
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+    el.name = TagName::new("url");
+    el.attrs.retain(|(k, _)| k != "value");
+    el.attrs
+        .insert(0, ("value".to_string(), format!("mailto:{address}")));
+    el
+}
+
+/// Text ノード中の `#topic` を `tag` 要素に切り出す
+/// リンクやコードの中、既存の `[tag]` の中は対象外
+fn detect_hashtags_in(nodes: Vec<Node>) -> Vec<Node> {
+    let mut out = Vec::with_capacity(nodes.len());
+    for n in nodes {
+        match n {
+            Node::Text { span, text } => split_hashtags(span, &text, &mut out),
+            Node::Element(el) if matches!(el.name.as_str(), "code" | "url" | "img" | "tag") => {
+                out.push(Node::Element(el));
+            }
+            Node::Element(mut el) => {
+                el.children = detect_hashtags_in(el.children);
+                out.push(Node::Element(el));
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+fn split_hashtags(span: Span, text: &str, out: &mut Vec<Node>) {
+    // エスケープ等でテキストと入力がずれている場合は部分範囲を出せないのでノード全体の範囲を使う
+    let exact = span.end - span.start == text.len();
+    let sub_span = |start: usize, end: usize| {
+        if exact {
+            Span {
+                start: span.start + start,
+                end: span.start + end,
+            }
+        } else {
+            span
+        }
+    };
+
+    let mut last = 0;
+    let mut prev: Option<char> = None;
+    let mut iter = text.char_indices().peekable();
+    while let Some((i, c)) = iter.next() {
+        // `&#123;` や `a#b`、URL 断片の `/#x` はハッシュタグとみなさない
+        let boundary = prev.is_none_or(|p| !(p.is_alphanumeric() || "_&#/".contains(p)));
+        prev = Some(c);
+        if c != '#' || !boundary {
+            continue;
+        }
+        let mut end = i + 1;
+        while let Some(&(j, n)) = iter.peek() {
+            if !(n.is_alphanumeric() || n == '_') {
+                break;
+            }
+            end = j + n.len_utf8();
+            prev = Some(n);
+            iter.next();
+        }
+        let topic = &text[i + 1..end];
+        // `#1` のような番号は除外
+        if topic.is_empty() || topic.chars().all(|c| c.is_ascii_digit()) {
+            continue;
+        }
+        if last < i {
+            out.push(Node::Text {
+                span: sub_span(last, i),
+                text: text[last..i].to_string(),
+            });
+        }
+        let tag_span = sub_span(i, end);
+        out.push(Node::Element(Element {
+            span: tag_span,
+            name: TagName::new("tag"),
+            attrs: vec![("value".to_string(), topic.to_string())],
+            children: vec![Node::Text {
+                span: tag_span,
+                text: text[i..end].to_string(),
+            }],
+        }));
+        last = end;
+    }
+    if last < text.len() || last == 0 {
+        out.push(Node::Text {
+            span: sub_span(last, text.len()),
+            text: text[last..].to_string(),
+        });
+    }
+}
+
+/// Text ノード内の連続改行を max 個までに詰める（verbatim な `[code]` と空白を残す `[pre]` は除く）
+/// span は入力上の範囲なので、text だけを書き換える（NFC 正規化などと同じ）
+fn squash_newlines_in(nodes: &mut [Node], max: usize) {
+    for n in nodes {
+        match n {
+            Node::Text { text, .. } => *text = squash_newlines(text, max),
+            Node::Element(el) if matches!(el.name.as_str(), "code" | "pre") => {}
+            Node::Element(el) => squash_newlines_in(&mut el.children, max),
+            Node::Unsupported(_) => {}
+        }
+    }
+}
+
+/// 改行の連続（間に挟まる空白・タブも含めて空行とみなす）を max 個までにする
+fn squash_newlines(text: &str, max: usize) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut run = 0;
+    let mut pending = String::new(); // 改行の間の空白
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\r' | '\n' => {
+                let crlf = c == '\r' && chars.peek() == Some(&'\n');
+                if crlf {
+                    chars.next();
+                }
+                run += 1;
+                if run <= max {
+                    out.push_str(&pending);
+                    out.push_str(if crlf {
+                        "\r\n"
+                    } else if c == '\r' {
+                        "\r"
+                    } else {
+                        "\n"
+                    });
+                }
+                pending.clear();
+            }
+            ' ' | '\t' if run > 0 => pending.push(c),
+            _ => {
+                out.push_str(&pending);
+                pending.clear();
+                run = 0;
+                out.push(c);
+            }
+        }
+    }
+    out.push_str(&pending);
+    out
+}
+
+/// Text ノードの長さ（バイト）を再帰的に合計する
+fn total_text_len(nodes: &[Node]) -> usize {
+    nodes
+        .iter()
+        .map(|n| match n {
+            Node::Text { text, .. } => text.len(),
+            Node::Element(el) => total_text_len(&el.children),
+            Node::Unsupported(_) => 0,
+        })
+        .sum()
+}
+
+/// 隣接 Text をマージして扱いやすくする
+fn normalize_text_nodes(nodes: Vec<Node>) -> Vec<Node> {
+    let mut normalized: Vec<Node> = Vec::with_capacity(nodes.len());
+
+    for n in nodes {
+        match n {
+            Node::Text { .. } | Node::Unsupported(_) => normalized.push(n),
+            Node::Element(mut el) => {
+                el.children = normalize_text_nodes(el.children);
+                normalized.push(Node::Element(el));
+            }
+        }
+    }
+
+    let mut out: Vec<Node> = Vec::with_capacity(normalized.len());
+    for n in normalized {
+        match (out.last_mut(), n) {
+            (
+                Some(Node::Text {
+                    span: prev_span,
+                    text: prev_text,
+                }),
+                Node::Text {
+                    span: cur_span,
+                    text: cur_text,
+                },
+            ) => {
+                prev_text.push_str(&cur_text);
+                prev_span.end = cur_span.end;
+            }
+            (_, other) => out.push(other),
+        }
+    }
+
+    out
+}
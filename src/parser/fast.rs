@@ -0,0 +1,322 @@
+// bbcode.pest と同じ文法を手書きした字句解析器（fast-parser フィーチャ）
+// 各関数が文法の規則に対応し、PEG と同じ順序で選択肢を試す。
+// 区切り文字はすべて ASCII なのでバイト単位で走査しても UTF-8 の境界を壊さない
+
+use crate::ast::Span;
+use crate::parser::tree::{attr_value_of, OpenTag, Token};
+
+/// 入力をトークン列に分解する。どのトークンにもならない `[` があればその位置を返す
+pub(crate) fn tokenize(input: &str) -> Result<Vec<Token>, usize> {
+    let mut lexer = Lexer {
+        input,
+        bytes: input.as_bytes(),
+        tokens: vec![],
+        allow_code: true,
+    };
+    lexer.run()?;
+    Ok(lexer.tokens)
+}
+
+fn span(start: usize, end: usize) -> Span {
+    Span { start, end }
+}
+
+/// 属性値の範囲と、クォートで囲まれているか
+#[derive(Clone, Copy)]
+struct AttrValue {
+    start: usize,
+    end: usize,
+    quoted: bool,
+}
+
+struct Lexer<'a> {
+    input: &'a str,
+    bytes: &'a [u8],
+    tokens: Vec<Token>,
+    /// 閉じタグの無い [code] を見つけた後は、以降の [code] を通常のタグとして読む（BBCodeNoCode）
+    allow_code: bool,
+}
+
+impl<'a> Lexer<'a> {
+    fn run(&mut self) -> Result<(), usize> {
+        let mut pos = 0;
+        while pos < self.bytes.len() {
+            pos = self.token(pos)?;
+        }
+        Ok(())
+    }
+
+    fn at(&self, pos: usize) -> Option<u8> {
+        self.bytes.get(pos).copied()
+    }
+
+    fn starts_with(&self, pos: usize, s: &str) -> bool {
+        self.bytes[pos..].starts_with(s.as_bytes())
+    }
+
+    /// token / token_no_code。次のトークンの終端を返す
+    fn token(&mut self, pos: usize) -> Result<usize, usize> {
+        if self.at(pos) == Some(b'[') {
+            if self.allow_code {
+                if let Some(end) = self.code_block(pos) {
+                    return Ok(end);
+                }
+            }
+            if self.starts_with(pos, "[*]") {
+                self.push(Token::ItemOpen {
+                    span: span(pos, pos + 3),
+                });
+                return Ok(pos + 3);
+            }
+            if self.starts_with(pos, "[/*]") {
+                self.push(Token::ItemClose {
+                    span: span(pos, pos + 4),
+                });
+                return Ok(pos + 4);
+            }
+            if let Some(end) = self.close_tag(pos) {
+                return Ok(end);
+            }
+            if let Some(end) = self.open_tag(pos) {
+                return Ok(end);
+            }
+            return Err(pos);
+        }
+        if self.starts_with(pos, "\\[") {
+            self.push(Token::Escaped {
+                span: span(pos, pos + 2),
+            });
+            return Ok(pos + 2);
+        }
+        // text: `[` / `\[` の手前まで
+        let end = self.bytes[pos..]
+            .iter()
+            .position(|&b| b == b'[')
+            .map_or(self.bytes.len(), |n| pos + n);
+        let end = if end < self.bytes.len() && self.bytes[end - 1] == b'\\' {
+            end - 1
+        } else {
+            end
+        };
+        self.push(Token::Text {
+            span: span(pos, end),
+        });
+        Ok(end)
+    }
+
+    fn push(&mut self, token: Token) {
+        self.tokens.push(token);
+    }
+
+    /// code_block
+    fn code_block(&mut self, pos: usize) -> Option<usize> {
+        let name_end = self.code_tag_name(pos + 1)?;
+        let (attr, mut p) = match self.tag_attr(name_end) {
+            Some((attr, end)) => (Some(attr), end),
+            None => (None, name_end),
+        };
+        if self.at(p) != Some(b']') {
+            return None;
+        }
+        p += 1;
+
+        let value = attr.map(|a| self.attr_value(a));
+        let open_name = self.input[pos + 1..name_end].to_string();
+        let body_start = p;
+        while p < self.bytes.len() && !self.is_code_close(p) {
+            p += 1;
+        }
+        let body = span(body_start, p);
+
+        if p == self.bytes.len() {
+            // 閉じタグが無ければ通常の開始タグとして扱い、後ろを読み直す
+            self.push(Token::Open(OpenTag {
+                span: span(pos, body_start),
+                name: open_name,
+                value,
+                named: vec![],
+            }));
+            self.allow_code = false;
+            return Some(body_start);
+        }
+
+        let close_name = self.input[p + 2..p + 6].to_string();
+        let end = p + 7;
+        self.push(Token::Code {
+            span: span(pos, end),
+            open_name,
+            close_name,
+            value,
+            body,
+        });
+        Some(end)
+    }
+
+    /// code_tag_name: 大文字・小文字を区別しない `code` の直後に `=` か `]`
+    fn code_tag_name(&self, pos: usize) -> Option<usize> {
+        let name = self.bytes.get(pos..pos + 4)?;
+        if !name.eq_ignore_ascii_case(b"code") {
+            return None;
+        }
+        matches!(self.at(pos + 4), Some(b'=' | b']')).then_some(pos + 4)
+    }
+
+    /// `[/code]`（大文字・小文字を区別しない）
+    fn is_code_close(&self, pos: usize) -> bool {
+        self.starts_with(pos, "[/")
+            && self
+                .bytes
+                .get(pos + 2..pos + 6)
+                .is_some_and(|n| n.eq_ignore_ascii_case(b"code"))
+            && self.at(pos + 6) == Some(b']')
+    }
+
+    /// close_tag: `[/name]` または `[/]`
+    fn close_tag(&mut self, pos: usize) -> Option<usize> {
+        if !self.starts_with(pos, "[/") {
+            return None;
+        }
+        let name_start = pos + 2;
+        let mut p = name_start;
+        if !self.starts_with(p, "*]") {
+            while let Some(b) = self.at(p) {
+                if matches!(b, b']' | b' ' | b'\t' | b'\n' | b'\r') {
+                    break;
+                }
+                p += 1;
+            }
+        }
+        if self.at(p) != Some(b']') {
+            return None;
+        }
+        self.push(Token::Close {
+            span: span(pos, p + 1),
+            name: self.input[name_start..p].to_string(),
+        });
+        Some(p + 1)
+    }
+
+    /// open_tag: `[name=value key=val]`
+    fn open_tag(&mut self, pos: usize) -> Option<usize> {
+        let name_start = pos + 1;
+        if self.starts_with(name_start, "*]") {
+            return None;
+        }
+        let mut p = name_start;
+        while let Some(b) = self.at(p) {
+            if matches!(b, b'=' | b']' | b'/' | b' ' | b'\t' | b'\n' | b'\r') {
+                break;
+            }
+            p += 1;
+        }
+        if p == name_start {
+            return None;
+        }
+        let name_end = p;
+
+        let attr = self.tag_attr(p).map(|(attr, end)| {
+            p = end;
+            attr
+        });
+        let mut named = vec![];
+        while let Some((key, value, end)) = self.named_attr(p) {
+            named.push((key, value));
+            p = end;
+        }
+        if self.at(p) != Some(b']') {
+            return None;
+        }
+
+        let named = named
+            .into_iter()
+            .map(|((ks, ke), v)| (self.input[ks..ke].to_ascii_lowercase(), self.attr_value(v)))
+            .collect();
+        self.push(Token::Open(OpenTag {
+            span: span(pos, p + 1),
+            name: self.input[name_start..name_end].to_string(),
+            value: attr.map(|a| self.attr_value(a)),
+            named,
+        }));
+        Some(p + 1)
+    }
+
+    /// tag_attr: `=` に続く quoted_attr（後ろが名前付き属性と `]` の場合のみ）か bare_attr
+    fn tag_attr(&self, pos: usize) -> Option<(AttrValue, usize)> {
+        if self.at(pos) != Some(b'=') {
+            return None;
+        }
+        let start = pos + 1;
+        if let Some(end) = self.quoted_attr(start) {
+            let mut p = end;
+            while let Some((_, _, next)) = self.named_attr(p) {
+                p = next;
+            }
+            if self.at(p) == Some(b']') {
+                let attr = AttrValue {
+                    start,
+                    end,
+                    quoted: true,
+                };
+                return Some((attr, end));
+            }
+        }
+        // bare_attr: `]` の手前まで（空でもよい）
+        let end = self.scan_until(start, |b| b == b']');
+        let attr = AttrValue {
+            start,
+            end,
+            quoted: false,
+        };
+        Some((attr, end))
+    }
+
+    /// named_attr: 空白 + `key=value`。キーの範囲・値・終端を返す
+    fn named_attr(&self, pos: usize) -> Option<((usize, usize), AttrValue, usize)> {
+        let key_start = self.scan_until(pos, |b| !matches!(b, b' ' | b'\t'));
+        if key_start == pos {
+            return None;
+        }
+        let key_end = self.scan_until(key_start, |b| {
+            !(b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+        });
+        if key_end == key_start || self.at(key_end) != Some(b'=') {
+            return None;
+        }
+        let start = key_end + 1;
+        let (end, quoted) = match self.quoted_attr(start) {
+            Some(end) => (end, true),
+            // named_bare_attr: `]` と空白の手前まで（空でもよい）
+            None => (
+                self.scan_until(start, |b| matches!(b, b']' | b' ' | b'\t')),
+                false,
+            ),
+        };
+        let value = AttrValue { start, end, quoted };
+        Some(((key_start, key_end), value, end))
+    }
+
+    /// quoted_attr: `"..."` / `'...'`。`\` の次の1文字は閉じクォートとみなさない
+    fn quoted_attr(&self, pos: usize) -> Option<usize> {
+        let quote = self.at(pos).filter(|&b| b == b'"' || b == b'\'')?;
+        let mut p = pos + 1;
+        loop {
+            match self.at(p)? {
+                b'\\' if p + 1 < self.bytes.len() => p += 2,
+                b if b == quote => return Some(p + 1),
+                _ => p += 1,
+            }
+        }
+    }
+
+    /// pos から pred を満たすバイトの手前まで進めた位置
+    fn scan_until(&self, pos: usize, pred: impl Fn(u8) -> bool) -> usize {
+        self.bytes[pos..]
+            .iter()
+            .position(|&b| pred(b))
+            .map_or(self.bytes.len(), |n| pos + n)
+    }
+
+    fn attr_value(&self, attr: AttrValue) -> String {
+        attr_value_of(&self.input[attr.start..attr.end], attr.quoted)
+    }
+}
@@ -0,0 +1,129 @@
+// パースの前に入力に施す変換
+
+use std::borrow::Cow;
+
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+use crate::ast::Node;
+use crate::options::{BbCodeOptions, ControlChars};
+use crate::parser::tree::Token;
+
+/// opts に従って入力を変換する。何も変えなければ借用のまま返す
+pub(crate) fn normalize_input<'a>(input: &'a str, opts: &BbCodeOptions) -> Cow<'a, str> {
+    let input = match input.strip_prefix('\u{FEFF}') {
+        Some(rest) if opts.strip_bom => rest,
+        _ => input,
+    };
+    let input = replace_control_chars(input, opts.control_chars);
+    let pairs = opts.syntax_chars.translation();
+    if pairs.is_empty() {
+        return input;
+    }
+    Cow::Owned(translate(&input, &pairs))
+}
+
+/// syntax_chars で既定の文字に置き換えた文字を、タグの属性値で元に戻す
+/// 属性値の検証が元の値に対して行われるよう、AST を組み立てる前に戻す
+pub(crate) fn restore_syntax_chars_in_tokens(tokens: &mut [Token], opts: &BbCodeOptions) {
+    let pairs = restoration(opts);
+    if pairs.is_empty() {
+        return;
+    }
+    for token in tokens {
+        match token {
+            Token::Code { value, .. } => restore_value(value, &pairs),
+            Token::Open(open) => {
+                restore_value(&mut open.value, &pairs);
+                for (_, value) in &mut open.named {
+                    *value = translate(value, &pairs);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// syntax_chars で既定の文字に置き換えた文字を、Text ノードで元に戻す
+pub(crate) fn restore_syntax_chars(nodes: &mut [Node], opts: &BbCodeOptions) {
+    let pairs = restoration(opts);
+    if !pairs.is_empty() {
+        restore_in(nodes, &pairs);
+    }
+}
+
+fn restoration(opts: &BbCodeOptions) -> Vec<(char, char)> {
+    opts.syntax_chars
+        .translation()
+        .into_iter()
+        .map(|(from, to)| (to, from))
+        .collect()
+}
+
+fn restore_value(value: &mut Option<String>, pairs: &[(char, char)]) {
+    if let Some(v) = value {
+        *v = translate(v, pairs);
+    }
+}
+
+fn restore_in(nodes: &mut [Node], pairs: &[(char, char)]) {
+    for n in nodes {
+        match n {
+            Node::Text { text, .. } => *text = translate(text, pairs),
+            Node::Element(el) => restore_in(&mut el.children, pairs),
+            Node::Unsupported(_) => {}
+        }
+    }
+}
+
+/// pairs の（置き換え元, 置き換え先）に従って1文字ずつ置き換える
+fn translate(s: &str, pairs: &[(char, char)]) -> String {
+    s.chars()
+        .map(|c| {
+            pairs
+                .iter()
+                .find(|(from, _)| *from == c)
+                .map_or(c, |(_, to)| *to)
+        })
+        .collect()
+}
+
+fn replace_control_chars(input: &str, policy: ControlChars) -> Cow<'_, str> {
+    if policy == ControlChars::Keep || !input.chars().any(is_unsafe_control) {
+        return Cow::Borrowed(input);
+    }
+    let mut out = String::with_capacity(input.len());
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        // 改行の \r\n は残す
+        if !is_unsafe_control(c) || (c == '\r' && chars.peek() == Some(&'\n')) {
+            out.push(c);
+            continue;
+        }
+        if policy == ControlChars::Escape {
+            out.push_str(&format!("\\u{:04X}", u32::from(c)));
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// Text ノードを NFC に正規化する
+pub(crate) fn normalize_nfc_in(nodes: &mut [Node]) {
+    for n in nodes {
+        match n {
+            Node::Text { text, .. } => {
+                if !is_nfc(text) {
+                    *text = text.nfc().collect();
+                }
+            }
+            Node::Element(el) => normalize_nfc_in(&mut el.children),
+            Node::Unsupported(_) => {}
+        }
+    }
+}
+
+/// JavaScript の文字列やログの行を壊す文字
+fn is_unsafe_control(c: char) -> bool {
+    (c.is_ascii_control() && c != '\n' && c != '\t' && c != '\x7f')
+        || c == '\u{2028}'
+        || c == '\u{2029}'
+}
@@ -1,264 +1,215 @@
-use pest::Parser;
+use pest::error::{Error, ErrorVariant, InputLocation, LineColLocation};
+use pest::iterators::{Pair, Pairs};
+use pest::{Parser, Position};
 use pest_derive::Parser;
 
-use crate::ast::{Element, Node, Span};
-use crate::error::BbCodeError;
-use crate::options::BbCodeOptions;
-use crate::registry::TagRegistry;
+use crate::ast::Span;
+use crate::error::{BbCodeError, SyntaxError};
+use crate::parser::tree::{attr_value_of, OpenTag, Token};
 
 #[derive(Parser)]
 #[grammar = "bbcode.pest"]
 pub struct BBCodeParser;
 
-/// AST構築時のコンテキスト
-struct BuildAstContext<'a> {
-    opts: &'a BbCodeOptions,
-    tag_count: usize,
+/// 文法そのままの解析結果を返す低レベル API
+///
+/// ルートは `Rule::BBCode` で、その下に `code_block` / `item_marker` / `item_close` /
+/// `close_tag` / `open_tag` / `escaped_bracket` / `text` が平坦に並ぶ。
+/// 開始タグと閉じタグの対応付けやタグ仕様の検証は行わない。
+/// 閉じタグの無い `[code]` は中身が入力の末尾までの `code_block` になる
+/// （`parse_bbcode_to_ast` では通常の開始タグとして扱い直している）
+pub fn raw_parse(input: &str) -> Result<Pairs<'_, Rule>, BbCodeError> {
+    BBCodeParser::parse(Rule::BBCode, input).map_err(|e| from_pest(e).into())
 }
 
-impl<'a> BuildAstContext<'a> {
-    fn new(opts: &'a BbCodeOptions) -> Self {
-        Self { opts, tag_count: 0 }
+/// 入力をトークン列に分解する
+pub(crate) fn tokenize(input: &str) -> Result<Vec<Token>, SyntaxError> {
+    let mut tokens = vec![];
+    let pairs = BBCodeParser::parse(Rule::BBCode, input).map_err(from_pest)?;
+    push_tokens(input, pairs, 0, &mut tokens).map_err(from_pest)?;
+    Ok(tokens)
+}
+
+/// トークン列として解釈できない位置 pos のエラー（どのタグも閉じない閉じタグなど）
+pub(crate) fn syntax_error(input: &str, pos: usize, message: &str) -> SyntaxError {
+    let (line, column) = Position::new(input, pos).map_or((1, 1), |p| p.line_col());
+    SyntaxError {
+        message: message.to_string(),
+        span: Span {
+            start: pos,
+            end: pos,
+        },
+        line,
+        column,
+        expected: vec![],
     }
+}
 
-    fn on_tag(&mut self) -> Result<(), BbCodeError> {
-        self.tag_count += 1;
-        if self.tag_count > self.opts.max_tags {
-            return Err(BbCodeError::TagCountExceeded {
-                max_tags: self.opts.max_tags,
-            });
-        }
-        Ok(())
+/// pest のエラーを公開用の形にする
+fn from_pest(e: Error<Rule>) -> SyntaxError {
+    let span = match e.location {
+        InputLocation::Pos(pos) => Span {
+            start: pos,
+            end: pos,
+        },
+        InputLocation::Span((start, end)) => Span { start, end },
+    };
+    let (line, column) = match e.line_col {
+        LineColLocation::Pos(pos) | LineColLocation::Span(pos, _) => pos,
+    };
+    let expected = match &e.variant {
+        ErrorVariant::ParsingError { positives, .. } => positives
+            .iter()
+            .map(|rule| match rule {
+                Rule::EOI => "end of input".to_string(),
+                rule => format!("{rule:?}"),
+            })
+            .collect(),
+        ErrorVariant::CustomError { .. } => vec![],
+    };
+    SyntaxError {
+        message: e.variant.message().into_owned(),
+        span,
+        line,
+        column,
+        expected,
     }
+}
 
-    fn check_depth(
-        &self,
-        depth: usize,
-        pair: &pest::iterators::Pair<Rule>,
-    ) -> Result<(), BbCodeError> {
-        let level = depth.checked_add(1).unwrap_or(usize::MAX);
-        if level > self.opts.max_depth {
-            let sp = pair.as_span();
-            let (line, column) = sp.start_pos().line_col();
-            return Err(BbCodeError::NestDepthExceeded {
-                max_depth: self.opts.max_depth,
-                near: pair.as_str().to_string(),
-                span: Span {
-                    start: sp.start(),
-                    end: sp.end(),
-                },
-                line,
-                column,
-            });
+/// pairs は input[offset..] をパースした結果
+fn push_tokens(
+    input: &str,
+    pairs: Pairs<Rule>,
+    offset: usize,
+    tokens: &mut Vec<Token>,
+) -> Result<(), Error<Rule>> {
+    let span_of = |pair: &Pair<Rule>| {
+        let sp = pair.as_span();
+        Span {
+            start: sp.start() + offset,
+            end: sp.end() + offset,
         }
-        Ok(())
-    }
+    };
 
-    fn build_nodes(
-        &mut self,
-        pair: pest::iterators::Pair<Rule>,
-        depth: usize,
-    ) -> Result<Vec<Node>, BbCodeError> {
+    for pair in pairs.flat_map(|p| p.into_inner()) {
+        let span = span_of(&pair);
         match pair.as_rule() {
-            Rule::BBCode | Rule::content => {
-                let mut result = vec![];
-                for inner in pair.into_inner() {
-                    result.extend(self.build_nodes(inner, depth)?);
-                }
-                Ok(result)
-            }
-
-            Rule::tag_block => {
-                self.check_depth(depth, &pair)?;
-                self.on_tag()?;
-
-                let span = pair_span(&pair);
-
-                let original = pair.as_str().to_string(); // フォールバック用
-
+            // 文法と形が合わない（名前や中身が無い）ペアは、捨てずに元のテキストとして残す
+            Rule::code_block => {
                 let mut inner = pair.into_inner();
-
-                let open_name = inner.next().unwrap().as_str().to_string();
-                let open_name_lc = open_name.to_ascii_lowercase();
-
-                // optional: tag_attr (=...)
-                let mut value_attr: Option<String> = None;
-                if let Some(next) = inner.peek() {
-                    if next.as_rule() == Rule::tag_attr {
-                        let raw = inner.next().unwrap().as_str(); // "=xxxx"
-                        value_attr = Some(raw[1..].to_string());
-                    }
-                }
-
-                // children (content*) を close_tag_name まで集める
-                let mut content_pairs = vec![];
-                loop {
-                    match inner.peek() {
-                        Some(p) if p.as_rule() == Rule::close_tag_name => break,
-                        Some(_) => content_pairs.push(inner.next().unwrap()),
-                        None => break,
-                    }
-                }
-
-                let close_name = inner.next().unwrap().as_str().to_string();
-                let close_name_lc = close_name.to_ascii_lowercase();
-
-                // タグ不整合は「その部分を丸ごとテキストへ」(構造を壊さない方針)
-                if open_name_lc != close_name_lc {
-                    return Ok(vec![Node::Text {
-                        span,
-                        text: original,
-                    }]);
-                }
-
-                // TagSpec に従って属性を許可・検証する
-                // unknown tag は BBCode として扱わない
-                let spec = match TagRegistry::get(&open_name_lc) {
-                    Some(s) => s,
-                    None => {
-                        // unknown tag は丸ごとテキストへ（中身も含めて構造化しない）
-                        return Ok(vec![Node::Text {
-                            span,
-                            text: original,
-                        }]);
-                    }
+                let Some(open_name) = inner.next().map(|p| p.as_str()) else {
+                    tokens.push(Token::Text { span });
+                    continue;
                 };
-
-                // 子要素を再帰で構築
-                let mut children = vec![];
-                for cp in content_pairs {
-                    children.extend(self.build_nodes(cp, depth + 1)?);
-                }
-
-                // 値属性があるのに許可されてない -> フォールバック
-                if value_attr.is_some() && !spec.allow_value_attr {
-                    return Ok(vec![Node::Text {
+                let (value, _) = take_attrs(&mut inner);
+                let Some(body) = inner.next() else {
+                    tokens.push(Token::Text { span });
+                    continue;
+                };
+                let body_span = span_of(&body);
+                match inner.next() {
+                    Some(close) => tokens.push(Token::Code {
                         span,
-                        text: original,
-                    }]);
-                }
-
-                // 値属性の検証（colorなど）
-                if let (Some(val), Some(validator)) = (&value_attr, spec.validate_value_attr) {
-                    if !(validator)(val) {
-                        return Ok(vec![Node::Text {
-                            span,
-                            text: original,
-                        }]);
+                        open_name: open_name.to_string(),
+                        close_name: close.as_str().to_string(),
+                        value,
+                        body: body_span,
+                    }),
+                    // 閉じタグが無ければ通常の開始タグとして扱い、後ろを読み直す
+                    // これより後ろにも `[/code]` は無いので [code] を verbatim 扱いしない規則で読む
+                    None => {
+                        tokens.push(Token::Open(OpenTag {
+                            span: Span {
+                                start: span.start,
+                                end: body_span.start,
+                            },
+                            name: open_name.to_string(),
+                            value,
+                            named: vec![],
+                        }));
+                        let Some(rest) = input.get(body_span.start..) else {
+                            tokens.push(Token::Text { span });
+                            continue;
+                        };
+                        let pairs = BBCodeParser::parse(Rule::BBCodeNoCode, rest)
+                            .map_err(|e| relocate_error(input, body_span.start, e))?;
+                        push_tokens(input, pairs, body_span.start, tokens)?;
                     }
                 }
-
-                let mut elem = Element::new(open_name_lc, span).with_children(children);
-
-                if let Some(val) = value_attr {
-                    // `[color=red]` を attrs=[("value","red")] に正規化
-                    elem.attrs
-                        .push(("value".to_string(), val.trim().to_string()));
-                }
-
-                Ok(vec![Node::Element(elem)])
             }
-
-            Rule::unclosed_tag => {
-                // 開始タグのみで閉じタグがないケースはその部分を丸ごとテキストへ
-                // DoS耐性としてタグ数制限の対象に含める
-                self.on_tag()?;
-                let span = pair_span(&pair);
-                Ok(vec![Node::Text {
-                    span,
-                    text: pair.as_str().to_string(),
-                }])
-            }
-
-            Rule::escaped_bracket => {
-                let span = pair_span(&pair);
-                Ok(vec![Node::Text {
-                    span,
-                    text: "[".to_string(),
-                }])
-            }
-
-            Rule::text => {
-                let span = pair_span(&pair);
-                Ok(vec![Node::Text {
+            Rule::open_tag => {
+                let mut inner = pair.into_inner();
+                let Some(name) = inner.next().map(|p| p.as_str()) else {
+                    tokens.push(Token::Text { span });
+                    continue;
+                };
+                let (value, named) = take_attrs(&mut inner);
+                tokens.push(Token::Open(OpenTag {
                     span,
-                    text: pair.as_str().to_string(),
-                }])
+                    name: name.to_string(),
+                    value,
+                    named,
+                }));
             }
-            Rule::EOI => Ok(vec![]),
-
-            _ => {
-                let span = pair_span(&pair);
-                Ok(vec![Node::Text {
+            Rule::close_tag => {
+                let name = pair
+                    .into_inner()
+                    .next()
+                    .map(|p| p.as_str())
+                    .unwrap_or_default();
+                tokens.push(Token::Close {
                     span,
-                    text: pair.as_str().to_string(),
-                }])
+                    name: name.to_string(),
+                });
             }
+            Rule::item_marker => tokens.push(Token::ItemOpen { span }),
+            Rule::item_close => tokens.push(Token::ItemClose { span }),
+            Rule::escaped_bracket => tokens.push(Token::Escaped { span }),
+            Rule::text => tokens.push(Token::Text { span }),
+            _ => {}
         }
     }
+    Ok(())
 }
 
-/// 公開API：入力文字列をASTにパース
-pub fn parse_bbcode_to_ast(input: &str, opts: &BbCodeOptions) -> Result<Vec<Node>, BbCodeError> {
-    if input.len() > opts.max_input_size {
-        return Err(BbCodeError::InputSizeExceeded {
-            max_size: opts.max_input_size,
-            actual_size: input.len(),
-        });
-    }
-
-    let pairs = BBCodeParser::parse(Rule::BBCode, input)?;
-    let mut ctx = BuildAstContext::new(opts);
-
-    let mut nodes = vec![];
-    for p in pairs {
-        nodes.extend(ctx.build_nodes(p, 0)?);
-    }
-
-    Ok(normalize_text_nodes(nodes))
+/// input[offset..] に対するエラーを input 全体の位置に直す
+fn relocate_error(input: &str, offset: usize, e: Error<Rule>) -> Error<Rule> {
+    let pos = match e.location {
+        InputLocation::Pos(p) => p,
+        InputLocation::Span((s, _)) => s,
+    };
+    Error::new_from_pos(
+        e.variant,
+        Position::new(input, offset + pos).unwrap_or_else(|| Position::from_start(input)),
+    )
 }
 
-/// 隣接 Text をマージして扱いやすくする
-fn normalize_text_nodes(nodes: Vec<Node>) -> Vec<Node> {
-    let mut normalized: Vec<Node> = Vec::with_capacity(nodes.len());
-
-    for n in nodes {
-        match n {
-            Node::Text { .. } => normalized.push(n),
-            Node::Element(mut el) => {
-                el.children = normalize_text_nodes(el.children);
-                normalized.push(Node::Element(el));
+/// tag_name の直後にある値属性と名前付き属性を取り出す
+fn take_attrs(inner: &mut Pairs<Rule>) -> (Option<String>, Vec<(String, String)>) {
+    let mut value_attr = None;
+    let mut named_attrs = vec![];
+    while let Some(next) = inner.peek() {
+        match next.as_rule() {
+            Rule::tag_attr => {
+                inner.next(); // "=xxxx" / "=\"xx xx\""
+                value_attr = Some(next.into_inner().next().map(attr_value).unwrap_or_default());
             }
-        }
-    }
-
-    let mut out: Vec<Node> = Vec::with_capacity(normalized.len());
-    for n in normalized {
-        match (out.last_mut(), n) {
-            (
-                Some(Node::Text {
-                    span: prev_span,
-                    text: prev_text,
-                }),
-                Node::Text {
-                    span: cur_span,
-                    text: cur_text,
-                },
-            ) => {
-                prev_text.push_str(&cur_text);
-                prev_span.end = cur_span.end;
+            Rule::named_attr => {
+                inner.next();
+                let mut kv = next.into_inner();
+                let key = kv
+                    .next()
+                    .map(|k| k.as_str().to_ascii_lowercase())
+                    .unwrap_or_default();
+                let value = kv.next().map(attr_value).unwrap_or_default();
+                named_attrs.push((key, value));
             }
-            (_, other) => out.push(other),
+            _ => break,
         }
     }
-
-    out
+    (value_attr, named_attrs)
 }
 
-fn pair_span(pair: &pest::iterators::Pair<Rule>) -> Span {
-    let sp = pair.as_span();
-    Span {
-        start: sp.start(),
-        end: sp.end(),
-    }
+fn attr_value(pair: Pair<Rule>) -> String {
+    attr_value_of(pair.as_str(), pair.as_rule() == Rule::quoted_attr)
 }
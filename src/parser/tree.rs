@@ -0,0 +1,234 @@
+use crate::ast::Span;
+
+/// 開始タグ `[name=value key=val]` の内容
+#[derive(Debug, Clone)]
+pub(crate) struct OpenTag {
+    pub span: Span,
+    /// 入力どおりの表記（大文字・小文字もそのまま）
+    pub name: String,
+    pub value: Option<String>,
+    /// 名前付き属性（キーは小文字化済み）
+    pub named: Vec<(String, String)>,
+}
+
+/// 字句解析の結果。入れ子の対応はまだ付いていない
+#[derive(Debug, Clone)]
+pub(crate) enum Token {
+    /// 閉じタグまで含めた verbatim な `[code]..[/code]`
+    Code {
+        span: Span,
+        open_name: String,
+        close_name: String,
+        value: Option<String>,
+        body: Span,
+    },
+    Open(OpenTag),
+    /// `[/name]`。`[/]` なら name は空
+    Close {
+        span: Span,
+        name: String,
+    },
+    /// `[*]`
+    ItemOpen {
+        span: Span,
+    },
+    /// `[/*]`
+    ItemClose {
+        span: Span,
+    },
+    /// `\[`
+    Escaped {
+        span: Span,
+    },
+    Text {
+        span: Span,
+    },
+}
+
+/// 開始タグと閉じタグの対応を付けた木
+/// 対応が付かなかった開始タグは Unclosed / Marker として残り、中身は親の子として並ぶ
+#[derive(Debug, Clone)]
+pub(crate) enum Raw {
+    Code {
+        span: Span,
+        open_name: String,
+        close_name: String,
+        value: Option<String>,
+        body: Span,
+    },
+    /// `[name]..[/xxx]`（閉じタグ名の一致は AST 構築時に確認する）
+    Block {
+        span: Span,
+        open: OpenTag,
+        children: Vec<Raw>,
+        close_name: String,
+        close_span: Span,
+    },
+    /// `[*]..[/*]`
+    Item {
+        span: Span,
+        children: Vec<Raw>,
+    },
+    /// 閉じタグが見つからなかった開始タグ
+    /// close_at は閉じタグを補うならその位置（`[/*]` の手前）。None なら入力の終わり
+    Unclosed {
+        span: Span,
+        name: String,
+        close_at: Option<usize>,
+    },
+    /// 閉じタグの無い `[*]`
+    Marker {
+        span: Span,
+    },
+    Escaped {
+        span: Span,
+    },
+    Text {
+        span: Span,
+    },
+}
+
+impl Raw {
+    pub fn span(&self) -> Span {
+        match self {
+            Raw::Code { span, .. }
+            | Raw::Block { span, .. }
+            | Raw::Item { span, .. }
+            | Raw::Unclosed { span, .. }
+            | Raw::Marker { span }
+            | Raw::Escaped { span }
+            | Raw::Text { span } => *span,
+        }
+    }
+}
+
+/// 開いているタグ。start は対応が付くまでの仮の Unclosed / Marker を置いた位置
+enum Frame {
+    Block { open: OpenTag, start: usize },
+    Item { span: Span, start: usize },
+}
+
+/// トークン列の開始タグと閉じタグを対応付ける
+///
+/// 閉じタグは名前に関係なく直前に開いたタグを閉じる（名前の不一致は AST 構築時にテキストへ戻す）。
+/// `[/*]` 以外の閉じタグは開いている `[*]` を閉じず、`[/*]` は一般のタグを閉じない。
+/// 閉じられなかったタグは開始タグだけがテキスト扱いになり、中身は親の子になる。
+/// どのタグも閉じない閉じタグは構文エラーとし、その位置を返す
+///
+/// 子要素は1本のバッファに積み、閉じたときにまとめて切り出すので入力長に対して線形
+pub(crate) fn build_tree(tokens: Vec<Token>) -> Result<Vec<Raw>, usize> {
+    let mut buf: Vec<Raw> = Vec::with_capacity(tokens.len());
+    let mut stack: Vec<Frame> = vec![];
+
+    for tok in tokens {
+        match tok {
+            Token::Code {
+                span,
+                open_name,
+                close_name,
+                value,
+                body,
+            } => buf.push(Raw::Code {
+                span,
+                open_name,
+                close_name,
+                value,
+                body,
+            }),
+            Token::Open(open) => {
+                buf.push(Raw::Unclosed {
+                    span: open.span,
+                    name: open.name.clone(),
+                    close_at: None,
+                });
+                stack.push(Frame::Block {
+                    start: buf.len() - 1,
+                    open,
+                });
+            }
+            Token::ItemOpen { span } => {
+                stack.push(Frame::Item {
+                    span,
+                    start: buf.len(),
+                });
+                buf.push(Raw::Marker { span });
+            }
+            Token::Close { span, name } => loop {
+                match stack.pop() {
+                    Some(Frame::Block { open, start }) => {
+                        let children = buf.drain(start + 1..).collect();
+                        buf[start] = Raw::Block {
+                            span: Span {
+                                start: open.span.start,
+                                end: span.end,
+                            },
+                            open,
+                            children,
+                            close_name: name,
+                            close_span: span,
+                        };
+                        break;
+                    }
+                    // `[*]` は `[/*]` でしか閉じないので、区切りとして残して外側を探す
+                    Some(Frame::Item { .. }) => continue,
+                    None => return Err(span.start),
+                }
+            },
+            Token::ItemClose { span } => loop {
+                match stack.pop() {
+                    Some(Frame::Item { span: open, start }) => {
+                        let children = buf.drain(start + 1..).collect();
+                        buf[start] = Raw::Item {
+                            span: Span {
+                                start: open.start,
+                                end: span.end,
+                            },
+                            children,
+                        };
+                        break;
+                    }
+                    // 一般のタグは `[/*]` では閉じないので、開始タグだけテキストに戻す
+                    Some(Frame::Block { start, .. }) => {
+                        if let Raw::Unclosed { close_at, .. } = &mut buf[start] {
+                            *close_at = Some(span.start);
+                        }
+                    }
+                    None => return Err(span.start),
+                }
+            },
+            Token::Escaped { span } => buf.push(Raw::Escaped { span }),
+            Token::Text { span } => buf.push(Raw::Text { span }),
+        }
+    }
+
+    // 最後まで閉じられなかったものは仮の Unclosed / Marker のまま
+    Ok(buf)
+}
+
+/// 属性値を取り出す
+/// - bare: 前後の空白を除去
+/// - quoted: 囲みクォートを外し、`\"` `\'` `\\` をアンエスケープ（空白はそのまま）
+pub(crate) fn attr_value_of(raw: &str, quoted: bool) -> String {
+    if !quoted {
+        return raw.trim().to_string();
+    }
+    let quote = raw.chars().next().unwrap_or('"');
+    let body = &raw[1..raw.len() - 1];
+    let mut out = String::with_capacity(body.len());
+    let mut chars = body.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some(n) if n == quote || n == '\\' => out.push(n),
+                Some(n) => {
+                    out.push(c);
+                    out.push(n);
+                }
+                None => out.push(c),
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
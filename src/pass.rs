@@ -0,0 +1,31 @@
+// パース後に AST を書き換える処理（パス）
+//
+// 禁止語の伏せ字・URL の自動リンク・独自の検証などを、パーサーを別の処理で包まずに
+// BbCodeOptions::ast_passes に登録して行うためのもの。
+
+use std::fmt;
+
+use crate::ast::Node;
+use crate::diagnostic::Diagnostic;
+
+/// パース後に AST を書き換える処理
+/// 隣り合うテキストをまとめた後（改行の詰め・NFC 正規化・テキスト長の制限の前）に、登録した順に実行する。
+/// 診断は diagnostics に追加する。`Fn(Vec<Node>, &mut Vec<Diagnostic>) -> Vec<Node>` の関数・クロージャも使える
+pub trait AstPass: Send + Sync {
+    fn run(&self, nodes: Vec<Node>, diagnostics: &mut Vec<Diagnostic>) -> Vec<Node>;
+}
+
+impl<F> AstPass for F
+where
+    F: Fn(Vec<Node>, &mut Vec<Diagnostic>) -> Vec<Node> + Send + Sync,
+{
+    fn run(&self, nodes: Vec<Node>, diagnostics: &mut Vec<Diagnostic>) -> Vec<Node> {
+        self(nodes, diagnostics)
+    }
+}
+
+impl fmt::Debug for dyn AstPass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AstPass")
+    }
+}
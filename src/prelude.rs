@@ -0,0 +1,10 @@
+// よく使う型と関数をまとめて読み込む（`use bbcode_parser::prelude::*;`）
+
+pub use crate::ast::{Element, Node, Span};
+pub use crate::document::{parse, BbCodeDocument};
+pub use crate::error::{BbCodeError, ErrorKind};
+pub use crate::lint::{lint, Lint, LintCode, Severity};
+pub use crate::options::BbCodeOptions;
+pub use crate::registry::{TagRegistry, TagSpec};
+pub use crate::render::{ast_to_html, ast_to_html_with, ast_to_plain_text, HtmlRenderOptions};
+pub use crate::{bbcode_to_html, parse_bbcode_to_ast};
@@ -1,12 +1,94 @@
+use std::collections::{HashMap, HashSet};
+
 use once_cell::sync::Lazy;
 use regex::Regex;
 
+use crate::ast::{Element, Node};
+use crate::color::Color;
+use crate::error::TemplateError;
+use crate::length::{Length, LengthRule};
+use crate::render::attr_value;
+use crate::template::TagTemplate;
+
+/// 生の値属性を構造化された属性列に分解する関数
+/// `None` を返した場合は不正な値としてフォールバックする
+pub type ValueSplitter = fn(&str) -> Option<Vec<(String, String)>>;
+
+/// 要素の表示上の種類（HTML のブロック要素 / インライン要素に相当）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DisplayKind {
+    Block,
+    #[default]
+    Inline,
+}
+
+/// 投稿者の権限。上の権限は下の権限で使えるタグをすべて使える
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PermissionLevel {
+    /// ログインしていない利用者
+    Guest,
+    /// 通常の会員
+    #[default]
+    Member,
+    /// 信頼済みの会員（動画の埋め込みなどを許す）
+    Trusted,
+    Moderator,
+    Admin,
+}
+
+/// 同じタグを入れ子にした場合（`[b]a[b]b[/b]c[/b]`）の扱い
+/// 間に別の要素を挟んでいても入れ子とみなす
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SameTagNesting {
+    /// 入れ子のまま残す
+    #[default]
+    Allow,
+    /// 外側と属性まで同じ内側の要素を外し、中身だけにする（属性が違えば残す）
+    Collapse,
+    /// 内側の要素をテキストに戻す
+    Reject,
+}
+
 #[derive(Debug, Clone)]
 pub struct TagSpec {
     /// `[color=xxx]` のように 1つの “値属性” を許可するか
     pub allow_value_attr: bool,
     /// 値属性を検証する（colorのようなケース）
     pub validate_value_attr: Option<fn(&str) -> bool>,
+    /// 値属性を複数の属性に分解する（`[quote=Alice;123]` のようなケース）
+    /// 未指定なら `("value", 値)` として格納する
+    pub split_value_attr: Option<ValueSplitter>,
+    /// 許可する名前付き属性（`[quote author=Alice]` の `author` など、小文字）
+    pub named_attrs: Vec<String>,
+    /// 閉じタグ無しの `[*]` を項目の区切りとして扱うか（`[list]`）
+    pub implicit_items: bool,
+    /// 構築後の要素全体を検証する（`[url]` / `[img]` の中身など）
+    pub validate_element: Option<fn(&Element) -> bool>,
+    /// ブロック / インラインの別。インライン要素はブロック要素を子に持てない
+    pub display: DisplayKind,
+    /// 子に置ける要素名（正規名）。None なら制限しない。テキストは対象外
+    pub allowed_children: Option<HashSet<String>>,
+    /// 直接の親でなければならない要素名（正規名。`[*]` なら `list`）
+    pub required_parent: Option<String>,
+    /// 廃止予定のタグ。パースは通常どおり行い Diagnostic::Deprecated を出す
+    pub deprecated: bool,
+    /// 廃止予定のタグの代わりに使うべきもの（`[size]` など）
+    pub replacement: Option<String>,
+    /// このタグを使うのに必要な投稿者の権限（BbCodeOptions::author_level と比べる）
+    pub required_level: PermissionLevel,
+    /// 中身を BBCode として解釈せず、入力どおりの1つの Text にする（`[html]`）
+    pub raw_content: bool,
+    /// 値属性として許可する値（小文字）。None なら制限しない（validate_value_attr も適用する）
+    pub allowed_values: Option<HashSet<String>>,
+    /// 中身が無くても意味を持つ（`[anchor=x][/anchor]`）。BbCodeOptions::empty_elements の対象外
+    pub keep_empty: bool,
+    /// 同じタグを入れ子にした場合の扱い
+    pub same_tag_nesting: SameTagNesting,
+    /// 値属性が色（`[color]` / `[highlight]`）。BbCodeOptions::color_formats で許可した形式に限る
+    pub color_value: bool,
+    /// 値属性が長さ（`[size]` / `[img=WxH]`）。受け付ける単位と範囲
+    pub length_value: Option<LengthRule>,
 }
 
 impl TagSpec {
@@ -14,39 +96,657 @@ impl TagSpec {
         Self {
             allow_value_attr: false,
             validate_value_attr: None,
+            split_value_attr: None,
+            named_attrs: vec![],
+            implicit_items: false,
+            validate_element: None,
+            display: DisplayKind::Inline,
+            allowed_children: None,
+            required_parent: None,
+            deprecated: false,
+            replacement: None,
+            required_level: PermissionLevel::Guest,
+            raw_content: false,
+            allowed_values: None,
+            keep_empty: false,
+            same_tag_nesting: SameTagNesting::Allow,
+            color_value: false,
+            length_value: None,
+        }
+    }
+
+    /// 値属性を許可するタグ
+    pub fn with_value(validate: Option<fn(&str) -> bool>) -> Self {
+        Self {
+            allow_value_attr: true,
+            validate_value_attr: validate,
+            ..Self::simple()
+        }
+    }
+
+    pub fn with_splitter(mut self, splitter: ValueSplitter) -> Self {
+        self.split_value_attr = Some(splitter);
+        self
+    }
+
+    pub fn with_named_attrs(mut self, names: &[&str]) -> Self {
+        self.named_attrs = names.iter().map(|n| n.to_ascii_lowercase()).collect();
+        self
+    }
+
+    pub fn with_implicit_items(mut self) -> Self {
+        self.implicit_items = true;
+        self
+    }
+
+    pub fn with_element_validator(mut self, validate: fn(&Element) -> bool) -> Self {
+        self.validate_element = Some(validate);
+        self
+    }
+
+    pub fn with_display(mut self, display: DisplayKind) -> Self {
+        self.display = display;
+        self
+    }
+
+    pub fn with_allowed_children(mut self, names: &[&str]) -> Self {
+        self.allowed_children = Some(names.iter().map(|n| n.to_ascii_lowercase()).collect());
+        self
+    }
+
+    pub fn with_required_parent(mut self, name: &str) -> Self {
+        self.required_parent = Some(name.to_ascii_lowercase());
+        self
+    }
+
+    /// 廃止予定にする。replacement は案内に使う代替
+    pub fn with_deprecation(mut self, replacement: Option<&str>) -> Self {
+        self.deprecated = true;
+        self.replacement = replacement.map(str::to_string);
+        self
+    }
+
+    /// level 以上の権限を持つ投稿者だけが使えるようにする
+    pub fn with_required_level(mut self, level: PermissionLevel) -> Self {
+        self.required_level = level;
+        self
+    }
+
+    pub fn with_raw_content(mut self) -> Self {
+        self.raw_content = true;
+        self
+    }
+
+    pub fn with_keep_empty(mut self) -> Self {
+        self.keep_empty = true;
+        self
+    }
+
+    pub fn with_same_tag_nesting(mut self, policy: SameTagNesting) -> Self {
+        self.same_tag_nesting = policy;
+        self
+    }
+
+    /// 値属性を色として受け付ける（color::Color で読めるもの）
+    pub fn with_color_value(mut self) -> Self {
+        self.allow_value_attr = true;
+        self.validate_value_attr = Some(is_valid_color_value);
+        self.color_value = true;
+        self
+    }
+
+    /// 値属性を rule で読める長さとして受け付ける
+    pub fn with_length_value(mut self, rule: LengthRule) -> Self {
+        self.allow_value_attr = true;
+        self.length_value = Some(rule);
+        self
+    }
+
+    /// 値属性を列挙した値（大文字・小文字は区別しない）に限る
+    pub fn with_allowed_values(mut self, values: &[&str]) -> Self {
+        self.allowed_values = Some(
+            values
+                .iter()
+                .map(|v| v.trim().to_ascii_lowercase())
+                .collect(),
+        );
+        self
+    }
+
+    /// 値属性の有無・内容がこの仕様で受け入れられるか
+    pub fn accepts_value(&self, value: Option<&str>) -> bool {
+        match value {
+            None => true,
+            Some(_) if !self.allow_value_attr => false,
+            Some(v) => {
+                self.validate_value_attr.is_none_or(|f| f(v))
+                    && self.length_value.as_ref().is_none_or(|r| r.accepts(v))
+                    && self
+                        .allowed_values
+                        .as_ref()
+                        .is_none_or(|a| a.contains(&v.trim().to_ascii_lowercase()))
+            }
         }
     }
 }
 
+/// タグ名 -> TagSpec の対応表
+/// 既定では組み込みタグを持ち、方言アダプタなどが差し替え・追加できる
 #[derive(Debug, Clone)]
-pub struct TagRegistry;
+pub struct TagRegistry {
+    specs: HashMap<String, TagSpec>,
+    /// 別名 -> 正規名（`[strike]` -> `s` など）
+    aliases: HashMap<String, String>,
+    /// HTML のテンプレートで定義したタグ
+    templates: HashMap<String, TagTemplate>,
+}
 
 impl TagRegistry {
-    /// タグの仕様を返す
-    pub fn get(tag_name: &str) -> Option<TagSpec> {
-        match tag_name.to_ascii_lowercase().as_str() {
-            "b" => Some(TagSpec::simple()),
-            "i" => Some(TagSpec::simple()),
-            "u" => Some(TagSpec::simple()),
-            "s" => Some(TagSpec::simple()),
-            "quote" => Some(TagSpec::simple()),
-            "left" => Some(TagSpec::simple()),
-            "center" => Some(TagSpec::simple()),
-            "right" => Some(TagSpec::simple()),
-            "color" => Some(TagSpec {
-                allow_value_attr: true,
-                validate_value_attr: Some(is_valid_color_value),
-            }),
-            _ => None,
-        }
-    }
-}
-
-/// 英字 or #RGB or #RRGGBB
-fn is_valid_color_value(s: &str) -> bool {
-    static COLOR_RE: Lazy<Regex> = Lazy::new(|| {
-        Regex::new(r"^([A-Za-z]+|#[0-9A-Fa-f]{3}([0-9A-Fa-f]{3})?)$")
-            .expect("color regex must be valid")
+    /// 組み込みタグのみを持つレジストリ
+    pub fn builtin() -> Self {
+        BUILTIN.clone()
+    }
+
+    /// タグを持たない空のレジストリ
+    pub fn empty() -> Self {
+        Self {
+            specs: HashMap::new(),
+            aliases: HashMap::new(),
+            templates: HashMap::new(),
+        }
+    }
+
+    /// タグの仕様を返す（別名も解決する）
+    pub fn get(&self, tag_name: &str) -> Option<&TagSpec> {
+        self.specs.get(&self.canonical_name(tag_name))
+    }
+
+    /// タグを登録する（既存の同名タグは置き換え）
+    pub fn insert(&mut self, tag_name: impl Into<String>, spec: TagSpec) {
+        let name = tag_name.into().to_ascii_lowercase();
+        self.templates.remove(&name);
+        self.specs.insert(name, spec);
+    }
+
+    /// HTML のテンプレートだけでタグを定義する（`<span class="x">{content}</span>` など）
+    /// 値属性・名前付き属性はテンプレートで使うものだけを許可する
+    /// HTML として出力するには HtmlRenderOptions::templates に templates() を渡す
+    pub fn insert_template(
+        &mut self,
+        tag_name: impl Into<String>,
+        template: &str,
+    ) -> Result<(), TemplateError> {
+        let name = tag_name.into().to_ascii_lowercase();
+        let template = TagTemplate::parse(template)?;
+        let spec = if template.uses_value() {
+            TagSpec::with_value(None)
+        } else {
+            TagSpec::simple()
+        };
+        let mut spec = spec
+            .with_named_attrs(&template.attr_names())
+            .with_display(template.display());
+        // `{content}` を使わないテンプレートは空で使う
+        spec.keep_empty = !template.has_content();
+        self.insert(name.clone(), spec);
+        self.templates.insert(name, template);
+        Ok(())
+    }
+
+    /// テンプレートで定義したタグ（正規名 -> テンプレート）
+    pub fn templates(&self) -> &HashMap<String, TagTemplate> {
+        &self.templates
+    }
+
+    /// 中身を HTML としてそのまま出力する `[html]` を登録する（既定では未登録）
+    /// 使えるのは Admin の投稿だけで、HTML として出力されるのは
+    /// HtmlRenderOptions::trust_raw_html を有効にした場合だけ
+    #[cfg(feature = "tags-embed")]
+    pub fn enable_raw_html(&mut self) {
+        self.insert(
+            "html",
+            TagSpec::simple()
+                .with_display(DisplayKind::Block)
+                .with_raw_content()
+                .with_required_level(PermissionLevel::Admin),
+        );
+    }
+
+    /// `[color]` を列挙した色（`red` / `#336699` など）に限る
+    /// 別の色は `[color]` ごとテキストとして扱う
+    pub fn restrict_color_palette(&mut self, colors: &[&str]) {
+        if let Some(spec) = self.get("color").cloned() {
+            self.insert("color", spec.with_allowed_values(colors));
+        }
+    }
+
+    /// タグの登録を解除する
+    pub fn remove(&mut self, tag_name: &str) -> Option<TagSpec> {
+        let name = tag_name.to_ascii_lowercase();
+        self.templates.remove(&name);
+        self.specs.remove(&name)
+    }
+
+    /// 別名を登録する。AST 上は正規名の要素になる
+    pub fn alias(&mut self, alias: impl Into<String>, canonical: impl Into<String>) {
+        self.aliases.insert(
+            alias.into().to_ascii_lowercase(),
+            canonical.into().to_ascii_lowercase(),
+        );
+    }
+
+    /// 別名なら正規名を、そうでなければ小文字化した名前を返す
+    pub fn canonical_name(&self, tag_name: &str) -> String {
+        let lc = tag_name.to_ascii_lowercase();
+        match self.aliases.get(&lc) {
+            Some(canonical) => canonical.clone(),
+            None => lc,
+        }
+    }
+
+    /// 登録済みタグ名の一覧
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.specs.keys().map(|k| k.as_str())
+    }
+
+    /// 未知のタグ名に近い登録済みタグ名（別名も含む）を返す（`colr` -> `color`）
+    /// 編集距離が短い名前なら1、それ以外は2以内のものだけを候補にする
+    pub fn suggest(&self, tag_name: &str) -> Option<&str> {
+        let name = tag_name.to_ascii_lowercase();
+        let max = if name.chars().count() <= 3 { 1 } else { 2 };
+        self.specs
+            .keys()
+            .chain(self.aliases.keys())
+            .map(|k| k.as_str())
+            .filter(|k| *k != "*")
+            .map(|k| (edit_distance(&name, k), k))
+            .filter(|(d, _)| *d <= max)
+            // 同じ距離なら名前順で安定させる
+            .min()
+            .map(|(_, k)| k)
+    }
+}
+
+/// レーベンシュタイン距離（文字単位）
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != *cb);
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+impl Default for TagRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+// 組み込みタグはフィーチャ（tags-basic / tags-layout / tags-media）ごとに登録する
+static BUILTIN: Lazy<TagRegistry> = Lazy::new(|| {
+    #[allow(unused_mut)]
+    let mut r = TagRegistry::empty();
+    #[cfg(feature = "tags-basic")]
+    insert_basic_tags(&mut r);
+    #[cfg(feature = "tags-layout")]
+    insert_layout_tags(&mut r);
+    #[cfg(feature = "tags-media")]
+    insert_media_tags(&mut r);
+    r
+});
+
+/// 装飾・色・サイズ・フォント・リンク・ページ内リンク・ハッシュタグ
+#[cfg(feature = "tags-basic")]
+fn insert_basic_tags(r: &mut TagRegistry) {
+    // 装飾は同じものを重ねても見た目が変わらないので、内側を外す
+    let style = |spec: TagSpec| spec.with_same_tag_nesting(SameTagNesting::Collapse);
+    r.insert("b", style(TagSpec::simple()));
+    r.insert("i", style(TagSpec::simple()));
+    r.insert("u", style(TagSpec::simple()));
+    r.insert("s", style(TagSpec::simple()));
+    r.insert("color", style(TagSpec::simple().with_color_value()));
+    // `[highlight]` / `[highlight=#ff0]`。`[mark]` は別名
+    r.insert("highlight", style(TagSpec::simple().with_color_value()));
+    r.alias("mark", "highlight");
+    // キー表記と等幅。`[code]` と違い中身の BBCode は解釈する
+    r.insert("kbd", style(TagSpec::simple()));
+    r.insert("tt", style(TagSpec::simple()));
+    r.alias("mono", "tt");
+    r.insert(
+        "size",
+        style(TagSpec::simple().with_length_value(LengthRule::font_size())),
+    );
+    // 周りの文字より一段大きく・小さく。数値を取らないので極端な大きさにならない
+    r.insert("big", TagSpec::simple());
+    r.insert("small", TagSpec::simple());
+    r.insert(
+        "font",
+        style(TagSpec::with_value(Some(is_valid_font_value))),
+    );
+    // `[url]https://..[/url]` / `[url=https://..]label[/url]`
+    r.insert(
+        "url",
+        TagSpec::with_value(Some(is_valid_url)).with_element_validator(validate_url_element),
+    );
+    // ページ内リンク。`[anchor=name]` で飛び先、`[goto=name]` でそこへのリンク
+    r.insert(
+        "anchor",
+        TagSpec::with_value(Some(is_valid_anchor_name))
+            .with_element_validator(has_value_attr)
+            .with_keep_empty(),
+    );
+    r.insert(
+        "goto",
+        TagSpec::with_value(Some(is_valid_anchor_name)).with_element_validator(has_value_attr),
+    );
+    // `[tag=topic]label[/tag]` / `[tag]topic[/tag]`。`[hashtag]` は別名
+    r.insert(
+        "tag",
+        TagSpec::with_value(Some(is_valid_hashtag)).with_element_validator(validate_tag_element),
+    );
+    r.alias("hashtag", "tag");
+}
+
+/// 引用・寄せ・リスト・コード
+#[cfg(feature = "tags-layout")]
+fn insert_layout_tags(r: &mut TagRegistry) {
+    // `[quote=Alice]` / `[quote="Alice [admin]"]` で引用元を指定できる
+    r.insert(
+        "quote",
+        TagSpec::with_value(None).with_display(DisplayKind::Block),
+    );
+    r.insert("left", TagSpec::simple().with_display(DisplayKind::Block));
+    r.insert("center", TagSpec::simple().with_display(DisplayKind::Block));
+    r.insert("right", TagSpec::simple().with_display(DisplayKind::Block));
+    // 中身は grammar 側で verbatim に扱う。値は言語名
+    r.insert(
+        "code",
+        TagSpec::with_value(Some(is_valid_code_language)).with_display(DisplayKind::Block),
+    );
+    // 空白・改行をそのまま残す整形済みテキスト。`[code]` と違い中身の BBCode は解釈する
+    r.insert("pre", TagSpec::simple().with_display(DisplayKind::Block));
+    r.insert(
+        "list",
+        TagSpec::with_value(Some(is_valid_list_type))
+            .with_implicit_items()
+            .with_display(DisplayKind::Block),
+    );
+    // `[*]..[/*]` と明示的に閉じた項目
+    r.insert(
+        "*",
+        TagSpec::simple()
+            .with_display(DisplayKind::Block)
+            .with_required_parent("list"),
+    );
+}
+
+/// 画像
+#[cfg(feature = "tags-media")]
+fn insert_media_tags(r: &mut TagRegistry) {
+    // `[img]https://..[/img]` / `[img=100x50]https://..[/img]`
+    r.insert(
+        "img",
+        TagSpec::simple()
+            .with_length_value(LengthRule::image_size())
+            .with_named_attrs(&["alt"])
+            .with_element_validator(validate_img_element),
+    );
+}
+
+/// color::Color で読める色（形式は問わない。BbCodeOptions::color_formats はパース時に適用する）
+pub(crate) fn is_valid_color_value(s: &str) -> bool {
+    Color::parse(s).is_some()
+}
+
+/// 色を RGB に変換する（不透明度は無視する）
+pub(crate) fn color_to_rgb(value: &str) -> Option<(u8, u8, u8)> {
+    Color::parse(value).map(|c| (c.r, c.g, c.b))
+}
+
+/// WCAG 2.x のコントラスト比（1.0〜21.0）
+pub(crate) fn contrast_ratio(a: (u8, u8, u8), b: (u8, u8, u8)) -> f32 {
+    fn luminance((r, g, b): (u8, u8, u8)) -> f32 {
+        let channel = |c: u8| {
+            let c = f32::from(c) / 255.0;
+            if c <= 0.039_28 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        0.2126 * channel(r) + 0.7152 * channel(g) + 0.0722 * channel(b)
+    }
+    let (la, lb) = (luminance(a), luminance(b));
+    (la.max(lb) + 0.05) / (la.min(lb) + 0.05)
+}
+
+/// `[size]` の値属性を既定の LengthRule::font_size で読んだもの
+pub(crate) fn font_size(el: &Element) -> Option<Length> {
+    static RULE: Lazy<LengthRule> = Lazy::new(LengthRule::font_size);
+    RULE.parse(attr_value(el)?)
+}
+
+/// 英数字・空白・`_`・`-` のみのフォント名（CSS に埋め込むため記号は許可しない）
+pub(crate) fn is_valid_font_value(s: &str) -> bool {
+    static FONT_RE: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r"^[A-Za-z0-9][A-Za-z0-9 _-]{0,63}$").expect("font regex must be valid")
     });
-    COLOR_RE.is_match(s.trim())
+    FONT_RE.is_match(s.trim())
+}
+
+/// http(s) / mailto の絶対URL、または `/` 始まりのサイト内パス・`#` 始まりのページ内リンク
+pub(crate) fn is_valid_url(s: &str) -> bool {
+    let s = s.trim();
+    if s.is_empty()
+        || s.chars().any(|c| {
+            c.is_whitespace() || c.is_control() || matches!(c, '"' | '\'' | '<' | '>' | '`')
+        })
+    {
+        return false;
+    }
+    let lower = s.to_ascii_lowercase();
+    if let Some(rest) = lower
+        .strip_prefix("http://")
+        .or_else(|| lower.strip_prefix("https://"))
+    {
+        return !rest.is_empty() && !rest.starts_with('/');
+    }
+    if let Some(rest) = lower.strip_prefix("mailto:") {
+        return rest.contains('@');
+    }
+    (s.starts_with('/') && !s.starts_with("//")) || s.starts_with('#')
+}
+
+/// リンク先のホスト名（小文字、ユーザー情報・ポートを除く）。mailto はメールアドレスのドメイン
+/// サイト内パス・ページ内リンクは None
+pub(crate) fn url_host(s: &str) -> Option<String> {
+    let lower = s.trim().to_ascii_lowercase();
+    if let Some(rest) = lower.strip_prefix("mailto:") {
+        let addr = rest.split(['?', '#']).next()?;
+        return addr.rsplit_once('@').map(|(_, d)| d.to_string());
+    }
+    let rest = lower
+        .strip_prefix("http://")
+        .or_else(|| lower.strip_prefix("https://"))?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host_port = authority.rsplit_once('@').map_or(authority, |(_, h)| h);
+    let host = match host_port.strip_prefix('[') {
+        // IPv6 アドレス
+        Some(v6) => v6.split(']').next()?,
+        None => host_port.split(':').next()?,
+    };
+    let host = host.trim_end_matches('.');
+    (!host.is_empty()).then(|| host.to_string())
+}
+
+/// 画像は http(s) の絶対URLかサイト内パスのみ
+pub(crate) fn is_valid_image_url(s: &str) -> bool {
+    let lower = s.trim().to_ascii_lowercase();
+    is_valid_url(s)
+        && (lower.starts_with("http://") || lower.starts_with("https://") || lower.starts_with('/'))
+}
+
+/// `[img=WxH]` の表示サイズ（幅, 高さのピクセル数）を既定の LengthRule::image_size で読んだもの
+pub(crate) fn image_size(el: &Element) -> Option<(u32, u32)> {
+    static RULE: Lazy<LengthRule> = Lazy::new(LengthRule::image_size);
+    let (w, h) = RULE.parse_dimensions(attr_value(el)?)?;
+    Some((w.pixels()?, h.pixels()?))
+}
+
+pub(crate) fn is_valid_code_language(s: &str) -> bool {
+    static LANG_RE: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"^[A-Za-z0-9_+#-]{1,32}$").expect("language regex must be valid"));
+    LANG_RE.is_match(s.trim())
+}
+
+/// `[list=1]` / `[list=a]` など HTML の ol type に対応するもの
+#[cfg(feature = "tags-layout")]
+pub(crate) fn is_valid_list_type(s: &str) -> bool {
+    matches!(s.trim(), "1" | "a" | "A" | "i" | "I")
+}
+
+/// 子が Text 1つだけならその文字列を返す
+pub(crate) fn single_text_child(el: &Element) -> Option<&str> {
+    match el.children.as_slice() {
+        [Node::Text { text, .. }] => Some(text.as_str()),
+        _ => None,
+    }
+}
+
+/// 値属性が無い `[url]` は中身がURLでなければならない
+#[cfg(feature = "tags-basic")]
+fn validate_url_element(el: &Element) -> bool {
+    if el.attrs.iter().any(|(k, _)| k == "value") {
+        return true;
+    }
+    single_text_child(el).is_some_and(is_valid_url)
+}
+
+/// `[anchor]` / `[goto]` は名前の指定が必須
+#[cfg(feature = "tags-basic")]
+fn has_value_attr(el: &Element) -> bool {
+    el.attrs.iter().any(|(k, _)| k == "value")
+}
+
+/// `[tag]` は値か中身のどちらかで話題名を指定する
+#[cfg(feature = "tags-basic")]
+fn validate_tag_element(el: &Element) -> bool {
+    if has_value_attr(el) {
+        return true;
+    }
+    single_text_child(el).is_some_and(is_valid_hashtag)
+}
+
+#[cfg(feature = "tags-basic")]
+pub(crate) fn is_valid_hashtag(s: &str) -> bool {
+    hashtag_topic(s).is_some()
+}
+
+/// 先頭の `#` を除いた話題名。英数字・`_`・`-` の1〜64文字
+#[cfg(feature = "tags-basic")]
+pub(crate) fn hashtag_topic(s: &str) -> Option<&str> {
+    let s = s.trim();
+    let topic = s.strip_prefix('#').unwrap_or(s);
+    let ok = !topic.is_empty()
+        && topic.chars().count() <= 64
+        && topic
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+    ok.then_some(topic)
+}
+
+/// slug にして1文字以上残る名前
+#[cfg(feature = "tags-basic")]
+pub(crate) fn is_valid_anchor_name(s: &str) -> bool {
+    anchor_slug(s).is_some()
+}
+
+/// アンカー名を `id` 属性に安全な文字（英数字・`-`・`_`）だけの slug にする
+/// 空白は `-` にまとめ、それ以外の記号は捨てる。最大64文字
+pub(crate) fn anchor_slug(s: &str) -> Option<String> {
+    let mut slug = String::new();
+    for c in s.trim().chars() {
+        if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+            slug.push(c.to_ascii_lowercase());
+        } else if c.is_whitespace() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+        if slug.len() >= 64 {
+            break;
+        }
+    }
+    let slug = slug.trim_matches('-');
+    (!slug.is_empty()).then(|| slug.to_string())
+}
+
+/// `[img]` の中身は画像URLでなければならない
+#[cfg(feature = "tags-media")]
+fn validate_img_element(el: &Element) -> bool {
+    single_text_child(el).is_some_and(is_valid_image_url)
+}
+
+/// phpBB / vBulletin 形式の `名前;投稿ID` を `author` / `post_id` に分解する
+/// 末尾が数字でなければ全体を名前として扱う
+pub fn split_author_post_id(raw: &str) -> Option<Vec<(String, String)>> {
+    let mut attrs = vec![];
+    match raw.rsplit_once(';') {
+        Some((author, id))
+            if !id.trim().is_empty() && id.trim().bytes().all(|b| b.is_ascii_digit()) =>
+        {
+            attrs.push(("author".to_string(), author.trim().to_string()));
+            attrs.push(("post_id".to_string(), id.trim().to_string()));
+        }
+        _ => attrs.push(("author".to_string(), raw.trim().to_string())),
+    }
+    Some(attrs)
+}
+
+/// `[email]` の宛先。`user@example.com` の形で、`mailto:` を付けるとリンク先として使えるもの
+pub(crate) fn is_valid_email(s: &str) -> bool {
+    let s = s.trim();
+    match s.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && !local.contains(':')
+                && domain.split('.').all(|label| {
+                    !label.is_empty() && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+                })
+                && is_valid_url(&format!("mailto:{s}"))
+        }
+        None => false,
+    }
+}
+
+/// 値属性が無い `[email]` は中身がメールアドレスでなければならない
+pub(crate) fn validate_email_element(el: &Element) -> bool {
+    if el.attrs.iter().any(|(k, _)| k == "value") {
+        return true;
+    }
+    single_text_child(el).is_some_and(is_valid_email)
+}
+
+/// XenForo 形式の `名前, post: 123, member: 45` を分解する
+pub fn split_xenforo_quote(raw: &str) -> Option<Vec<(String, String)>> {
+    let mut parts = raw.split(',');
+    let author = parts.next().unwrap_or_default().trim();
+    let mut attrs = vec![("author".to_string(), author.to_string())];
+    for part in parts {
+        let (key, value) = part.split_once(':')?;
+        let value = value.trim();
+        if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        match key.trim() {
+            "post" => attrs.push(("post_id".to_string(), value.to_string())),
+            "member" => attrs.push(("member_id".to_string(), value.to_string())),
+            _ => return None,
+        }
+    }
+    Some(attrs)
 }
@@ -1,2 +1,68 @@
+pub mod asciidoc;
+pub mod cache;
+pub mod css;
 pub mod html;
-pub use html::ast_to_html;
+pub mod markdown;
+pub mod plain;
+pub mod resolvers;
+pub mod rst;
+pub mod rtf;
+pub use asciidoc::ast_to_asciidoc;
+pub use cache::{subtree_hash, LruRenderCache, RenderCache};
+pub use css::{CssProperty, StyleBuilder};
+pub use html::{
+    ast_to_html, ast_to_html_async, ast_to_html_cached, ast_to_html_with,
+    ast_to_html_with_placeholders, ast_to_html_with_resolvers, ast_to_html_with_source_map,
+    fill_placeholders, HtmlRenderOptions, HtmlTarget, MissingAltPolicy, RenderContext, SourceMap,
+    SourceMapping, TagOutput, TagRenderFn,
+};
+pub use markdown::{ast_to_markdown, ast_to_markdown_with, MarkdownDialect};
+pub use plain::ast_to_plain_text;
+pub use resolvers::{
+    resolve_async, AsyncResolvers, Resolver, ResolverRequests, Resolvers, UrlRewriter,
+};
+pub use rst::ast_to_rst;
+pub use rtf::ast_to_rtf;
+
+use crate::ast::{Element, Node, Span};
+
+/// `Renderer::enter` の戻り値。子要素を辿るかどうか
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visit {
+    Children,
+    /// 子要素は renderer 側で出力済み（`[code]` / `[img]` など）
+    Skip,
+}
+
+/// 出力形式ごとの renderer が実装するトレイト
+/// 木の走査は `walk` が行うので、各形式は開始・終了・テキストの出力だけを書けばよい
+pub trait Renderer {
+    fn text(&mut self, text: &str, span: Span);
+    fn enter(&mut self, el: &Element) -> Visit;
+    /// `enter` が `Skip` を返した場合も必ず呼ばれる
+    fn exit(&mut self, el: &Element);
+}
+
+/// AST を深さ優先で辿り、renderer に通知する
+pub fn walk<R: Renderer + ?Sized>(nodes: &[Node], renderer: &mut R) {
+    for n in nodes {
+        match n {
+            Node::Text { span, text } => renderer.text(text, *span),
+            Node::Element(el) => {
+                if renderer.enter(el) == Visit::Children {
+                    walk(&el.children, renderer);
+                }
+                renderer.exit(el);
+            }
+            Node::Unsupported(_) => {}
+        }
+    }
+}
+
+/// renderer 共通: `value` 属性を取り出す
+pub(crate) fn attr_value(el: &Element) -> Option<&str> {
+    el.attrs
+        .iter()
+        .find(|(k, _)| k == "value")
+        .map(|(_, v)| v.as_str())
+}
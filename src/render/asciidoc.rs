@@ -0,0 +1,377 @@
+use crate::ast::{Element, Node, Span, TagName};
+use crate::registry::{
+    anchor_slug, image_size, is_valid_code_language, is_valid_color_value, is_valid_image_url,
+    is_valid_url, single_text_child,
+};
+use crate::render::{attr_value, walk, Renderer, Visit};
+
+/// AST を AsciiDoc に変換する（Antora / Asciidoctor のドキュメントへの取り込み向け）
+/// 装飾は単語の途中でも効く unconstrained 形式（`**` / `__` / `##`）で出力する
+/// 同じ記号の装飾は入れ子にできないので、内側のものは中身だけ出力する
+pub fn ast_to_asciidoc(nodes: &[Node]) -> String {
+    let mut renderer = AsciiDocRenderer::default();
+    walk(nodes, &mut renderer);
+    renderer.out
+}
+
+struct Frame {
+    name: TagName,
+    close: String,
+    /// 開始記号を出力する前の位置
+    open_at: usize,
+    start: usize,
+    /// `[list]` の行頭記号（`*` / `.`）
+    marker: Option<char>,
+}
+
+#[derive(Default)]
+struct AsciiDocRenderer {
+    out: String,
+    stack: Vec<Frame>,
+    /// 直前がブロック要素で、続く本文の前に空行が要る
+    after_block: bool,
+}
+
+impl AsciiDocRenderer {
+    /// ブロックの前後は空行で区切る
+    fn ensure_blank_line(&mut self) {
+        if self.out.is_empty() {
+            return;
+        }
+        let newlines = self.out.len() - self.out.trim_end_matches('\n').len();
+        for _ in newlines..2 {
+            self.out.push('\n');
+        }
+    }
+
+    fn at_line_start(&self) -> bool {
+        self.out.is_empty() || self.out.ends_with('\n')
+    }
+
+    fn begin_block(&mut self) {
+        // 項目の中のブロックは空行ではなく `+` で項目に続ける
+        if self.stack.last().is_some_and(|f| f.name == "*") {
+            if !self.at_line_start() {
+                self.out.push('\n');
+            }
+            self.out.push_str("+\n");
+        } else {
+            self.ensure_blank_line();
+        }
+        self.after_block = false;
+    }
+
+    fn end_block(&mut self) {
+        if !self.out.ends_with('\n') {
+            self.out.push('\n');
+        }
+        self.after_block = true;
+    }
+
+    /// 囲んでいる `[quote]` の数（区切り線の長さを変えて入れ子にする）
+    fn quote_depth(&self) -> usize {
+        self.stack.iter().filter(|f| f.name == "quote").count()
+    }
+
+    fn push_text(&mut self, text: &str) {
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                self.out.push('\n');
+            }
+            // 行頭の空白は AsciiDoc ではリテラル段落になってしまう
+            let line = if self.at_line_start() {
+                line.trim_start_matches([' ', '\t'])
+            } else {
+                line
+            };
+            let line_start = self.at_line_start();
+            self.out.push_str(&escape_asciidoc(line, line_start));
+        }
+    }
+}
+
+impl Renderer for AsciiDocRenderer {
+    fn text(&mut self, text: &str, _span: Span) {
+        let in_list = self.stack.last().is_some_and(|f| f.name == "list");
+        if in_list && text.trim().is_empty() {
+            return;
+        }
+        if self.after_block {
+            if text.trim().is_empty() {
+                return;
+            }
+            self.ensure_blank_line();
+            self.after_block = false;
+            self.push_text(text.trim_start_matches(['\n', '\r']));
+            return;
+        }
+        self.push_text(text);
+    }
+
+    fn enter(&mut self, el: &Element) -> Visit {
+        let is_block = matches!(el.name.as_str(), "quote" | "list" | "code" | "*");
+        if self.after_block && !is_block {
+            self.ensure_blank_line();
+            self.after_block = false;
+        }
+        let mut marker = None;
+        let open_at = self.out.len();
+        let (close, visit) = match el.name.as_str() {
+            "b" => {
+                self.out.push_str("**");
+                ("**".to_string(), Visit::Children)
+            }
+            "i" => {
+                self.out.push_str("__");
+                ("__".to_string(), Visit::Children)
+            }
+            "u" => {
+                self.out.push_str("[.underline]##");
+                ("##".to_string(), Visit::Children)
+            }
+            "s" => {
+                self.out.push_str("[.line-through]##");
+                ("##".to_string(), Visit::Children)
+            }
+            "highlight" => {
+                self.out.push_str("##");
+                ("##".to_string(), Visit::Children)
+            }
+            // Asciidoctor の既定のスタイルシートには色名のロールがある
+            "color" => match attr_value(el)
+                .filter(|v| is_valid_color_value(v))
+                .map(str::trim)
+                .filter(|v| v.bytes().all(|b| b.is_ascii_alphabetic()))
+            {
+                Some(color) => {
+                    self.out
+                        .push_str(&format!("[.{}]##", color.to_ascii_lowercase()));
+                    ("##".to_string(), Visit::Children)
+                }
+                None => (String::new(), Visit::Children),
+            },
+            "kbd" | "tt" => {
+                self.out.push_str("``");
+                ("``".to_string(), Visit::Children)
+            }
+            "url" => {
+                let href = attr_value(el).or_else(|| single_text_child(el));
+                match href.filter(|h| is_valid_url(h)) {
+                    Some(href) => {
+                        self.out.push_str("link:");
+                        self.out.push_str(&escape_macro_target(href.trim()));
+                        self.out.push('[');
+                        // 中身がそのままURLなら表示文字列は省略する
+                        if attr_value(el).is_none() {
+                            ("]".to_string(), Visit::Skip)
+                        } else {
+                            ("]".to_string(), Visit::Children)
+                        }
+                    }
+                    None => (String::new(), Visit::Children),
+                }
+            }
+            "anchor" | "goto" => match attr_value(el).and_then(anchor_slug) {
+                Some(slug) if el.name == "anchor" => {
+                    self.out.push_str(&format!("[[{slug}]]"));
+                    (String::new(), Visit::Children)
+                }
+                Some(slug) => {
+                    self.out.push_str(&format!("xref:{slug}["));
+                    ("]".to_string(), Visit::Children)
+                }
+                None => (String::new(), Visit::Children),
+            },
+            "img" => {
+                if let Some(src) = single_text_child(el).filter(|s| is_valid_image_url(s)) {
+                    self.out.push_str("image:");
+                    self.out.push_str(&escape_macro_target(src.trim()));
+                    self.out.push('[');
+                    if let Some((w, h)) = image_size(el) {
+                        self.out.push_str(&format!(",{w},{h}"));
+                    }
+                    self.out.push(']');
+                }
+                (String::new(), Visit::Skip)
+            }
+            "code" => {
+                self.begin_block();
+                self.out.push_str("[source");
+                if let Some(lang) = attr_value(el).filter(|v| is_valid_code_language(v)) {
+                    self.out.push(',');
+                    self.out.push_str(lang.trim());
+                }
+                self.out.push_str("]\n");
+                let code = single_text_child(el).unwrap_or_default();
+                let code = code.trim_matches('\n');
+                // 中身に区切り線と同じ行があれば区切り線を長くする
+                let mut fence = "----".to_string();
+                while code.lines().any(|l| l.trim_end() == fence) {
+                    fence.push('-');
+                }
+                self.out.push_str(&fence);
+                self.out.push('\n');
+                if !code.is_empty() {
+                    self.out.push_str(code);
+                    self.out.push('\n');
+                }
+                self.out.push_str(&fence);
+                self.out.push('\n');
+                (String::new(), Visit::Skip)
+            }
+            "quote" => {
+                let fence = "_".repeat(4 + self.quote_depth());
+                self.begin_block();
+                let author = el
+                    .attrs
+                    .iter()
+                    .find(|(k, _)| k == "author" || k == "value")
+                    .map(|(_, v)| v.trim())
+                    .filter(|v| !v.is_empty());
+                match author {
+                    Some(author) => {
+                        self.out.push_str("[quote,\"");
+                        self.out
+                            .push_str(&escape_asciidoc(author, false).replace('"', "&#34;"));
+                        self.out.push_str("\"]\n");
+                    }
+                    None => self.out.push_str("[quote]\n"),
+                }
+                self.out.push_str(&fence);
+                self.out.push('\n');
+                (format!("{fence}\n"), Visit::Children)
+            }
+            "list" => {
+                // 入れ子のリストは行頭記号を重ねて表す
+                let parent = self
+                    .stack
+                    .iter()
+                    .rev()
+                    .find(|f| f.name == "list")
+                    .and_then(|f| f.marker);
+                let c = if attr_value(el).is_some() { '.' } else { '*' };
+                marker = Some(c);
+                if parent.is_some() {
+                    if !self.at_line_start() {
+                        self.out.push('\n');
+                    }
+                } else {
+                    self.ensure_blank_line();
+                }
+                self.after_block = false;
+                (String::new(), Visit::Children)
+            }
+            "*" => {
+                if !self.at_line_start() {
+                    self.out.push('\n');
+                }
+                self.after_block = false;
+                let depth = self.stack.iter().filter(|f| f.name == "list").count();
+                let c = self
+                    .stack
+                    .iter()
+                    .rev()
+                    .find_map(|f| f.marker)
+                    .unwrap_or('*');
+                self.out.push_str(&c.to_string().repeat(depth.max(1)));
+                self.out.push(' ');
+                (String::new(), Visit::Children)
+            }
+            _ => (String::new(), Visit::Children),
+        };
+
+        // 同じ記号の装飾の内側では記号を付けない
+        let close = if is_mark(&close) && self.stack.iter().any(|f| f.close == close) {
+            self.out.truncate(open_at);
+            String::new()
+        } else {
+            close
+        };
+        self.stack.push(Frame {
+            name: el.name.clone(),
+            close,
+            open_at,
+            start: self.out.len(),
+            marker,
+        });
+        visit
+    }
+
+    fn exit(&mut self, _el: &Element) {
+        let Some(frame) = self.stack.pop() else {
+            return;
+        };
+        match frame.name.as_str() {
+            "quote" => {
+                if !self.at_line_start() {
+                    self.out.push('\n');
+                }
+                self.out.push_str(&frame.close);
+                self.end_block();
+                return;
+            }
+            "code" => {
+                self.end_block();
+                return;
+            }
+            "list" => {
+                self.end_block();
+                return;
+            }
+            "*" => {
+                let trimmed = self.out.trim_end().len().max(frame.start);
+                self.out.truncate(trimmed);
+                self.out.push('\n');
+                self.after_block = false;
+            }
+            _ => {}
+        }
+        // 中身が空の装飾は区切り線などとして解釈されてしまうので出力しない
+        if is_mark(&frame.close) && self.out.len() == frame.start {
+            self.out.truncate(frame.open_at);
+            return;
+        }
+        self.out.push_str(&frame.close);
+    }
+}
+
+/// 文字の装飾の終了記号か
+fn is_mark(close: &str) -> bool {
+    matches!(close, "**" | "__" | "##" | "``")
+}
+
+/// リンク・画像マクロの対象に使えない文字をパーセントエンコードする
+fn escape_macro_target(url: &str) -> String {
+    let mut out = String::with_capacity(url.len());
+    for c in url.chars() {
+        match c {
+            '[' => out.push_str("%5B"),
+            ']' => out.push_str("%5D"),
+            ' ' => out.push_str("%20"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// AsciiDoc の記法として解釈される記号を文字参照に置き換える
+/// バックスラッシュによるエスケープは記法ごとに効き方が違うので使わない
+/// 行頭ではブロックのタイトル・見出し・箇条書きなどになる記号も置き換える
+fn escape_asciidoc(input: &str, line_start: bool) -> String {
+    let mut out = String::with_capacity(input.len());
+    for (i, c) in input.chars().enumerate() {
+        let block_marker =
+            i == 0 && line_start && matches!(c, '.' | '=' | '-' | ':' | '/' | '|' | '\'');
+        if block_marker
+            || matches!(
+                c,
+                '*' | '_' | '`' | '#' | '+' | '^' | '~' | '[' | ']' | '{' | '}' | '<' | '&'
+            )
+        {
+            out.push_str(&format!("&#{};", u32::from(c)));
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
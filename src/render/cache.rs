@@ -0,0 +1,120 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+
+use crate::ast::{Element, Node};
+
+/// 部分木の描画結果を保持するキャッシュ
+/// キーは `subtree_hash` に描画オプションと祖先の文脈（`[pre]` の中か・親のタグ）を混ぜた値。
+/// キーは 64 ビットのハッシュだけで、元の部分木とは照合しない。衝突すると別の部分木の
+/// 描画結果を返すので、衝突を許せない用途（別の利用者の投稿を混ぜられない場合など）では
+/// キャッシュを使わずに描画する
+pub trait RenderCache {
+    fn get(&mut self, key: u64) -> Option<String>;
+    fn put(&mut self, key: u64, rendered: String);
+}
+
+/// 最近使われていないものから捨てる、件数上限付きのキャッシュ
+/// 使うたびに世代を進めて order の末尾に積み、古い世代の記録は捨てるときに読み飛ばす。
+/// get / put は償却 O(1)
+#[derive(Debug, Clone)]
+pub struct LruRenderCache {
+    capacity: usize,
+    /// キー → (描画結果, 最後に使った世代)
+    entries: HashMap<u64, (String, u64)>,
+    /// (キー, 世代) の記録。先頭ほど古い。entries の世代と違うものは古い記録
+    order: VecDeque<(u64, u64)>,
+    generation: u64,
+}
+
+impl LruRenderCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            generation: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// key に新しい世代を振って order の末尾に積む
+    fn touch(&mut self, key: u64) {
+        self.generation += 1;
+        if let Some(entry) = self.entries.get_mut(&key) {
+            entry.1 = self.generation;
+        }
+        self.order.push_back((key, self.generation));
+        // 古い記録が上限の2倍を超えたら詰める（上限ぶん使うごとに1回なので償却 O(1)）
+        if self.order.len() > self.capacity.max(1) * 2 {
+            let entries = &self.entries;
+            self.order
+                .retain(|(k, g)| entries.get(k).is_some_and(|(_, current)| current == g));
+        }
+    }
+}
+
+impl RenderCache for LruRenderCache {
+    fn get(&mut self, key: u64) -> Option<String> {
+        let hit = self.entries.get(&key)?.0.clone();
+        self.touch(key);
+        Some(hit)
+    }
+
+    fn put(&mut self, key: u64, rendered: String) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.entries.insert(key, (rendered, 0));
+        self.touch(key);
+        while self.entries.len() > self.capacity {
+            let Some((oldest, generation)) = self.order.pop_front() else {
+                break;
+            };
+            if self
+                .entries
+                .get(&oldest)
+                .is_some_and(|(_, g)| *g == generation)
+            {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// 要素の部分木のハッシュ（タグ名・属性・テキストから計算し、span は含めない）
+/// 別の投稿に貼られた同じ引用は同じ値になる
+pub fn subtree_hash(el: &Element) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hash_element(el, &mut hasher);
+    hasher.finish()
+}
+
+fn hash_element(el: &Element, hasher: &mut DefaultHasher) {
+    el.name.hash(hasher);
+    el.attrs.hash(hasher);
+    el.children.len().hash(hasher);
+    for n in &el.children {
+        match n {
+            Node::Text { text, .. } => {
+                0u8.hash(hasher);
+                text.hash(hasher);
+            }
+            Node::Element(child) => {
+                1u8.hash(hasher);
+                hash_element(child, hasher);
+            }
+            Node::Unsupported(node) => {
+                2u8.hash(hasher);
+                node.raw.hash(hasher);
+            }
+        }
+    }
+}
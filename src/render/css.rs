@@ -0,0 +1,121 @@
+use crate::length::Length;
+use crate::registry::{is_valid_color_value, is_valid_font_value};
+
+/// style 属性に出力できるプロパティ
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum CssProperty {
+    /// `#RGB` / `#RRGGBB` / 色名、または `var(--bb-red, red)`
+    Color,
+    /// Color と同じ
+    BackgroundColor,
+    /// `small` などのキーワード、または単位付きの長さ（`150%` / `16px` / `1.5em` / `12pt`、3桁まで）
+    FontSize,
+    /// 英数字・空白・`_`・`-` のみのフォント名
+    FontFamily,
+    /// `left` / `center` / `right` / `justify`
+    TextAlign,
+}
+
+impl CssProperty {
+    pub fn name(self) -> &'static str {
+        match self {
+            CssProperty::Color => "color",
+            CssProperty::BackgroundColor => "background-color",
+            CssProperty::FontSize => "font-size",
+            CssProperty::FontFamily => "font-family",
+            CssProperty::TextAlign => "text-align",
+        }
+    }
+
+    /// value をこのプロパティの値として出力できるか
+    pub fn accepts(self, value: &str) -> bool {
+        match self {
+            CssProperty::Color | CssProperty::BackgroundColor => {
+                is_valid_color_value(value) || is_color_variable(value)
+            }
+            CssProperty::FontSize => is_font_size(value),
+            CssProperty::FontFamily => is_valid_font_value(value),
+            CssProperty::TextAlign => matches!(value, "left" | "center" | "right" | "justify"),
+        }
+    }
+}
+
+/// 許可したプロパティと検証した値だけからなるインラインスタイル（style 属性）
+/// 検証を通った値は CSS の宣言や属性値の外に出られる文字を含まない。
+/// 描画関数（TagRenderFn）で style 属性を出力する場合にも使える
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StyleBuilder {
+    declarations: Vec<(CssProperty, String)>,
+}
+
+impl StyleBuilder {
+    /// 宣言を追加する。値が使えなければ追加せずに false を返す
+    pub fn push(&mut self, property: CssProperty, value: &str) -> bool {
+        let value = value.trim();
+        if !property.accepts(value) {
+            return false;
+        }
+        self.declarations.push((property, value.to_string()));
+        true
+    }
+
+    /// push と同じ（使えない値は無視する）
+    pub fn with(mut self, property: CssProperty, value: &str) -> Self {
+        self.push(property, value);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.declarations.is_empty()
+    }
+
+    /// `color:red;font-size:large` の形
+    pub fn declarations(&self) -> String {
+        let declarations: Vec<String> = self
+            .declarations
+            .iter()
+            .map(|(property, value)| format!("{}:{value}", property.name()))
+            .collect();
+        declarations.join(";")
+    }
+
+    /// 開始タグに置く ` style="…"`。宣言が無ければ空文字列
+    pub fn to_attr(&self) -> String {
+        if self.is_empty() {
+            return String::new();
+        }
+        format!(" style=\"{}\"", self.declarations())
+    }
+}
+
+/// `var(--bb-red, red)` の形（HtmlRenderOptions::color_variables）
+fn is_color_variable(value: &str) -> bool {
+    let Some((name, fallback)) = value
+        .strip_prefix("var(--bb-")
+        .and_then(|rest| rest.strip_suffix(')'))
+        .and_then(|rest| rest.split_once(", "))
+    else {
+        return false;
+    };
+    !name.is_empty()
+        && name.bytes().all(|b| b.is_ascii_lowercase())
+        && name.eq_ignore_ascii_case(fallback)
+}
+
+fn is_font_size(value: &str) -> bool {
+    const KEYWORDS: [&str; 10] = [
+        "xx-small",
+        "x-small",
+        "small",
+        "medium",
+        "large",
+        "x-large",
+        "xx-large",
+        "xxx-large",
+        "smaller",
+        "larger",
+    ];
+    KEYWORDS.contains(&value)
+        || Length::parse(value).is_some_and(|l| l.unit.is_some() && l.value < 1000.0)
+}
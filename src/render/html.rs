@@ -1,145 +1,1418 @@
-use crate::ast::{Element, Node};
-use crate::registry::TagRegistry;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+
+use crate::ast::{Element, Node, Span, TagName};
+use crate::escape::{escape_attr, escape_text, escape_url_component};
+#[cfg(feature = "tags-layout")]
+use crate::extract::quote_source;
+#[cfg(feature = "tags-basic")]
+use crate::length::Length;
+#[cfg(all(feature = "url", feature = "tags-basic"))]
+use crate::link::{display_url, normalize_url};
+#[cfg(feature = "tags-basic")]
+use crate::lookalike::{deceptive_link_of, display_host};
+use crate::registry::is_valid_url;
+#[cfg(any(feature = "tags-basic", feature = "tags-media", feature = "tags-embed"))]
+use crate::registry::single_text_child;
+#[cfg(feature = "tags-basic")]
+use crate::registry::{anchor_slug, font_size, hashtag_topic};
+#[cfg(feature = "tags-media")]
+use crate::registry::{image_size, is_valid_image_url};
+#[cfg(feature = "tags-layout")]
+use crate::registry::{is_valid_code_language, is_valid_list_type};
+#[cfg(any(feature = "tags-basic", feature = "tags-layout"))]
+use crate::render::attr_value;
+use crate::render::cache::{subtree_hash, RenderCache};
+#[cfg(any(feature = "tags-basic", feature = "tags-layout"))]
+use crate::render::css::{CssProperty, StyleBuilder};
+use crate::render::resolvers::{
+    element_key, is_emoji_name_char, resolve_async, AsyncResolvers, ResolverRequests, Resolvers,
+};
+use crate::render::{walk, Renderer, Visit};
+use crate::template::TagTemplate;
 
 pub fn ast_to_html(nodes: &[Node]) -> String {
-    let mut out = String::new();
+    ast_to_html_with(nodes, &HtmlRenderOptions::default())
+}
+
+/// HTML 出力の挙動を切り替えるオプション
+/// クレートの外では `HtmlRenderOptions::default().with_target(..)` のように組み立てる
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct HtmlRenderOptions {
+    /// 各要素に入力上の範囲を `data-bb-start` / `data-bb-end` 属性として付ける
+    /// （ライブプレビューとエディタの位置同期用）
+    pub emit_source_spans: bool,
+    /// `[tag]` の話題名から検索ページなどの URL を作る。None ならリンクにしない
+    /// 話題名を URL に埋め込むときは escape::escape_url_component を通すこと
+    pub tag_url: Option<fn(&str) -> String>,
+    /// 出力先。メールでは Outlook などでも崩れないマークアップに限る
+    pub target: HtmlTarget,
+    /// `[html]` の中身を HTML としてそのまま出力する。false ならテキストとしてエスケープする
+    /// 管理者が書いた告知など、投稿者を信頼できる場合だけ有効にすること
+    pub trust_raw_html: bool,
+    /// `[html]` の中身を出力する前に通すサニタイザ（trust_raw_html が有効な場合のみ）
+    pub sanitize_html: Option<fn(&str) -> String>,
+    /// テンプレートで定義したタグ（TagRegistry::templates()）。組み込みのタグより優先する
+    pub templates: HashMap<String, TagTemplate>,
+    /// `[url]` のリンク先を正規化する（IDN の punycode 化・パーセントエンコードなど）
+    /// 中身がそのまま URL のリンクは、正規化した URL の読みやすい形を表示する
+    #[cfg(feature = "url")]
+    pub normalize_urls: bool,
+    /// サイト内パス（`/thread/5`）のリンク・画像を解決する基準（`https://forum.example` など）
+    /// RSS やメールなど、別のホストで表示される場合に指定する
+    pub base_url: Option<String>,
+    /// アクセシビリティを優先した出力にする（WCAG 向け）
+    /// `<b>` / `<i>` の代わりに `<strong>` / `<em>` を使い、ネタバレには role / aria-label を付け、
+    /// 代替テキスト（`[img alt=..]`）の無い画像は missing_alt に従って扱う
+    pub accessible: bool,
+    /// accessible が有効な場合の、代替テキストの無い画像の扱い
+    pub missing_alt: MissingAltPolicy,
+    /// `[color=red]` の色名を CSS 変数 `var(--bb-red, red)` として出力する
+    /// サイトの CSS で変数を定義すれば、ダークモードなどで読みやすい色に差し替えられる
+    /// （メール向けでは CSS 変数が使えないので無視する）
+    pub color_variables: bool,
+    /// 表示が別のリンク先に見える `[url=..]`（lookalike::deceptive_link）の後に、
+    /// 実際のホストを `<span class="bb-link-host">[ホスト]</span>` として添える
+    pub reveal_link_hosts: bool,
+    /// 利用者が登録したタグの描画関数（タグの正規名 -> 関数）。組み込みのタグ・テンプレートより優先する
+    /// フィード向けでは使わない
+    pub tag_renderers: HashMap<String, TagRenderFn>,
+    /// 出力する class・id・data 属性の名前の先頭に付ける文字列（`my-forum-` など）
+    /// 任意のページに埋め込むときに、サイトの CSS と名前が衝突しないようにする。
+    /// 英数字・`-`・`_` 以外の文字は取り除く。ページ内リンク `[goto]` の参照先にも付ける
+    pub css_prefix: String,
+}
+
+impl HtmlRenderOptions {
+    pub fn with_emit_source_spans(mut self, enabled: bool) -> Self {
+        self.emit_source_spans = enabled;
+        self
+    }
+
+    pub fn with_tag_url(mut self, tag_url: fn(&str) -> String) -> Self {
+        self.tag_url = Some(tag_url);
+        self
+    }
+
+    pub fn with_target(mut self, target: HtmlTarget) -> Self {
+        self.target = target;
+        self
+    }
+
+    pub fn with_trust_raw_html(mut self, enabled: bool) -> Self {
+        self.trust_raw_html = enabled;
+        self
+    }
+
+    pub fn with_sanitize_html(mut self, sanitize: fn(&str) -> String) -> Self {
+        self.sanitize_html = Some(sanitize);
+        self
+    }
+
+    pub fn with_templates(mut self, templates: HashMap<String, TagTemplate>) -> Self {
+        self.templates = templates;
+        self
+    }
+
+    #[cfg(feature = "url")]
+    pub fn with_normalize_urls(mut self, enabled: bool) -> Self {
+        self.normalize_urls = enabled;
+        self
+    }
+
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = Some(base_url.into());
+        self
+    }
+
+    pub fn with_accessible(mut self, enabled: bool) -> Self {
+        self.accessible = enabled;
+        self
+    }
+
+    pub fn with_missing_alt(mut self, policy: MissingAltPolicy) -> Self {
+        self.missing_alt = policy;
+        self
+    }
+
+    pub fn with_color_variables(mut self, enabled: bool) -> Self {
+        self.color_variables = enabled;
+        self
+    }
+
+    pub fn with_reveal_link_hosts(mut self, enabled: bool) -> Self {
+        self.reveal_link_hosts = enabled;
+        self
+    }
+
+    pub fn with_tag_renderer(mut self, tag_name: &str, render: TagRenderFn) -> Self {
+        self.tag_renderers
+            .insert(tag_name.to_ascii_lowercase(), render);
+        self
+    }
+
+    pub fn with_css_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.css_prefix = prefix.into();
+        self
+    }
+}
+
+/// 利用者が登録するタグの描画関数。None なら中身だけを出力する
+pub type TagRenderFn = fn(&Element, &RenderContext<'_>) -> Option<TagOutput>;
+
+/// 描画関数に渡す、要素の置かれた位置などの情報
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct RenderContext<'a> {
+    pub options: &'a HtmlRenderOptions,
+    pub resolvers: &'a Resolvers,
+    /// 外側にある要素の数（最上位なら 0）
+    pub depth: usize,
+    /// 直接の親要素の名前
+    pub parent_tag: Option<&'a str>,
+    /// 外側にある要素の名前（外側から順）
+    ancestors: Vec<&'a str>,
+}
+
+impl RenderContext<'_> {
+    /// name の要素の中にあるか（直接の親でなくてもよい）
+    pub fn is_inside(&self, name: &str) -> bool {
+        self.ancestors.contains(&name)
+    }
+}
+
+/// 描画関数が出力する HTML。中身を続けて描画するなら open と close の間に置く
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagOutput {
+    pub open: String,
+    pub close: String,
+    pub visit: Visit,
+}
+
+impl TagOutput {
+    /// open と close で中身を囲む
+    pub fn wrap(open: impl Into<String>, close: impl Into<String>) -> Self {
+        Self {
+            open: open.into(),
+            close: close.into(),
+            visit: Visit::Children,
+        }
+    }
+
+    /// 中身を描画せず、html だけを出力する
+    pub fn replace(html: impl Into<String>) -> Self {
+        Self {
+            open: html.into(),
+            close: String::new(),
+            visit: Visit::Skip,
+        }
+    }
+}
+
+/// 代替テキストの無い画像の扱い
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum MissingAltPolicy {
+    /// `alt=""` のまま `data-missing-alt` 属性を付けて出力する（後から補えるように）
+    #[default]
+    Flag,
+    /// 画像を出力しない
+    Drop,
+}
+
+/// HTML の出力先
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum HtmlTarget {
+    /// ブラウザ向け
+    #[default]
+    Web,
+    /// メールクライアント向け
+    /// class や `<mark>` / `<blockquote>` / `<kbd>` などを使わず、インラインスタイルと
+    /// レイアウト用のテーブルで表す
+    Email,
+    /// AMP ページ向け。画像は `<amp-img>` にし、インラインスタイルは AMP で使える
+    /// プロパティ（color / background-color / text-align / font-size）に限る
+    #[cfg(feature = "amp")]
+    Amp,
+    /// RSS / Atom のフィード向け
+    /// リンク・画像は絶対 URL のものだけにし（base_url で解決できるサイト内パスを含む）、
+    /// 使うタグは多くのフィードリーダーが受け付けるものに限る。
+    /// テンプレートで定義したタグ・`[html]` の生の HTML・インラインスタイルは出力しない
+    Feed,
+}
+
+pub fn ast_to_html_with(nodes: &[Node], opts: &HtmlRenderOptions) -> String {
+    let mut renderer = HtmlRenderer::with_options(opts.clone());
+    walk(nodes, &mut renderer);
+    renderer.finish()
+}
+
+/// 描画ごとの resolvers を使って HTML を出力する（閲覧者ごとのプロフィールの URL・CDN など）
+pub fn ast_to_html_with_resolvers(
+    nodes: &[Node],
+    opts: &HtmlRenderOptions,
+    resolvers: &Resolvers,
+) -> String {
+    let mut renderer = HtmlRenderer::with_options(opts.clone()).with_resolvers(resolvers.clone());
+    walk(nodes, &mut renderer);
+    renderer.finish()
+}
+
+/// resolvers を非同期にまとめて解決してから HTML を出力する
+/// 問い合わせは種類ごとに1回で、描画そのものは同期的に行う
+pub async fn ast_to_html_async(
+    nodes: &[Node],
+    opts: &HtmlRenderOptions,
+    resolvers: &impl AsyncResolvers,
+) -> String {
+    let resolvers = resolve_async(nodes, resolvers).await;
+    ast_to_html_with_resolvers(nodes, opts, &resolvers)
+}
+
+/// 要素ごとの描画結果を cache に保存・再利用しながら HTML を出力する
+/// スレッド内で同じ引用が何度も現れる場合に再描画を省ける
+/// `emit_source_spans` が有効な場合と tag_renderers がある場合は、出力が位置に依存するのでキャッシュしない
+pub fn ast_to_html_cached(
+    nodes: &[Node],
+    opts: &HtmlRenderOptions,
+    cache: &mut dyn RenderCache,
+) -> String {
+    let mut renderer = HtmlRenderer::with_options(opts.clone());
+    if opts.emit_source_spans || !opts.tag_renderers.is_empty() {
+        walk(nodes, &mut renderer);
+    } else {
+        walk_cached(nodes, &mut renderer, cache, options_hash(opts));
+    }
+    renderer.finish()
+}
+
+/// `walk` と同じ順に辿るが、キャッシュにある要素は描画結果をそのまま出力する
+fn walk_cached(
+    nodes: &[Node],
+    renderer: &mut HtmlRenderer,
+    cache: &mut dyn RenderCache,
+    salt: u64,
+) {
     for n in nodes {
-        render_node(n, &mut out);
+        match n {
+            Node::Text { span, text } => renderer.text(text, *span),
+            Node::Element(el) => {
+                let key = renderer.cache_key(el, salt);
+                if let Some(hit) = cache.get(key) {
+                    renderer.out.push_str(&hit);
+                    continue;
+                }
+                let start = renderer.out.len();
+                if renderer.enter(el) == Visit::Children {
+                    walk_cached(&el.children, renderer, cache, salt);
+                }
+                renderer.exit(el);
+                cache.put(key, renderer.out[start..].to_string());
+            }
+            Node::Unsupported(_) => {}
+        }
     }
-    out
 }
 
-fn render_node(node: &Node, out: &mut String) {
-    match node {
-        Node::Text { text, .. } => {
-            let escaped = escape_html(text);
-            let replaced = replace_newline_with_br(&escaped);
-            out.push_str(&replaced);
+/// 出力に影響するオプションをキーに混ぜる
+pub(crate) fn options_hash(opts: &HtmlRenderOptions) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    opts.emit_source_spans.hash(&mut hasher);
+    opts.tag_url.map(|f| f as usize).hash(&mut hasher);
+    opts.target.hash(&mut hasher);
+    opts.trust_raw_html.hash(&mut hasher);
+    opts.sanitize_html.map(|f| f as usize).hash(&mut hasher);
+    let mut templates: Vec<_> = opts.templates.iter().collect();
+    templates.sort_unstable_by_key(|(name, _)| *name);
+    templates.hash(&mut hasher);
+    #[cfg(feature = "url")]
+    opts.normalize_urls.hash(&mut hasher);
+    opts.base_url.hash(&mut hasher);
+    opts.accessible.hash(&mut hasher);
+    opts.missing_alt.hash(&mut hasher);
+    opts.color_variables.hash(&mut hasher);
+    opts.reveal_link_hosts.hash(&mut hasher);
+    let mut renderers: Vec<_> = opts
+        .tag_renderers
+        .iter()
+        .map(|(name, f)| (name, *f as usize))
+        .collect();
+    renderers.sort_unstable();
+    renderers.hash(&mut hasher);
+    opts.css_prefix.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// HTML を出力し、出力上の範囲と入力上の範囲の対応表もあわせて返す
+/// プレビューでクリックした位置からエディタのカーソル位置を求める用途
+pub fn ast_to_html_with_source_map(nodes: &[Node]) -> (String, SourceMap) {
+    let mut renderer = HtmlRenderer::new();
+    renderer.source_map = Some(vec![]);
+    walk(nodes, &mut renderer);
+    let mut mappings = renderer.source_map.take().unwrap_or_default();
+    // 出力位置順。同じ位置から始まるものは外側（長い方）を先に
+    mappings.sort_by_key(|m| (m.output.start, std::cmp::Reverse(m.output.end)));
+    (renderer.finish(), SourceMap { mappings })
+}
+
+/// 出力 HTML のバイト範囲と、元になった入力のバイト範囲の組
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceMapping {
+    pub output: Range<usize>,
+    pub input: Span,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    pub mappings: Vec<SourceMapping>,
+}
+
+impl SourceMap {
+    /// 出力上の位置を含む最も内側のノードの入力範囲を返す
+    pub fn lookup(&self, output_offset: usize) -> Option<Span> {
+        self.mappings
+            .iter()
+            .filter(|m| m.output.contains(&output_offset))
+            .min_by_key(|m| m.output.len())
+            .map(|m| m.input)
+    }
+}
+
+/// 開いている要素の情報
+struct Frame {
+    name: TagName,
+    span: Span,
+    /// exit 時に出力する閉じタグ（フォールバック時は空）
+    close: String,
+    /// enter 時点の出力位置
+    start: usize,
+}
+
+#[derive(Default)]
+pub struct HtmlRenderer {
+    out: String,
+    stack: Vec<Frame>,
+    opts: HtmlRenderOptions,
+    resolvers: Resolvers,
+    /// 有効なら出力範囲と入力範囲の対応を記録する
+    source_map: Option<Vec<SourceMapping>>,
+}
+
+impl HtmlRenderer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_options(opts: HtmlRenderOptions) -> Self {
+        Self {
+            opts,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_resolvers(mut self, resolvers: Resolvers) -> Self {
+        self.resolvers = resolvers;
+        self
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+
+    /// テキストをエスケープし、`:name:` のうち emoji で解決できたものを絵文字の画像にする
+    fn escape_text_with_emoji(&self, text: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(colon) = rest.find(':') {
+            let after = &rest[colon + 1..];
+            let len = after
+                .find(|c: char| !is_emoji_name_char(c))
+                .unwrap_or(after.len());
+            let image = Some(&after[..len])
+                .filter(|name| !name.is_empty() && after[len..].starts_with(':'))
+                .and_then(|name| lookup_html(Lookup::Emoji, name, &self.opts, &self.resolvers));
+            let Some((image, _)) = image else {
+                out.push_str(&escape_text(&rest[..=colon]));
+                rest = after;
+                continue;
+            };
+            out.push_str(&escape_text(&rest[..colon]));
+            out.push_str(&image);
+            rest = &after[len + 1..];
         }
-        Node::Element(el) => render_element(el, out),
+        out.push_str(&escape_text(rest));
+        out
+    }
+
+    fn parent_name(&self) -> Option<&str> {
+        self.stack.last().map(|f| f.name.as_str())
+    }
+
+    /// el の描画結果のキャッシュのキー
+    /// 同じ部分木でも祖先によって出力が変わる（`[pre]` の中は改行を <br> にしない、
+    /// `[list]` の直下は空白を捨てる）ので、`[pre]` の中かどうかと親のタグ名も混ぜる
+    fn cache_key(&self, el: &Element, salt: u64) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        subtree_hash(el).hash(&mut hasher);
+        salt.hash(&mut hasher);
+        self.stack.iter().any(|f| f.name == "pre").hash(&mut hasher);
+        self.parent_name().hash(&mut hasher);
+        hasher.finish()
     }
 }
 
-fn render_element(el: &Element, out: &mut String) {
-    // tag spec が無い = unknown tag
-    let Some(_spec) = TagRegistry::get(&el.name) else {
-        // unknown tag: タグ自体は捨てて中身だけ表示
-        for c in &el.children {
-            render_node(c, out);
+impl Renderer for HtmlRenderer {
+    fn text(&mut self, text: &str, span: Span) {
+        // <ul> 直下に置けない空白だけのテキストは捨てる
+        if self.parent_name() == Some("list") && text.trim().is_empty() {
+            return;
         }
-        return;
+        let start = self.out.len();
+        let escaped = if self.resolvers.emoji.is_some() || self.resolvers.placeholders {
+            self.escape_text_with_emoji(text)
+        } else {
+            escape_text(text)
+        };
+        // `[pre]` の中の改行は <pre> に任せる
+        if self.stack.iter().any(|f| f.name == "pre") {
+            self.out.push_str(&escaped);
+        } else {
+            self.out.push_str(&replace_newline_with_br(&escaped));
+        }
+        if let Some(map) = self.source_map.as_mut() {
+            map.push(SourceMapping {
+                output: start..self.out.len(),
+                input: span,
+            });
+        }
+    }
+
+    fn enter(&mut self, el: &Element) -> Visit {
+        let start = self.out.len();
+        let custom = self
+            .opts
+            .tag_renderers
+            .get(el.name.as_str())
+            .filter(|_| self.opts.target != HtmlTarget::Feed);
+        let (close, visit) = match custom {
+            Some(render) => {
+                let ancestors: Vec<&str> = self.stack.iter().map(|f| f.name.as_str()).collect();
+                let ctx = RenderContext {
+                    options: &self.opts,
+                    resolvers: &self.resolvers,
+                    depth: ancestors.len(),
+                    parent_tag: ancestors.last().copied(),
+                    ancestors,
+                };
+                match render(el, &ctx) {
+                    Some(output) => {
+                        self.out.push_str(&output.open);
+                        (output.close, output.visit)
+                    }
+                    None => (String::new(), Visit::Children),
+                }
+            }
+            None => open_element(el, &self.opts, &self.resolvers, &mut self.out),
+        };
+        if self.opts.emit_source_spans {
+            // 出力した最初の開始タグに範囲を付ける（フォールバックで何も出していなければ付けない）
+            // プレースホルダならコメントの中に入れておき、fill_placeholders で埋めたタグに移す
+            if let Some(pos) = self.out[start..].find('>') {
+                let attrs = format!(
+                    " data-{}=\"{}\" data-{}=\"{}\"",
+                    prefixed(&self.opts, "bb-start"),
+                    el.span.start,
+                    prefixed(&self.opts, "bb-end"),
+                    el.span.end
+                );
+                let head = &self.out[start..=start + pos];
+                let pos = if head.starts_with("<!--bb:") && head.ends_with("-->") {
+                    pos - "--".len()
+                } else {
+                    pos
+                };
+                self.out.insert_str(start + pos, &attrs);
+            }
+        }
+        self.stack.push(Frame {
+            name: el.name.clone(),
+            span: el.span,
+            close,
+            start,
+        });
+        visit
+    }
+
+    fn exit(&mut self, _el: &Element) {
+        let Some(frame) = self.stack.pop() else {
+            return;
+        };
+        if frame.name == "*" {
+            // 次の `[*]` までの改行は項目の区切りなので出力しない
+            let body_start = frame.start + "<li>".len();
+            let mut end = self.out.len();
+            loop {
+                let body = self.out[body_start..end].trim_end();
+                match body.strip_suffix("<br>") {
+                    Some(rest) => end = body_start + rest.len(),
+                    None => {
+                        end = body_start + body.len();
+                        break;
+                    }
+                }
+            }
+            self.out.truncate(end);
+        }
+        self.out.push_str(&frame.close);
+        if let Some(map) = self.source_map.as_mut() {
+            map.push(SourceMapping {
+                output: frame.start..self.out.len(),
+                input: frame.span,
+            });
+        }
+    }
+}
+
+/// 開始タグを出力し、対応する閉じタグを返す
+/// 検証に失敗した要素はタグを出さず中身だけ表示する（閉じタグも空）
+fn open_element(
+    el: &Element,
+    opts: &HtmlRenderOptions,
+    resolvers: &Resolvers,
+    out: &mut String,
+) -> (String, Visit) {
+    #[cfg(any(feature = "tags-basic", feature = "tags-layout"))]
+    let simple = |out: &mut String, open: &str, close: &str| {
+        out.push_str(open);
+        (close.to_string(), Visit::Children)
     };
 
+    if opts.target == HtmlTarget::Feed {
+        if let Some(result) = open_feed_element(el, opts, resolvers, out) {
+            return result;
+        }
+    }
+
+    if let Some(template) = opts
+        .templates
+        .get(el.name.as_str())
+        .filter(|_| opts.target != HtmlTarget::Feed)
+    {
+        let Some((open, close)) = template.render(el) else {
+            return (String::new(), Visit::Children);
+        };
+        out.push_str(&open);
+        let visit = if template.has_content() {
+            Visit::Children
+        } else {
+            Visit::Skip
+        };
+        return (close, visit);
+    }
+
+    if opts.target == HtmlTarget::Email {
+        if let Some(result) = open_email_element(el, opts, resolvers, out) {
+            return result;
+        }
+    }
+    #[cfg(feature = "amp")]
+    if opts.target == HtmlTarget::Amp {
+        if let Some(result) = open_amp_element(el, opts, resolvers, out) {
+            return result;
+        }
+    }
+
     match el.name.as_str() {
-        "b" => {
-            out.push_str("<b>");
-            for c in &el.children {
-                render_node(c, out);
+        #[cfg(feature = "tags-basic")]
+        "b" if opts.accessible => simple(out, "<strong>", "</strong>"),
+        #[cfg(feature = "tags-basic")]
+        "i" if opts.accessible => simple(out, "<em>", "</em>"),
+        #[cfg(feature = "tags-basic")]
+        "b" => simple(out, "<b>", "</b>"),
+        #[cfg(feature = "tags-basic")]
+        "i" => simple(out, "<i>", "</i>"),
+        // 組み込みではなく、方言や利用者が登録した場合
+        #[cfg(feature = "tags-basic")]
+        "spoiler" if opts.accessible => simple(
+            out,
+            &format!(
+                "<span class=\"{}\" role=\"note\" aria-label=\"Spoiler\">",
+                prefixed(opts, "spoiler")
+            ),
+            "</span>",
+        ),
+        #[cfg(feature = "tags-basic")]
+        "spoiler" => simple(
+            out,
+            &format!("<span class=\"{}\">", prefixed(opts, "spoiler")),
+            "</span>",
+        ),
+        #[cfg(feature = "tags-basic")]
+        "u" => simple(out, "<u>", "</u>"),
+        #[cfg(feature = "tags-basic")]
+        "s" => simple(out, "<s>", "</s>"),
+        #[cfg(feature = "tags-basic")]
+        "kbd" => simple(out, "<kbd>", "</kbd>"),
+        #[cfg(feature = "tags-basic")]
+        "tt" => simple(out, "<code>", "</code>"),
+        #[cfg(feature = "tags-layout")]
+        "quote" => {
+            out.push_str("<blockquote>");
+            // 引用元があれば cite として出力（`[quote=Alice;123]` は名前だけ）
+            let (author, post_id) = quote_source(el);
+            if let Some(author) = author {
+                out.push_str("<cite>");
+                out.push_str(&escape_text(&author));
+                out.push_str("</cite>");
             }
-            out.push_str("</b>");
+            // 投稿 ID があれば、引用した投稿へのリンクを添える
+            if let Some((link, _)) =
+                post_id.and_then(|id| lookup_html(Lookup::QuotePost, &id, opts, resolvers))
+            {
+                out.push_str(&link);
+            }
+            ("</blockquote>".to_string(), Visit::Children)
         }
-        "i" => {
-            out.push_str("<i>");
-            for c in &el.children {
-                render_node(c, out);
+        #[cfg(feature = "tags-layout")]
+        "left" | "center" | "right" => styled(out, "div", CssProperty::TextAlign, &el.name),
+        #[cfg(feature = "tags-basic")]
+        "color" => {
+            // attrs["value"] を探す（parserが正規化済み）
+            // valueが無い / StyleBuilder の検証（render層で二重に守る）に失敗したら中身だけ
+            let color = attr_value(el).unwrap_or_default().trim();
+            let use_variable = opts.color_variables && opts.target != HtmlTarget::Email;
+            if use_variable && color.bytes().all(|b| b.is_ascii_alphabetic()) {
+                let variable = format!("var(--bb-{}, {color})", color.to_ascii_lowercase());
+                return styled(out, "span", CssProperty::Color, &variable);
             }
-            out.push_str("</i>");
+            styled(out, "span", CssProperty::Color, color)
         }
-        "u" => {
-            out.push_str("<u>");
-            for c in &el.children {
-                render_node(c, out);
+        #[cfg(feature = "tags-basic")]
+        "highlight" => match attr_value(el) {
+            None => simple(out, "<mark>", "</mark>"),
+            Some(v) => styled(out, "mark", CssProperty::BackgroundColor, v),
+        },
+        #[cfg(feature = "tags-basic")]
+        "size" => {
+            let Some(size) = font_size(el) else {
+                return (String::new(), Visit::Children);
+            };
+            styled(out, "span", CssProperty::FontSize, &css_font_size(size))
+        }
+        #[cfg(feature = "tags-basic")]
+        "big" => styled(out, "span", CssProperty::FontSize, "larger"),
+        #[cfg(feature = "tags-basic")]
+        "small" => styled(out, "span", CssProperty::FontSize, "smaller"),
+        #[cfg(feature = "tags-basic")]
+        "font" => {
+            let font = attr_value(el).unwrap_or_default();
+            styled(out, "span", CssProperty::FontFamily, font)
+        }
+        #[cfg(feature = "tags-basic")]
+        "url" => {
+            // 値属性が無ければ中身がそのままリンク先
+            let href = attr_value(el).or_else(|| single_text_child(el));
+            #[cfg(feature = "url")]
+            if opts.normalize_urls {
+                return open_normalized_link(el, href, opts, resolvers, out);
             }
-            out.push_str("</u>");
+            let Some(href) = href.filter(|h| is_valid_url(h)) else {
+                return (String::new(), Visit::Children);
+            };
+            out.push_str("<a href=\"");
+            out.push_str(&escape_attr(&resolve_url(href.trim(), opts, resolvers)));
+            out.push_str("\" rel=\"nofollow\">");
+            (close_link(el, opts), Visit::Children)
         }
-        "s" => {
-            out.push_str("<s>");
-            for c in &el.children {
-                render_node(c, out);
+        #[cfg(feature = "tags-basic")]
+        "anchor" | "goto" => {
+            let Some(slug) = attr_value(el).and_then(anchor_slug) else {
+                return (String::new(), Visit::Children);
+            };
+            if el.name == "anchor" {
+                out.push_str("<a id=\"");
+            } else {
+                out.push_str("<a href=\"#");
             }
-            out.push_str("</s>");
+            out.push_str(&prefixed(opts, &slug));
+            out.push_str("\">");
+            ("</a>".to_string(), Visit::Children)
         }
-        "quote" => {
-            out.push_str("<blockquote>");
-            for c in &el.children {
-                render_node(c, out);
+        #[cfg(feature = "tags-basic")]
+        "tag" => {
+            let Some(topic) = attr_value(el)
+                .or_else(|| single_text_child(el))
+                .and_then(hashtag_topic)
+            else {
+                return (String::new(), Visit::Children);
+            };
+            match opts.tag_url {
+                Some(resolve) => {
+                    out.push_str("<a href=\"");
+                    out.push_str(&escape_attr(&resolve(topic)));
+                    out.push_str("\" class=\"");
+                    out.push_str(&prefixed(opts, "hashtag"));
+                    out.push_str("\" rel=\"tag\">");
+                    ("</a>".to_string(), Visit::Children)
+                }
+                None => simple(
+                    out,
+                    &format!("<span class=\"{}\">", prefixed(opts, "hashtag")),
+                    "</span>",
+                ),
             }
-            out.push_str("</blockquote>");
         }
-        "left" => {
-            out.push_str("<div style=\"text-align:left\">");
-            for c in &el.children {
-                render_node(c, out);
+        #[cfg(feature = "tags-media")]
+        "img" => {
+            let Some(src) = single_text_child(el).filter(|s| is_valid_image_url(s)) else {
+                return (String::new(), Visit::Children);
+            };
+            if !keeps_image(el, opts) {
+                return (String::new(), Visit::Skip);
+            }
+            out.push_str("<img src=\"");
+            out.push_str(&escape_attr(&resolve_url(src.trim(), opts, resolvers)));
+            push_alt(el, opts, out);
+            if let Some((w, h)) = image_size(el) {
+                out.push_str(&format!(" width=\"{w}\" height=\"{h}\""));
             }
-            out.push_str("</div>");
+            out.push('>');
+            (String::new(), Visit::Skip)
         }
-        "center" => {
-            out.push_str("<div style=\"text-align:center\">");
-            for c in &el.children {
-                render_node(c, out);
+        #[cfg(feature = "tags-embed")]
+        "html" => {
+            if !opts.trust_raw_html {
+                return (String::new(), Visit::Children);
+            }
+            let raw = single_text_child(el).unwrap_or_default();
+            match opts.sanitize_html {
+                Some(sanitize) => out.push_str(&sanitize(raw)),
+                None => out.push_str(raw),
             }
-            out.push_str("</div>");
+            (String::new(), Visit::Skip)
         }
-        "right" => {
-            out.push_str("<div style=\"text-align:right\">");
+        #[cfg(feature = "tags-layout")]
+        "code" => {
+            // 中身は verbatim。改行は <pre> に任せる
+            out.push_str("<pre><code");
+            if let Some(lang) = attr_value(el).filter(|v| is_valid_code_language(v)) {
+                out.push_str(" class=\"");
+                out.push_str(&prefixed(opts, "language-"));
+                out.push_str(&escape_attr(lang.trim()));
+                out.push('"');
+            }
+            out.push('>');
             for c in &el.children {
-                render_node(c, out);
+                if let Node::Text { text, .. } = c {
+                    out.push_str(&escape_text(text));
+                }
             }
-            out.push_str("</div>");
+            ("</code></pre>".to_string(), Visit::Skip)
         }
-        "color" => {
-            // attrs["value"] を探す（parserが正規化済み）
-            let value = el
-                .attrs
-                .iter()
-                .find(|(k, _)| k == "value")
-                .map(|(_, v)| v.as_str());
-
-            // valueが無いならタグを無視して中身だけ
-            let Some(color_val) = value else {
-                for c in &el.children {
-                    render_node(c, out);
-                }
-                return;
+        #[cfg(feature = "tags-layout")]
+        "pre" => simple(out, "<pre>", "</pre>"),
+        #[cfg(feature = "tags-layout")]
+        "list" => match attr_value(el).filter(|v| is_valid_list_type(v)) {
+            Some(t) => {
+                out.push_str("<ol type=\"");
+                out.push_str(t.trim());
+                out.push_str("\">");
+                ("</ol>".to_string(), Visit::Children)
+            }
+            None => simple(out, "<ul>", "</ul>"),
+        },
+        #[cfg(feature = "tags-layout")]
+        "*" => simple(out, "<li>", "</li>"),
+        // 組み込みではなく、利用者が登録した場合。URL は描画ごとの Resolvers で求める
+        "user" | "attach" => {
+            let lookup = if el.name == "user" {
+                Lookup::User
+            } else {
+                Lookup::Attachment
             };
+            let resolved =
+                element_key(el).and_then(|key| lookup_html(lookup, &key, opts, resolvers));
+            let Some((open, close)) = resolved else {
+                return (String::new(), Visit::Children);
+            };
+            out.push_str(&open);
+            (close, Visit::Children)
+        }
+        // unknown tag / 独自登録タグ: タグ自体は捨てて中身だけ表示
+        _ => (String::new(), Visit::Children),
+    }
+}
 
-            // 念のため再検証（render層で二重に守る）
-            if let Some(spec) = TagRegistry::get("color") {
-                if let Some(vfn) = spec.validate_value_attr {
-                    if !vfn(color_val) {
-                        for c in &el.children {
-                            render_node(c, out);
-                        }
-                        return;
-                    }
+/// Resolvers で解決する値の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Lookup {
+    User,
+    Attachment,
+    Emoji,
+    QuotePost,
+}
+
+impl Lookup {
+    /// プレースホルダでの名前
+    fn name(self) -> &'static str {
+        match self {
+            Lookup::User => "user",
+            Lookup::Attachment => "attach",
+            Lookup::Emoji => "emoji",
+            Lookup::QuotePost => "quote",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [
+            Lookup::User,
+            Lookup::Attachment,
+            Lookup::Emoji,
+            Lookup::QuotePost,
+        ]
+        .into_iter()
+        .find(|l| l.name() == name)
+    }
+}
+
+/// key を resolvers で解決した HTML（開始部分, 終了部分）。解決できなければ None
+/// プレースホルダを出力する場合は `<!--bb:user:key-->` / `<!--/bb:user:key-->` を返す
+fn lookup_html(
+    lookup: Lookup,
+    key: &str,
+    opts: &HtmlRenderOptions,
+    resolvers: &Resolvers,
+) -> Option<(String, String)> {
+    if resolvers.placeholders {
+        let (name, key) = (lookup.name(), escape_url_component(key));
+        let close = match lookup {
+            Lookup::User | Lookup::Attachment => format!("<!--/bb:{name}:{key}-->"),
+            Lookup::Emoji | Lookup::QuotePost => String::new(),
+        };
+        return Some((format!("<!--bb:{name}:{key}-->"), close));
+    }
+    let resolver = match lookup {
+        Lookup::User => &resolvers.user,
+        Lookup::Attachment => &resolvers.attachment,
+        Lookup::Emoji => &resolvers.emoji,
+        Lookup::QuotePost => &resolvers.quote_permalink,
+    };
+    let href = resolver.as_ref()?(key)
+        .map(|href| resolve_url(&href, opts, resolvers))
+        .filter(|href| is_valid_url(href))
+        .filter(|href| opts.target != HtmlTarget::Feed || is_absolute_url(href))?;
+    let href = escape_attr(&href);
+    let class = |name| prefixed(opts, name);
+    Some(match lookup {
+        Lookup::User => (
+            format!("<a href=\"{href}\" class=\"{}\">", class("bb-user")),
+            "</a>".to_string(),
+        ),
+        Lookup::Attachment => (
+            format!("<a href=\"{href}\" class=\"{}\">", class("bb-attachment")),
+            "</a>".to_string(),
+        ),
+        Lookup::Emoji => (
+            format!(
+                "<img class=\"{}\" src=\"{href}\" alt=\":{}:\">",
+                class("bb-emoji"),
+                escape_attr(key)
+            ),
+            String::new(),
+        ),
+        Lookup::QuotePost => (
+            format!(
+                "<a href=\"{href}\" class=\"{}\" title=\"Go to quoted post\">&uarr;</a>",
+                class("bb-quote-link")
+            ),
+            String::new(),
+        ),
+    })
+}
+
+/// Resolvers に頼る部分をプレースホルダ（`<!--bb:user:123-->` など）にした HTML と、必要な問い合わせの一覧
+/// プレースホルダは後から fill_placeholders で埋める（CDN のエッジなど、描画とは別の場所でもよい）。
+/// 利用者・添付ファイルへのリンクは開始と終了の2つ、絵文字・引用元へのリンクは1つのプレースホルダになる。
+/// キーは escape::escape_url_component でエンコードする
+pub fn ast_to_html_with_placeholders(
+    nodes: &[Node],
+    opts: &HtmlRenderOptions,
+) -> (String, ResolverRequests) {
+    let resolvers = Resolvers {
+        placeholders: true,
+        ..Resolvers::default()
+    };
+    (
+        ast_to_html_with_resolvers(nodes, opts, &resolvers),
+        ResolverRequests::collect(nodes),
+    )
+}
+
+/// ast_to_html_with_placeholders の HTML のプレースホルダを resolvers で埋める
+/// opts と resolvers が同じなら ast_to_html_with_resolvers と同じ HTML になる。
+/// 解決できないものは、絵文字なら `:name:` のテキストに、それ以外は何も出力しない
+pub fn fill_placeholders(html: &str, opts: &HtmlRenderOptions, resolvers: &Resolvers) -> String {
+    let mut resolved: HashMap<(Lookup, String), Option<(String, String)>> = HashMap::new();
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(pos) = rest.find("<!--") {
+        out.push_str(&rest[..pos]);
+        let after = &rest[pos + "<!--".len()..];
+        let placeholder = after
+            .find("-->")
+            .and_then(|end| Some((parse_placeholder(&after[..end])?, end)));
+        let Some(((closing, lookup, key, attrs), end)) = placeholder else {
+            out.push_str("<!--");
+            rest = after;
+            continue;
+        };
+        let html = resolved
+            .entry((lookup, key.clone()))
+            .or_insert_with(|| lookup_html(lookup, &key, opts, resolvers));
+        match (html, closing) {
+            // 範囲の属性は埋めた HTML の最初のタグに付ける
+            (Some((open, _)), false) => match open.find('>') {
+                Some(pos) if !attrs.is_empty() => {
+                    out.push_str(&open[..pos]);
+                    out.push_str(attrs);
+                    out.push_str(&open[pos..]);
                 }
+                _ => out.push_str(open),
+            },
+            (Some((_, close)), true) => out.push_str(close),
+            (None, false) if lookup == Lookup::Emoji => {
+                out.push(':');
+                out.push_str(&escape_text(&key));
+                out.push(':');
             }
+            (None, _) => {}
+        }
+        rest = &after[end + "-->".len()..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// `bb:user:123` / `/bb:user:123` を (終了か, 種類, キー, 範囲の属性) にする
+/// emit_source_spans なら `bb:user:123 data-bb-start="0" data-bb-end="9"` のように属性が続く
+fn parse_placeholder(comment: &str) -> Option<(bool, Lookup, String, &str)> {
+    let (closing, body) = match comment.strip_prefix('/') {
+        Some(body) => (true, body),
+        None => (false, comment),
+    };
+    // キーは escape_url_component 済みで空白を含まない
+    let (body, attrs) = match body.find(' ') {
+        Some(pos) => body.split_at(pos),
+        None => (body, ""),
+    };
+    let (name, key) = body.strip_prefix("bb:")?.split_once(':')?;
+    Some((
+        closing,
+        Lookup::from_name(name)?,
+        decode_url_component(key)?,
+        attrs,
+    ))
+}
+
+/// escape_url_component の逆
+fn decode_url_component(input: &str) -> Option<String> {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+/// 正規化した URL へのリンク。中身がそのまま URL なら読みやすい形で表示する
+#[cfg(all(feature = "url", feature = "tags-basic"))]
+fn open_normalized_link(
+    el: &Element,
+    href: Option<&str>,
+    opts: &HtmlRenderOptions,
+    resolvers: &Resolvers,
+    out: &mut String,
+) -> (String, Visit) {
+    let Some(href) = href.and_then(normalize_url) else {
+        return (String::new(), Visit::Children);
+    };
+    let href = resolve_url(&href, opts, resolvers);
+    out.push_str("<a href=\"");
+    out.push_str(&escape_attr(&href));
+    out.push_str("\" rel=\"nofollow\">");
+    if attr_value(el).is_some() {
+        return (close_link(el, opts), Visit::Children);
+    }
+    out.push_str(&escape_text(&display_url(&href)));
+    ("</a>".to_string(), Visit::Skip)
+}
 
-            let escaped_color = escape_html(color_val);
-            out.push_str("<span style=\"color:");
-            out.push_str(&escaped_color);
+/// `[url]` の終了タグ。reveal_link_hosts なら、表示と食い違う実際のホストを添える
+#[cfg(feature = "tags-basic")]
+fn close_link(el: &Element, opts: &HtmlRenderOptions) -> String {
+    let mut close = "</a>".to_string();
+    if let Some(link) = deceptive_link_of(el).filter(|_| opts.reveal_link_hosts) {
+        close.push_str(" <span class=\"");
+        close.push_str(&prefixed(opts, "bb-link-host"));
+        close.push_str("\">[");
+        close.push_str(&escape_text(&display_host(&link.actual)));
+        close.push_str("]</span>");
+    }
+    close
+}
+
+/// サイト内パスを base_url のオリジン（`https://host:port`）からの URL にし、Resolvers::url で書き換える
+/// base_url が無い・http(s) の URL でない場合や、サイト内パス以外は base_url を使わない
+fn resolve_url(url: &str, opts: &HtmlRenderOptions, resolvers: &Resolvers) -> String {
+    let origin = opts.base_url.as_deref().and_then(|base| {
+        let base = base.trim();
+        let lower = base.to_ascii_lowercase();
+        let scheme_len = if lower.starts_with("https://") {
+            "https://".len()
+        } else if lower.starts_with("http://") {
+            "http://".len()
+        } else {
+            return None;
+        };
+        let host_len = base[scheme_len..]
+            .find(['/', '?', '#'])
+            .unwrap_or(base.len() - scheme_len);
+        (host_len > 0).then(|| &base[..scheme_len + host_len])
+    });
+    let url = match origin {
+        Some(origin) if url.starts_with('/') && !url.starts_with("//") => format!("{origin}{url}"),
+        _ => url.to_string(),
+    };
+    match &resolvers.url {
+        Some(rewrite) => rewrite(&url),
+        None => url,
+    }
+}
+
+/// `[img alt=..]` の代替テキスト
+#[cfg(feature = "tags-media")]
+fn image_alt(el: &Element) -> Option<&str> {
+    el.attrs
+        .iter()
+        .find(|(k, _)| k == "alt")
+        .map(|(_, v)| v.trim())
+        .filter(|v| !v.is_empty())
+}
+
+/// 代替テキストの無い画像を出力するか
+#[cfg(feature = "tags-media")]
+fn keeps_image(el: &Element, opts: &HtmlRenderOptions) -> bool {
+    !opts.accessible || opts.missing_alt != MissingAltPolicy::Drop || image_alt(el).is_some()
+}
+
+/// property: value のインラインスタイルを付けた tag で中身を囲む。値が使えなければ中身だけ
+#[cfg(any(feature = "tags-basic", feature = "tags-layout"))]
+fn styled(out: &mut String, tag: &str, property: CssProperty, value: &str) -> (String, Visit) {
+    let mut style = StyleBuilder::default();
+    if !style.push(property, value) {
+        return (String::new(), Visit::Children);
+    }
+    out.push_str(&format!("<{tag}{}>", style.to_attr()));
+    (format!("</{tag}>"), Visit::Children)
+}
+
+/// css_prefix を付けた class・id・data 属性の名前
+fn prefixed(opts: &HtmlRenderOptions, name: &str) -> String {
+    let mut out: String = opts
+        .css_prefix
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+        .collect();
+    out.push_str(name);
+    out
+}
+
+/// src 属性の閉じ引用符と alt 属性を出力する
+#[cfg(feature = "tags-media")]
+fn push_alt(el: &Element, opts: &HtmlRenderOptions, out: &mut String) {
+    out.push_str("\" alt=\"");
+    match image_alt(el) {
+        Some(alt) => {
+            out.push_str(&escape_attr(alt));
+            out.push('"');
+        }
+        None if opts.accessible => {
+            out.push_str("\" data-");
+            out.push_str(&prefixed(opts, "missing-alt"));
+        }
+        None => out.push('"'),
+    }
+}
+
+/// http(s) / mailto の絶対 URL か
+fn is_absolute_url(url: &str) -> bool {
+    let lower = url.to_ascii_lowercase();
+    ["http://", "https://", "mailto:"]
+        .iter()
+        .any(|scheme| lower.starts_with(scheme))
+}
+
+/// フィード向けに出力を変える要素の開始タグ。Web と同じでよければ None
+#[cfg_attr(not(feature = "tags-basic"), allow(unused_variables, clippy::ptr_arg))]
+fn open_feed_element(
+    el: &Element,
+    opts: &HtmlRenderOptions,
+    resolvers: &Resolvers,
+    out: &mut String,
+) -> Option<(String, Visit)> {
+    let children_only = Some((String::new(), Visit::Children));
+    match el.name.as_str() {
+        // スタイル・ページ内リンクはフィードリーダーで意味を持たない（取り除かれることも多い）
+        #[cfg(feature = "tags-basic")]
+        "color" | "highlight" | "size" | "big" | "small" | "font" | "anchor" | "goto" => {
+            children_only
+        }
+        #[cfg(feature = "tags-layout")]
+        "left" | "center" | "right" => children_only,
+        #[cfg(feature = "tags-embed")]
+        "html" => children_only,
+        #[cfg(feature = "tags-basic")]
+        "kbd" => {
+            out.push_str("<code>");
+            Some(("</code>".to_string(), Visit::Children))
+        }
+        #[cfg(feature = "tags-basic")]
+        "url" => {
+            let href = attr_value(el)
+                .or_else(|| single_text_child(el))
+                .filter(|h| is_valid_url(h))
+                .map(|h| h.trim().to_string());
+            #[cfg(feature = "url")]
+            let href = match href {
+                Some(h) if opts.normalize_urls => normalize_url(&h),
+                href => href,
+            };
+            let href = href
+                .map(|h| resolve_url(&h, opts, resolvers))
+                .filter(|h| is_absolute_url(h));
+            let Some(href) = href else {
+                return children_only;
+            };
+            out.push_str("<a href=\"");
+            out.push_str(&escape_attr(&href));
             out.push_str("\">");
-            for c in &el.children {
-                render_node(c, out);
+            Some((close_link(el, opts), Visit::Children))
+        }
+        #[cfg(feature = "tags-basic")]
+        "tag" => {
+            let href = attr_value(el)
+                .or_else(|| single_text_child(el))
+                .and_then(hashtag_topic)
+                .zip(opts.tag_url)
+                .map(|(topic, resolve)| resolve_url(&resolve(topic), opts, resolvers))
+                .filter(|h| is_absolute_url(h));
+            let Some(href) = href else {
+                return children_only;
+            };
+            out.push_str("<a href=\"");
+            out.push_str(&escape_attr(&href));
+            out.push_str("\" rel=\"tag\">");
+            Some(("</a>".to_string(), Visit::Children))
+        }
+        #[cfg(feature = "tags-media")]
+        "img" => {
+            let src = single_text_child(el)
+                .filter(|s| is_valid_image_url(s))
+                .map(|s| resolve_url(s.trim(), opts, resolvers))
+                .filter(|s| is_absolute_url(s));
+            // 解決できない画像は壊れた画像になるので出力しない
+            if let Some(src) = src.filter(|_| keeps_image(el, opts)) {
+                out.push_str("<img src=\"");
+                out.push_str(&escape_attr(&src));
+                push_alt(el, opts, out);
+                if let Some((w, h)) = image_size(el) {
+                    out.push_str(&format!(" width=\"{w}\" height=\"{h}\""));
+                }
+                out.push('>');
+            }
+            Some((String::new(), Visit::Skip))
+        }
+        _ => None,
+    }
+}
+
+/// メール向けに出力を変える要素の開始タグ。Web と同じでよければ None
+#[cfg_attr(
+    not(all(feature = "tags-basic", feature = "tags-layout")),
+    allow(unused_variables, clippy::ptr_arg)
+)]
+fn open_email_element(
+    el: &Element,
+    opts: &HtmlRenderOptions,
+    resolvers: &Resolvers,
+    out: &mut String,
+) -> Option<(String, Visit)> {
+    let simple = |out: &mut String, open: &str, close: &str| {
+        out.push_str(open);
+        Some((close.to_string(), Visit::Children))
+    };
+
+    match el.name.as_str() {
+        #[cfg(feature = "tags-basic")]
+        "s" => simple(
+            out,
+            "<span style=\"text-decoration:line-through\">",
+            "</span>",
+        ),
+        #[cfg(feature = "tags-basic")]
+        "kbd" | "tt" => simple(
+            out,
+            "<span style=\"font-family:Consolas,'Courier New',monospace\">",
+            "</span>",
+        ),
+        #[cfg(feature = "tags-layout")]
+        "quote" => {
+            out.push_str(
+                "<table role=\"presentation\" cellpadding=\"0\" cellspacing=\"0\" border=\"0\"><tr>\
+                 <td style=\"border-left:3px solid #cccccc;padding:4px 0 4px 12px;color:#555555\">",
+            );
+            // 引用元と投稿へのリンクは Web と同じく quote_source と Resolvers で求める
+            let (author, post_id) = quote_source(el);
+            let link = post_id.and_then(|id| lookup_html(Lookup::QuotePost, &id, opts, resolvers));
+            if author.is_some() || link.is_some() {
+                out.push_str("<div style=\"font-weight:bold\">");
+                out.push_str(&escape_text(author.as_deref().unwrap_or_default()));
+                if let Some((link, _)) = link {
+                    out.push_str(&link);
+                }
+                out.push_str("</div>");
             }
-            out.push_str("</span>");
+            Some(("</td></tr></table>".to_string(), Visit::Children))
+        }
+        #[cfg(feature = "tags-layout")]
+        "left" | "center" | "right" => {
+            // 配置はテーブルのセルで行う（div の text-align を無視するクライアントがある）
+            let style = StyleBuilder::default().with(CssProperty::TextAlign, &el.name);
+            out.push_str(&format!(
+                "<table role=\"presentation\" width=\"100%\" cellpadding=\"0\" cellspacing=\"0\" border=\"0\"><tr><td align=\"{}\"{}>",
+                el.name,
+                style.to_attr()
+            ));
+            Some(("</td></tr></table>".to_string(), Visit::Children))
+        }
+        #[cfg(feature = "tags-basic")]
+        "highlight" => {
+            let color = attr_value(el).unwrap_or("#ffff00");
+            Some(styled(out, "span", CssProperty::BackgroundColor, color))
+        }
+        #[cfg(feature = "tags-basic")]
+        "size" => {
+            let Some(size) = font_size(el) else {
+                return Some((String::new(), Visit::Children));
+            };
+            Some(styled(
+                out,
+                "span",
+                CssProperty::FontSize,
+                &email_font_size(size),
+            ))
         }
-        _ => {
-            // registryで unknown 扱いにしないならここは基本来ない
+        #[cfg(feature = "tags-layout")]
+        "code" => {
+            out.push_str(
+                "<pre style=\"font-family:Consolas,'Courier New',monospace;white-space:pre-wrap;\
+                 background-color:#f5f5f5;padding:8px\">",
+            );
             for c in &el.children {
-                render_node(c, out);
+                if let Node::Text { text, .. } = c {
+                    out.push_str(&escape_text(text));
+                }
             }
+            Some(("</pre>".to_string(), Visit::Skip))
         }
+        _ => None,
     }
 }
 
-fn escape_html(input: &str) -> String {
-    input
-        .replace('&', "&amp;")
-        .replace('<', "&lt;")
-        .replace('>', "&gt;")
-        .replace('"', "&quot;")
-        .replace('\'', "&apos;")
+/// AMP 向けに出力を変える要素の開始タグ。Web と同じでよければ None
+#[cfg(feature = "amp")]
+#[cfg_attr(not(feature = "tags-media"), allow(unused_variables, clippy::ptr_arg))]
+fn open_amp_element(
+    el: &Element,
+    opts: &HtmlRenderOptions,
+    resolvers: &Resolvers,
+    out: &mut String,
+) -> Option<(String, Visit)> {
+    match el.name.as_str() {
+        // font-family は AMP の許可リストに無いので中身だけ
+        #[cfg(feature = "tags-basic")]
+        "font" => Some((String::new(), Visit::Children)),
+        #[cfg(feature = "tags-media")]
+        "img" => {
+            let Some(src) = single_text_child(el).filter(|s| is_valid_image_url(s)) else {
+                return Some((String::new(), Visit::Children));
+            };
+            if !keeps_image(el, opts) {
+                return Some((String::new(), Visit::Skip));
+            }
+            out.push_str("<amp-img src=\"");
+            out.push_str(&escape_attr(&resolve_url(src.trim(), opts, resolvers)));
+            push_alt(el, opts, out);
+            // amp-img は大きさが必須。指定が無ければ高さだけ決めて幅は自動にする
+            match image_size(el) {
+                Some((w, h)) => {
+                    out.push_str(&format!(
+                        " width=\"{w}\" height=\"{h}\" layout=\"responsive\""
+                    ));
+                }
+                None => out.push_str(" height=\"300\" layout=\"fixed-height\""),
+            }
+            out.push_str("></amp-img>");
+            Some((String::new(), Visit::Skip))
+        }
+        _ => None,
+    }
+}
+
+/// メール向けの文字サイズ。キーワードの解釈がクライアントごとに違うので px で指定する
+#[cfg(feature = "tags-basic")]
+fn email_font_size(size: Length) -> String {
+    const PIXELS: [usize; 7] = [10, 13, 16, 18, 24, 32, 48];
+    match size.unit {
+        None => match size.value as usize {
+            n @ 1..=7 => format!("{}px", PIXELS[n - 1]),
+            n => format!("{n}%"),
+        },
+        Some(_) => size.to_string(),
+    }
+}
+
+/// 単位なしの 1〜7 は HTML の font size 相当のキーワード、それ以上はパーセント
+#[cfg(feature = "tags-basic")]
+fn css_font_size(size: Length) -> String {
+    const KEYWORDS: [&str; 7] = [
+        "x-small",
+        "small",
+        "medium",
+        "large",
+        "x-large",
+        "xx-large",
+        "xxx-large",
+    ];
+    match size.unit {
+        None => match size.value as usize {
+            n @ 1..=7 => KEYWORDS[n - 1].to_string(),
+            n => format!("{n}%"),
+        },
+        Some(_) => size.to_string(),
+    }
 }
 
 fn replace_newline_with_br(input: &str) -> String {
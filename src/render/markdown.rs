@@ -0,0 +1,330 @@
+use crate::ast::{Element, Node, Span, TagName};
+use crate::registry::{anchor_slug, is_valid_image_url, is_valid_url, single_text_child};
+use crate::render::{attr_value, walk, Renderer, Visit};
+
+/// 出力する Markdown の方言
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum MarkdownDialect {
+    #[default]
+    CommonMark,
+    /// Discord のメッセージ。下線 `__` とスポイラー `||` があり、画像はURLを貼るだけ
+    Discord,
+    /// Telegram の MarkdownV2。記号は常にエスケープが必要で、太字は `*`
+    Telegram,
+}
+
+/// AST を CommonMark 形式の Markdown に変換する
+/// 色・サイズ・フォント・配置など Markdown で表せない装飾は中身だけ出力する
+pub fn ast_to_markdown(nodes: &[Node]) -> String {
+    ast_to_markdown_with(nodes, MarkdownDialect::CommonMark)
+}
+
+/// AST を指定した方言の Markdown に変換する（チャットへの転載用）
+/// `[spoiler]` はレジストリに登録した場合のみ要素になる
+pub fn ast_to_markdown_with(nodes: &[Node], dialect: MarkdownDialect) -> String {
+    let mut renderer = MarkdownRenderer {
+        dialect,
+        ..Default::default()
+    };
+    walk(nodes, &mut renderer);
+    renderer.out
+}
+
+struct Frame {
+    name: TagName,
+    close: String,
+    start: usize,
+    /// `[list]` 内の項目番号（番号付きリストのみ）
+    counter: Option<usize>,
+}
+
+#[derive(Default)]
+struct MarkdownRenderer {
+    dialect: MarkdownDialect,
+    out: String,
+    stack: Vec<Frame>,
+}
+
+impl MarkdownRenderer {
+    /// 文字装飾の記号。方言で表せない装飾は None
+    fn mark(&self, name: &str) -> Option<&'static str> {
+        use MarkdownDialect::*;
+        match (name, self.dialect) {
+            ("b", Telegram) => Some("*"),
+            ("b", _) => Some("**"),
+            ("i", Telegram) => Some("_"),
+            ("i", _) => Some("*"),
+            ("u", Discord | Telegram) => Some("__"),
+            ("s", Telegram) => Some("~"),
+            ("s", _) => Some("~~"),
+            ("spoiler", Discord | Telegram) => Some("||"),
+            _ => None,
+        }
+    }
+
+    fn escape(&self, input: &str) -> String {
+        match self.dialect {
+            MarkdownDialect::Telegram => escape_telegram(input),
+            _ => escape_markdown(input),
+        }
+    }
+
+    /// ブロック要素の前で行頭にそろえる
+    fn ensure_line_start(&mut self) {
+        if !self.out.is_empty() && !self.out.ends_with('\n') {
+            self.out.push('\n');
+        }
+    }
+}
+
+impl Renderer for MarkdownRenderer {
+    fn text(&mut self, text: &str, _span: Span) {
+        let in_list = self.stack.last().is_some_and(|f| f.name == "list");
+        if in_list && text.trim().is_empty() {
+            return;
+        }
+        let text = self.escape(text);
+        self.out.push_str(&text);
+    }
+
+    fn enter(&mut self, el: &Element) -> Visit {
+        let mut counter = None;
+        let mut start = None;
+        let telegram = self.dialect == MarkdownDialect::Telegram;
+        let (close, visit) = match el.name.as_str() {
+            name if self.mark(name).is_some() => {
+                let mark = self.mark(name).unwrap_or_default();
+                self.out.push_str(mark);
+                (mark.to_string(), Visit::Children)
+            }
+            // Telegram のコード内では ` と \ だけをエスケープする
+            "kbd" | "tt" if telegram => match single_text_child(el) {
+                Some(text) => {
+                    self.out.push('`');
+                    self.out.push_str(&escape_telegram_code(text));
+                    ("`".to_string(), Visit::Skip)
+                }
+                None => (String::new(), Visit::Children),
+            },
+            // 中身がテキストだけならコードスパンにする（記号はエスケープ不要）
+            "kbd" | "tt" => match single_text_child(el) {
+                Some(text) => {
+                    let fence = if text.contains('`') { "`` " } else { "`" };
+                    self.out.push_str(fence);
+                    self.out.push_str(text);
+                    (fence.chars().rev().collect(), Visit::Skip)
+                }
+                None => (String::new(), Visit::Children),
+            },
+            "quote" => {
+                self.ensure_line_start();
+                start = Some(self.out.len());
+                let author = el
+                    .attrs
+                    .iter()
+                    .find(|(k, _)| k == "author" || k == "value")
+                    .map(|(_, v)| v.as_str());
+                if let Some(author) = author {
+                    let author = self.escape(author);
+                    self.out.push_str(&author);
+                    self.out.push_str(":\n");
+                }
+                (String::new(), Visit::Children)
+            }
+            "code" => {
+                self.ensure_line_start();
+                self.out.push_str("```");
+                if let Some(lang) = attr_value(el) {
+                    self.out.push_str(lang.trim());
+                }
+                self.out.push('\n');
+                if let Some(code) = single_text_child(el) {
+                    if telegram {
+                        self.out.push_str(&escape_telegram_code(code));
+                    } else {
+                        self.out.push_str(code);
+                    }
+                    if !code.ends_with('\n') {
+                        self.out.push('\n');
+                    }
+                }
+                ("```\n".to_string(), Visit::Skip)
+            }
+            "url" => {
+                let href = attr_value(el).or_else(|| single_text_child(el));
+                match href.filter(|h| is_valid_url(h)) {
+                    // Telegram は URL を自動でリンクにする
+                    Some(href) if telegram && attr_value(el).is_none() => {
+                        self.out.push_str(&escape_telegram(href.trim()));
+                        (String::new(), Visit::Skip)
+                    }
+                    // 中身がそのままURLなら autolink
+                    Some(href) if attr_value(el).is_none() => {
+                        self.out.push('<');
+                        self.out.push_str(href.trim());
+                        self.out.push('>');
+                        (String::new(), Visit::Skip)
+                    }
+                    Some(href) if telegram => {
+                        self.out.push('[');
+                        (
+                            format!("]({})", escape_telegram_url(href.trim())),
+                            Visit::Children,
+                        )
+                    }
+                    Some(href) => {
+                        self.out.push('[');
+                        (format!("]({})", href.trim()), Visit::Children)
+                    }
+                    None => (String::new(), Visit::Children),
+                }
+            }
+            // チャットにはページ内リンクが無い
+            "goto" if self.dialect != MarkdownDialect::CommonMark => {
+                (String::new(), Visit::Children)
+            }
+            "goto" => match attr_value(el).and_then(anchor_slug) {
+                Some(slug) => {
+                    self.out.push('[');
+                    (format!("](#{slug})"), Visit::Children)
+                }
+                None => (String::new(), Visit::Children),
+            },
+            "img" => {
+                if let Some(src) = single_text_child(el).filter(|s| is_valid_image_url(s)) {
+                    // チャットでは URL を貼ればプレビューが表示される
+                    match self.dialect {
+                        MarkdownDialect::CommonMark => {
+                            self.out.push_str("![](");
+                            self.out.push_str(src.trim());
+                            self.out.push(')');
+                        }
+                        MarkdownDialect::Discord => self.out.push_str(src.trim()),
+                        MarkdownDialect::Telegram => {
+                            self.out.push_str(&escape_telegram(src.trim()))
+                        }
+                    }
+                }
+                (String::new(), Visit::Skip)
+            }
+            "list" => {
+                self.ensure_line_start();
+                if attr_value(el).is_some() {
+                    counter = Some(0);
+                }
+                (String::new(), Visit::Children)
+            }
+            "*" => {
+                self.ensure_line_start();
+                let number = self.stack.last_mut().and_then(|f| {
+                    let n = f.counter.as_mut()?;
+                    *n += 1;
+                    Some(*n)
+                });
+                // Telegram には箇条書きの記法が無いので記号を文字として出す
+                match (number, telegram) {
+                    (Some(n), true) => self.out.push_str(&format!("{n}\\. ")),
+                    (None, true) => self.out.push_str("• "),
+                    (Some(n), false) => self.out.push_str(&format!("{n}. ")),
+                    (None, false) => self.out.push_str("- "),
+                }
+                (String::new(), Visit::Children)
+            }
+            _ => (String::new(), Visit::Children),
+        };
+
+        self.stack.push(Frame {
+            name: el.name.clone(),
+            close,
+            start: start.unwrap_or(self.out.len()),
+            counter,
+        });
+        visit
+    }
+
+    fn exit(&mut self, _el: &Element) {
+        let Some(frame) = self.stack.pop() else {
+            return;
+        };
+        match frame.name.as_str() {
+            "quote" => {
+                // 引用内の各行に "> " を付ける（引用元の行も含む）
+                let prefix = match self.dialect {
+                    MarkdownDialect::Telegram => ">",
+                    _ => "> ",
+                };
+                let body = self.out.split_off(frame.start);
+                for line in body.trim_end_matches('\n').split('\n') {
+                    self.out.push_str(prefix);
+                    self.out.push_str(line);
+                    self.out.push('\n');
+                }
+            }
+            "*" => {
+                let trimmed = self.out.trim_end().len().max(frame.start);
+                self.out.truncate(trimmed);
+                self.out.push('\n');
+            }
+            _ => {}
+        }
+        self.out.push_str(&frame.close);
+    }
+}
+
+/// Markdown の記法として解釈される記号をバックスラッシュでエスケープする
+fn escape_markdown(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    for c in input.chars() {
+        if matches!(
+            c,
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#' | '~' | '|'
+        ) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Telegram MarkdownV2 では記号はすべてエスケープが必要
+fn escape_telegram(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    for c in input.chars() {
+        if matches!(
+            c,
+            '\\' | '_'
+                | '*'
+                | '['
+                | ']'
+                | '('
+                | ')'
+                | '~'
+                | '`'
+                | '>'
+                | '#'
+                | '+'
+                | '-'
+                | '='
+                | '|'
+                | '{'
+                | '}'
+                | '.'
+                | '!'
+        ) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Telegram のコードの中では ` と \ だけをエスケープする
+fn escape_telegram_code(input: &str) -> String {
+    input.replace('\\', "\\\\").replace('`', "\\`")
+}
+
+/// Telegram のリンク先の中では ) と \ だけをエスケープする
+fn escape_telegram_url(input: &str) -> String {
+    input.replace('\\', "\\\\").replace(')', "\\)")
+}
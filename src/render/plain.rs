@@ -0,0 +1,70 @@
+use crate::ast::{Element, Node, Span, TagName};
+use crate::registry::single_text_child;
+use crate::render::{attr_value, walk, Renderer, Visit};
+
+/// AST から装飾を取り除いたプレーンテキストを得る（通知・検索インデックス向け）
+pub fn ast_to_plain_text(nodes: &[Node]) -> String {
+    let mut renderer = PlainTextRenderer::default();
+    walk(nodes, &mut renderer);
+    renderer.out
+}
+
+#[derive(Default)]
+struct PlainTextRenderer {
+    out: String,
+    stack: Vec<TagName>,
+}
+
+impl Renderer for PlainTextRenderer {
+    fn text(&mut self, text: &str, _span: Span) {
+        if self.stack.last().is_some_and(|n| n == "list") && text.trim().is_empty() {
+            return;
+        }
+        self.out.push_str(text);
+    }
+
+    fn enter(&mut self, el: &Element) -> Visit {
+        let visit = match el.name.as_str() {
+            "code" => {
+                if let Some(code) = single_text_child(el) {
+                    self.out.push_str(code);
+                }
+                Visit::Skip
+            }
+            // 画像は本文ではないので出力しない
+            "img" => Visit::Skip,
+            "*" => {
+                if !self.out.is_empty() && !self.out.ends_with('\n') {
+                    self.out.push('\n');
+                }
+                self.out.push_str("- ");
+                Visit::Children
+            }
+            _ => Visit::Children,
+        };
+        self.stack.push(el.name.clone());
+        visit
+    }
+
+    fn exit(&mut self, el: &Element) {
+        self.stack.pop();
+        match el.name.as_str() {
+            // 表示文字列と異なるリンク先は括弧書きで残す
+            "url" => {
+                if let Some(href) = attr_value(el) {
+                    if single_text_child(el) != Some(href) {
+                        self.out.push_str(" (");
+                        self.out.push_str(href.trim());
+                        self.out.push(')');
+                    }
+                }
+            }
+            "*" => {
+                let trimmed = self.out.trim_end().len();
+                self.out.truncate(trimmed);
+                self.out.push('\n');
+            }
+            _ => {}
+        }
+    }
+}
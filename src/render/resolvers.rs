@@ -0,0 +1,211 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::future::{self, Future};
+use std::sync::Arc;
+
+use crate::ast::{Element, Node};
+use crate::extract::quote_source;
+use crate::registry::single_text_child;
+use crate::render::attr_value;
+
+/// キー（利用者 ID・添付ファイル ID・絵文字名）から URL を求める関数。None なら解決できない
+pub type Resolver = Arc<dyn Fn(&str) -> Option<String> + Send + Sync>;
+
+/// URL を書き換える関数
+pub type UrlRewriter = Arc<dyn Fn(&str) -> String + Send + Sync>;
+
+/// 描画のたびに変わる情報（閲覧者・言語・CDN のホストなど）を使う関数の組
+/// レジストリや描画オプションに入れずに、描画の呼び出しごとに渡す
+#[derive(Clone, Default)]
+#[non_exhaustive]
+pub struct Resolvers {
+    /// `[user=123]Alice[/user]` / `[user]Alice[/user]` の利用者（値が無ければ中身）からプロフィールの URL
+    pub user: Option<Resolver>,
+    /// `[attach=12]label[/attach]` / `[attach]12[/attach]` の添付ファイル ID からファイルの URL
+    pub attachment: Option<Resolver>,
+    /// テキスト中の `:name:` の名前から絵文字の画像の URL。解決できない名前はテキストのまま
+    pub emoji: Option<Resolver>,
+    /// `[quote=Alice;123]` / `[quote post_id=123]` の投稿 ID から、引用した投稿の URL
+    pub quote_permalink: Option<Resolver>,
+    /// リンク・画像の URL を出力の直前に書き換える（CDN のホストやリダイレクト経由にするなど）
+    /// base_url でサイト内パスを解決した後に適用する
+    pub url: Option<UrlRewriter>,
+    /// 解決する代わりにプレースホルダを出力する（html::ast_to_html_with_placeholders）
+    pub(crate) placeholders: bool,
+}
+
+impl Resolvers {
+    pub fn with_user(
+        mut self,
+        resolve: impl Fn(&str) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.user = Some(Arc::new(resolve));
+        self
+    }
+
+    pub fn with_attachment(
+        mut self,
+        resolve: impl Fn(&str) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.attachment = Some(Arc::new(resolve));
+        self
+    }
+
+    pub fn with_emoji(
+        mut self,
+        resolve: impl Fn(&str) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.emoji = Some(Arc::new(resolve));
+        self
+    }
+
+    pub fn with_quote_permalink(
+        mut self,
+        resolve: impl Fn(&str) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.quote_permalink = Some(Arc::new(resolve));
+        self
+    }
+
+    pub fn with_url_rewriter(
+        mut self,
+        rewrite: impl Fn(&str) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.url = Some(Arc::new(rewrite));
+        self
+    }
+}
+
+impl fmt::Debug for Resolvers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Resolvers")
+            .field("user", &self.user.is_some())
+            .field("attachment", &self.attachment.is_some())
+            .field("emoji", &self.emoji.is_some())
+            .field("quote_permalink", &self.quote_permalink.is_some())
+            .field("url", &self.url.is_some())
+            .field("placeholders", &self.placeholders)
+            .finish()
+    }
+}
+
+/// 非同期に解決する Resolvers（利用者・添付ファイルの情報をデータベースから引くなど）
+/// 描画の前に AST から必要なキーを集め、種類ごとに1回ずつまとめて問い合わせる。
+/// 各メソッドはキーから URL への対応を返す（含まれないキーは解決できないものとして扱う）
+pub trait AsyncResolvers {
+    fn users(&self, keys: &[String]) -> impl Future<Output = HashMap<String, String>> + Send {
+        let _ = keys;
+        future::ready(HashMap::new())
+    }
+
+    fn attachments(&self, ids: &[String]) -> impl Future<Output = HashMap<String, String>> + Send {
+        let _ = ids;
+        future::ready(HashMap::new())
+    }
+
+    fn emoji(&self, names: &[String]) -> impl Future<Output = HashMap<String, String>> + Send {
+        let _ = names;
+        future::ready(HashMap::new())
+    }
+
+    fn quote_permalinks(
+        &self,
+        post_ids: &[String],
+    ) -> impl Future<Output = HashMap<String, String>> + Send {
+        let _ = post_ids;
+        future::ready(HashMap::new())
+    }
+}
+
+/// HTML の描画で Resolvers に問い合わせるキー（種類ごとに重複を除いて名前順）
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResolverRequests {
+    pub users: Vec<String>,
+    pub attachments: Vec<String>,
+    pub emoji: Vec<String>,
+    pub quote_posts: Vec<String>,
+}
+
+impl ResolverRequests {
+    /// nodes を描画するときに必要なキーを集める
+    pub fn collect(nodes: &[Node]) -> Self {
+        let mut sets: [BTreeSet<String>; 4] = Default::default();
+        collect_keys(nodes, &mut sets);
+        let [users, attachments, emoji, quote_posts] = sets.map(|set| set.into_iter().collect());
+        Self {
+            users,
+            attachments,
+            emoji,
+            quote_posts,
+        }
+    }
+}
+
+/// nodes のキーを resolvers でまとめて解決し、描画に使う Resolvers にする
+/// 問い合わせの無い種類は呼ばない。URL の書き換えは戻り値に with_url_rewriter で加える
+pub async fn resolve_async(nodes: &[Node], resolvers: &impl AsyncResolvers) -> Resolvers {
+    let requests = ResolverRequests::collect(nodes);
+    let mut out = Resolvers::default();
+    if !requests.users.is_empty() {
+        out.user = Some(lookup(resolvers.users(&requests.users).await));
+    }
+    if !requests.attachments.is_empty() {
+        out.attachment = Some(lookup(resolvers.attachments(&requests.attachments).await));
+    }
+    if !requests.emoji.is_empty() {
+        out.emoji = Some(lookup(resolvers.emoji(&requests.emoji).await));
+    }
+    if !requests.quote_posts.is_empty() {
+        out.quote_permalink = Some(lookup(
+            resolvers.quote_permalinks(&requests.quote_posts).await,
+        ));
+    }
+    out
+}
+
+fn lookup(resolved: HashMap<String, String>) -> Resolver {
+    Arc::new(move |key| resolved.get(key).cloned())
+}
+
+fn collect_keys(nodes: &[Node], sets: &mut [BTreeSet<String>; 4]) {
+    for node in nodes {
+        match node {
+            Node::Text { text, .. } => sets[2].extend(emoji_candidates(text).map(str::to_string)),
+            // `[code]` の中身は描画でも解釈しない
+            Node::Element(el) if el.name == "code" => {}
+            Node::Element(el) => {
+                match el.name.as_str() {
+                    "user" => sets[0].extend(element_key(el)),
+                    "attach" => sets[1].extend(element_key(el)),
+                    "quote" => sets[3].extend(quote_source(el).1),
+                    _ => {}
+                }
+                collect_keys(&el.children, sets);
+            }
+            Node::Unsupported(_) => {}
+        }
+    }
+}
+
+/// `[user]` / `[attach]` のキー（値属性、無ければ中身）
+pub(crate) fn element_key(el: &Element) -> Option<String> {
+    attr_value(el)
+        .or_else(|| single_text_child(el))
+        .map(|key| key.trim().to_string())
+}
+
+/// 絵文字名に使える文字
+pub(crate) fn is_emoji_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '-')
+}
+
+/// text の `:name:` の名前になり得るもの（描画で問い合わせるものをすべて含む）
+fn emoji_candidates(text: &str) -> impl Iterator<Item = &str> {
+    text.match_indices(':').filter_map(|(colon, _)| {
+        let after = &text[colon + 1..];
+        let len = after
+            .find(|c: char| !is_emoji_name_char(c))
+            .unwrap_or(after.len());
+        (len > 0 && after[len..].starts_with(':')).then(|| &after[..len])
+    })
+}
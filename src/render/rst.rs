@@ -0,0 +1,391 @@
+use crate::ast::{Element, Node, Span, TagName};
+use crate::registry::{
+    anchor_slug, image_size, is_valid_code_language, is_valid_image_url, is_valid_url,
+    single_text_child,
+};
+use crate::render::{ast_to_plain_text, attr_value, walk, Renderer, Visit};
+
+/// AST を reStructuredText に変換する（Sphinx などのドキュメントへの取り込み向け）
+/// reST のインライン装飾は入れ子にできないので、装飾の内側にある装飾は中身だけ出力する
+pub fn ast_to_rst(nodes: &[Node]) -> String {
+    let mut renderer = RstRenderer::default();
+    walk(nodes, &mut renderer);
+    renderer.out
+}
+
+struct Frame {
+    name: TagName,
+    /// 中身の出力を始めた位置
+    start: usize,
+    /// インライン装飾の (開始記号, 終了記号)。中身が確定する exit で付ける
+    inline: Option<(String, String)>,
+    /// `[list]` 内の項目番号（番号付きリストのみ）
+    counter: Option<usize>,
+    /// `[*]` の行頭記号の幅（2行目以降の字下げに使う）
+    indent: usize,
+}
+
+#[derive(Default)]
+struct RstRenderer {
+    out: String,
+    stack: Vec<Frame>,
+    /// 開いているインライン装飾の数
+    inline_depth: usize,
+    /// 直前がブロック要素で、続く本文の前に空行が要る
+    after_block: bool,
+    /// 直前でインライン装飾を閉じた（英数字が続くならエスケープした空白を挟む）
+    after_inline: bool,
+}
+
+impl RstRenderer {
+    /// ブロック要素の前後は空行で区切る必要がある
+    fn ensure_blank_line(&mut self) {
+        if self.out.is_empty() {
+            return;
+        }
+        let newlines = self.out.len() - self.out.trim_end_matches('\n').len();
+        for _ in newlines..2 {
+            self.out.push('\n');
+        }
+    }
+
+    fn at_line_start(&self) -> bool {
+        self.out.is_empty() || self.out.ends_with('\n')
+    }
+
+    fn begin_block(&mut self) {
+        self.ensure_blank_line();
+        self.after_block = false;
+        self.after_inline = false;
+    }
+
+    fn end_block(&mut self) {
+        if !self.out.ends_with('\n') {
+            self.out.push('\n');
+        }
+        self.after_block = true;
+    }
+
+    /// インライン装飾の中身を開始する。すでに装飾の内側なら記号は付けない
+    fn open_inline(&mut self, open: &str, close: String) -> Option<(String, String)> {
+        if self.inline_depth > 0 {
+            return None;
+        }
+        self.inline_depth += 1;
+        Some((open.to_string(), close))
+    }
+
+    /// 中身の前後の空白を記号の外へ出して装飾で囲む
+    /// 中身が空・空行をまたぐ場合は reST で装飾にならないので中身だけ残す
+    fn close_inline(&mut self, start: usize, open: &str, close: &str) {
+        self.inline_depth -= 1;
+        let body = self.out.split_off(start);
+        let core = body.trim();
+        if core.is_empty() || core.contains("\n\n") {
+            self.out.push_str(&body);
+            return;
+        }
+        let lead = &body[..body.len() - body.trim_start().len()];
+        let trail = &body[body.trim_end().len()..];
+        self.out.push_str(lead);
+        if self
+            .out
+            .chars()
+            .next_back()
+            .is_some_and(needs_boundary_before)
+        {
+            self.out.push_str("\\ ");
+        }
+        self.out.push_str(open);
+        self.out.push_str(core);
+        self.out.push_str(close);
+        self.out.push_str(trail);
+        self.after_inline = trail.is_empty();
+    }
+
+    fn push_text(&mut self, text: &str) {
+        if self.after_inline && text.chars().next().is_some_and(needs_boundary_after) {
+            self.out.push_str("\\ ");
+        }
+        self.after_inline = false;
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                self.out.push('\n');
+            }
+            // 行頭の空白は reST では字下げ（引用ブロック）になってしまう
+            let line = if self.at_line_start() {
+                line.trim_start_matches([' ', '\t'])
+            } else {
+                line
+            };
+            let line_start = self.at_line_start();
+            self.out.push_str(&escape_rst(line, line_start));
+        }
+    }
+}
+
+impl Renderer for RstRenderer {
+    fn text(&mut self, text: &str, _span: Span) {
+        let in_list = self.stack.last().is_some_and(|f| f.name == "list");
+        if in_list && text.trim().is_empty() {
+            return;
+        }
+        if self.after_block {
+            if text.trim().is_empty() {
+                return;
+            }
+            self.ensure_blank_line();
+            self.after_block = false;
+            self.push_text(text.trim_start_matches(['\n', '\r']));
+            return;
+        }
+        self.push_text(text);
+    }
+
+    fn enter(&mut self, el: &Element) -> Visit {
+        let mut counter = None;
+        let mut indent = 0;
+        let mut inline = None;
+        let is_block = matches!(el.name.as_str(), "quote" | "list" | "code" | "img" | "*");
+        if self.after_block && !is_block {
+            self.ensure_blank_line();
+            self.after_block = false;
+        }
+        let content_start = self.out.len();
+        let visit = match el.name.as_str() {
+            "b" => {
+                inline = self.open_inline("**", "**".to_string());
+                Visit::Children
+            }
+            "i" => {
+                inline = self.open_inline("*", "*".to_string());
+                Visit::Children
+            }
+            // 中身がテキストだけならインラインリテラルにする（記号はエスケープ不要）
+            "kbd" | "tt" => match single_text_child(el).filter(|t| !t.contains("``")) {
+                Some(text) => {
+                    inline = self.open_inline("``", "``".to_string());
+                    if inline.is_some() {
+                        self.out.push_str(text);
+                    } else {
+                        self.push_text(text);
+                    }
+                    Visit::Skip
+                }
+                None => Visit::Children,
+            },
+            "url" | "goto" => {
+                let href = if el.name == "url" {
+                    attr_value(el)
+                        .or_else(|| single_text_child(el))
+                        .filter(|h| is_valid_url(h))
+                        .map(|h| h.trim().to_string())
+                } else {
+                    attr_value(el)
+                        .and_then(anchor_slug)
+                        .map(|s| format!("#{s}"))
+                };
+                match href {
+                    // 中身がそのままURLならスタンドアロンのリンクとして認識される
+                    Some(href) if el.name == "url" && attr_value(el).is_none() => {
+                        if std::mem::take(&mut self.after_inline) {
+                            self.out.push_str("\\ ");
+                        }
+                        self.out.push_str(&href);
+                        Visit::Skip
+                    }
+                    Some(href) => {
+                        inline = self.open_inline("`", format!(" <{href}>`__"));
+                        if inline.is_some() {
+                            // リンクの表示文字列には装飾を入れられない
+                            let label = ast_to_plain_text(&el.children);
+                            let label = label.split_whitespace().collect::<Vec<_>>().join(" ");
+                            self.out
+                                .push_str(&escape_rst(&label, false).replace('<', "\\<"));
+                            Visit::Skip
+                        } else {
+                            Visit::Children
+                        }
+                    }
+                    None => Visit::Children,
+                }
+            }
+            "img" => {
+                if let Some(src) = single_text_child(el).filter(|s| is_valid_image_url(s)) {
+                    self.begin_block();
+                    self.out.push_str(".. image:: ");
+                    self.out.push_str(src.trim());
+                    self.out.push('\n');
+                    if let Some((w, h)) = image_size(el) {
+                        self.out
+                            .push_str(&format!("   :width: {w}px\n   :height: {h}px\n"));
+                    }
+                    self.end_block();
+                }
+                Visit::Skip
+            }
+            "code" => {
+                self.begin_block();
+                match attr_value(el).filter(|v| is_valid_code_language(v)) {
+                    Some(lang) => {
+                        self.out.push_str(".. code-block:: ");
+                        self.out.push_str(lang.trim());
+                        self.out.push_str("\n\n");
+                    }
+                    None => self.out.push_str("::\n\n"),
+                }
+                let code = single_text_child(el).unwrap_or_default();
+                for line in code.trim_matches('\n').split('\n') {
+                    if !line.trim().is_empty() {
+                        self.out.push_str("   ");
+                        self.out.push_str(line.trim_end_matches('\r'));
+                    }
+                    self.out.push('\n');
+                }
+                self.end_block();
+                Visit::Skip
+            }
+            "quote" | "list" => {
+                self.begin_block();
+                if el.name == "list" && attr_value(el).is_some() {
+                    counter = Some(0);
+                }
+                Visit::Children
+            }
+            "*" => {
+                // 前の項目が入れ子のリストなどで終わっていれば空行で区切る
+                if std::mem::take(&mut self.after_block) {
+                    self.ensure_blank_line();
+                } else if !self.at_line_start() {
+                    self.out.push('\n');
+                }
+                let number = self.stack.last_mut().and_then(|f| {
+                    let n = f.counter.as_mut()?;
+                    *n += 1;
+                    Some(*n)
+                });
+                let marker = match number {
+                    Some(n) => format!("{n}. "),
+                    None => "- ".to_string(),
+                };
+                indent = marker.len();
+                self.out.push_str(&marker);
+                Visit::Children
+            }
+            _ => Visit::Children,
+        };
+
+        // インライン装飾は enter で出力した中身も囲む。
+        // `[*]` は行頭記号も含めて字下げし直すので、記号の前から記録する
+        let start = if inline.is_some() {
+            content_start
+        } else {
+            self.out.len() - indent
+        };
+        self.stack.push(Frame {
+            name: el.name.clone(),
+            start,
+            inline,
+            counter,
+            indent,
+        });
+        visit
+    }
+
+    fn exit(&mut self, el: &Element) {
+        let Some(frame) = self.stack.pop() else {
+            return;
+        };
+        if let Some((open, close)) = &frame.inline {
+            self.close_inline(frame.start, open, close);
+            return;
+        }
+        match frame.name.as_str() {
+            "quote" => {
+                // 引用は字下げで表し、引用元は末尾の "-- 名前" で示す
+                let body = self.out.split_off(frame.start);
+                for line in body.trim_matches('\n').split('\n') {
+                    if !line.is_empty() {
+                        self.out.push_str("    ");
+                        self.out.push_str(line);
+                    }
+                    self.out.push('\n');
+                }
+                let author = el
+                    .attrs
+                    .iter()
+                    .find(|(k, _)| k == "author" || k == "value")
+                    .map(|(_, v)| v.trim())
+                    .filter(|v| !v.is_empty());
+                if let Some(author) = author {
+                    self.out.push_str("\n    -- ");
+                    self.out.push_str(&escape_rst(author, false));
+                    self.out.push('\n');
+                }
+                self.end_block();
+            }
+            "list" => self.end_block(),
+            "*" => {
+                let ended_with_block = self.after_block;
+                // 2行目以降（入れ子のリストなど）を本文の位置にそろえる
+                let body = self.out.split_off(frame.start);
+                let pad = " ".repeat(frame.indent);
+                for (i, line) in body.trim_end().split('\n').enumerate() {
+                    if i > 0 {
+                        self.out.push('\n');
+                        if !line.is_empty() {
+                            self.out.push_str(&pad);
+                        }
+                    }
+                    self.out.push_str(line);
+                }
+                self.out.push('\n');
+                self.after_block = ended_with_block;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// インライン装飾の開始記号の直前に来てよい文字か
+fn needs_boundary_before(c: char) -> bool {
+    !(c.is_whitespace() || matches!(c, '-' | ':' | '/' | '\'' | '"' | '<' | '(' | '[' | '{'))
+}
+
+/// インライン装飾の終了記号の直後に来てよい文字か
+fn needs_boundary_after(c: char) -> bool {
+    !(c.is_whitespace()
+        || matches!(
+            c,
+            '-' | '.'
+                | ','
+                | ':'
+                | ';'
+                | '!'
+                | '?'
+                | '\\'
+                | '/'
+                | '\''
+                | '"'
+                | ')'
+                | ']'
+                | '}'
+                | '>'
+        ))
+}
+
+/// reST のインライン記法として解釈される記号をバックスラッシュでエスケープする
+/// 行頭では箇条書き・見出しの下線・コメントなどになる記号もエスケープする
+fn escape_rst(input: &str, line_start: bool) -> String {
+    let mut out = String::with_capacity(input.len());
+    for (i, c) in input.chars().enumerate() {
+        let block_marker = i == 0
+            && line_start
+            && matches!(c, '-' | '+' | '.' | '=' | '#' | ':' | '>' | '~' | '^');
+        if block_marker || matches!(c, '\\' | '`' | '*' | '_' | '|') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
@@ -0,0 +1,301 @@
+use crate::ast::{Element, Node, Span, TagName};
+use crate::length::{Length, LengthUnit};
+use crate::registry::{
+    color_to_rgb, font_size, is_valid_color_value, is_valid_font_value, is_valid_url,
+    single_text_child,
+};
+use crate::render::{attr_value, walk, Renderer, Visit};
+
+/// AST を RTF 文書に変換する（Word などで開ける形式）
+pub fn ast_to_rtf(nodes: &[Node]) -> String {
+    // 色表・フォント表は本文より前に出す必要があるので先に集める
+    let mut tables = Tables::default();
+    collect_tables(nodes, &mut tables);
+
+    let mut renderer = RtfRenderer {
+        tables,
+        body: String::new(),
+        stack: vec![],
+    };
+    walk(nodes, &mut renderer);
+    let RtfRenderer { tables, body, .. } = renderer;
+
+    let mut out = String::from("{\\rtf1\\ansi\\deff0");
+    out.push_str("{\\fonttbl{\\f0\\fswiss Helvetica;}{\\f1\\fmodern Courier New;}");
+    for (i, font) in tables.fonts.iter().enumerate() {
+        out.push_str(&format!("{{\\f{} {};}}", i + 2, escape_rtf(font)));
+    }
+    out.push('}');
+    if !tables.colors.is_empty() {
+        // 先頭の空エントリは「既定色」(cf0)
+        out.push_str("{\\colortbl ;");
+        for (r, g, b) in &tables.colors {
+            out.push_str(&format!("\\red{r}\\green{g}\\blue{b};"));
+        }
+        out.push('}');
+    }
+    out.push_str("\\f0\\fs24 ");
+    out.push_str(&body);
+    out.push('}');
+    out
+}
+
+#[derive(Default)]
+struct Tables {
+    colors: Vec<(u8, u8, u8)>,
+    fonts: Vec<String>,
+}
+
+impl Tables {
+    fn color_index(&self, value: &str) -> Option<usize> {
+        let rgb = color_to_rgb(value)?;
+        self.colors.iter().position(|c| *c == rgb).map(|i| i + 1)
+    }
+
+    fn font_index(&self, value: &str) -> Option<usize> {
+        let name = value.trim();
+        self.fonts.iter().position(|f| f == name).map(|i| i + 2)
+    }
+}
+
+fn collect_tables(nodes: &[Node], tables: &mut Tables) {
+    for n in nodes {
+        let Node::Element(el) = n else { continue };
+        match (el.name.as_str(), attr_value(el)) {
+            ("color", Some(v)) if is_valid_color_value(v) => {
+                if let Some(rgb) = color_to_rgb(v) {
+                    if !tables.colors.contains(&rgb) {
+                        tables.colors.push(rgb);
+                    }
+                }
+            }
+            // 色指定の無いマーカーは黄色
+            ("highlight", v) => {
+                let rgb = v
+                    .filter(|v| is_valid_color_value(v))
+                    .and_then(color_to_rgb)
+                    .unwrap_or((255, 255, 0));
+                if !tables.colors.contains(&rgb) {
+                    tables.colors.push(rgb);
+                }
+            }
+            ("font", Some(v)) if is_valid_font_value(v) => {
+                let name = v.trim().to_string();
+                if !tables.fonts.contains(&name) {
+                    tables.fonts.push(name);
+                }
+            }
+            _ => {}
+        }
+        collect_tables(&el.children, tables);
+    }
+}
+
+/// 開いている要素の情報
+struct Frame {
+    name: TagName,
+    close: String,
+    /// `[list]` 内の項目番号（番号付きリストのみ）
+    counter: Option<usize>,
+    start: usize,
+}
+
+struct RtfRenderer {
+    tables: Tables,
+    body: String,
+    stack: Vec<Frame>,
+}
+
+impl Renderer for RtfRenderer {
+    fn text(&mut self, text: &str, _span: Span) {
+        let in_list = self.stack.last().is_some_and(|f| f.name == "list");
+        if in_list && text.trim().is_empty() {
+            return;
+        }
+        self.body
+            .push_str(&replace_newline_with_par(&escape_rtf(text)));
+    }
+
+    fn enter(&mut self, el: &Element) -> Visit {
+        let start = self.body.len();
+        let mut counter = None;
+        let out = &mut self.body;
+
+        // `{\b ...}` のようにグループで囲んで書式を局所化する
+        let group = |out: &mut String, control: &str| {
+            out.push('{');
+            out.push_str(control);
+            out.push(' ');
+            "}".to_string()
+        };
+
+        let (close, visit) = match el.name.as_str() {
+            "b" => (group(out, "\\b"), Visit::Children),
+            "i" => (group(out, "\\i"), Visit::Children),
+            "u" => (group(out, "\\ul"), Visit::Children),
+            "s" => (group(out, "\\strike"), Visit::Children),
+            "kbd" | "tt" => (group(out, "\\f1"), Visit::Children),
+            "color" => match attr_value(el).and_then(|v| self.tables.color_index(v)) {
+                Some(idx) => (group(out, &format!("\\cf{idx}")), Visit::Children),
+                None => (String::new(), Visit::Children),
+            },
+            "highlight" => match self.tables.color_index(attr_value(el).unwrap_or("yellow")) {
+                Some(idx) => (group(out, &format!("\\highlight{idx}")), Visit::Children),
+                None => (String::new(), Visit::Children),
+            },
+            "font" => match attr_value(el).and_then(|v| self.tables.font_index(v)) {
+                Some(idx) => (group(out, &format!("\\f{idx}")), Visit::Children),
+                None => (String::new(), Visit::Children),
+            },
+            "size" => match font_size(el) {
+                Some(v) => (
+                    group(out, &format!("\\fs{}", rtf_font_size(v))),
+                    Visit::Children,
+                ),
+                None => (String::new(), Visit::Children),
+            },
+            "left" | "center" | "right" => {
+                let align = match el.name.as_str() {
+                    "center" => "\\qc",
+                    "right" => "\\qr",
+                    _ => "\\ql",
+                };
+                out.push_str("{\\pard");
+                out.push_str(align);
+                out.push(' ');
+                ("\\par}".to_string(), Visit::Children)
+            }
+            "quote" => {
+                out.push_str("{\\pard\\li720 ");
+                let author = el
+                    .attrs
+                    .iter()
+                    .find(|(k, _)| k == "author" || k == "value")
+                    .map(|(_, v)| v.as_str());
+                if let Some(author) = author {
+                    out.push_str("{\\i ");
+                    out.push_str(&escape_rtf(author));
+                    out.push_str(":}\\line ");
+                }
+                ("\\par}".to_string(), Visit::Children)
+            }
+            "code" => {
+                out.push_str("{\\pard\\f1 ");
+                for c in &el.children {
+                    if let Node::Text { text, .. } = c {
+                        out.push_str(&escape_rtf(text).replace('\n', "\\line\n"));
+                    }
+                }
+                ("\\par}".to_string(), Visit::Skip)
+            }
+            "list" => {
+                if attr_value(el).is_some() {
+                    counter = Some(0);
+                }
+                (String::new(), Visit::Children)
+            }
+            "*" => {
+                out.push_str("{\\pard\\li360\\fi-360 ");
+                let number = self.stack.last_mut().and_then(|f| {
+                    let n = f.counter.as_mut()?;
+                    *n += 1;
+                    Some(*n)
+                });
+                match number {
+                    Some(n) => out.push_str(&format!("{n}.\\tab ")),
+                    None => out.push_str("\\bullet\\tab "),
+                }
+                ("\\par}".to_string(), Visit::Children)
+            }
+            "url" => {
+                let href = attr_value(el).or_else(|| single_text_child(el));
+                match href.filter(|h| is_valid_url(h)) {
+                    Some(href) => {
+                        out.push_str("{\\field{\\*\\fldinst HYPERLINK \"");
+                        out.push_str(&escape_rtf(href.trim()));
+                        out.push_str("\"}{\\fldrslt ");
+                        ("}}".to_string(), Visit::Children)
+                    }
+                    None => (String::new(), Visit::Children),
+                }
+            }
+            "img" => {
+                // 外部画像は埋め込めないのでリンクとして残す
+                if let Some(src) = single_text_child(el) {
+                    out.push_str("{\\field{\\*\\fldinst HYPERLINK \"");
+                    out.push_str(&escape_rtf(src.trim()));
+                    out.push_str("\"}{\\fldrslt [image]}}");
+                }
+                (String::new(), Visit::Skip)
+            }
+            _ => (String::new(), Visit::Children),
+        };
+
+        self.stack.push(Frame {
+            name: el.name.clone(),
+            close,
+            counter,
+            start,
+        });
+        visit
+    }
+
+    fn exit(&mut self, _el: &Element) {
+        let Some(frame) = self.stack.pop() else {
+            return;
+        };
+        if frame.name == "*" {
+            // 項目末尾の改行は段落の区切りと重なるので落とす
+            while self.body.len() > frame.start && self.body.ends_with("\\par\n") {
+                self.body.truncate(self.body.len() - "\\par\n".len());
+            }
+        }
+        self.body.push_str(&frame.close);
+    }
+}
+
+/// `\fs` は半ポイント単位。1〜7 は段階、それ以上は 12pt に対するパーセント
+fn rtf_font_size(size: Length) -> u32 {
+    const HALF_POINTS: [u32; 7] = [16, 20, 24, 28, 36, 48, 72];
+    let half_points = match size.unit {
+        None => match size.value as u32 {
+            n @ 1..=7 => return HALF_POINTS[n as usize - 1],
+            n => return 24 * n / 100,
+        },
+        Some(LengthUnit::Percent) => 24.0 * size.value / 100.0,
+        Some(LengthUnit::Em) => 24.0 * size.value,
+        Some(LengthUnit::Pt) => 2.0 * size.value,
+        // 1px = 0.75pt
+        Some(LengthUnit::Px) => 1.5 * size.value,
+    };
+    half_points.round() as u32
+}
+
+/// RTF の制御文字をエスケープし、非ASCIIは \uN? 形式にする
+fn escape_rtf(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '{' => out.push_str("\\{"),
+            '}' => out.push_str("\\}"),
+            '\t' => out.push_str("\\tab "),
+            c if c.is_ascii() => out.push(c),
+            c => {
+                // \u は符号付き16bit。BMP外はサロゲートペアで表す
+                let mut buf = [0u16; 2];
+                for unit in c.encode_utf16(&mut buf) {
+                    out.push_str(&format!("\\u{}?", *unit as i16));
+                }
+            }
+        }
+    }
+    out
+}
+
+fn replace_newline_with_par(input: &str) -> String {
+    input
+        .replace("\r\n", "\n")
+        .replace('\r', "\n")
+        .replace('\n', "\\par\n")
+}
@@ -0,0 +1,77 @@
+// 崩れた投稿の書式を直す（投稿画面の「書式を直す」ボタン用）
+//
+// lint の書き換えの案のうち、意図を推測しなくてよいもの（閉じていないタグを閉じる・
+// 何も閉じない閉じタグを消す・交差した閉じタグを内側から閉じ直す・閉じタグを横取りする `[1]` のような
+// 本文中の括弧をエスケープする）だけを適用する。未知のタグの名前の置き換えなどは行わない。
+
+use crate::lint::{lint, LintCode, TextEdit};
+use crate::options::BbCodeOptions;
+use crate::parser::parse_lenient;
+
+/// 書き換えを適用し直す回数の上限（案が収束しない場合の歯止め）
+const MAX_ROUNDS: usize = 200;
+
+/// 適用した書き換え1つ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppliedFix {
+    pub code: LintCode,
+    /// 直した問題の説明（lint の message）
+    pub message: String,
+    /// 1つ前の書き換えまでを適用したテキストに対する書き換え
+    /// 返した順に適用すると repair の結果になる
+    pub edit: TextEdit,
+}
+
+/// input の書式を直したテキストと、適用した書き換えを返す
+/// 直すものが無ければ input をそのまま返す。書き換えは、結果がパースでき（元の入力が
+/// パースできた場合）、かつ lint の件数が減るものだけを適用する
+pub fn repair(input: &str, opts: &BbCodeOptions) -> (String, Vec<AppliedFix>) {
+    let mut text = input.to_string();
+    let mut lints = lint(&text, opts);
+    let mut parses = parse_lenient(&text, opts).is_ok();
+    let mut applied = vec![];
+    for _ in 0..MAX_ROUNDS {
+        // 内側（後ろ）のタグから直すと、外側の閉じタグの位置がずれない
+        let candidates = lints.iter().rev().filter(|l| {
+            matches!(
+                l.code,
+                LintCode::Unclosed | LintCode::MismatchedClose | LintCode::Syntax
+            )
+        });
+        let mut accepted = None;
+        for l in candidates {
+            let Some(edit) = &l.suggested_fix else {
+                continue;
+            };
+            let Some(fixed) = edit.apply(&text).filter(|fixed| *fixed != text) else {
+                continue;
+            };
+            // 深さの制限を超えるなど、直したことで読めなくなるものは採らない
+            let fixed_parses = parse_lenient(&fixed, opts).is_ok();
+            if parses && !fixed_parses {
+                continue;
+            }
+            // 読めない入力の何も閉じない閉じタグは、消せば入力が短くなるので件数に関わらず採る
+            let fixed_lints = lint(&fixed, opts);
+            let removes_stray_close = !parses && l.code == LintCode::Syntax;
+            if fixed_lints.len() >= lints.len() && !removes_stray_close {
+                continue;
+            }
+            let fix = AppliedFix {
+                code: l.code,
+                message: l.message.clone(),
+                edit: edit.clone(),
+            };
+            accepted = Some((fix, fixed, fixed_lints, fixed_parses));
+            break;
+        }
+        let Some((fix, fixed, fixed_lints, fixed_parses)) = accepted else {
+            break;
+        };
+        applied.push(fix);
+        text = fixed;
+        lints = fixed_lints;
+        parses = fixed_parses;
+    }
+    (text, applied)
+}
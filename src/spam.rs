@@ -0,0 +1,198 @@
+// モデレーション向けのスパム判定
+//
+// 生のテキストではなく AST の構造（リンクの数、同じタグの繰り返し、[size] の乱用など）
+// から特徴を取り出し、0.0〜1.0 の点数にまとめる。描画する前に判定できる。
+
+use std::collections::HashSet;
+
+use crate::ast::{Element, Node};
+use crate::length::LengthUnit;
+use crate::registry::font_size;
+
+/// spam_score の特徴ごとの重みとしきい値
+#[derive(Debug, Clone)]
+pub struct SpamHeuristics {
+    /// 1語あたりのリンク数がこの値以上なら、リンク密度の特徴は満点
+    pub max_link_density: f32,
+    pub link_weight: f32,
+    /// 直前の兄弟・親と同じタグがこの数以上あれば、繰り返しの特徴は満点
+    pub max_repeated_tags: usize,
+    pub repeat_weight: f32,
+    /// `[size]` の値がこれ以上なら大きすぎる文字とみなす（単位なしの 1〜7 はキーワード、それ以上は %）
+    /// 単位付きの値は本文に対するパーセントにして large_size_percent と比べる
+    pub large_size_keyword: u32,
+    pub large_size_percent: u32,
+    /// 大きすぎる文字の割合に掛ける重み
+    pub size_weight: f32,
+    /// 大文字の割合に掛ける重み
+    pub caps_weight: f32,
+    /// 英字がこれより少なければ大文字の割合は数えない（短い略語だけの投稿を除く）
+    pub min_caps_letters: usize,
+    /// 含まれていれば満点になる語（小文字で比較する）
+    pub blocked_words: HashSet<String>,
+    pub blocked_word_weight: f32,
+}
+
+impl Default for SpamHeuristics {
+    fn default() -> Self {
+        Self {
+            max_link_density: 0.2,
+            link_weight: 1.0,
+            max_repeated_tags: 5,
+            repeat_weight: 0.5,
+            large_size_keyword: 5,
+            large_size_percent: 150,
+            size_weight: 0.5,
+            caps_weight: 0.5,
+            min_caps_letters: 20,
+            blocked_words: HashSet::new(),
+            blocked_word_weight: 1.0,
+        }
+    }
+}
+
+/// AST から取り出した特徴
+#[derive(Default)]
+struct Features {
+    words: usize,
+    links: usize,
+    repeated_tags: usize,
+    /// テキストの文字数と、そのうち大きすぎる `[size]` の中にある文字数
+    chars: usize,
+    large_chars: usize,
+    letters: usize,
+    upper: usize,
+    blocked_hits: usize,
+}
+
+/// スパムらしさを 0.0（普通の投稿）〜1.0 で返す
+/// 各特徴を 0.0〜1.0 に正規化し、heuristics の重みで加重平均する
+pub fn spam_score(nodes: &[Node], heuristics: &SpamHeuristics) -> f32 {
+    let mut collector = Collector {
+        heuristics,
+        f: Features::default(),
+        large: false,
+        in_link: false,
+    };
+    collector.nodes(nodes, None);
+    let f = collector.f;
+
+    let link_density = f.links as f32 / f.words.max(1) as f32;
+    let link = (link_density / heuristics.max_link_density.max(f32::EPSILON)).min(1.0);
+    let repeat = (f.repeated_tags as f32 / heuristics.max_repeated_tags.max(1) as f32).min(1.0);
+    let size = f.large_chars as f32 / f.chars.max(1) as f32;
+    let caps = if f.letters >= heuristics.min_caps_letters {
+        f.upper as f32 / f.letters as f32
+    } else {
+        0.0
+    };
+    let blocked = if f.blocked_hits > 0 { 1.0 } else { 0.0 };
+
+    let weighted = [
+        (link, heuristics.link_weight),
+        (repeat, heuristics.repeat_weight),
+        (size, heuristics.size_weight),
+        (caps, heuristics.caps_weight),
+        (blocked, heuristics.blocked_word_weight),
+    ];
+    let total: f32 = weighted.iter().map(|(_, w)| w.max(0.0)).sum();
+    if total == 0.0 {
+        return 0.0;
+    }
+    let score: f32 = weighted.iter().map(|(v, w)| v * w.max(0.0)).sum();
+    (score / total).clamp(0.0, 1.0)
+}
+
+/// AST をたどって特徴を数える
+struct Collector<'a> {
+    heuristics: &'a SpamHeuristics,
+    f: Features,
+    /// 大きすぎる `[size]` の中か
+    large: bool,
+    /// `[url]` の中か（中身の URL を二重に数えない）
+    in_link: bool,
+}
+
+impl Collector<'_> {
+    fn nodes(&mut self, nodes: &[Node], parent: Option<&Element>) {
+        let mut prev: Option<&Element> = None;
+        for node in nodes {
+            match node {
+                Node::Text { text, .. } => {
+                    if !text.trim().is_empty() {
+                        prev = None;
+                    }
+                    self.text(text);
+                }
+                Node::Element(el) => {
+                    // 同じタグを並べる・重ねるのは目立たせるための典型的な手口
+                    let repeats_sibling = prev.is_some_and(|p| p.name == el.name);
+                    let repeats_parent = parent.is_some_and(|p| p.name == el.name);
+                    if repeats_sibling || repeats_parent {
+                        self.f.repeated_tags += 1;
+                    }
+                    prev = Some(el);
+
+                    match el.name.as_str() {
+                        "url" => self.f.links += 1,
+                        // 画像の URL は本文ではない
+                        "img" => continue,
+                        _ => {}
+                    }
+                    let (large, in_link) = (self.large, self.in_link);
+                    self.large |= el.name == "size" && is_large_size(el, self.heuristics);
+                    self.in_link |= el.name == "url";
+                    self.nodes(&el.children, Some(el));
+                    (self.large, self.in_link) = (large, in_link);
+                }
+                Node::Unsupported(_) => {}
+            }
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        let f = &mut self.f;
+        for word in text.split_whitespace() {
+            f.words += 1;
+            // 本文にそのまま書かれた URL もリンクとして数える
+            if !self.in_link && (word.contains("://") || word.starts_with("www.")) {
+                f.links += 1;
+            }
+            if !self.heuristics.blocked_words.is_empty() {
+                let word = word
+                    .trim_matches(|c: char| !c.is_alphanumeric())
+                    .to_lowercase();
+                if self.heuristics.blocked_words.contains(&word) {
+                    f.blocked_hits += 1;
+                }
+            }
+        }
+        for c in text.chars().filter(|c| !c.is_whitespace()) {
+            f.chars += 1;
+            if self.large {
+                f.large_chars += 1;
+            }
+            if c.is_uppercase() || c.is_lowercase() {
+                f.letters += 1;
+                if c.is_uppercase() {
+                    f.upper += 1;
+                }
+            }
+        }
+    }
+}
+
+fn is_large_size(el: &Element, heuristics: &SpamHeuristics) -> bool {
+    let Some(size) = font_size(el) else {
+        return false;
+    };
+    // 単位付きの値は本文（16px = 12pt = 1em）に対するパーセントにして比べる
+    let percent = match size.unit {
+        None if size.value <= 7.0 => return size.value as u32 >= heuristics.large_size_keyword,
+        None | Some(LengthUnit::Percent) => size.value,
+        Some(LengthUnit::Em) => size.value * 100.0,
+        Some(LengthUnit::Px) => size.value / 16.0 * 100.0,
+        Some(LengthUnit::Pt) => size.value / 12.0 * 100.0,
+    };
+    percent >= heuristics.large_size_percent as f32
+}
@@ -0,0 +1,81 @@
+use crate::ast::{Element, Node, Span};
+
+/// テキスト片に実際に適用されるインラインスタイル
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TextStyle {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub strike: bool,
+    /// `[kbd]` / `[tt]` の内側か
+    pub monospace: bool,
+    /// `[color]` の値（最も内側のもの）
+    pub color: Option<String>,
+    /// `[highlight]` の内側か
+    pub highlight: bool,
+    /// `[highlight=xxx]` の色（最も内側のもの）
+    pub highlight_color: Option<String>,
+    /// `[size]` の値（最も内側のもの）
+    pub size: Option<String>,
+    /// `[font]` の値（最も内側のもの）
+    pub font: Option<String>,
+}
+
+/// スタイルが確定したテキスト片
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StyledRun {
+    pub text: String,
+    pub span: Span,
+    pub style: TextStyle,
+}
+
+/// ネストした装飾タグを解決し、テキスト片ごとの実効スタイルを文書順に返す
+/// 同じ種類のタグが入れ子になった場合は内側の値が優先される
+pub fn resolve_styles(nodes: &[Node]) -> Vec<StyledRun> {
+    let mut runs = vec![];
+    collect_runs(nodes, &TextStyle::default(), &mut runs);
+    runs
+}
+
+fn collect_runs(nodes: &[Node], style: &TextStyle, runs: &mut Vec<StyledRun>) {
+    for n in nodes {
+        match n {
+            Node::Text { span, text } => runs.push(StyledRun {
+                text: text.clone(),
+                span: *span,
+                style: style.clone(),
+            }),
+            Node::Element(el) => {
+                let inner = apply_element(style, el);
+                collect_runs(&el.children, &inner, runs);
+            }
+            Node::Unsupported(_) => {}
+        }
+    }
+}
+
+fn apply_element(parent: &TextStyle, el: &Element) -> TextStyle {
+    let mut style = parent.clone();
+    let value = || {
+        el.attrs
+            .iter()
+            .find(|(k, _)| k == "value")
+            .map(|(_, v)| v.clone())
+    };
+    match el.name.as_str() {
+        "b" => style.bold = true,
+        "i" => style.italic = true,
+        "u" => style.underline = true,
+        "s" => style.strike = true,
+        "kbd" | "tt" => style.monospace = true,
+        "color" => style.color = value().or(style.color),
+        "highlight" => {
+            style.highlight = true;
+            style.highlight_color = value().or(style.highlight_color);
+        }
+        "size" => style.size = value().or(style.size),
+        "font" => style.font = value().or(style.font),
+        _ => {}
+    }
+    style
+}
@@ -0,0 +1,174 @@
+// 一覧ページや OpenGraph の説明文向けの要約
+//
+// 単語数は空白区切りで数え、空白で区切らない日本語・中国語は1文字を1語とみなす。
+
+use crate::ast::{Element, Node, Span};
+use crate::render::ast_to_plain_text;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SummaryOptions {
+    /// 抜粋に含める単語数の上限
+    pub max_words: usize,
+    /// 読了時間の計算に使う1分あたりの単語数
+    pub words_per_minute: usize,
+}
+
+impl Default for SummaryOptions {
+    fn default() -> Self {
+        Self {
+            max_words: 50,
+            words_per_minute: 200,
+        }
+    }
+}
+
+impl SummaryOptions {
+    pub fn with_max_words(mut self, max_words: usize) -> Self {
+        self.max_words = max_words;
+        self
+    }
+
+    pub fn with_words_per_minute(mut self, words_per_minute: usize) -> Self {
+        self.words_per_minute = words_per_minute;
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Summary {
+    /// 先頭から max_words 語までの AST。装飾は保ち、画像は含めない
+    pub excerpt_ast: Vec<Node>,
+    /// 抜粋が元の文書より短いか
+    pub truncated: bool,
+    /// 文書全体の単語数
+    pub word_count: usize,
+    /// 読了時間（分、切り上げ）。本文が無ければ 0
+    pub reading_minutes: usize,
+    /// 画像を含むか
+    pub has_media: bool,
+}
+
+pub fn summarize(nodes: &[Node], opts: SummaryOptions) -> Summary {
+    let word_count = count_words(&ast_to_plain_text(nodes));
+    let reading_minutes = word_count.div_ceil(opts.words_per_minute.max(1));
+
+    let mut excerpt = Excerpt {
+        budget: opts.max_words,
+        truncated: false,
+        has_media: false,
+    };
+    let excerpt_ast = excerpt.nodes(nodes);
+
+    Summary {
+        excerpt_ast,
+        truncated: excerpt.truncated,
+        word_count,
+        reading_minutes,
+        has_media: excerpt.has_media || has_media(nodes),
+    }
+}
+
+/// 抜粋を作る途中の状態
+struct Excerpt {
+    /// 残りの単語数
+    budget: usize,
+    truncated: bool,
+    has_media: bool,
+}
+
+impl Excerpt {
+    fn nodes(&mut self, nodes: &[Node]) -> Vec<Node> {
+        let mut out = vec![];
+        for node in nodes {
+            if self.truncated {
+                break;
+            }
+            match node {
+                Node::Text { span, text } => {
+                    let (cut, used) = take_words(text, self.budget);
+                    self.budget -= used;
+                    let kept = if cut < text.len() {
+                        self.truncated = true;
+                        text[..cut].trim_end()
+                    } else {
+                        text.as_str()
+                    };
+                    if kept.is_empty() {
+                        continue;
+                    }
+                    // span が元の文字列と対応しているときだけ終端を詰める
+                    let end = if span.end - span.start == text.len() {
+                        span.start + kept.len()
+                    } else {
+                        span.end
+                    };
+                    out.push(Node::Text {
+                        span: Span {
+                            start: span.start,
+                            end,
+                        },
+                        text: kept.to_string(),
+                    });
+                }
+                Node::Element(el) if el.name == "img" => self.has_media = true,
+                Node::Element(el) => {
+                    let children = self.nodes(&el.children);
+                    if children.is_empty() && !el.children.is_empty() {
+                        continue;
+                    }
+                    out.push(Node::Element(Element {
+                        children,
+                        ..el.clone()
+                    }));
+                }
+                Node::Unsupported(_) => {}
+            }
+        }
+        out
+    }
+}
+
+fn has_media(nodes: &[Node]) -> bool {
+    nodes.iter().any(|n| match n {
+        Node::Element(el) => el.name == "img" || has_media(&el.children),
+        _ => false,
+    })
+}
+
+/// 空白で単語を区切らない文字（かな・漢字・ハングル）
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30FF}'
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{AC00}'..='\u{D7AF}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{FF66}'..='\u{FF9F}')
+}
+
+fn count_words(text: &str) -> usize {
+    take_words(text, usize::MAX).1
+}
+
+/// 先頭から budget 語を取る。(切る位置, 取った単語数) を返す
+fn take_words(text: &str, budget: usize) -> (usize, usize) {
+    let mut count = 0;
+    let mut in_word = false;
+    for (i, c) in text.char_indices() {
+        if c.is_whitespace() {
+            in_word = false;
+            continue;
+        }
+        let cjk = is_cjk(c);
+        if cjk || !in_word {
+            // 新しい単語の始まり
+            if count == budget {
+                return (i, count);
+            }
+            count += 1;
+        }
+        in_word = !cjk;
+    }
+    (text.len(), count)
+}
@@ -0,0 +1,349 @@
+// HTML のテンプレート文字列だけで定義するタグ
+//
+// 設定ファイルなどから `<span class="spoiler">{content}</span>` のような文字列を読み込んで
+// タグを追加できるようにする。置き換える値は置かれた位置（要素の中身 / 引用符で囲んだ属性値）
+// に合わせてエスケープし、エスケープでは防げない位置（引用符の無い属性値・`<script>` の中・
+// イベント属性など）に置いたテンプレートは登録時にエラーにする。
+
+use crate::ast::Element;
+use crate::error::TemplateError;
+use crate::escape::{escape_attr, escape_text};
+use crate::registry::{is_valid_url, DisplayKind};
+
+/// 値を置く位置
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Context {
+    /// 要素の中身
+    Content,
+    /// 引用符で囲んだ属性値。url ならリンク先として検証する（`href` / `src` など）
+    Attribute { url: bool },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Segment {
+    Literal(String),
+    /// `{value}`（`[tag=値]` の値）
+    Value(Context),
+    /// `{attr:name}`（`[tag name=値]` の値）
+    Attr(String, Context),
+}
+
+/// `{value}` / `{content}` / `{attr:name}` を含む HTML のテンプレート
+/// `{{` / `}}` は `{` / `}` そのものを表す
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TagTemplate {
+    source: String,
+    /// `{content}` より前
+    open: Vec<Segment>,
+    /// `{content}` より後。None なら中身は出力しない
+    close: Option<Vec<Segment>>,
+}
+
+/// 値にリンク先を取る属性（srcset は URL の並びだが、1つの URL として検証できなければ拒否する）
+const URL_ATTRS: &[&str] = &[
+    "href",
+    "src",
+    "action",
+    "formaction",
+    "cite",
+    "poster",
+    "data",
+    "xlink:href",
+    "background",
+    "ping",
+    "srcset",
+    "codebase",
+    "manifest",
+];
+
+/// 値が文字列ではなく HTML・CSS・ヘッダとして解釈される属性（エスケープでは安全にならない）
+/// `srcdoc` は文字参照を戻した後に文書として読まれる
+const UNSAFE_ATTRS: &[&str] = &["srcdoc", "style", "http-equiv"];
+
+/// 中身が HTML として解釈されない要素（エスケープでは値を安全にできない）
+const RAW_TEXT_ELEMENTS: &[&str] = &["script", "style", "textarea", "title"];
+
+/// ブロック要素として扱う最初のタグ
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address",
+    "aside",
+    "blockquote",
+    "details",
+    "div",
+    "figure",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "ul",
+];
+
+impl TagTemplate {
+    pub fn parse(source: &str) -> Result<Self, TemplateError> {
+        let mut scanner = Scanner::default();
+        let mut open = vec![];
+        let mut close: Option<Vec<Segment>> = None;
+        let mut chars = source.chars().peekable();
+        while let Some(c) = chars.next() {
+            let segments = close.as_mut().unwrap_or(&mut open);
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    push_literal(segments, '{');
+                    scanner.feed('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    push_literal(segments, '}');
+                    scanner.feed('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(TemplateError::UnclosedPlaceholder),
+                        }
+                    }
+                    let ctx = scanner.context(&name)?;
+                    match name.trim() {
+                        "content" => {
+                            if ctx != Context::Content || close.is_some() {
+                                return Err(TemplateError::MisplacedContent);
+                            }
+                            close = Some(vec![]);
+                        }
+                        "value" => segments.push(Segment::Value(ctx)),
+                        other => match other.strip_prefix("attr:").map(str::trim) {
+                            Some(attr) if is_attr_name(attr) => {
+                                segments.push(Segment::Attr(attr.to_ascii_lowercase(), ctx));
+                            }
+                            _ => return Err(TemplateError::UnknownPlaceholder(name)),
+                        },
+                    }
+                }
+                _ => {
+                    push_literal(segments, c);
+                    scanner.feed(c);
+                }
+            }
+        }
+        Ok(Self {
+            source: source.to_string(),
+            open,
+            close,
+        })
+    }
+
+    /// 元のテンプレート文字列
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// `{value}` を使うか（値属性を許可するか）
+    pub fn uses_value(&self) -> bool {
+        self.segments().any(|s| matches!(s, Segment::Value(_)))
+    }
+
+    /// `{attr:name}` で使う属性名
+    pub fn attr_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self
+            .segments()
+            .filter_map(|s| match s {
+                Segment::Attr(name, _) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+
+    /// 最初のタグがブロック要素（div / blockquote など）ならブロック要素として扱う
+    pub fn display(&self) -> DisplayKind {
+        let first = self
+            .source
+            .trim_start()
+            .strip_prefix('<')
+            .map(|rest| {
+                rest.chars()
+                    .take_while(|c| c.is_ascii_alphanumeric())
+                    .collect::<String>()
+                    .to_ascii_lowercase()
+            })
+            .unwrap_or_default();
+        if BLOCK_ELEMENTS.contains(&first.as_str()) {
+            DisplayKind::Block
+        } else {
+            DisplayKind::Inline
+        }
+    }
+
+    /// 中身を出力するか（`{content}` を含むか）
+    pub(crate) fn has_content(&self) -> bool {
+        self.close.is_some()
+    }
+
+    /// el の値で置き換えた (開始部分, 終了部分) を返す
+    /// リンク先の属性に使えない値があれば None（中身だけを出力させる）
+    pub(crate) fn render(&self, el: &Element) -> Option<(String, String)> {
+        let open = render_segments(&self.open, el)?;
+        let close = match &self.close {
+            Some(close) => render_segments(close, el)?,
+            None => String::new(),
+        };
+        Some((open, close))
+    }
+
+    fn segments(&self) -> impl Iterator<Item = &Segment> {
+        self.open.iter().chain(self.close.iter().flatten())
+    }
+}
+
+fn push_literal(segments: &mut Vec<Segment>, c: char) {
+    match segments.last_mut() {
+        Some(Segment::Literal(s)) => s.push(c),
+        _ => segments.push(Segment::Literal(c.to_string())),
+    }
+}
+
+fn is_attr_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn render_segments(segments: &[Segment], el: &Element) -> Option<String> {
+    let mut out = String::new();
+    for segment in segments {
+        let (value, ctx) = match segment {
+            Segment::Literal(s) => {
+                out.push_str(s);
+                continue;
+            }
+            Segment::Value(ctx) => (find_attr(el, "value"), ctx),
+            Segment::Attr(name, ctx) => (find_attr(el, name), ctx),
+        };
+        let value = value.unwrap_or_default().trim();
+        match ctx {
+            Context::Content => out.push_str(&escape_text(value)),
+            Context::Attribute { url } => {
+                if *url && !is_valid_url(value) {
+                    return None;
+                }
+                out.push_str(&escape_attr(value));
+            }
+        }
+    }
+    Some(out)
+}
+
+fn find_attr<'a>(el: &'a Element, name: &str) -> Option<&'a str> {
+    el.attrs
+        .iter()
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v.as_str())
+}
+
+/// テンプレートの HTML を先頭から読み、いまどの位置にいるかを追う
+/// 完全な HTML の字句解析ではなく、値を置いてよい位置かの判定に要る分だけを扱う
+#[derive(Default)]
+struct Scanner {
+    /// `<` から `>` までの中
+    in_tag: bool,
+    /// 属性値を囲んでいる引用符
+    quote: Option<char>,
+    /// 読んでいるタグ名・属性名
+    name: String,
+    /// 読み終えたタグ名（`/` 付きなら閉じタグ）
+    tag: String,
+    /// 直前の `=` の前にあった属性名
+    attr: String,
+    /// 中身が HTML として解釈されない要素の中
+    raw_text: Option<String>,
+}
+
+impl Scanner {
+    fn feed(&mut self, c: char) {
+        if let Some(q) = self.quote {
+            if c == q {
+                self.quote = None;
+            }
+            return;
+        }
+        if !self.in_tag {
+            if c == '<' {
+                self.in_tag = true;
+                self.tag.clear();
+                self.name.clear();
+            }
+            return;
+        }
+        match c {
+            '"' | '\'' => self.quote = Some(c),
+            '>' => {
+                self.finish_name();
+                self.in_tag = false;
+                let tag = self.tag.to_ascii_lowercase();
+                match tag.strip_prefix('/') {
+                    Some(closed) if self.raw_text.as_deref() == Some(closed) => {
+                        self.raw_text = None;
+                    }
+                    None if RAW_TEXT_ELEMENTS.contains(&tag.as_str()) => {
+                        self.raw_text = Some(tag);
+                    }
+                    _ => {}
+                }
+            }
+            '=' => {
+                self.finish_name();
+                self.attr.make_ascii_lowercase();
+            }
+            c if c.is_whitespace() => self.finish_name(),
+            c => self.name.push(c),
+        }
+    }
+
+    /// 読んでいた名前を、最初ならタグ名、それ以外なら属性名として確定する
+    fn finish_name(&mut self) {
+        if self.name.is_empty() {
+            return;
+        }
+        let name = std::mem::take(&mut self.name);
+        if self.tag.is_empty() {
+            self.tag = name;
+        } else {
+            self.attr = name;
+        }
+    }
+
+    /// 現在の位置に置いた値の扱い。エスケープで安全にできない位置ならエラー
+    fn context(&self, placeholder: &str) -> Result<Context, TemplateError> {
+        let unsafe_context = || TemplateError::UnsafeContext(placeholder.to_string());
+        if self.in_tag {
+            if self.quote.is_none() {
+                return Err(TemplateError::UnquotedAttribute(placeholder.to_string()));
+            }
+            // イベント属性・srcdoc などは値を HTML としてエスケープしても安全にならない
+            if self.attr.starts_with("on") || UNSAFE_ATTRS.contains(&self.attr.as_str()) {
+                return Err(unsafe_context());
+            }
+            return Ok(Context::Attribute {
+                url: URL_ATTRS.contains(&self.attr.as_str()),
+            });
+        }
+        if self.raw_text.is_some() {
+            return Err(unsafe_context());
+        }
+        Ok(Context::Content)
+    }
+}
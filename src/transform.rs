@@ -0,0 +1,390 @@
+// パース済みの AST を書き換えるユーティリティ
+
+use std::collections::HashSet;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::ast::{Element, Node, Span};
+use crate::error::{BbCodeError, LimitError};
+
+/// `[quote]` の部分木をすべて取り除く（通知メールに返信の新しい部分だけを載せる用途）
+/// 引用の直後の改行1つは引用ブロックのレイアウト用なので一緒に取り除く
+pub fn strip_quotes(nodes: &[Node]) -> Vec<Node> {
+    let mut out = vec![];
+    let mut after_quote = false;
+    for node in nodes {
+        match node {
+            Node::Element(el) if el.name == "quote" => after_quote = true,
+            Node::Element(el) => {
+                after_quote = false;
+                out.push(Node::Element(Element {
+                    children: strip_quotes(&el.children),
+                    ..el.clone()
+                }));
+            }
+            Node::Text { span, text } => {
+                let skip = if after_quote {
+                    text.len() - strip_leading_newline(text).len()
+                } else {
+                    0
+                };
+                after_quote = false;
+                if skip == text.len() {
+                    continue;
+                }
+                // span が元の文字列と対応しているときだけ開始位置をずらす
+                let start = if span.end - span.start == text.len() {
+                    span.start + skip
+                } else {
+                    span.start
+                };
+                out.push(Node::Text {
+                    span: Span {
+                        start,
+                        end: span.end,
+                    },
+                    text: text[skip..].to_string(),
+                });
+            }
+            Node::Unsupported(_) => {
+                after_quote = false;
+                out.push(node.clone());
+            }
+        }
+    }
+    out
+}
+
+fn strip_leading_newline(text: &str) -> &str {
+    text.strip_prefix("\r\n")
+        .or_else(|| text.strip_prefix('\n'))
+        .unwrap_or(text)
+}
+
+/// 署名に適用する制限（投稿本文とは別に持つ）
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct SignaturePolicy {
+    /// 署名で使えるタグ。これ以外の要素はタグを外して中身だけ残す
+    pub allowed_tags: HashSet<String>,
+    /// 署名の要素数の上限（許可されないタグを外した後で数える）
+    pub max_tags: usize,
+    /// 署名のテキスト長の合計上限（バイト）
+    pub max_text_len: usize,
+    /// 本文と署名の間に入れるノード
+    pub separator: Vec<Node>,
+}
+
+impl Default for SignaturePolicy {
+    fn default() -> Self {
+        Self {
+            allowed_tags: ["b", "i", "u", "s", "color", "url"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            max_tags: 10,
+            max_text_len: 300,
+            separator: vec![Node::text("\n--\n")],
+        }
+    }
+}
+
+impl SignaturePolicy {
+    pub fn with_allowed_tags(mut self, names: &[&str]) -> Self {
+        self.allowed_tags = names.iter().map(|n| n.to_ascii_lowercase()).collect();
+        self
+    }
+
+    pub fn with_max_tags(mut self, max_tags: usize) -> Self {
+        self.max_tags = max_tags;
+        self
+    }
+
+    pub fn with_max_text_len(mut self, max_text_len: usize) -> Self {
+        self.max_text_len = max_text_len;
+        self
+    }
+
+    pub fn with_separator(mut self, separator: Vec<Node>) -> Self {
+        self.separator = separator;
+        self
+    }
+}
+
+/// 投稿の AST の末尾に区切りと署名を付け足す
+/// 署名は policy に従ってタグを取り除いた上で、制限を超えていればエラーにする
+pub fn append_signature(
+    post: &[Node],
+    signature: &[Node],
+    policy: &SignaturePolicy,
+) -> Result<Vec<Node>, BbCodeError> {
+    let signature = unwrap_disallowed(signature, &policy.allowed_tags);
+    if signature.is_empty() {
+        return Ok(post.to_vec());
+    }
+
+    let (tags, text_len) = measure(&signature);
+    if tags > policy.max_tags {
+        return Err(LimitError::TagCountExceeded {
+            max_tags: policy.max_tags,
+        }
+        .into());
+    }
+    if text_len > policy.max_text_len {
+        return Err(LimitError::TextLengthExceeded {
+            max_len: policy.max_text_len,
+            actual_len: text_len,
+        }
+        .into());
+    }
+
+    let mut out = post.to_vec();
+    out.extend(policy.separator.iter().cloned());
+    out.extend(signature);
+    Ok(out)
+}
+
+/// 許可されない要素をその子で置き換える
+fn unwrap_disallowed(nodes: &[Node], allowed: &HashSet<String>) -> Vec<Node> {
+    let mut out = vec![];
+    for node in nodes {
+        match node {
+            Node::Element(el) => {
+                let children = unwrap_disallowed(&el.children, allowed);
+                if allowed.contains(el.name.as_str()) {
+                    out.push(Node::Element(Element {
+                        children,
+                        ..el.clone()
+                    }));
+                } else {
+                    out.extend(children);
+                }
+            }
+            Node::Text { .. } => out.push(node.clone()),
+            // 中身の分からないノードは許可されたタグかを判断できないので捨てる
+            Node::Unsupported(_) => {}
+        }
+    }
+    out
+}
+
+/// (要素数, テキスト長の合計)
+fn measure(nodes: &[Node]) -> (usize, usize) {
+    nodes.iter().fold((0, 0), |(tags, len), n| match n {
+        Node::Text { text, .. } => (tags, len + text.len()),
+        Node::Element(el) => {
+            let (t, l) = measure(&el.children);
+            (tags + 1 + t, len + l)
+        }
+        Node::Unsupported(_) => (tags, len),
+    })
+}
+
+/// `truncate` の設定
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TruncateOptions {
+    /// 残すテキストの書記素クラスタ数の上限（省略記号を含む）
+    pub max_graphemes: usize,
+    /// 切り詰めたときに末尾に付ける文字列
+    pub ellipsis: String,
+    /// 単語の途中で切らない（空白で区切らない日本語などは文字単位で切れる）
+    pub preserve_words: bool,
+}
+
+impl Default for TruncateOptions {
+    fn default() -> Self {
+        Self {
+            max_graphemes: 200,
+            ellipsis: "…".to_string(),
+            preserve_words: true,
+        }
+    }
+}
+
+impl TruncateOptions {
+    pub fn with_max_graphemes(mut self, max_graphemes: usize) -> Self {
+        self.max_graphemes = max_graphemes;
+        self
+    }
+
+    pub fn with_ellipsis(mut self, ellipsis: impl Into<String>) -> Self {
+        self.ellipsis = ellipsis.into();
+        self
+    }
+
+    pub fn with_preserve_words(mut self, enabled: bool) -> Self {
+        self.preserve_words = enabled;
+        self
+    }
+}
+
+/// テキストが長すぎれば切り詰める。タグの対応は保たれる
+/// `[url]` / `[code]` は途中で切らずに丸ごと残すか捨て、本文中の URL も途中で切らない。
+/// 切り詰めたときは末尾に省略記号の Text ノードを足す
+pub fn truncate(nodes: &[Node], opts: &TruncateOptions) -> Vec<Node> {
+    if grapheme_len(nodes) <= opts.max_graphemes {
+        return nodes.to_vec();
+    }
+    let mut truncator = Truncator {
+        budget: opts
+            .max_graphemes
+            .saturating_sub(opts.ellipsis.graphemes(true).count()),
+        preserve_words: opts.preserve_words,
+        done: false,
+        end: 0,
+    };
+    let mut out = truncator.nodes(nodes);
+    let end = trim_end_nodes(&mut out).unwrap_or(truncator.end);
+    if !opts.ellipsis.is_empty() {
+        out.push(Node::Text {
+            span: Span { start: end, end },
+            text: opts.ellipsis.clone(),
+        });
+    }
+    out
+}
+
+/// 切り詰めの途中の状態
+struct Truncator {
+    /// 残りの書記素クラスタ数
+    budget: usize,
+    preserve_words: bool,
+    /// 切った後は何も足さない
+    done: bool,
+    /// 最後に残したノードの終端
+    end: usize,
+}
+
+impl Truncator {
+    fn nodes(&mut self, nodes: &[Node]) -> Vec<Node> {
+        let mut out = vec![];
+        for node in nodes {
+            if self.done {
+                break;
+            }
+            match node {
+                Node::Text { span, text } => {
+                    let len = text.graphemes(true).count();
+                    if len <= self.budget {
+                        self.budget -= len;
+                        self.end = span.end;
+                        out.push(node.clone());
+                        continue;
+                    }
+                    self.done = true;
+                    let cut = cut_point(text, self.budget, self.preserve_words);
+                    if cut == 0 {
+                        continue;
+                    }
+                    // span が元の文字列と対応しているときだけ終端を詰める
+                    let end = if span.end - span.start == text.len() {
+                        span.start + cut
+                    } else {
+                        span.end
+                    };
+                    self.end = end;
+                    out.push(Node::Text {
+                        span: Span {
+                            start: span.start,
+                            end,
+                        },
+                        text: text[..cut].to_string(),
+                    });
+                }
+                // 途中で切ると意味が変わる要素は丸ごと残すか捨てる
+                Node::Element(el) if matches!(el.name.as_str(), "url" | "code" | "img") => {
+                    let len = grapheme_len(std::slice::from_ref(node));
+                    if len <= self.budget {
+                        self.budget -= len;
+                        self.end = el.span.end;
+                        out.push(node.clone());
+                    } else {
+                        self.done = true;
+                    }
+                }
+                Node::Element(el) => {
+                    let children = self.nodes(&el.children);
+                    if children.is_empty() && !el.children.is_empty() {
+                        continue;
+                    }
+                    if !self.done {
+                        self.end = el.span.end;
+                    }
+                    out.push(Node::Element(Element {
+                        children,
+                        ..el.clone()
+                    }));
+                }
+                Node::Unsupported(_) => out.push(node.clone()),
+            }
+        }
+        out
+    }
+}
+
+/// 表示されるテキストの書記素クラスタ数（画像の URL は数えない）
+fn grapheme_len(nodes: &[Node]) -> usize {
+    nodes
+        .iter()
+        .map(|n| match n {
+            Node::Text { text, .. } => text.graphemes(true).count(),
+            Node::Element(el) if el.name == "img" => 0,
+            Node::Element(el) => grapheme_len(&el.children),
+            Node::Unsupported(_) => 0,
+        })
+        .sum()
+}
+
+/// text を budget 個の書記素クラスタ以内に収める切り位置（バイト）
+fn cut_point(text: &str, budget: usize, preserve_words: bool) -> usize {
+    let mut cut = text
+        .grapheme_indices(true)
+        .nth(budget)
+        .map_or(text.len(), |(i, _)| i);
+    if preserve_words {
+        cut = text
+            .split_word_bound_indices()
+            .map(|(i, _)| i)
+            .take_while(|&i| i <= cut)
+            .last()
+            .unwrap_or(0);
+    }
+    // 本文中の URL は途中で切らない
+    let token_start = text[..cut].rfind(char::is_whitespace).map_or(0, |i| {
+        i + text[i..].chars().next().map_or(1, char::len_utf8)
+    });
+    let token_end = text[cut..]
+        .find(char::is_whitespace)
+        .map_or(text.len(), |i| cut + i);
+    let token = &text[token_start..token_end];
+    if token_start < cut && (token.contains("://") || token.starts_with("www.")) {
+        cut = token_start;
+    }
+    text[..cut].trim_end().len()
+}
+
+/// 末尾の Text ノードの後ろの空白を取り除き、残った最後のノードの終端を返す
+fn trim_end_nodes(nodes: &mut Vec<Node>) -> Option<usize> {
+    loop {
+        match nodes.last_mut()? {
+            Node::Text { span, text } => {
+                let trimmed = text.trim_end().len();
+                if trimmed == 0 {
+                    nodes.pop();
+                    continue;
+                }
+                if span.end - span.start == text.len() {
+                    span.end = span.start + trimmed;
+                }
+                text.truncate(trimmed);
+                return Some(span.end);
+            }
+            Node::Element(el) => {
+                trim_end_nodes(&mut el.children);
+                return Some(el.span.end);
+            }
+            Node::Unsupported(node) => return Some(node.span.end),
+        }
+    }
+}
@@ -0,0 +1,59 @@
+// 投稿を受け付けるかどうかだけを調べる検証（HTML は作らない）
+//
+// 投稿の送信時など、合否と理由だけが要る場面で使う。パースと同じ検査を行うが描画はしないので、
+// パースして描画するより軽い。
+
+use crate::diagnostic::Diagnostic;
+use crate::document::ParseMetrics;
+use crate::error::BbCodeError;
+use crate::options::BbCodeOptions;
+use crate::parser::parse_counting_fallbacks;
+
+/// 検証の結果
+#[derive(Debug)]
+pub struct ValidationReport {
+    /// 投稿を受け付けられる（errors が空）
+    pub ok: bool,
+    /// 受け付けられない理由。今はパースのエラー1つまで
+    pub errors: Vec<BbCodeError>,
+    /// 受け付けられるが、投稿者に知らせたい事柄
+    pub warnings: Vec<Diagnostic>,
+    /// エラーになった場合は既定値
+    pub stats: ValidationStats,
+}
+
+/// 検証した投稿の統計
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ValidationStats {
+    /// 入力の長さ（バイト）
+    pub input_len: usize,
+    pub metrics: ParseMetrics,
+    /// テキストに戻したタグの数（未知のタグ・不正な値・閉じていないタグなど）
+    pub fallbacks: usize,
+}
+
+/// input を opts でパースできるか調べる
+pub fn validate(input: &str, opts: &BbCodeOptions) -> ValidationReport {
+    let mut stats = ValidationStats {
+        input_len: input.len(),
+        ..ValidationStats::default()
+    };
+    match parse_counting_fallbacks(input, opts) {
+        Ok(parsed) => {
+            stats.metrics = ParseMetrics::of(&parsed.nodes);
+            stats.fallbacks = parsed.fallbacks.len();
+            ValidationReport {
+                ok: true,
+                errors: vec![],
+                warnings: parsed.diagnostics,
+                stats,
+            }
+        }
+        Err(e) => ValidationReport {
+            ok: false,
+            errors: vec![e],
+            warnings: vec![],
+            stats,
+        },
+    }
+}
@@ -0,0 +1,69 @@
+use std::time::{Duration, Instant};
+
+use bbcode_parser::{parse_bbcode_to_ast, BbCodeError, BbCodeOptions};
+
+/// 大きめの入力でも許容する制限
+fn options() -> BbCodeOptions {
+    BbCodeOptions::default()
+        .with_max_depth(16)
+        .with_max_tags(1_000_000)
+        .with_max_input_size(1 << 20)
+        .with_max_text_len(1 << 20)
+}
+
+/// 入力を n 回繰り返したものと 4n 回繰り返したもののパース時間を比べる
+/// 線形ならおよそ4倍、二次以上なら16倍以上になるので、10倍未満であることを確かめる
+/// （小さい入力の計測誤差を避けるため、短い方にも下限 1ms を設ける）
+fn assert_linear(unit: &str, n: usize) {
+    let opts = options();
+    let time = |count: usize| {
+        let input = unit.repeat(count);
+        let start = Instant::now();
+        let _ = parse_bbcode_to_ast(&input, &opts);
+        start.elapsed()
+    };
+    // 初回の確保などの影響を除く
+    time(n);
+    let small = time(n).max(Duration::from_millis(1));
+    let large = time(n * 4);
+    assert!(
+        large < small * 10,
+        "{unit:?}: {n} reps took {small:?}, {} reps took {large:?}",
+        n * 4
+    );
+}
+
+#[test]
+fn test_nested_unclosed_tags_are_linear() {
+    // 以前の文法では閉じられないタグごとに中身を読み直していた（[b]x を 22 個で約1秒）
+    assert_linear("[b]x", 5_000);
+    assert_linear("[quote=a][i]", 2_000);
+    assert_linear("[*]a", 5_000);
+}
+
+#[test]
+fn test_unterminated_code_and_attrs_are_linear() {
+    assert_linear("[code]a", 5_000);
+    assert_linear("[b=x", 5_000);
+    assert_linear("[quote=\"a", 5_000);
+    assert_linear("[[]]", 5_000);
+}
+
+#[test]
+fn test_adversarial_results_are_unchanged() {
+    let opts = options();
+    // 閉じられないタグはテキストのまま残る
+    let input = "[b]x".repeat(100);
+    let ast = parse_bbcode_to_ast(&input, &opts).unwrap();
+    assert_eq!(bbcode_parser::ast_to_html(&ast), input);
+
+    // [/code] の無い [code] は通常のタグとして扱われ、後ろの [code] も verbatim にならない
+    let ast = parse_bbcode_to_ast("[code][b]x[/b][code]", &opts).unwrap();
+    assert_eq!(bbcode_parser::ast_to_html(&ast), "[code]<b>x</b>[code]");
+
+    // 対応する開始タグの無い閉じタグは構文エラー
+    assert!(matches!(
+        parse_bbcode_to_ast(&"[/b]".repeat(1000), &opts),
+        Err(BbCodeError::Parse(_))
+    ));
+}
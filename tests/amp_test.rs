@@ -0,0 +1,33 @@
+#![cfg(feature = "amp")]
+
+use bbcode_parser::render::{ast_to_html_with, HtmlRenderOptions, HtmlTarget};
+use bbcode_parser::{parse_bbcode_to_ast, BbCodeOptions};
+
+fn amp(input: &str) -> String {
+    let ast = parse_bbcode_to_ast(input, &BbCodeOptions::default()).unwrap();
+    let opts = HtmlRenderOptions::default().with_target(HtmlTarget::Amp);
+    ast_to_html_with(&ast, &opts)
+}
+
+#[test]
+fn test_amp_images() {
+    assert_eq!(
+        amp("[img=640x480]https://a.example/x.png[/img]"),
+        "<amp-img src=\"https://a.example/x.png\" alt=\"\" width=\"640\" height=\"480\" \
+         layout=\"responsive\"></amp-img>"
+    );
+    assert_eq!(
+        amp("[img]/y.png[/img]"),
+        "<amp-img src=\"/y.png\" alt=\"\" height=\"300\" layout=\"fixed-height\"></amp-img>"
+    );
+}
+
+#[test]
+fn test_amp_limits_inline_styles() {
+    let html = amp("[font=Arial]f[/font][color=red]c[/color][center]x[/center]");
+    assert_eq!(
+        html,
+        "f<span style=\"color:red\">c</span><div style=\"text-align:center\">x</div>"
+    );
+    assert!(!html.contains("font-family"));
+}
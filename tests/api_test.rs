@@ -0,0 +1,40 @@
+use bbcode_parser::api::{self, Node, Options};
+use bbcode_parser::prelude::*;
+use bbcode_parser::ParseError;
+
+#[test]
+fn test_api_facade() {
+    let nodes = api::parse("[b]x[/b]", &Options::default()).unwrap();
+    assert!(matches!(&nodes[..], [Node::Element(el)] if el.name == "b"));
+    assert_eq!(api::render_html(&nodes), "<b>x</b>");
+
+    let err = api::parse("a\n[/b]", &Options::default()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Parse);
+}
+
+#[test]
+fn test_prelude_and_syntax_error() {
+    let opts = BbCodeOptions::default();
+    let doc = parse("[i]y[/i]", &opts).unwrap();
+    assert_eq!(doc.to_html(), "<i>y</i>");
+
+    // 文法エラーは位置だけを公開する
+    match parse_bbcode_to_ast("ab\ncd[/b]", &opts) {
+        Err(BbCodeError::Parse(ParseError::Syntax(e))) => {
+            assert_eq!(e.span, Span { start: 5, end: 5 });
+            assert_eq!((e.line, e.column), (2, 3));
+            assert_eq!(
+                e.to_string(),
+                "Closing tag does not close any open tag at line 2, col 3"
+            );
+        }
+        other => panic!("Expected a syntax error, got {other:?}"),
+    }
+    match parse_bbcode_to_ast("[", &opts) {
+        Err(BbCodeError::Parse(ParseError::Syntax(e))) => {
+            assert_eq!((e.line, e.column), (1, 2));
+            assert!(!e.expected.is_empty());
+        }
+        other => panic!("Expected a syntax error, got {other:?}"),
+    }
+}
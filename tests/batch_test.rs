@@ -0,0 +1,102 @@
+use bbcode_parser::render::{ast_to_html_with, HtmlRenderOptions, LruRenderCache};
+use bbcode_parser::{
+    bbcode_to_html, parse_bbcode_to_ast, render_many, BbCodeError, BbCodeOptions, LimitError,
+    RenderManyOptions,
+};
+
+const POSTS: [&str; 5] = [
+    "[quote=Alice]hi[/quote] [b]one[/b]",
+    "[i]two[/i]",
+    "[quote=Alice]hi[/quote] [b]one[/b]",
+    "[b][i][u][s]too deep[/s][/u][/i][/b]",
+    "three",
+];
+
+#[test]
+fn test_render_many() {
+    let opts = RenderManyOptions::default();
+    let mut cache = LruRenderCache::new(64);
+    let posts = render_many(POSTS, &opts, &mut cache);
+    assert_eq!(posts.len(), POSTS.len());
+    for (post, input) in posts.iter().zip(POSTS) {
+        match bbcode_to_html(input, &BbCodeOptions::default()) {
+            Ok(expected) => assert_eq!(post.html.as_ref().unwrap(), &expected),
+            Err(_) => assert!(matches!(
+                post.html,
+                Err(BbCodeError::Limit(LimitError::NestDepthExceeded { .. }))
+            )),
+        }
+    }
+    // 同じ投稿は2回目からキャッシュを使う
+    let hits: Vec<_> = posts.iter().map(|p| p.cache_hit).collect();
+    assert_eq!(hits, [false, false, true, false, false]);
+
+    // 次のページの描画でも同じキャッシュを使える（エラーになった投稿はキャッシュしない）
+    let posts = render_many(POSTS, &opts, &mut cache);
+    let hits: Vec<_> = posts.iter().map(|p| p.cache_hit).collect();
+    assert_eq!(hits, [true, true, true, false, true]);
+
+    // HTML の設定が違えば別のキー
+    let spans = opts.with_html(HtmlRenderOptions::default().with_emit_source_spans(true));
+    let posts = render_many(&POSTS[..1], &spans, &mut cache);
+    assert!(!posts[0].cache_hit);
+    assert!(posts[0].html.as_ref().unwrap().contains("data-bb-start"));
+}
+
+#[test]
+fn test_render_many_in_threads() {
+    let posts: Vec<String> = (0..20).map(|i| format!("[b]post {i}[/b]")).collect();
+    let sequential = render_many(
+        &posts,
+        &RenderManyOptions::default(),
+        &mut LruRenderCache::new(0),
+    );
+    let opts = RenderManyOptions::default().with_threads(4);
+    let mut cache = LruRenderCache::new(64);
+    let parallel = render_many(&posts, &opts, &mut cache);
+    let html = |posts: &[bbcode_parser::RenderedPost]| {
+        posts
+            .iter()
+            .map(|p| p.html.as_ref().unwrap().clone())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(html(&parallel), html(&sequential));
+    assert_eq!(html(&parallel)[3], "<b>post 3</b>");
+    assert_eq!(cache.len(), 20);
+    assert!(render_many(&posts, &opts, &mut cache)
+        .iter()
+        .all(|p| p.cache_hit));
+}
+
+#[test]
+fn test_render_many_modes_agree() {
+    // 同じ部分木が別の祖先・別の位置に現れる投稿。1スレッドでは部分木のキャッシュを使う
+    let posts = [
+        "[quote=Alice][b]a\nb[/b][/quote]",
+        "[pre][b]a\nb[/b][/pre]",
+        "x [b]a\nb[/b] [quote=Alice][b]a\nb[/b][/quote]",
+        "[list][*][b]a\nb[/b][*]c[/list]",
+        "[pre][quote=Alice][b]a\nb[/b][/quote][/pre]",
+    ];
+    for html in [
+        HtmlRenderOptions::default(),
+        HtmlRenderOptions::default().with_emit_source_spans(true),
+    ] {
+        let expected: Vec<_> = posts
+            .iter()
+            .map(|p| {
+                let ast = parse_bbcode_to_ast(p, &BbCodeOptions::default()).unwrap();
+                ast_to_html_with(&ast, &html)
+            })
+            .collect();
+        let opts = RenderManyOptions::default().with_html(html);
+        let rendered = |opts: &RenderManyOptions| {
+            render_many(posts, opts, &mut LruRenderCache::new(64))
+                .into_iter()
+                .map(|p| p.html.unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(rendered(&opts), expected);
+        assert_eq!(rendered(&opts.clone().with_threads(3)), expected);
+    }
+}
@@ -1,4 +1,15 @@
-use bbcode_parser::{ast_to_html, parse_bbcode_to_ast, BbCodeError, BbCodeOptions, Node};
+use std::borrow::Cow;
+
+use bbcode_parser::ast::{Span, TagName};
+use bbcode_parser::render::{ast_to_html_with, HtmlRenderOptions};
+use bbcode_parser::{
+    ast_to_html, ast_to_plain_text, lookalike, parse_bbcode_to_ast, parse_bbcode_with_diagnostics,
+    raw_parse, registry, validate_fragment, AttrWhitespace, BbCodeError, BbCodeOptions,
+    ContrastAction, ContrastCheck, ControlChars, Diagnostic, DomainPolicy, DuplicateAttrs,
+    EmptyElements, ErrorKind, FragmentContext, InvalidTagReason, LimitError, LinkPolicyAction,
+    NestingStrictness, Node, ParseError, PermissionLevel, Rule, SameTagNesting, SyntaxChars,
+    TagRegistry, TagSpec,
+};
 
 fn assert_text(node: &Node, expected: &str) {
     match node {
@@ -68,22 +79,19 @@ fn test_color_invalid() {
 
 #[test]
 fn test_nest_depth_exceeded() {
-    let opts = BbCodeOptions {
-        max_depth: 2,
-        ..Default::default()
-    };
+    let opts = BbCodeOptions::default().with_max_depth(2);
     // 3階層のネスト
     let input = "[b][i][color=red]Nested[/color][/i][/b]";
     let result = parse_bbcode_to_ast(input, &opts);
 
     match result {
-        Err(BbCodeError::NestDepthExceeded {
+        Err(BbCodeError::Limit(LimitError::NestDepthExceeded {
             max_depth,
             near,
             span,
             line,
             column,
-        }) => {
+        })) => {
             assert_eq!(max_depth, 2);
             // どのタグ付近で落ちたかは実装依存になり得るので、最低限の確認に留める
             assert!(
@@ -108,17 +116,14 @@ fn test_generate_html() {
 
 #[test]
 fn test_input_size_exceeded() {
-    let opts = BbCodeOptions {
-        max_input_size: 10, // 10byte
-        ..Default::default()
-    };
+    let opts = BbCodeOptions::default().with_max_input_size(10); // 10byte
     let long_input = "a".repeat(50); // 50byte
     let result = parse_bbcode_to_ast(&long_input, &opts);
     match result {
-        Err(BbCodeError::InputSizeExceeded {
+        Err(BbCodeError::Limit(LimitError::InputSizeExceeded {
             max_size,
             actual_size,
-        }) => {
+        })) => {
             assert_eq!(max_size, 10);
             assert_eq!(actual_size, 50);
         }
@@ -128,21 +133,43 @@ fn test_input_size_exceeded() {
 
 #[test]
 fn test_tag_count_exceeded() {
-    let opts = BbCodeOptions {
-        max_tags: 2,
-        ..Default::default()
-    };
+    let opts = BbCodeOptions::default().with_max_tags(2);
     // 3つのタグ
     let input = "[b][i][color=red]three tags[/color][/i][/b]";
     let result = parse_bbcode_to_ast(input, &opts);
     match result {
-        Err(BbCodeError::TagCountExceeded { max_tags }) => {
+        Err(BbCodeError::Limit(LimitError::TagCountExceeded { max_tags })) => {
             assert_eq!(max_tags, 2);
         }
         _ => panic!("Expected TagCountExceeded error"),
     }
 }
 
+#[test]
+fn test_child_count_exceeded() {
+    let opts = BbCodeOptions::default().with_max_children_per_element(2);
+    // 子要素2つまでは通る（テキストと最上位は数えない）
+    assert!(parse_bbcode_to_ast("[quote]a[b]1[/b] b [i]2[/i] c[/quote] [b]x[/b]", &opts).is_ok());
+    assert!(parse_bbcode_to_ast("[b]1[/b][b]2[/b][b]3[/b]", &opts).is_ok());
+
+    let input = "x\n[quote][b]1[/b][b]2[/b][b]3[/b][/quote]";
+    match parse_bbcode_to_ast(input, &opts) {
+        Err(BbCodeError::Limit(LimitError::ChildCountExceeded {
+            max_children: 2,
+            span,
+            line: 2,
+            column: 1,
+        })) => assert_eq!((span.start, span.end), (2, input.len())),
+        other => panic!("Expected ChildCountExceeded error: {other:?}"),
+    }
+    // 閉じタグの無い `[*]` の項目も数える
+    let list = "[list][*][b]1[/b][b]2[/b][b]3[/b][/list]";
+    assert!(matches!(
+        parse_bbcode_to_ast(list, &opts),
+        Err(BbCodeError::Limit(LimitError::ChildCountExceeded { .. }))
+    ));
+}
+
 #[test]
 fn test_mismatched_tags() {
     let opts = BbCodeOptions::default();
@@ -265,11 +292,46 @@ fn test_pest_parse_error_for_lone_bracket() {
     let result = parse_bbcode_to_ast(input, &opts);
 
     match result {
-        Err(BbCodeError::PestError(_)) => {}
+        Err(BbCodeError::Parse(_)) => {}
         _ => panic!("Expected PestError for lone '['"),
     }
 }
 
+#[test]
+fn test_error_kind() {
+    let err = parse_bbcode_to_ast("[", &BbCodeOptions::default()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Parse);
+
+    let opts = BbCodeOptions::default().with_max_input_size(1);
+    let err = parse_bbcode_to_ast("ab", &opts).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Limit);
+    assert_eq!(err.to_string(), "Input size exceeded limit (max 1 bytes)");
+}
+
+/// 名前や中身の欠けた形のタグでも panic も内部エラーも起こさず、
+/// テキストとして残すか構文エラーにする
+#[test]
+fn test_degenerate_tag_shapes() {
+    let opts = BbCodeOptions::default();
+    for (input, expected) in [
+        ("[code]", Ok("[code]")),
+        ("[code=]x[/code]", Ok("[code=]x[/code]")),
+        ("[url=]x[/url]", Ok("[url=]x[/url]")),
+        ("[*]", Ok("[*]")),
+        ("[[b]]x[/b]", Ok("[[b]]x[/b]")),
+        ("[]", Err(ErrorKind::Parse)),
+        ("[=x]", Err(ErrorKind::Parse)),
+        ("[/]", Err(ErrorKind::Parse)),
+        ("[/*]", Err(ErrorKind::Parse)),
+        ("[code][/code", Err(ErrorKind::Parse)),
+    ] {
+        let actual = parse_bbcode_to_ast(input, &opts)
+            .map(|ast| ast_to_html(&ast))
+            .map_err(|e| e.kind());
+        assert_eq!(actual, expected.map(str::to_string), "input: {input:?}");
+    }
+}
+
 #[test]
 fn test_unknown_tag_fallback_to_text() {
     let opts = BbCodeOptions::default();
@@ -351,10 +413,7 @@ fn test_fallback_text_span_is_whole_block() {
 
 #[test]
 fn test_nest_depth_exceeded_linecol_with_japanese() {
-    let opts = BbCodeOptions {
-        max_depth: 2,
-        ..Default::default()
-    };
+    let opts = BbCodeOptions::default().with_max_depth(2);
 
     // 先頭に日本語1文字（UTF-8で3バイト）
     // 3階層目の [color...] で落ちる
@@ -362,9 +421,9 @@ fn test_nest_depth_exceeded_linecol_with_japanese() {
     let result = parse_bbcode_to_ast(input, &opts);
 
     match result {
-        Err(BbCodeError::NestDepthExceeded {
+        Err(BbCodeError::Limit(LimitError::NestDepthExceeded {
             span, line, column, ..
-        }) => {
+        })) => {
             // 行は1行目
             assert_eq!(line, 1);
 
@@ -377,3 +436,1081 @@ fn test_nest_depth_exceeded_linecol_with_japanese() {
         _ => panic!("Expected NestDepthExceeded error"),
     }
 }
+
+#[test]
+fn test_text_length_exceeded() {
+    let opts = BbCodeOptions::default().with_max_text_len(8);
+    // タグ部分はテキスト長に含まれない
+    let ok = parse_bbcode_to_ast("[b]12345678[/b]", &opts);
+    assert!(ok.is_ok());
+
+    let result = parse_bbcode_to_ast("[b]1234[/b][i]56789[/i]", &opts);
+    match result {
+        Err(BbCodeError::Limit(LimitError::TextLengthExceeded {
+            max_len,
+            actual_len,
+        })) => {
+            assert_eq!(max_len, 8);
+            assert_eq!(actual_len, 9);
+        }
+        _ => panic!("Expected TextLengthExceeded error"),
+    }
+}
+
+#[test]
+fn test_quoted_attr_value() {
+    let opts = BbCodeOptions::default();
+    let input = r#"[quote="Alice [admin]"]hi[/quote]"#;
+    let ast = parse_bbcode_to_ast(input, &opts).unwrap();
+
+    assert_eq!(ast.len(), 1);
+    match &ast[0] {
+        Node::Element(el) => {
+            assert_eq!(el.name, "quote");
+            // クォートは除去され、`]` や空白もそのまま残る
+            assert_eq!(
+                el.attrs,
+                vec![("value".to_string(), "Alice [admin]".to_string())]
+            );
+            assert_text(&el.children[0], "hi");
+        }
+        _ => panic!("Expected Element(quote) node"),
+    }
+
+    let html = ast_to_html(&ast);
+    assert_eq!(
+        html,
+        "<blockquote><cite>Alice [admin]</cite>hi</blockquote>"
+    );
+}
+
+#[test]
+fn test_quoted_attr_escaped_quotes() {
+    let opts = BbCodeOptions::default();
+    let ast = parse_bbcode_to_ast(r#"[quote='It\'s \"me\"']x[/quote]"#, &opts).unwrap();
+    match &ast[0] {
+        Node::Element(el) => assert_eq!(el.attrs[0].1, r#"It's \"me\""#),
+        _ => panic!("Expected Element(quote) node"),
+    }
+
+    let ast = parse_bbcode_to_ast(r#"[quote="say \"hi\""]x[/quote]"#, &opts).unwrap();
+    match &ast[0] {
+        Node::Element(el) => assert_eq!(el.attrs[0].1, r#"say "hi""#),
+        _ => panic!("Expected Element(quote) node"),
+    }
+}
+
+/// 無効にしたグループの組み込みタグは登録されない
+#[test]
+fn test_builtin_tag_groups() {
+    let registry = TagRegistry::builtin();
+    assert_eq!(registry.get("b").is_some(), cfg!(feature = "tags-basic"));
+    assert_eq!(
+        registry.get("list").is_some(),
+        cfg!(feature = "tags-layout")
+    );
+    assert_eq!(registry.get("img").is_some(), cfg!(feature = "tags-media"));
+    assert!(registry.get("html").is_none());
+}
+
+#[test]
+fn test_value_splitter_hook() {
+    let mut registry = TagRegistry::builtin();
+    registry.insert(
+        "quote",
+        TagSpec::with_value(None).with_splitter(registry::split_author_post_id),
+    );
+    let opts = BbCodeOptions::default().with_registry(registry);
+
+    let ast = parse_bbcode_to_ast("[quote=Alice;12345]hi[/quote]", &opts).unwrap();
+    match &ast[0] {
+        Node::Element(el) => {
+            assert_eq!(
+                el.attrs,
+                vec![
+                    ("author".to_string(), "Alice".to_string()),
+                    ("post_id".to_string(), "12345".to_string()),
+                ]
+            );
+        }
+        _ => panic!("Expected Element(quote) node"),
+    }
+    assert_eq!(
+        ast_to_html(&ast),
+        "<blockquote><cite>Alice</cite>hi</blockquote>"
+    );
+
+    // 投稿IDが数字でなければ全体を名前として扱う
+    let ast = parse_bbcode_to_ast("[quote=a;b]hi[/quote]", &opts).unwrap();
+    match &ast[0] {
+        Node::Element(el) => {
+            assert_eq!(el.attrs, vec![("author".to_string(), "a;b".to_string())]);
+        }
+        _ => panic!("Expected Element(quote) node"),
+    }
+}
+
+#[test]
+fn test_value_splitter_rejects_value() {
+    fn reject(_: &str) -> Option<Vec<(String, String)>> {
+        None
+    }
+    let mut registry = TagRegistry::builtin();
+    registry.insert("quote", TagSpec::with_value(None).with_splitter(reject));
+    let opts = BbCodeOptions::default().with_registry(registry);
+
+    let input = "[quote=x]hi[/quote]";
+    let ast = parse_bbcode_to_ast(input, &opts).unwrap();
+    assert_eq!(ast.len(), 1);
+    assert_text(&ast[0], input);
+}
+
+#[test]
+fn test_url_and_img() {
+    let opts = BbCodeOptions::default();
+    let html = |input: &str| ast_to_html(&parse_bbcode_to_ast(input, &opts).unwrap());
+
+    assert_eq!(
+        html("[url]https://example.com/a?b=1&c=2[/url]"),
+        "<a href=\"https://example.com/a?b=1&amp;c=2\" rel=\"nofollow\">https://example.com/a?b=1&amp;c=2</a>"
+    );
+    assert_eq!(
+        html("[url=/thread/5]スレッド[/url]"),
+        "<a href=\"/thread/5\" rel=\"nofollow\">スレッド</a>"
+    );
+    assert_eq!(
+        html("[img=100x50]https://example.com/x.png[/img]"),
+        "<img src=\"https://example.com/x.png\" alt=\"\" width=\"100\" height=\"50\">"
+    );
+
+    // 不正なURLはテキストへ fallback
+    assert_eq!(
+        html("[url]javascript:alert(1)[/url]"),
+        "[url]javascript:alert(1)[/url]"
+    );
+    assert_eq!(html("[img][b]x[/b][/img]"), "[img][b]x[/b][/img]");
+}
+
+#[test]
+fn test_code_is_verbatim() {
+    let opts = BbCodeOptions::default();
+    let input = "[code=rust][b]x[/b] [/i]\nfn main() {}[/code]";
+    let ast = parse_bbcode_to_ast(input, &opts).unwrap();
+
+    assert_eq!(ast.len(), 1);
+    match &ast[0] {
+        Node::Element(el) => {
+            assert_eq!(el.name, "code");
+            assert_eq!(el.attrs, vec![("value".to_string(), "rust".to_string())]);
+            assert_text(&el.children[0], "[b]x[/b] [/i]\nfn main() {}");
+        }
+        _ => panic!("Expected Element(code) node"),
+    }
+    assert_eq!(
+        ast_to_html(&ast),
+        "<pre><code class=\"language-rust\">[b]x[/b] [/i]\nfn main() {}</code></pre>"
+    );
+}
+
+#[test]
+fn test_list_items() {
+    let opts = BbCodeOptions::default();
+    let input = "[list]\n[*]one\n[*][b]two[/b]\n[/list]";
+    let ast = parse_bbcode_to_ast(input, &opts).unwrap();
+
+    match &ast[0] {
+        Node::Element(list) => {
+            assert_eq!(list.name, "list");
+            let items: Vec<_> = list
+                .children
+                .iter()
+                .filter_map(|c| match c {
+                    Node::Element(el) => Some(el),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(items.len(), 2);
+            assert_eq!(items[0].name, "*");
+            assert_eq!(&input[items[0].span.start..items[0].span.end], "[*]one\n");
+        }
+        _ => panic!("Expected Element(list) node"),
+    }
+    assert_eq!(
+        ast_to_html(&ast),
+        "<ul><li>one</li><li><b>two</b></li></ul>"
+    );
+
+    let ast = parse_bbcode_to_ast("[list=1][*]a[/*][*]b[/*][/list]", &opts).unwrap();
+    assert_eq!(
+        ast_to_html(&ast),
+        "<ol type=\"1\"><li>a</li><li>b</li></ol>"
+    );
+}
+
+#[test]
+fn test_size_and_font() {
+    let opts = BbCodeOptions::default();
+    let html = |input: &str| ast_to_html(&parse_bbcode_to_ast(input, &opts).unwrap());
+
+    assert_eq!(
+        html("[size=5]x[/size]"),
+        "<span style=\"font-size:x-large\">x</span>"
+    );
+    assert_eq!(
+        html("[size=150]x[/size]"),
+        "<span style=\"font-size:150%\">x</span>"
+    );
+    assert_eq!(
+        html("[font=Times New Roman]x[/font]"),
+        "<span style=\"font-family:Times New Roman\">x</span>"
+    );
+    assert_eq!(html("[size=999]x[/size]"), "[size=999]x[/size]");
+    assert_eq!(
+        html("[font=a;color:red]x[/font]"),
+        "[font=a;color:red]x[/font]"
+    );
+}
+
+#[test]
+fn test_big_and_small() {
+    let opts = BbCodeOptions::default();
+    let html = |input: &str| ast_to_html(&parse_bbcode_to_ast(input, &opts).unwrap());
+
+    assert_eq!(
+        html("[BIG]a[small]b[/small][/big]"),
+        "<span style=\"font-size:larger\">a<span style=\"font-size:smaller\">b</span></span>"
+    );
+    // 値は取らない
+    assert_eq!(html("[big=7]x[/big]"), "[big=7]x[/big]");
+}
+
+#[test]
+fn test_case_sensitive_tags() {
+    let default_opts = BbCodeOptions::default();
+    let strict = BbCodeOptions::default().with_case_sensitive_tags(true);
+
+    // 既定では大文字・小文字を区別しない
+    let ast = parse_bbcode_to_ast("[B]x[/b]", &default_opts).unwrap();
+    assert_eq!(ast_to_html(&ast), "<b>x</b>");
+
+    let ast = parse_bbcode_to_ast("[B]x[/b]", &strict).unwrap();
+    assert_text(&ast[0], "[B]x[/b]");
+    let ast = parse_bbcode_to_ast("[B]x[/B]", &strict).unwrap();
+    assert_eq!(ast_to_html(&ast), "<b>x</b>");
+
+    let ast = parse_bbcode_to_ast("[CODE]x[/code]", &strict).unwrap();
+    assert_text(&ast[0], "[CODE]x[/code]");
+}
+
+#[test]
+fn test_universal_close() {
+    let default_opts = BbCodeOptions::default();
+    let opts = BbCodeOptions::default().with_universal_close(true);
+
+    // 無効時は閉じタグとして扱わない
+    let ast = parse_bbcode_to_ast("[b]x[/]", &default_opts).unwrap();
+    assert_text(&ast[0], "[b]x[/]");
+
+    let (ast, diagnostics) =
+        parse_bbcode_with_diagnostics("[b]bold [i]both[/] bold[/]", &opts).unwrap();
+    assert_eq!(ast_to_html(&ast), "<b>bold <i>both</i> bold</b>");
+    assert!(diagnostics.is_empty());
+
+    // 未知のタグを閉じてしまった場合は診断を出す
+    let input = "[b][foo]x[/][/b]";
+    let (ast, diagnostics) = parse_bbcode_with_diagnostics(input, &opts).unwrap();
+    assert_eq!(ast_to_html(&ast), "<b>[foo]x[/]</b>");
+    assert_eq!(
+        diagnostics,
+        vec![Diagnostic::UniversalCloseMismatch {
+            closed: "foo".to_string(),
+            span: Span { start: 3, end: 12 },
+        }]
+    );
+}
+
+#[test]
+fn test_control_chars() {
+    let input = "a\u{0}b\u{2028}[b]c\u{1b}[/b]\r\n\td\re";
+    let text = |policy| {
+        let opts = BbCodeOptions::default().with_control_chars(policy);
+        ast_to_plain_text(&parse_bbcode_to_ast(input, &opts).unwrap())
+    };
+    assert_eq!(text(ControlChars::Keep), "a\u{0}b\u{2028}c\u{1b}\r\n\td\re");
+    // 改行の \r\n とタブは残す
+    assert_eq!(text(ControlChars::Strip), "abc\r\n\tde");
+    assert_eq!(
+        text(ControlChars::Escape),
+        "a\\u0000b\\u2028c\\u001B\r\n\td\\u000De"
+    );
+
+    // タグの中の制御文字も取り除いてからパースする
+    let opts = BbCodeOptions::default().with_control_chars(ControlChars::Strip);
+    let ast = parse_bbcode_to_ast("[b\u{0}]x[/b]", &opts).unwrap();
+    assert_eq!(ast_to_html(&ast), "<b>x</b>");
+}
+
+#[test]
+fn test_syntax_chars() {
+    let html = |input: &str, chars| {
+        let opts = BbCodeOptions::default().with_syntax_chars(chars);
+        ast_to_html(&parse_bbcode_to_ast(input, &opts).unwrap())
+    };
+    assert_eq!(
+        html(
+            "{b}x [y] \\{z}{/b} {quote=\"a}[b]\"}c{/quote}",
+            SyntaxChars::braces()
+        ),
+        "<b>x [y] {z}</b> <blockquote><cite>a}[b]</cite>c</blockquote>"
+    );
+    assert_eq!(
+        html(
+            "<url=https://a.example/[1]>link</url> <zz>",
+            SyntaxChars::angle_brackets()
+        ),
+        "<a href=\"https://a.example/[1]\" rel=\"nofollow\">link</a> &lt;zz&gt;"
+    );
+    // エスケープの文字も変えられる
+    let chars = SyntaxChars::new('{', '}', '^').unwrap();
+    assert_eq!(html("{i}^{i} \\n [b]{/i}", chars), "<i>{i} \\n [b]</i>");
+    // span は入力上の位置のまま
+    let opts = BbCodeOptions::default().with_syntax_chars(SyntaxChars::braces());
+    let ast = parse_bbcode_to_ast("ab{b}c{/b}", &opts).unwrap();
+    let Node::Element(b) = &ast[1] else {
+        panic!("expected b element: {ast:?}");
+    };
+    assert_eq!(b.span, Span { start: 2, end: 10 });
+    // 親の制約でテキストに戻したタグも元の文字のまま
+    assert_eq!(
+        ast_to_plain_text(&parse_bbcode_to_ast("{*}[x]{/*}", &opts).unwrap()),
+        "{*}[x]{/*}"
+    );
+
+    assert!(SyntaxChars::new('{', '{', '\\').is_none());
+    assert!(SyntaxChars::new('a', '}', '\\').is_none());
+    assert!(SyntaxChars::new('{', '}', '/').is_none());
+}
+
+#[test]
+fn test_empty_elements() {
+    let input = "[b][/b]a[code][/code][url=https://a.example][/url][anchor=top][/anchor][i]x[/i]";
+    let html = |policy| {
+        let opts = BbCodeOptions::default().with_empty_elements(policy);
+        ast_to_html(&parse_bbcode_to_ast(input, &opts).unwrap())
+    };
+    assert_eq!(
+        html(EmptyElements::Keep),
+        "<b></b>a<pre><code></code></pre><a href=\"https://a.example\" rel=\"nofollow\"></a>\
+         <a id=\"top\"></a><i>x</i>"
+    );
+    // 空で使う `[anchor]` は残す
+    assert_eq!(html(EmptyElements::Drop), "a<a id=\"top\"></a><i>x</i>");
+    assert_eq!(
+        html(EmptyElements::Text),
+        "[b][/b]a[code][/code][url=https://a.example][/url]<a id=\"top\"></a><i>x</i>"
+    );
+}
+
+#[test]
+fn test_duplicate_attrs() {
+    let input = "[img alt=first alt=second]https://a.example/x.png[/img]";
+    let alt = |policy| {
+        let opts = BbCodeOptions::default().with_duplicate_attrs(policy);
+        let ast = parse_bbcode_to_ast(input, &opts)?;
+        let Node::Element(img) = &ast[0] else {
+            panic!("expected img element: {ast:?}");
+        };
+        Ok::<_, BbCodeError>(img.attrs.clone())
+    };
+    let attr = |v: &str| vec![("alt".to_string(), v.to_string())];
+    assert_eq!(alt(DuplicateAttrs::FirstWins).unwrap(), attr("first"));
+    assert_eq!(alt(DuplicateAttrs::LastWins).unwrap(), attr("second"));
+    let err = alt(DuplicateAttrs::Error).unwrap_err();
+    assert!(matches!(
+        &err,
+        BbCodeError::Parse(ParseError::DuplicateAttribute { tag, attr, line: 1, column: 1, .. })
+            if tag == "img" && attr == "alt"
+    ));
+    assert_eq!(
+        err.to_string(),
+        "Duplicate attribute \"alt\" in [img] at line 1, col 1"
+    );
+}
+
+#[test]
+fn test_attr_whitespace() {
+    let input = "[img alt=\"  a \n  b \"]https://a.example/x.png[/img][quote=\" Alice \"]x[/quote]";
+    let attrs = |policy| {
+        let opts = BbCodeOptions::default().with_attr_whitespace(policy);
+        let ast = parse_bbcode_to_ast(input, &opts).unwrap();
+        ast.iter()
+            .map(|n| match n {
+                Node::Element(el) => el.attrs[0].1.clone(),
+                _ => panic!("expected element: {n:?}"),
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(attrs(AttrWhitespace::Keep), ["  a \n  b ", " Alice "]);
+    assert_eq!(attrs(AttrWhitespace::Trim), ["a \n  b", "Alice"]);
+    assert_eq!(attrs(AttrWhitespace::Collapse), ["a b", "Alice"]);
+    // 空白だけの値は空になり、値の検証もその値で行う
+    let ast = parse_bbcode_to_ast("[color=\"  \"]x[/color]", &BbCodeOptions::default()).unwrap();
+    assert_text(&ast[0], "[color=\"  \"]x[/color]");
+}
+
+#[test]
+fn test_same_tag_nesting() {
+    let html =
+        |input: &str, opts: &BbCodeOptions| ast_to_html(&parse_bbcode_to_ast(input, opts).unwrap());
+    let opts = BbCodeOptions::default();
+    // 装飾は内側を外す（間に別の要素があっても同じ）
+    assert_eq!(html("[b]a[b]b[/b]c[/b]", &opts), "<b>abc</b>");
+    assert_eq!(html("[b]a[i][b]b[/b][/i][/b]", &opts), "<b>a<i>b</i></b>");
+    // 属性が違えば見た目が変わるので残す
+    assert_eq!(
+        html("[color=red]a[color=blue]b[/color][/color]", &opts),
+        "<span style=\"color:red\">a<span style=\"color:blue\">b</span></span>"
+    );
+    // 引用は入れ子のまま
+    let ast = parse_bbcode_to_ast("[quote][quote]x[/quote][/quote]", &opts).unwrap();
+    let Node::Element(outer) = &ast[0] else {
+        panic!("expected quote: {ast:?}");
+    };
+    assert!(matches!(&outer.children[0], Node::Element(inner) if inner.name == "quote"));
+
+    // タグごとに変えられる
+    let mut registry = TagRegistry::builtin();
+    registry.insert(
+        "b",
+        TagSpec::simple().with_same_tag_nesting(SameTagNesting::Reject),
+    );
+    registry.insert(
+        "i",
+        TagSpec::simple().with_same_tag_nesting(SameTagNesting::Allow),
+    );
+    let opts = BbCodeOptions::default().with_registry(registry);
+    assert_eq!(html("[b]a[b]b[/b][/b]", &opts), "<b>a[b]b[/b]</b>");
+    assert_eq!(html("[i]a[i]b[/i][/i]", &opts), "<i>a<i>b</i></i>");
+    let strict = opts.with_strict(true);
+    assert!(matches!(
+        parse_bbcode_to_ast("[b]a[b]b[/b][/b]", &strict),
+        Err(BbCodeError::Parse(ParseError::InvalidTag {
+            reason: InvalidTagReason::NotAllowedHere,
+            ..
+        }))
+    ));
+}
+
+#[test]
+fn test_preprocess() {
+    // 古い投稿に残った `<br>` と `:smile:` を直す
+    fn legacy(input: &str) -> Cow<'_, str> {
+        if input.contains("<br>") || input.contains(":smile:") {
+            Cow::Owned(input.replace("<br>", "\n").replace(":smile:", "[b]:)[/b]"))
+        } else {
+            Cow::Borrowed(input)
+        }
+    }
+    let opts = BbCodeOptions::default().with_preprocess(legacy);
+    let ast = parse_bbcode_to_ast("a<br>b :smile:", &opts).unwrap();
+    assert_eq!(ast_to_html(&ast), "a<br>b <b>:)</b>");
+    // span は書き換えた後の入力での位置
+    let Node::Element(b) = &ast[1] else {
+        panic!("expected b: {ast:?}");
+    };
+    assert_eq!((b.span.start, b.span.end), (4, 13));
+
+    // 書き換えた結果も入力サイズの制限を受ける
+    let opts = opts.with_max_input_size(8);
+    assert!(parse_bbcode_to_ast(":smile:", &opts).is_err());
+}
+
+#[test]
+fn test_strict_mode() {
+    let opts = BbCodeOptions::default().with_strict(true);
+    let reason = |input: &str| match parse_bbcode_to_ast(input, &opts) {
+        Err(BbCodeError::Parse(ParseError::InvalidTag { reason, .. })) => Some(reason),
+        Err(e) => panic!("unexpected error for {input:?}: {e}"),
+        Ok(_) => None,
+    };
+    assert_eq!(reason("[b]x[/b] [url=https://a.example]y[/url]"), None);
+    assert_eq!(reason("[b]x[/i]"), Some(InvalidTagReason::MismatchedClose));
+    assert_eq!(reason("a [b]x"), Some(InvalidTagReason::Unclosed));
+    assert_eq!(reason("[zzz]x[/zzz]"), Some(InvalidTagReason::UnknownTag));
+    assert_eq!(
+        reason("[color=zz#]x[/color]"),
+        Some(InvalidTagReason::InvalidValue)
+    );
+    assert_eq!(
+        reason("[b x=1]x[/b]"),
+        Some(InvalidTagReason::InvalidAttribute)
+    );
+    assert_eq!(
+        reason("[img]javascript:x[/img]"),
+        Some(InvalidTagReason::InvalidContent)
+    );
+    assert_eq!(reason("[*]x[/*]"), Some(InvalidTagReason::NotAllowedHere));
+
+    // span と行・列で場所を示す
+    let err = parse_bbcode_to_ast("ok\n  [zzz]x[/zzz]", &opts).unwrap_err();
+    assert!(matches!(
+        &err,
+        BbCodeError::Parse(ParseError::InvalidTag { span, line: 2, column: 3, .. })
+            if *span == Span { start: 5, end: 17 }
+    ));
+    assert_eq!(
+        err.to_string(),
+        "Invalid tag (unknown tag) at line 2, col 3. Near: \"[zzz]x[/zzz]\""
+    );
+    // strict でなければテキストに戻す
+    let ast = parse_bbcode_to_ast("[zzz]x[/zzz]", &BbCodeOptions::default()).unwrap();
+    assert_eq!(ast_to_plain_text(&ast), "[zzz]x[/zzz]");
+}
+
+#[test]
+fn test_strip_bom_and_nfc() {
+    // 結合文字の「か + ゛」と e + U+0301
+    let input = "\u{feff}[b]\u{304b}\u{3099}[/b]cafe\u{301}";
+    let ast = parse_bbcode_to_ast(input, &BbCodeOptions::default()).unwrap();
+    assert_eq!(
+        ast_to_plain_text(&ast),
+        "\u{feff}\u{304b}\u{3099}cafe\u{301}"
+    );
+
+    let opts = BbCodeOptions::default()
+        .with_strip_bom(true)
+        .with_normalize_nfc(true);
+    let ast = parse_bbcode_to_ast(input, &opts).unwrap();
+    assert_eq!(ast_to_html(&ast), "<b>\u{304c}</b>caf\u{e9}");
+    // span は BOM を除いた入力上の位置
+    let Node::Element(b) = &ast[0] else {
+        panic!("expected b element: {ast:?}");
+    };
+    assert_eq!(b.span, Span { start: 0, end: 13 });
+}
+
+#[test]
+fn test_max_consecutive_newlines() {
+    let opts = BbCodeOptions::default().with_max_consecutive_newlines(2);
+    let input = "a\n\n\n\n\nb\r\n \r\n\t\r\nc[b]\n\n\n[/b][code]\n\n\n\n[/code]";
+    let ast = parse_bbcode_to_ast(input, &opts).unwrap();
+
+    assert_text(&ast[0], "a\n\nb\r\n \r\nc");
+    match (&ast[1], &ast[2]) {
+        (Node::Element(b), Node::Element(code)) => {
+            assert_text(&b.children[0], "\n\n");
+            // [code] の中身はそのまま
+            assert_text(&code.children[0], "\n\n\n\n");
+        }
+        _ => panic!("Expected Element(b) and Element(code)"),
+    }
+
+    // span は詰める前の入力上の範囲のままで、後ろのノードの位置もずれない
+    let input = "a\n\n\n\nb[b]c[/b]";
+    let ast = parse_bbcode_to_ast(input, &opts).unwrap();
+    match &ast[..] {
+        [Node::Text { span, text }, Node::Element(b)] => {
+            assert_eq!(text, "a\n\nb");
+            assert_eq!(*span, Span { start: 0, end: 6 });
+            assert_eq!(&input[span.start..span.end], "a\n\n\n\nb");
+            assert_eq!(b.span, Span { start: 6, end: 14 });
+        }
+        _ => panic!("Expected Text and Element(b): {ast:?}"),
+    }
+
+    // 既定では詰めない
+    let ast = parse_bbcode_to_ast("a\n\n\n\nb", &BbCodeOptions::default()).unwrap();
+    assert_text(&ast[0], "a\n\n\n\nb");
+}
+
+#[test]
+fn test_highlight_tag() {
+    let opts = BbCodeOptions::default();
+    let html = |input: &str| ast_to_html(&parse_bbcode_to_ast(input, &opts).unwrap());
+
+    assert_eq!(html("[highlight]x[/highlight]"), "<mark>x</mark>");
+    assert_eq!(
+        html("[mark=#ff0]x[/mark]"),
+        "<mark style=\"background-color:#ff0\">x</mark>"
+    );
+    assert_eq!(html("[mark=url(x)]x[/mark]"), "[mark=url(x)]x[/mark]");
+}
+
+#[test]
+fn test_anchor_and_goto_tags() {
+    let opts = BbCodeOptions::default();
+    let html = |input: &str| ast_to_html(&parse_bbcode_to_ast(input, &opts).unwrap());
+
+    assert_eq!(
+        html("[anchor=Install Guide]Setup[/anchor]"),
+        "<a id=\"install-guide\">Setup</a>"
+    );
+    assert_eq!(
+        html("[goto=\"install guide<script>\"]jump[/goto]"),
+        "<a href=\"#install-guidescript\">jump</a>"
+    );
+    // 名前が無い・slug にできない場合はテキスト扱い
+    assert_eq!(html("[goto]x[/goto]"), "[goto]x[/goto]");
+    assert_eq!(html("[anchor=!!!]x[/anchor]"), "[anchor=!!!]x[/anchor]");
+}
+
+#[test]
+fn test_hashtag_detection_and_tag_element() {
+    let opts = BbCodeOptions::default().with_detect_hashtags(true);
+    let ast = parse_bbcode_to_ast("see #rust_lang, not #1 or a#b [url]/x#y[/url]", &opts).unwrap();
+    let Node::Element(tag) = &ast[1] else {
+        panic!("expected tag element: {ast:?}");
+    };
+    assert_eq!(tag.name, "tag");
+    assert_eq!(
+        tag.attrs,
+        vec![("value".to_string(), "rust_lang".to_string())]
+    );
+    assert_eq!(tag.span, Span { start: 4, end: 14 });
+    assert_eq!(ast.len(), 4);
+
+    let render_opts =
+        HtmlRenderOptions::default().with_tag_url(|topic| format!("/search?tag={topic}"));
+    let explicit = parse_bbcode_to_ast("[hashtag]Rust[/hashtag] [tag=a&b]x[/tag]", &opts).unwrap();
+    assert_eq!(
+        ast_to_html_with(&explicit, &render_opts),
+        "<a href=\"/search?tag=Rust\" class=\"hashtag\" rel=\"tag\">Rust</a> [tag=a&amp;b]x[/tag]"
+    );
+    assert_eq!(
+        ast_to_html(&ast[..2]),
+        "see <span class=\"hashtag\">#rust_lang</span>"
+    );
+}
+
+#[test]
+fn test_block_in_inline_strictness() {
+    let input = "[b]x[quote]q[/quote][/b]";
+
+    // 既定では受け入れる
+    let (ast, diags) = parse_bbcode_with_diagnostics(input, &BbCodeOptions::default()).unwrap();
+    assert!(matches!(&ast[0], Node::Element(e) if e.name == "b"));
+    assert!(diags.is_empty());
+
+    let warn = BbCodeOptions::default().with_block_in_inline(NestingStrictness::Warn);
+    let (ast, diags) = parse_bbcode_with_diagnostics(input, &warn).unwrap();
+    assert!(matches!(&ast[0], Node::Element(e) if e.name == "b"));
+    assert_eq!(
+        diags,
+        vec![Diagnostic::BlockInInline {
+            parent: "b".to_string(),
+            child: "quote".to_string(),
+            span: Span { start: 4, end: 20 },
+        }]
+    );
+
+    let fallback = BbCodeOptions::default().with_block_in_inline(NestingStrictness::Fallback);
+    let ast = parse_bbcode_to_ast(input, &fallback).unwrap();
+    assert_eq!(ast.len(), 1);
+    assert_text(&ast[0], input);
+    // ブロック要素同士の入れ子は問題ない
+    let ast = parse_bbcode_to_ast("[center][quote]q[/quote][/center]", &fallback).unwrap();
+    assert!(matches!(&ast[0], Node::Element(e) if e.name == "center"));
+}
+
+#[test]
+fn test_parent_and_children_constraints() {
+    let opts = BbCodeOptions::default();
+    // `[*]` は `[list]` の直下でしか項目にならない
+    let ast = parse_bbcode_to_ast("[*]a[/*] [b][*]x[/*][/b]", &opts).unwrap();
+    assert_eq!(ast_to_html(&ast), "[*]a[/*] <b>[*]x[/*]</b>");
+
+    let mut registry = TagRegistry::builtin();
+    registry.insert("table", TagSpec::simple().with_allowed_children(&["tr"]));
+    registry.insert(
+        "tr",
+        TagSpec::simple()
+            .with_required_parent("table")
+            .with_allowed_children(&["td"]),
+    );
+    registry.insert("td", TagSpec::simple().with_required_parent("tr"));
+    let opts = BbCodeOptions::default()
+        .with_registry(registry)
+        .with_max_depth(4);
+    let ast = parse_bbcode_to_ast(
+        "[table][tr][td]a[/td][b]x[/b][/tr][/table][td]b[/td]",
+        &opts,
+    )
+    .unwrap();
+    let Node::Element(table) = &ast[0] else {
+        panic!("expected table: {ast:?}");
+    };
+    let Node::Element(tr) = &table.children[0] else {
+        panic!("expected tr: {table:?}");
+    };
+    assert!(matches!(&tr.children[0], Node::Element(td) if td.name == "td"));
+    assert_text(&tr.children[1], "[b]x[/b]");
+    assert_text(&ast[1], "[td]b[/td]");
+}
+
+#[test]
+fn test_deprecated_tag_diagnostic() {
+    let mut registry = TagRegistry::builtin();
+    let font = registry.get("font").unwrap().clone();
+    registry.insert("font", font.with_deprecation(Some("size")));
+    let opts = BbCodeOptions::default().with_registry(registry);
+    let (ast, diags) = parse_bbcode_with_diagnostics("a [font=Arial]b[/font]", &opts).unwrap();
+    // パース結果は通常どおり
+    assert!(matches!(&ast[1], Node::Element(e) if e.name == "font"));
+    assert_eq!(
+        diags,
+        vec![Diagnostic::Deprecated {
+            tag: "font".to_string(),
+            replacement: Some("size".to_string()),
+            span: Span { start: 2, end: 22 },
+        }]
+    );
+}
+
+#[test]
+fn test_tag_permission_levels() {
+    let mut registry = TagRegistry::builtin();
+    registry.insert(
+        "video",
+        TagSpec::simple().with_required_level(PermissionLevel::Trusted),
+    );
+    let code = registry.get("code").unwrap().clone();
+    registry.insert("code", code.with_required_level(PermissionLevel::Moderator));
+    let input = "[video]v.mp4[/video] [code]x[/code]";
+
+    let member = BbCodeOptions::default().with_registry(registry);
+    let (ast, diags) = parse_bbcode_with_diagnostics(input, &member).unwrap();
+    assert_eq!(ast_to_plain_text(&ast), input);
+    assert_eq!(
+        diags,
+        vec![
+            Diagnostic::PermissionDenied {
+                tag: "video".to_string(),
+                required: PermissionLevel::Trusted,
+                span: Span { start: 0, end: 20 },
+            },
+            Diagnostic::PermissionDenied {
+                tag: "code".to_string(),
+                required: PermissionLevel::Moderator,
+                span: Span { start: 21, end: 35 },
+            },
+        ]
+    );
+
+    // 上の権限は下の権限のタグも使える
+    let trusted = member.clone().with_author_level(PermissionLevel::Trusted);
+    let (ast, diags) = parse_bbcode_with_diagnostics(input, &trusted).unwrap();
+    assert!(matches!(&ast[0], Node::Element(e) if e.name == "video"));
+    assert_eq!(diags.len(), 1);
+
+    let moderator = member.with_author_level(PermissionLevel::Moderator);
+    let (ast, diags) = parse_bbcode_with_diagnostics(input, &moderator).unwrap();
+    assert!(matches!(&ast[2], Node::Element(e) if e.name == "code"));
+    assert!(diags.is_empty());
+}
+
+#[test]
+fn test_color_contrast_check() {
+    let input = "[color=yellow]a[/color] [color=navy]b[/color] [color=teal]c[/color] [color=Unknown]d[/color]";
+    let opts = BbCodeOptions::default().with_color_contrast(ContrastCheck::default());
+    let (ast, diags) = parse_bbcode_with_diagnostics(input, &opts).unwrap();
+    // teal は白に対して 4.77 で足りる。色として読めない値は `[color]` ごとテキストに戻す
+    assert_eq!(
+        diags,
+        vec![Diagnostic::LowContrast {
+            color: "yellow".to_string(),
+            background: "#ffffff".to_string(),
+            span: Span { start: 0, end: 23 },
+        }]
+    );
+    assert!(matches!(&ast[0], Node::Element(e) if e.name == "color"));
+
+    // 暗い背景では逆になる
+    let dark = BbCodeOptions::default().with_color_contrast(
+        ContrastCheck::default()
+            .with_background("#121212")
+            .with_action(ContrastAction::Strip),
+    );
+    let (ast, diags) = parse_bbcode_with_diagnostics(input, &dark).unwrap();
+    assert_eq!(
+        ast_to_html(&ast),
+        "<span style=\"color:yellow\">a</span> b c [color=Unknown]d[/color]"
+    );
+    assert_eq!(diags.len(), 2);
+}
+
+#[test]
+fn test_max_links() {
+    let input = "[url]https://a.example[/url] [url=/local]b[/url] [url=https://c.example]c[/url]";
+    let opts = BbCodeOptions::default().with_max_links(2);
+    let (ast, diags) = parse_bbcode_with_diagnostics(input, &opts).unwrap();
+    // 既定では診断だけ出してリンクは残す
+    assert_eq!(ast.len(), 5);
+    assert_eq!(
+        diags,
+        vec![Diagnostic::TooManyLinks {
+            max_links: 2,
+            span: Span { start: 49, end: 79 },
+        }]
+    );
+
+    let opts = opts.with_link_policy_action(LinkPolicyAction::Strip);
+    let ast = parse_bbcode_to_ast(input, &opts).unwrap();
+    assert_eq!(
+        ast_to_html(&ast),
+        "<a href=\"https://a.example\" rel=\"nofollow\">https://a.example</a> \
+         <a href=\"/local\" rel=\"nofollow\">b</a> c"
+    );
+}
+
+#[test]
+fn test_link_domain_policy() {
+    let input = "[url=https://www.Example.com/x]ok[/url] [url=https://spam.example:8080]no[/url] \
+                 [url=mailto:a@evil.example]mail[/url]";
+    let opts = BbCodeOptions::default()
+        .with_link_domains(DomainPolicy::AllowList(vec!["example.com".to_string()]))
+        .with_link_policy_action(LinkPolicyAction::Strip);
+    let (ast, diags) = parse_bbcode_with_diagnostics(input, &opts).unwrap();
+    assert_eq!(
+        ast_to_plain_text(&ast),
+        "ok (https://www.Example.com/x) no mail"
+    );
+    assert!(matches!(
+        &diags[..],
+        [
+            Diagnostic::LinkDomainNotAllowed { domain: a, .. },
+            Diagnostic::LinkDomainNotAllowed { domain: b, .. },
+        ] if a == "spam.example" && b == "evil.example"
+    ));
+
+    // 拒否リストはサブドメインにも一致し、ドメインが似ているだけのものには一致しない
+    let deny = DomainPolicy::DenyList(vec!["spam.example".to_string()]);
+    assert!(!deny.allows("spam.example"));
+    assert!(!deny.allows("www.spam.example"));
+    assert!(deny.allows("notspam.example"));
+}
+
+#[test]
+fn test_deceptive_link_text() {
+    let opts = BbCodeOptions::default();
+    let input = "[url=https://evil.example/login]https://paypal.com[/url] \
+                 [url=https://p\u{430}ypal.com]www.paypal.com[/url] \
+                 [url=https://shop.example.com]https://example.com/[/url] \
+                 [url=https://evil.example]PayPal[/url] [url]https://a.example[/url]";
+    let (_, diags) = parse_bbcode_with_diagnostics(input, &opts).unwrap();
+    assert!(matches!(
+        &diags[..],
+        [
+            Diagnostic::DeceptiveLink { shown: a, actual: b, lookalike: false, .. },
+            Diagnostic::DeceptiveLink { shown: c, lookalike: true, .. },
+        ] if a == "paypal.com" && b == "evil.example" && c == "www.paypal.com"
+    ));
+
+    // サイト内パスはリンク先そのものを返す
+    let link = |href, label| lookalike::deceptive_link(href, label);
+    assert_eq!(
+        link("/logout", "https://bank.example").map(|l| l.actual),
+        Some("/logout".to_string())
+    );
+    assert!(link("https://WWW.Bank.example.", "http://bank.example/x").is_none());
+    assert!(link("https://bank.example.evil.example", "https://bank.example").is_some());
+    assert_eq!(lookalike::skeleton("rnicr0soft.com"), "microsoft.com");
+}
+
+#[test]
+fn test_unknown_tag_suggestion() {
+    let opts = BbCodeOptions::default();
+    let (ast, diags) =
+        parse_bbcode_with_diagnostics("[colr=red]x[/colr] [zzzz]y[/zzzz]", &opts).unwrap();
+    assert_text(&ast[0], "[colr=red]x[/colr] [zzzz]y[/zzzz]");
+    assert_eq!(
+        diags,
+        vec![
+            Diagnostic::UnknownTag {
+                tag: "colr".to_string(),
+                suggestion: Some("color".to_string()),
+                span: Span { start: 0, end: 18 },
+            },
+            Diagnostic::UnknownTag {
+                tag: "zzzz".to_string(),
+                suggestion: None,
+                span: Span { start: 19, end: 33 },
+            },
+        ]
+    );
+    // 別名も候補になる
+    assert_eq!(opts.registry.suggest("MONOO"), Some("mono"));
+}
+
+#[test]
+fn test_tag_names_are_interned() {
+    let opts = BbCodeOptions::default();
+    let ast = parse_bbcode_to_ast("[B]x[/b][mark]y[/mark]", &opts).unwrap();
+    for n in &ast {
+        let Node::Element(el) = n else {
+            panic!("expected element: {n:?}");
+        };
+        // 組み込みタグ名は文字列を確保しない
+        assert!(matches!(el.name, TagName::Builtin(_)), "{:?}", el.name);
+    }
+    assert_eq!(TagName::new("spoiler"), "spoiler");
+
+    // それ以外の名前は共有表から取り、同じ名前なら同じ文字列を指す
+    let mut registry = TagRegistry::builtin();
+    registry.insert("spoiler", TagSpec::simple());
+    let opts = BbCodeOptions::default().with_registry(registry);
+    let ast = parse_bbcode_to_ast("[spoiler]a[/spoiler][SPOILER]b[/SPOILER]", &opts).unwrap();
+    let names: Vec<_> = ast
+        .iter()
+        .map(|n| match n {
+            Node::Element(el) => el.name.clone(),
+            _ => panic!("expected element: {n:?}"),
+        })
+        .collect();
+    match (&names[0], &names[1], TagName::new("spoiler")) {
+        (TagName::Other(a), TagName::Other(b), TagName::Other(c)) => {
+            assert!(std::sync::Arc::ptr_eq(a, b) && std::sync::Arc::ptr_eq(a, &c));
+        }
+        other => panic!("expected shared names: {other:?}"),
+    }
+}
+
+#[test]
+fn test_raw_parse_exposes_grammar_pairs() {
+    let root = raw_parse("[b=x]hi[/b][code]a[/code]")
+        .unwrap()
+        .next()
+        .unwrap();
+    assert_eq!(root.as_rule(), Rule::BBCode);
+
+    let tokens: Vec<_> = root
+        .into_inner()
+        .map(|p| (p.as_rule(), p.as_str()))
+        .collect();
+    assert_eq!(
+        tokens,
+        vec![
+            (Rule::open_tag, "[b=x]"),
+            (Rule::text, "hi"),
+            (Rule::close_tag, "[/b]"),
+            (Rule::code_block, "[code]a[/code]"),
+            (Rule::EOI, ""),
+        ]
+    );
+
+    assert!(matches!(raw_parse("["), Err(BbCodeError::Parse(_))));
+}
+
+/// テキストの途中の `\[` もエスケープとして扱う
+#[test]
+fn test_escaped_bracket_inside_text() {
+    let ast = parse_bbcode_to_ast("a \\[b] c\\[ d", &BbCodeOptions::default()).unwrap();
+    assert_eq!(ast.len(), 1);
+    assert_text(&ast[0], "a [b] c[ d");
+}
+
+/// テキストに戻したタグの中の `\[` も、通常のテキストと同じく `[` にする
+#[test]
+fn test_escaped_bracket_inside_fallback() {
+    let text = |input: &str, opts: &BbCodeOptions| {
+        let ast = parse_bbcode_to_ast(input, opts).unwrap();
+        assert_eq!(ast.len(), 1);
+        match &ast[0] {
+            Node::Text { text, .. } => text.clone(),
+            _ => panic!("Expected Text node"),
+        }
+    };
+    let opts = BbCodeOptions::default();
+    // 未知のタグ・不正な値
+    assert_eq!(text("[zzz]a \\[b] c[/zzz]", &opts), "[zzz]a [b] c[/zzz]");
+    assert_eq!(
+        text("[color=zz#][b]\\[i][/b][/color]", &opts),
+        "[color=zz#][b][i][/b][/color]"
+    );
+    // 親の制約でテキストに戻した要素（子の要素の中のエスケープも戻す）
+    assert_eq!(
+        text("[*]\\[x] [b]\\[y][/b][/*]", &opts),
+        "[*][x] [b][y][/b][/*]"
+    );
+    // 区切り文字を変えたときは変えた文字のエスケープ
+    let braces = BbCodeOptions::default().with_syntax_chars(SyntaxChars::braces());
+    assert_eq!(text("{zzz}\\{b} [x]{/zzz}", &braces), "{zzz}{b} [x]{/zzz}");
+}
+
+#[test]
+fn test_merge_adjacent_text_can_be_disabled() {
+    let input = "\\[b] c [foo]z[/foo] tail";
+
+    // 既定では1つの Text ノードにまとめる
+    let merged = parse_bbcode_to_ast(input, &BbCodeOptions::default()).unwrap();
+    assert_eq!(merged.len(), 1);
+    assert_text(&merged[0], "[b] c [foo]z[/foo] tail");
+
+    // まとめなければ字句ごとのノードと元の span が残る
+    let opts = BbCodeOptions::default().with_merge_adjacent_text(false);
+    let ast = parse_bbcode_to_ast(input, &opts).unwrap();
+    let texts: Vec<_> = ast
+        .iter()
+        .map(|n| match n {
+            Node::Text { span, text } => (span.start, span.end, text.as_str()),
+            _ => panic!("Expected Text node"),
+        })
+        .collect();
+    assert_eq!(
+        texts,
+        vec![
+            (0, 2, "["),
+            (2, 7, "b] c "),
+            (7, 19, "[foo]z[/foo]"),
+            (19, 24, " tail"),
+        ]
+    );
+}
+
+#[test]
+fn test_validate_fragment_inside_list() {
+    let opts = BbCodeOptions::default();
+
+    // `[list]` の中なら閉じタグの無い `[*]` が項目になる
+    let (nodes, diagnostics) =
+        validate_fragment("[*]one[*]two", &opts, FragmentContext::inside("list")).unwrap();
+    assert!(diagnostics.is_empty());
+    let names: Vec<_> = nodes
+        .iter()
+        .map(|n| match n {
+            Node::Element(el) => el.name.to_string(),
+            _ => panic!("Expected Element"),
+        })
+        .collect();
+    assert_eq!(names, ["*", "*"]);
+
+    // 最上位では `[*]..[/*]` は `[list]` の外なのでテキスト
+    let (nodes, _) = validate_fragment("[*]one[/*]", &opts, FragmentContext::default()).unwrap();
+    assert_text(&nodes[0], "[*]one[/*]");
+}
+
+#[test]
+fn test_validate_fragment_applies_context_depth_and_content_model() {
+    let opts = BbCodeOptions::default()
+        .with_max_depth(2)
+        .with_block_in_inline(NestingStrictness::Warn);
+
+    let deep = FragmentContext {
+        parent: Some("b".to_string()),
+        depth: 2,
+    };
+    assert!(matches!(
+        validate_fragment("[i]x[/i]", &opts, deep),
+        Err(BbCodeError::Limit(LimitError::NestDepthExceeded { .. }))
+    ));
+
+    let (_, diagnostics) =
+        validate_fragment("[quote]x[/quote]", &opts, FragmentContext::inside("B")).unwrap();
+    assert_eq!(
+        diagnostics,
+        vec![Diagnostic::BlockInInline {
+            parent: "b".to_string(),
+            child: "quote".to_string(),
+            span: Span { start: 0, end: 16 },
+        }]
+    );
+}
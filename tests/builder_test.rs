@@ -0,0 +1,83 @@
+use bbcode_parser::ast::{Element, Node, Span};
+use bbcode_parser::{ast_to_html, bbcode, bbcode_to_html, BbCodeOptions};
+
+/// 組み立てた AST がパース結果と同じ HTML になることを確認する
+fn assert_same_html(nodes: &[Node], input: &str) {
+    let expected = bbcode_to_html(input, &BbCodeOptions::default()).unwrap();
+    assert_eq!(ast_to_html(nodes), expected);
+}
+
+#[test]
+fn test_element_constructors() {
+    let nodes = vec![
+        Node::text("Hi "),
+        Element::bold(vec!["there".into()]).into(),
+        Element::url(
+            "https://example.com",
+            vec![Element::italic(vec!["link".into()]).into()],
+        )
+        .into(),
+        Element::color("red", vec!["x".into()]).into(),
+        Element::list(vec![
+            Element::item(vec!["one".into()]).into(),
+            Element::item(vec!["two".into()]).into(),
+        ])
+        .into(),
+        Element::code("[b]raw[/b]").into(),
+        Element::img("https://example.com/a.png").into(),
+    ];
+    assert_same_html(
+        &nodes,
+        "Hi [b]there[/b][url=https://example.com][i]link[/i][/url][color=red]x[/color]\
+         [list][*]one[*]two[/list][code][b]raw[/b][/code][img]https://example.com/a.png[/img]",
+    );
+}
+
+#[test]
+fn test_builder_chaining() {
+    let el = Element::tag("quote")
+        .with_value("Alice")
+        .child("hello ")
+        .child(Element::tag("b").child("world"));
+
+    assert_eq!(el.span, Span::default());
+    assert_eq!(el.name, "quote");
+    assert_eq!(el.attrs, [("value".to_string(), "Alice".to_string())]);
+    assert_same_html(&[el.into()], "[quote=Alice]hello [b]world[/b][/quote]");
+}
+
+#[test]
+fn test_bbcode_macro() {
+    let who = String::from("Alice");
+    let nodes = bbcode! {
+        "Hi " b { (who.as_str()) } "!"
+        quote("Bob") { "quoted " i { "text" } }
+        color("#f00") { "red" }
+        list { "*" { "one" } "*" { "two" } }
+        (Element::code("x"))
+    };
+
+    assert_eq!(nodes.len(), 7);
+    assert_same_html(
+        &nodes,
+        "Hi [b]Alice[/b]![quote=Bob]quoted [i]text[/i][/quote]\
+         [color=#f00]red[/color][list][*]one[*]two[/list][code]x[/code]",
+    );
+    assert!(bbcode! {}.is_empty());
+}
+
+#[test]
+fn test_bbcode_macro_named_attrs() {
+    let nodes = bbcode! { quote[author = "Bob", date = 1.to_string()] { "x" } };
+    let Node::Element(el) = &nodes[0] else {
+        panic!("Expected Element");
+    };
+    assert_eq!(
+        el.attrs,
+        [
+            ("author".to_string(), "Bob".to_string()),
+            ("date".to_string(), "1".to_string()),
+        ]
+    );
+    assert_eq!(el.children, vec![Node::text("x")]);
+}
@@ -0,0 +1,84 @@
+use bbcode_parser::{ast_to_html, parse_bbcode_to_ast, BbCodeOptions, Color, ColorFormats, Node};
+
+#[test]
+fn test_color_parse() {
+    assert_eq!(
+        Color::parse("RebeccaPurple"),
+        Some(Color::new(0x66, 0x33, 0x99))
+    );
+    assert_eq!(Color::parse(" #f00 "), Some(Color::new(255, 0, 0)));
+    assert_eq!(Color::parse("#336699"), Some(Color::new(0x33, 0x66, 0x99)));
+    assert_eq!(
+        Color::parse("#33669980"),
+        Some(Color::new(0x33, 0x66, 0x99).with_alpha(0x80))
+    );
+    assert_eq!(Color::parse("rgb(255, 0, 0)"), Some(Color::new(255, 0, 0)));
+    assert_eq!(
+        Color::parse("rgba(0, 128, 0, 0.5)"),
+        Some(Color::new(0, 128, 0).with_alpha(128))
+    );
+    assert_eq!(
+        Color::parse("rgb(100% 0% 0% / 50%)"),
+        Some(Color::new(255, 0, 0).with_alpha(128))
+    );
+    assert_eq!(
+        Color::parse("hsl(120, 100%, 25%)"),
+        Some(Color::new(0, 128, 0))
+    );
+    assert_eq!(
+        Color::parse("hsl(240deg 100% 50%)"),
+        Some(Color::new(0, 0, 255))
+    );
+    assert_eq!(Color::parse("transparent").map(|c| c.a), Some(0));
+    assert_eq!(Color::new(0, 128, 0).with_alpha(128).to_hex(), "#00800080");
+
+    for bad in [
+        "zzzz",
+        "#12",
+        "#ggg",
+        "rgb(256, 0, 0)",
+        "rgb(1, 2)",
+        "rgb(-1, 0, 0)",
+        "hsl(0, 50, 50%)",
+        "rgb(0, 0, 0, 2)",
+        "url(x)",
+    ] {
+        assert_eq!(Color::parse(bad), None, "{bad}");
+    }
+
+    // 許可した形式だけを読む
+    let hex_only = ColorFormats::default().with_named(false);
+    assert!(hex_only.accepts("#fff"));
+    assert!(!hex_only.accepts("white"));
+    assert!(!hex_only.accepts("#ffff"));
+    assert!(!ColorFormats::default().accepts("rgb(0, 0, 0)"));
+}
+
+#[test]
+fn test_color_formats_option() {
+    let input = "[color=zzzz]a[/color] [color=rgb(0, 0, 255)]b[/color] [color=navy]c[/color]";
+    let ast = parse_bbcode_to_ast(input, &BbCodeOptions::default()).unwrap();
+    // 既定では色名と #RGB / #RRGGBB だけ
+    assert_eq!(
+        ast_to_html(&ast),
+        "[color=zzzz]a[/color] [color=rgb(0, 0, 255)]b[/color] \
+         <span style=\"color:navy\">c</span>"
+    );
+
+    let opts = BbCodeOptions::default().with_color_formats(ColorFormats::all());
+    let ast = parse_bbcode_to_ast(input, &opts).unwrap();
+    assert_eq!(
+        ast_to_html(&ast),
+        "[color=zzzz]a[/color] <span style=\"color:rgb(0, 0, 255)\">b</span> \
+         <span style=\"color:navy\">c</span>"
+    );
+    // 読んだ色は要素から取り出せる
+    let colors: Vec<_> = ast
+        .iter()
+        .filter_map(|n| match n {
+            Node::Element(el) => el.color_value(),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(colors, [Color::new(0, 0, 255), Color::new(0, 0, 128)]);
+}
@@ -0,0 +1,53 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bbcode_parser::conformance::{run_corpus, run_corpus_with, ConformanceOutput};
+use bbcode_parser::render::HtmlRenderOptions;
+use bbcode_parser::{BbCodeOptions, TagRegistry};
+
+fn corpus_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/conformance")
+}
+
+#[test]
+fn test_reference_corpus() {
+    let report = run_corpus(corpus_dir()).unwrap();
+    assert!(report.is_ok(), "{report}");
+    assert!(report.passed > 0, "corpus must not be empty");
+}
+
+/// 基準から外れたレジストリは、どの入力のどの出力が違うかで報告される
+#[test]
+fn test_corpus_reports_deviations() {
+    let mut registry = TagRegistry::builtin();
+    registry.remove("font");
+    let opts = BbCodeOptions::default().with_registry(registry);
+    let report = run_corpus_with(corpus_dir(), &opts, &HtmlRenderOptions::default()).unwrap();
+
+    let failed: Vec<_> = report
+        .failures
+        .iter()
+        .map(|f| {
+            let name = f.case.file_name().unwrap().to_str().unwrap().to_string();
+            (name, f.output)
+        })
+        .collect();
+    assert_eq!(
+        failed,
+        [("style.bbcode".to_string(), ConformanceOutput::Html)]
+    );
+    assert!(report.failures[0].actual.contains("[font=Arial]f[/font]"));
+}
+
+#[test]
+fn test_corpus_case_without_expectation() {
+    let dir = std::env::temp_dir().join(format!("bbcode_conformance_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.bbcode"), "[b]x[/b]\n").unwrap();
+    let err = run_corpus(&dir).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+    fs::write(dir.join("a.html"), "<b>x</b>\r\n").unwrap();
+    assert!(run_corpus(&dir).unwrap().is_ok());
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,34 @@
+use bbcode_parser::corpus::scan;
+use bbcode_parser::{BbCodeOptions, LimitError};
+
+#[test]
+fn test_scan_corpus() {
+    let posts = [
+        "[b]a[/b] [url=https://a.example]x[/url] [mark]s[/mark]",
+        "[quote=Bob]q[/quote] [b]b[/b]",
+        "[spoiler]x[/spoiler] [color=zz#]y[/color] [i]unclosed",
+        "[b][i][u][s]deep[/s][/u][/i][/b]",
+    ];
+    let report = scan(posts, &BbCodeOptions::default());
+
+    assert_eq!(report.posts, 4);
+    assert_eq!(report.parsed, 3);
+    // 別名は正規名で数える
+    assert_eq!(report.tags.get("b"), Some(&2));
+    assert_eq!(report.tags.get("highlight"), Some(&1));
+    assert_eq!(report.tags.get("color"), None);
+    assert_eq!(
+        report.attrs.get(&("url".to_string(), "value".to_string())),
+        Some(&1)
+    );
+    assert_eq!(report.unknown_tags.get("spoiler"), Some(&1));
+    // 未知のタグ・不正な値・閉じていないタグ
+    assert_eq!(report.fallbacks, 3);
+    assert_eq!(report.posts_with_fallbacks, vec![2]);
+    assert_eq!(report.fallback_rate(), 0.25);
+    assert!(matches!(
+        report.limit_exceeded[..],
+        [(3, LimitError::NestDepthExceeded { .. })]
+    ));
+    assert!(report.failed.is_empty());
+}
@@ -0,0 +1,150 @@
+use bbcode_parser::{
+    ast_to_html, parse_bbcode_to_ast, parse_bbcode_with_diagnostics, BbCodeOptions, Diagnostic,
+    Dialect, Node,
+};
+
+fn first_element_attrs(input: &str, opts: &BbCodeOptions) -> Vec<(String, String)> {
+    let ast = parse_bbcode_to_ast(input, opts).unwrap();
+    match &ast[0] {
+        Node::Element(el) => el.attrs.clone(),
+        _ => panic!("Expected Element node"),
+    }
+}
+
+fn attrs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+#[test]
+fn test_phpbb_quote_named_attrs() {
+    let opts = BbCodeOptions::for_dialect(Dialect::PhpBB);
+    let input = r#"[quote="Alice" post_id=12 user_id=3]hi[/quote]"#;
+    assert_eq!(
+        first_element_attrs(input, &opts),
+        attrs(&[("author", "Alice"), ("post_id", "12"), ("user_id", "3")])
+    );
+
+    // 許可されていない名前付き属性はテキストへ
+    let input = r#"[quote="Alice" onclick=x]hi[/quote]"#;
+    let ast = parse_bbcode_to_ast(input, &opts).unwrap();
+    assert!(matches!(&ast[0], Node::Text { text, .. } if text == input));
+
+    // 標準の方言では名前付き属性を受け付けない
+    let input = "[quote author=Alice]hi[/quote]";
+    let ast = parse_bbcode_to_ast(input, &BbCodeOptions::default()).unwrap();
+    assert!(matches!(&ast[0], Node::Text { text, .. } if text == input));
+}
+
+#[test]
+fn test_vbulletin_quote_and_alias() {
+    let opts = BbCodeOptions::for_dialect(Dialect::VBulletin);
+    assert_eq!(
+        first_element_attrs("[QUOTE=Alice;123]hi[/QUOTE]", &opts),
+        attrs(&[("author", "Alice"), ("post_id", "123")])
+    );
+
+    // 別名は正規名の要素になり、閉じタグも正規名で照合する
+    let ast = parse_bbcode_to_ast("[strike]x[/s]", &opts).unwrap();
+    assert_eq!(ast_to_html(&ast), "<s>x</s>");
+
+    // `[*]..[/*]` 形式は使えない
+    let ast = parse_bbcode_to_ast("[list][*]a[/*][/list]", &opts).unwrap();
+    assert_eq!(ast_to_html(&ast), "<ul>[*]a[/*]</ul>");
+}
+
+#[test]
+fn test_xenforo_quote() {
+    let opts = BbCodeOptions::for_dialect(Dialect::XenForo);
+    assert_eq!(
+        first_element_attrs(r#"[QUOTE="Alice, post: 123, member: 45"]hi[/QUOTE]"#, &opts),
+        attrs(&[("author", "Alice"), ("post_id", "123"), ("member_id", "45")])
+    );
+}
+
+#[test]
+fn test_smf_quote_named_attrs() {
+    let opts = BbCodeOptions::for_dialect(Dialect::Smf);
+    assert_eq!(
+        first_element_attrs(
+            "[quote author=Alice link=topic=1.msg2#msg2 date=1700000000]hi[/quote]",
+            &opts
+        ),
+        attrs(&[
+            ("author", "Alice"),
+            ("link", "topic=1.msg2#msg2"),
+            ("date", "1700000000"),
+        ])
+    );
+    let ast = parse_bbcode_to_ast("[iurl=/a]x[/iurl]", &opts).unwrap();
+    assert_eq!(ast_to_html(&ast), "<a href=\"/a\" rel=\"nofollow\">x</a>");
+}
+
+#[test]
+fn test_xenforo_email() {
+    let opts = BbCodeOptions::for_dialect(Dialect::XenForo);
+    let html = |input: &str| ast_to_html(&parse_bbcode_to_ast(input, &opts).unwrap());
+    // 宛先は中身でも値でもよく、`mailto:` のリンクになる
+    assert_eq!(
+        html("[EMAIL]alice@example.com[/EMAIL]"),
+        "<a href=\"mailto:alice@example.com\" rel=\"nofollow\">alice@example.com</a>"
+    );
+    assert_eq!(
+        html("[email=alice@example.com]Alice[/email]"),
+        "<a href=\"mailto:alice@example.com\" rel=\"nofollow\">Alice</a>"
+    );
+    assert_eq!(
+        first_element_attrs("[email]alice@example.com[/email]", &opts),
+        attrs(&[("value", "mailto:alice@example.com")])
+    );
+    // メールアドレスでないものはテキストへ
+    for input in [
+        "[email]alice[/email]",
+        "[email]https://example.com/@alice[/email]",
+        "[email=a@b@c]x[/email]",
+        "[email]a b@example.com[/email]",
+    ] {
+        assert_eq!(html(input), input);
+    }
+    // リンクの数の制限も `[url]` と同じに数える
+    let limited = BbCodeOptions::for_dialect(Dialect::XenForo).with_max_links(1);
+    let (_, diags) = parse_bbcode_with_diagnostics(
+        "[email]a@x.example[/email] [url]https://y.example[/url]",
+        &limited,
+    )
+    .unwrap();
+    assert!(matches!(&diags[..], [Diagnostic::TooManyLinks { .. }]));
+}
+
+#[test]
+fn test_dialect_closing_tag_rules() {
+    for dialect in [
+        Dialect::Standard,
+        Dialect::PhpBB,
+        Dialect::VBulletin,
+        Dialect::XenForo,
+        Dialect::Smf,
+    ] {
+        let opts = BbCodeOptions::for_dialect(dialect);
+        let html = |input: &str| ast_to_html(&parse_bbcode_to_ast(input, &opts).unwrap());
+        assert_eq!(html("[B]x[/B]"), "<b>x</b>", "{dialect:?}");
+        // 表記の違う閉じタグは phpBB だけ不一致にする
+        let mixed = if dialect == Dialect::PhpBB {
+            "[B]x[/b]"
+        } else {
+            "<b>x</b>"
+        };
+        assert_eq!(html("[B]x[/b]"), mixed, "{dialect:?}");
+        // `[/]` はどの方言でも閉じタグにしない
+        assert_eq!(html("[b]x[/]"), "[b]x[/]", "{dialect:?}");
+    }
+
+    // apply は前の方言の設定を残さない
+    let mut opts = BbCodeOptions::default().with_universal_close(true);
+    Dialect::PhpBB.apply(&mut opts);
+    assert!(opts.case_sensitive_tags && !opts.universal_close);
+    Dialect::XenForo.apply(&mut opts);
+    assert!(!opts.case_sensitive_tags);
+}
@@ -0,0 +1,134 @@
+use bbcode_parser::ast::Node;
+use bbcode_parser::{
+    ast_to_html, parse, parse_any, parse_bbcode_to_ast, BbCodeDocument, BbCodeError, BbCodeOptions,
+    Cursor, Diagnostic, Format, LimitError, ParseMetrics,
+};
+
+#[test]
+fn test_document_wraps_nodes_and_metrics() {
+    let opts = BbCodeOptions::default();
+    let input = "[quote]Hi [b]there[/b][/quote] [colr=red]x[/colr]";
+
+    let doc = parse(input, &opts).unwrap();
+
+    assert_eq!(doc.nodes, parse_bbcode_to_ast(input, &opts).unwrap());
+    assert_eq!(doc.source_len, input.len());
+    assert_eq!(
+        doc.metrics,
+        ParseMetrics {
+            element_count: 2,
+            text_node_count: 3,
+            text_len: "Hi there [colr=red]x[/colr]".len(),
+            max_depth: 2,
+        }
+    );
+    assert!(matches!(
+        doc.warnings[..],
+        [Diagnostic::UnknownTag { ref tag, .. }] if tag == "colr"
+    ));
+    assert_eq!(doc.to_html(), ast_to_html(&doc.nodes));
+    assert_eq!(doc.to_plain_text(), "Hi there [colr=red]x[/colr]");
+}
+
+#[test]
+fn test_document_links() {
+    let opts = BbCodeOptions::default();
+    let doc = BbCodeDocument::parse(
+        "[url=https://a.example]a[/url] [b][url]https://b.example[/url][/b] \
+         [url=javascript:alert(1)]x[/url]",
+        &opts,
+    )
+    .unwrap();
+
+    assert_eq!(doc.links(), vec!["https://a.example", "https://b.example"]);
+}
+
+/// ノードを比較しやすい文字列にする（要素はタグ名、テキストは中身）
+fn label(node: &Node) -> String {
+    match node {
+        Node::Text { text, .. } => text.clone(),
+        Node::Element(el) => format!("[{}]", el.name),
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn test_document_iter_is_depth_first() {
+    let doc = parse("a[quote][b]x[/b]y[/quote]z", &BbCodeOptions::default()).unwrap();
+
+    let visited: Vec<_> = doc.iter().map(|(d, n)| (d, label(n))).collect();
+    assert_eq!(
+        visited,
+        vec![
+            (0, "a".to_string()),
+            (0, "[quote]".to_string()),
+            (1, "[b]".to_string()),
+            (2, "x".to_string()),
+            (1, "y".to_string()),
+            (0, "z".to_string()),
+        ]
+    );
+
+    // 途中で打ち切れる
+    let first_bold = doc
+        .iter()
+        .find(|(_, n)| matches!(n, Node::Element(el) if el.name == "b"));
+    assert_eq!(first_bold.map(|(d, _)| d), Some(1));
+}
+
+#[test]
+fn test_cursor_navigation() {
+    let doc = parse("a[quote][b]x[/b]y[/quote]z", &BbCodeOptions::default()).unwrap();
+    let mut cur = doc.cursor();
+    assert_eq!(cur.node().map(label).as_deref(), Some("a"));
+
+    // 行きがけ順で最後まで進み、同じ順で戻れる
+    let mut forward = vec![label(cur.node().unwrap())];
+    while let Some(n) = cur.move_next() {
+        forward.push(label(n));
+    }
+    assert_eq!(forward, ["a", "[quote]", "[b]", "x", "y", "z"]);
+    assert_eq!(cur.node().map(label).as_deref(), Some("z"));
+
+    let mut backward = vec![label(cur.node().unwrap())];
+    while let Some(n) = cur.move_prev() {
+        backward.push(label(n));
+    }
+    backward.reverse();
+    assert_eq!(backward, forward);
+
+    // 親・子・兄弟
+    cur.move_next_sibling().unwrap();
+    assert_eq!(cur.move_first_child().map(label).as_deref(), Some("[b]"));
+    assert_eq!(cur.move_first_child().map(label).as_deref(), Some("x"));
+    assert_eq!(cur.depth(), 2);
+    assert_eq!(cur.path(), [1, 0, 0]);
+    assert!(cur.move_first_child().is_none());
+    assert_eq!(cur.move_parent().map(label).as_deref(), Some("[b]"));
+    assert_eq!(cur.move_next_sibling().map(label).as_deref(), Some("y"));
+    assert!(cur.move_next_sibling().is_none());
+    assert_eq!(cur.move_prev_sibling().map(label).as_deref(), Some("[b]"));
+
+    assert!(Cursor::new(&[]).node().is_none());
+}
+
+#[test]
+fn test_parse_any_plain_text() {
+    let opts = BbCodeOptions::default().with_max_consecutive_newlines(2);
+    let input = "[b]not bold[/b] a [ b\n\n\n\nc";
+
+    let doc = parse_any(input, Format::Plain, &opts).unwrap();
+    assert_eq!(doc.to_plain_text(), "[b]not bold[/b] a [ b\n\nc");
+    assert_eq!(doc.metrics.element_count, 0);
+    assert_eq!(doc.source_len, input.len());
+
+    // BBCode として読めば装飾になる
+    let doc = parse_any("[b]x[/b]", Format::BbCode, &opts).unwrap();
+    assert_eq!(doc.to_html(), "<b>x</b>");
+
+    let opts = BbCodeOptions::default().with_max_text_len(3);
+    assert!(matches!(
+        parse_any("abcd", Format::Plain, &opts),
+        Err(BbCodeError::Limit(LimitError::TextLengthExceeded { .. }))
+    ));
+}
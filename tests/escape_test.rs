@@ -0,0 +1,38 @@
+use bbcode_parser::escape::{escape_attr, escape_css_value, escape_text, escape_url_component};
+use bbcode_parser::render::{ast_to_html_with, HtmlRenderOptions};
+use bbcode_parser::{ast_to_html, parse_bbcode_to_ast, BbCodeOptions};
+
+#[test]
+fn test_escape_contexts() {
+    assert_eq!(escape_text("a < b & \"c\" 'd'"), "a &lt; b &amp; \"c\" 'd'");
+    assert_eq!(
+        escape_attr("a < b & \"c\" 'd'"),
+        "a &lt; b &amp; &quot;c&quot; &apos;d&apos;"
+    );
+    assert_eq!(escape_css_value("#ff0000"), "#ff0000");
+    assert_eq!(escape_css_value("Times New Roman"), "Times New Roman");
+    assert_eq!(
+        escape_css_value("red;x:url(\"y\")"),
+        "red\\3b x\\3a url\\28 \\22 y\\22 \\29 "
+    );
+    assert_eq!(escape_url_component("a b/c?d=é"), "a%20b%2Fc%3Fd%3D%C3%A9");
+}
+
+#[test]
+fn test_html_escapes_by_context() {
+    let opts = BbCodeOptions::default();
+    let ast =
+        parse_bbcode_to_ast("[url=/a?x=1&y=2]\"q\" & [color=red]r[/color][/url]", &opts).unwrap();
+    assert_eq!(
+        ast_to_html(&ast),
+        "<a href=\"/a?x=1&amp;y=2\" rel=\"nofollow\">\"q\" &amp; \
+         <span style=\"color:red\">r</span></a>"
+    );
+
+    // 話題名は URL の一部としてエスケープしてから埋め込む
+    let opts = BbCodeOptions::default().with_detect_hashtags(true);
+    let ast = parse_bbcode_to_ast("#日本", &opts).unwrap();
+    let html =
+        HtmlRenderOptions::default().with_tag_url(|t| format!("/tags/{}", escape_url_component(t)));
+    assert!(ast_to_html_with(&ast, &html).contains("href=\"/tags/%E6%97%A5%E6%9C%AC\""));
+}
@@ -0,0 +1,102 @@
+use std::fs;
+use std::path::Path;
+
+use bbcode_parser::ast::Span;
+use bbcode_parser::export::{ast_from_json_v1, ast_to_json_v1, ast_to_xml_v1, ExportError};
+use bbcode_parser::{ast_to_html, parse_bbcode_to_ast, BbCodeOptions, Node};
+
+#[test]
+fn test_json_v1_roundtrip() {
+    let opts = BbCodeOptions::default();
+    let ast = parse_bbcode_to_ast("[quote=\"A\"]x [url=/a]y[/url][/quote]\nz", &opts).unwrap();
+    let json = ast_to_json_v1(&ast);
+    assert!(json.starts_with("{\"version\":1,"));
+    assert_eq!(ast_from_json_v1(&json).unwrap(), ast);
+}
+
+/// 過去に保存された v1 JSON が読み込めて、同じ形で書き戻せること
+/// 各 JSON は同じ名前の .bbcode をパースした結果で、いまのパーサでも同じ AST になること
+#[test]
+fn test_json_v1_compatibility_corpus() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/ast_v1");
+    let mut count = 0;
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        let stored = fs::read_to_string(&path).unwrap();
+        let ast = ast_from_json_v1(&stored)
+            .unwrap_or_else(|e| panic!("{} failed to load: {e}", path.display()));
+        assert_eq!(
+            ast_to_json_v1(&ast),
+            stored.trim_end(),
+            "{}",
+            path.display()
+        );
+
+        let source = fs::read_to_string(path.with_extension("bbcode"))
+            .unwrap_or_else(|e| panic!("{} has no source: {e}", path.display()));
+        let source = source.strip_suffix('\n').unwrap_or(&source);
+        assert_eq!(
+            parse_bbcode_to_ast(source, &BbCodeOptions::default()).unwrap(),
+            ast,
+            "{}",
+            path.display()
+        );
+        count += 1;
+    }
+    assert!(count > 0, "corpus must not be empty");
+}
+
+#[test]
+fn test_json_unsupported_version() {
+    match ast_from_json_v1(r#"{"version":2,"nodes":[]}"#) {
+        Err(ExportError::UnsupportedVersion { found, expected }) => {
+            assert_eq!(found, 2);
+            assert_eq!(expected, 1);
+        }
+        other => panic!("Expected UnsupportedVersion, got {other:?}"),
+    }
+    assert!(matches!(
+        ast_from_json_v1("not json"),
+        Err(ExportError::InvalidJson(_))
+    ));
+}
+
+#[test]
+fn test_xml_v1_export() {
+    let opts = BbCodeOptions::default();
+    let ast = parse_bbcode_to_ast("[color=red]a<b[/color]", &opts).unwrap();
+    assert_eq!(
+        ast_to_xml_v1(&ast),
+        "<bbcode version=\"1\"><element name=\"color\" start=\"0\" end=\"22\">\
+         <attr name=\"value\">red</attr><text start=\"11\" end=\"14\">a&lt;b</text>\
+         </element></bbcode>"
+    );
+}
+
+/// 新しいバージョンで追加された種類のノードは、読み込んで書き戻しても失われない
+#[test]
+fn test_json_v1_unknown_node_type() {
+    let stored = r#"{"version":1,"nodes":[{"type":"text","text":"a","span":[0,1]},{"span":[1,9],"type":"poll","question":"?"}]}"#;
+    let ast = ast_from_json_v1(stored).unwrap();
+    match &ast[1] {
+        Node::Unsupported(node) => {
+            assert_eq!(node.kind, "poll");
+            assert_eq!(node.span, Span { start: 1, end: 9 });
+        }
+        other => panic!("Expected Unsupported, got {other:?}"),
+    }
+    // 描画では何も出力しない
+    assert_eq!(ast_to_html(&ast), "a");
+    let json = ast_to_json_v1(&ast);
+    assert!(json.contains(r#""question":"?""#));
+    assert_eq!(ast_from_json_v1(&json).unwrap(), ast);
+
+    // 既知の種類なのに形が合わないものはエラー
+    assert!(matches!(
+        ast_from_json_v1(r#"{"version":1,"nodes":[{"type":"text"}]}"#),
+        Err(ExportError::InvalidJson(_))
+    ));
+}
@@ -0,0 +1,169 @@
+use bbcode_parser::ast::{Node, Span};
+use bbcode_parser::{
+    extract_images, extract_links, extract_mentions, extract_preview, extract_quotes,
+    parse_bbcode_to_ast, BbCodeOptions, Dialect, ImageRef, LinkRef, MentionRef, PostPreview,
+    QuoteRef, TagSpec,
+};
+
+fn parse(input: &str) -> Vec<Node> {
+    parse_bbcode_to_ast(input, &BbCodeOptions::default()).unwrap()
+}
+
+#[test]
+fn test_extract_links() {
+    let input = "[url=https://a.example]see [b]this[/b][/url] \
+                 [quote][url]https://b.example[/url][/quote] \
+                 [url=javascript:alert(1)]x[/url]";
+    let links = extract_links(&parse(input));
+
+    assert_eq!(
+        links,
+        vec![
+            LinkRef {
+                url: "https://a.example".to_string(),
+                label_text: "see this".to_string(),
+                span: Span { start: 0, end: 44 },
+                inside_quote: false,
+            },
+            LinkRef {
+                url: "https://b.example".to_string(),
+                label_text: "https://b.example".to_string(),
+                span: Span { start: 52, end: 80 },
+                inside_quote: true,
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_extract_images() {
+    let input = "[img]https://a.example/1.png[/img] [b][img=120x80] /2.png [/img][/b] \
+                 [img]javascript:x[/img]";
+    let images = extract_images(&parse(input));
+
+    assert_eq!(
+        images,
+        vec![
+            ImageRef {
+                src: "https://a.example/1.png".to_string(),
+                alt: None,
+                declared_size: None,
+                span: Span { start: 0, end: 34 },
+            },
+            ImageRef {
+                src: "/2.png".to_string(),
+                alt: None,
+                declared_size: Some((120, 80)),
+                span: Span { start: 38, end: 64 },
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_extract_mentions() {
+    let mut opts = BbCodeOptions::default();
+    opts.registry.insert("user", TagSpec::with_value(None));
+    let input = "hi [user=42]Alice[/user] and [quote][user] Bob [/user][/quote]";
+    let nodes = parse_bbcode_to_ast(input, &opts).unwrap();
+
+    assert_eq!(
+        extract_mentions(&nodes),
+        vec![
+            MentionRef {
+                user_id: Some("42".to_string()),
+                name: "Alice".to_string(),
+                span: Span { start: 3, end: 24 },
+            },
+            MentionRef {
+                user_id: None,
+                name: "Bob".to_string(),
+                span: Span { start: 36, end: 54 },
+            },
+        ]
+    );
+
+    // `[user]` を登録していなければテキストのまま
+    assert!(extract_mentions(&parse(input)).is_empty());
+}
+
+#[test]
+fn test_extract_preview() {
+    let input = "[quote=Bob]quoted text[/quote]\n[size=5]Release notes[/size]\n\
+                 The [b]new[/b] version\nis out.\n\nSecond paragraph.\n\
+                 [img]https://a.example/shot.png[/img]";
+    let preview = extract_preview(&parse(input));
+
+    assert_eq!(preview.title_guess.as_deref(), Some("Release notes"));
+    assert_eq!(
+        preview.description.as_deref(),
+        Some("The new version is out.")
+    );
+    assert_eq!(
+        preview.first_image.map(|i| i.src).as_deref(),
+        Some("https://a.example/shot.png")
+    );
+}
+
+#[test]
+fn test_extract_preview_without_title() {
+    let long = "word ".repeat(60);
+    let preview = extract_preview(&parse(&format!("[b]Hi[/b] there. {long}")));
+
+    assert_eq!(preview.title_guess, None);
+    let description = preview.description.unwrap();
+    assert!(description.starts_with("Hi there. word"));
+    assert!(description.ends_with('…'));
+    assert_eq!(description.chars().count(), 200);
+
+    assert_eq!(
+        extract_preview(&[]),
+        PostPreview {
+            title_guess: None,
+            description: None,
+            first_image: None,
+        }
+    );
+}
+
+#[test]
+fn test_extract_quotes() {
+    let input = "[quote=Alice;123]a [quote=Bob]b[/quote][/quote] [quote]c[/quote]";
+    let quotes = extract_quotes(&parse(input));
+
+    assert_eq!(
+        quotes,
+        vec![
+            QuoteRef {
+                author: Some("Alice".to_string()),
+                post_id: Some("123".to_string()),
+                depth: 1,
+                span: Span { start: 0, end: 47 },
+            },
+            QuoteRef {
+                author: Some("Bob".to_string()),
+                post_id: None,
+                depth: 2,
+                span: Span { start: 19, end: 39 },
+            },
+            QuoteRef {
+                author: None,
+                post_id: None,
+                depth: 1,
+                span: Span { start: 48, end: 64 },
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_extract_quotes_from_dialect_attrs() {
+    let opts = BbCodeOptions::for_dialect(Dialect::PhpBB);
+    let input = "[quote=\"Alice\" post_id=7 time=1 user_id=2]x[/quote]";
+    let nodes = parse_bbcode_to_ast(input, &opts).unwrap();
+
+    let quotes = extract_quotes(&nodes);
+    assert_eq!(quotes.len(), 1);
+    assert_eq!(quotes[0].author.as_deref(), Some("Alice"));
+    assert_eq!(quotes[0].post_id.as_deref(), Some("7"));
+}
@@ -0,0 +1,150 @@
+#![cfg(feature = "fast-parser")]
+
+use bbcode_parser::{
+    parse_bbcode_with_diagnostics, BbCodeError, BbCodeOptions, NestingStrictness, ParserBackend,
+};
+
+/// pest 版（基準実装）と手書き版で結果が一致することを確認する
+/// 構文エラーの位置は実装ごとに異なってよいので、エラーの種類だけを比べる
+fn assert_same(input: &str, opts: &BbCodeOptions) {
+    let pest = parse_bbcode_with_diagnostics(input, opts);
+    let fast =
+        parse_bbcode_with_diagnostics(input, &opts.clone().with_backend(ParserBackend::Fast));
+    match (pest, fast) {
+        (Ok(expected), Ok(actual)) => assert_eq!(expected, actual, "input: {input:?}"),
+        (Err(BbCodeError::Parse(_)), Err(BbCodeError::Parse(_))) => {}
+        (Err(expected), Err(actual)) => {
+            assert_eq!(expected.to_string(), actual.to_string(), "input: {input:?}")
+        }
+        (expected, actual) => panic!("input: {input:?}\npest: {expected:?}\nfast: {actual:?}"),
+    }
+}
+
+/// 再現性のある疑似乱数（xorshift）
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 as usize
+    }
+}
+
+/// タグや属性の断片をつなげた入力を作る
+fn random_input(rng: &mut Rng) -> String {
+    const PIECES: &[&str] = &[
+        "[b]",
+        "[/b]",
+        "[i]",
+        "[/I]",
+        "[u]",
+        "[/u]",
+        "[quote]",
+        "[quote=Alice]",
+        "[/quote]",
+        "[quote author=\"A B\" date=1]",
+        "[color=red]",
+        "[color=\"#f00\"]",
+        "[/color]",
+        "[url=https://example.com]",
+        "[url]",
+        "[/url]",
+        "[img]",
+        "[/img]",
+        "[code]",
+        "[CODE]",
+        "[code=rust]",
+        "[/code]",
+        "[/Code]",
+        "[list]",
+        "[/list]",
+        "[*]",
+        "[/*]",
+        "[/]",
+        "[",
+        "]",
+        "[]",
+        "[/",
+        "=",
+        "\"",
+        "'",
+        "\\",
+        "\\[",
+        " ",
+        "\t",
+        "\n",
+        "x",
+        "あ",
+        "[foo]",
+        "[/foo]",
+        "[b x=1]",
+        "[b=\"a\" x]",
+        "[size='3']",
+        "#tag",
+        "[tag]",
+        "[/tag]",
+        "[*x]",
+        "[[b]]",
+        "[code=\"a\" b]",
+        "[code=\"x\" k=v]",
+    ];
+    let len = rng.next() % 12;
+    (0..len)
+        .map(|_| PIECES[rng.next() % PIECES.len()])
+        .collect()
+}
+
+#[test]
+fn test_fast_parser_matches_pest_on_examples() {
+    let opts = BbCodeOptions::default();
+    for input in [
+        "",
+        "plain text",
+        "[b]bold[/b] and [i]italic[/i]",
+        "[quote=Alice]Hi [b]there[/b][/quote]",
+        "[quote author=\"Alice Smith\" date=2024]x[/quote]",
+        "[url=https://example.com]link[/url]",
+        "[code]let x = [b]1[/b];[/code]",
+        "[code=rust]fn main() {}[/CODE]",
+        "[code]unterminated [code] [b]x[/b]",
+        "[list][*]one[*]two[/list]",
+        "[list][*]one[/*][*]two[/*][/list]",
+        "[b]unclosed [i]tags",
+        "[B]case[/b]",
+        "\\[b]escaped\\[/b]",
+        "a\\[b]x[/b]",
+        "[color=\"red]\"]x[/color]",
+        "[b=\"a\\\"b\" c=d]x[/b]",
+        "[",
+        "ab[",
+        "[b=x",
+        "x[/b]",
+        "[b]x[/b][/b]",
+    ] {
+        assert_same(input, &opts);
+    }
+}
+
+#[test]
+fn test_fast_parser_matches_pest_on_random_inputs() {
+    let variants = [
+        BbCodeOptions::default(),
+        BbCodeOptions::default()
+            .with_universal_close(true)
+            .with_case_sensitive_tags(true),
+        BbCodeOptions::default()
+            .with_max_depth(8)
+            .with_detect_hashtags(true)
+            .with_max_consecutive_newlines(1)
+            .with_block_in_inline(NestingStrictness::Fallback),
+    ];
+    let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+    for _ in 0..20_000 {
+        let input = random_input(&mut rng);
+        for opts in &variants {
+            assert_same(&input, opts);
+        }
+    }
+}
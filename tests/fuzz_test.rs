@@ -0,0 +1,102 @@
+#![cfg(feature = "fuzz")]
+
+use bbcode_parser::fuzz::{check_balanced_html, check_invariants};
+
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 as usize
+    }
+}
+
+/// タグ・属性・特殊文字の断片をつなげた入力を作る
+fn random_input(rng: &mut Rng) -> String {
+    const PIECES: &[&str] = &[
+        "[b]",
+        "[/b]",
+        "[i]",
+        "[/i]",
+        "[quote=\"A [b]\"]",
+        "[/quote]",
+        "[color=red]",
+        "[color=\"><script>\"]",
+        "[/color]",
+        "[url=https://example.com/?a=1&b=\"2\"]",
+        "[url]",
+        "[/url]",
+        "[img alt=\"<x>\"]",
+        "[/img]",
+        "[list=1]",
+        "[*]",
+        "[/*]",
+        "[/list]",
+        "[code]",
+        "[/code]",
+        "[size=200]",
+        "[/size]",
+        "[/]",
+        "\\[",
+        "[",
+        "]",
+        "=",
+        "\"",
+        "<",
+        "&",
+        "#tag ",
+        "日本語",
+        "😀",
+        "\n",
+        " ",
+        "https://example.com/a.png",
+        "javascript:alert(1)",
+    ];
+    let len = rng.next() % 24;
+    (0..len)
+        .map(|_| PIECES[rng.next() % PIECES.len()])
+        .collect()
+}
+
+#[test]
+fn test_check_invariants_on_examples() {
+    for input in [
+        "",
+        "[",
+        "]",
+        "[b]",
+        "[/b]",
+        "[b][i]x[/b][/i]",
+        "[b][i][u][s]too deep[/s][/u][/i][/b]",
+        "[url=javascript:alert(1)]x[/url]",
+        "[img]https://example.com/a.png\"onerror=x[/img]",
+        "[list][*]a[*]b[/list]",
+        "[code][/code][/code]",
+        "a < b & c > d",
+        "\u{0}\u{202e}\u{feff}",
+    ] {
+        check_invariants(input);
+    }
+    check_invariants(&"[b]".repeat(1000));
+    check_invariants(&"x".repeat(60 * 1024));
+}
+
+#[test]
+fn test_check_invariants_on_random_inputs() {
+    let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+    for _ in 0..5_000 {
+        check_invariants(&random_input(&mut rng));
+    }
+}
+
+#[test]
+fn test_check_balanced_html() {
+    assert!(check_balanced_html("<b>x</b><img src=\"a\"><br/>").is_ok());
+    assert!(check_balanced_html("<b><i>x</b></i>").is_err());
+    assert!(check_balanced_html("<b>x").is_err());
+    assert!(check_balanced_html("x</b>").is_err());
+    assert!(check_balanced_html("a < b").is_err());
+    assert!(check_balanced_html("<b title=\"日本語の長い属性値を含むタグ").is_err());
+}
@@ -0,0 +1,66 @@
+#![cfg(feature = "markdown")]
+
+use bbcode_parser::import::{markdown_to_bbcode, parse_markdown};
+use bbcode_parser::{ast_to_html, parse_any, BbCodeError, BbCodeOptions, Format, LimitError};
+
+#[test]
+fn test_markdown_inline_formatting() {
+    assert_eq!(
+        markdown_to_bbcode("**b** *i* ~~s~~ `x[y]` [site](https://example.com)"),
+        "[b]b[/b] [i]i[/i] [s]s[/s] [tt]x\\[y][/tt] [url=https://example.com]site[/url]"
+    );
+    assert_eq!(
+        markdown_to_bbcode("![alt *text*](https://e.com/a.png)"),
+        "[img]https://e.com/a.png[/img]"
+    );
+}
+
+#[test]
+fn test_markdown_blocks() {
+    assert_eq!(
+        markdown_to_bbcode(
+            "# Title\n\nfirst\nline\n\n> quoted\n\n- a\n- b\n\n1. x\n\n```rust\nlet a = 1;\n```"
+        ),
+        "[size=6][b]Title[/b][/size]\n\nfirst\nline\n\n[quote]quoted[/quote]\n\n\
+         [list]\n[*]a\n[*]b\n[/list]\n\n[list=1]\n[*]x\n[/list]\n\n[code=rust]let a = 1;[/code]"
+    );
+    assert_eq!(
+        markdown_to_bbcode("- a\n  - b"),
+        "[list]\n[*]a\n[list]\n[*]b\n[/list]\n[/list]"
+    );
+}
+
+/// Markdown の中の BBCode らしい文字列はタグとして解釈しない
+#[test]
+fn test_markdown_brackets_are_literal() {
+    let opts = BbCodeOptions::default();
+    let nodes = parse_markdown("[b]not bold[/b] a [ b \\\\**x**", &opts).unwrap();
+    assert_eq!(
+        ast_to_html(&nodes),
+        "[b]not bold[/b] a [ b \\\u{200B}<b>x</b>"
+    );
+}
+
+/// 変換後は BBCode と同じ制限がかかる
+#[test]
+fn test_markdown_uses_bbcode_policy() {
+    let opts = BbCodeOptions::default().with_max_depth(2);
+    assert!(matches!(
+        parse_markdown("> > > deep", &opts),
+        Err(BbCodeError::Limit(LimitError::NestDepthExceeded { .. }))
+    ));
+
+    let opts = BbCodeOptions::default().with_max_input_size(4);
+    assert!(matches!(
+        parse_markdown("hello", &opts),
+        Err(BbCodeError::Limit(LimitError::InputSizeExceeded { .. }))
+    ));
+}
+
+#[test]
+fn test_parse_any_markdown() {
+    let opts = BbCodeOptions::default();
+    let doc = parse_any("**hi** [x]", Format::Markdown, &opts).unwrap();
+    assert_eq!(doc.to_html(), "<b>hi</b> [x]");
+    assert_eq!(doc.source_len, "**hi** [x]".len());
+}
@@ -0,0 +1,51 @@
+use bbcode_parser::ast::{Element, Node};
+use bbcode_parser::indexed::NodeData;
+use bbcode_parser::{parse_bbcode_to_ast, BbCodeOptions, IndexedAst};
+
+fn parse(input: &str) -> Vec<Node> {
+    parse_bbcode_to_ast(input, &BbCodeOptions::default()).unwrap()
+}
+
+#[test]
+fn test_indexed_ast_round_trip() {
+    let nodes = parse("a[quote=Bob][b]x[/b]y[/quote]z");
+    let ast = IndexedAst::from(nodes.as_slice());
+
+    assert_eq!(ast.roots().len(), 3);
+    assert_eq!(ast.to_nodes(), nodes);
+}
+
+#[test]
+fn test_indexed_ast_navigation() {
+    let ast = IndexedAst::from(parse("a[quote][b]x[/b]y[/quote]z"));
+    let quote = ast.roots()[1];
+    let bold = ast.children(quote)[0];
+    let x = ast.children(bold)[0];
+
+    assert!(matches!(&ast.get(x).data, NodeData::Text(t) if t == "x"));
+    assert_eq!(ast.parent(x), Some(bold));
+    assert_eq!(ast.ancestors(x).collect::<Vec<_>>(), vec![bold, quote]);
+    assert_eq!(ast.parent(quote), None);
+
+    let y = ast.next_sibling(bold).unwrap();
+    assert!(matches!(&ast.get(y).data, NodeData::Text(t) if t == "y"));
+    assert_eq!(ast.prev_sibling(y), Some(bold));
+    assert_eq!(ast.next_sibling(y), None);
+    assert_eq!(ast.prev_sibling(quote), Some(ast.roots()[0]));
+}
+
+#[test]
+fn test_indexed_ast_replace_subtree() {
+    let mut ast = IndexedAst::from(parse("a[quote][b]x[/b]y[/quote]"));
+    let quote = ast.roots()[1];
+    let bold = ast.children(quote)[0];
+
+    let italic = ast.replace(bold, &Element::italic(vec![Node::text("new")]).into());
+
+    assert_eq!(ast.parent(italic), Some(quote));
+    assert_eq!(ast.children(quote)[0], italic);
+    assert_eq!(
+        bbcode_parser::ast_to_html(&ast.to_nodes()),
+        "a<blockquote><i>new</i>y</blockquote>"
+    );
+}
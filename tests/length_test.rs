@@ -0,0 +1,95 @@
+use bbcode_parser::{
+    ast_to_html, parse_bbcode_to_ast, BbCodeOptions, Length, LengthRule, LengthUnit, TagRegistry,
+    TagSpec,
+};
+
+fn html(input: &str, opts: &BbCodeOptions) -> String {
+    ast_to_html(&parse_bbcode_to_ast(input, opts).unwrap())
+}
+
+#[test]
+fn test_length_parse() {
+    let px = |value| Length {
+        value,
+        unit: Some(LengthUnit::Px),
+    };
+    assert_eq!(Length::parse(" 12PX "), Some(px(12.0)));
+    assert_eq!(
+        Length::parse("1.5em"),
+        Some(Length {
+            value: 1.5,
+            unit: Some(LengthUnit::Em)
+        })
+    );
+    assert_eq!(Length::parse("150").map(|l| l.unit), Some(None));
+    for bad in [
+        "",
+        "-1px",
+        "1e3",
+        "12 px",
+        "1.px",
+        ".5em",
+        "12px;color:red",
+        "1.234em",
+        "12vw",
+    ] {
+        assert_eq!(Length::parse(bad), None, "{bad}");
+    }
+    assert_eq!(px(12.0).to_string(), "12px");
+    assert_eq!(Length::parse("1.50em").unwrap().to_string(), "1.5em");
+    assert_eq!(Length::parse("40").unwrap().to_css(LengthUnit::Px), "40px");
+
+    let rule = LengthRule::font_size();
+    assert!(rule.accepts("7"));
+    assert!(rule.accepts("12pt"));
+    assert!(!rule.accepts("1.5"));
+    assert!(!rule.accepts("100px"));
+    let image = LengthRule::image_size();
+    assert_eq!(
+        image
+            .parse_dimensions("100x50")
+            .map(|(w, h)| (w.pixels(), h.pixels())),
+        Some((Some(100), Some(50)))
+    );
+    assert!(!image.accepts("100"));
+    assert!(!image.accepts("100pxx50px"));
+    assert!(LengthRule::default()
+        .allow(Some(LengthUnit::Px), 1.0..=500.0)
+        .with_dimensions()
+        .accepts("100pxx50px"));
+}
+
+#[test]
+fn test_size_units() {
+    let opts = BbCodeOptions::default();
+    assert_eq!(
+        html(
+            "[size=1.5em]a[/size][size=12pt]b[/size][size=5]c[/size]",
+            &opts
+        ),
+        "<span style=\"font-size:1.5em\">a</span><span style=\"font-size:12pt\">b</span>\
+         <span style=\"font-size:x-large\">c</span>"
+    );
+    // 許可していない単位・範囲外・CSS を続けた値はテキストに戻す
+    assert_eq!(
+        html("[size=3vw]a[/size] [size=500px]b[/size]", &opts),
+        "[size=3vw]a[/size] [size=500px]b[/size]"
+    );
+    assert_eq!(
+        html("[size=\"12px;color:red\"]a[/size]", &opts),
+        "[size=\"12px;color:red\"]a[/size]"
+    );
+
+    // タグごとに単位と範囲を決められる
+    let mut registry = TagRegistry::builtin();
+    registry.insert(
+        "size",
+        TagSpec::simple()
+            .with_length_value(LengthRule::default().allow(Some(LengthUnit::Px), 10.0..=20.0)),
+    );
+    let opts = BbCodeOptions::default().with_registry(registry);
+    assert_eq!(
+        html("[size=14px]a[/size] [size=1em]b[/size]", &opts),
+        "<span style=\"font-size:14px\">a</span> [size=1em]b[/size]"
+    );
+}
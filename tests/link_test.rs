@@ -0,0 +1,60 @@
+#![cfg(feature = "url")]
+
+use bbcode_parser::link::{display_url, normalize_url};
+use bbcode_parser::render::{ast_to_html_with, HtmlRenderOptions};
+use bbcode_parser::{parse_bbcode_to_ast, BbCodeOptions};
+
+#[test]
+fn test_normalize_url() {
+    assert_eq!(
+        normalize_url("https://例え.jp/パス?q=値").as_deref(),
+        Some("https://xn--r8jz45g.jp/%E3%83%91%E3%82%B9?q=%E5%80%A4")
+    );
+    assert_eq!(
+        normalize_url("HTTPS://Example.COM").as_deref(),
+        Some("https://example.com/")
+    );
+    // 制御文字は取り除き、書字方向の制御文字はエンコードする
+    assert_eq!(
+        normalize_url("https://example.com/a\u{7}b\u{202E}c").as_deref(),
+        Some("https://example.com/ab%E2%80%AEc")
+    );
+    assert_eq!(
+        normalize_url("/a/../b it's").as_deref(),
+        Some("/b%20it%27s")
+    );
+    assert_eq!(normalize_url("#top").as_deref(), Some("#top"));
+    assert_eq!(normalize_url("javascript:alert(1)"), None);
+    assert_eq!(normalize_url("https://exa\u{202E}mple.com/"), None);
+
+    assert_eq!(
+        display_url("https://xn--r8jz45g.jp/%E3%83%91%E3%82%B9?q=%2F"),
+        "https://例え.jp/パス?q=%2F"
+    );
+    // 見えない文字は復元しない
+    assert_eq!(
+        display_url("https://example.com/ab%E2%80%AEc"),
+        "https://example.com/ab%E2%80%AEc"
+    );
+}
+
+#[test]
+fn test_render_normalized_links() {
+    let opts = BbCodeOptions::default();
+    let html = HtmlRenderOptions::default().with_normalize_urls(true);
+    let render = |input: &str| {
+        let ast = parse_bbcode_to_ast(input, &opts).unwrap();
+        ast_to_html_with(&ast, &html)
+    };
+    assert_eq!(
+        render("[url]https://例え.jp/パス[/url]"),
+        "<a href=\"https://xn--r8jz45g.jp/%E3%83%91%E3%82%B9\" rel=\"nofollow\">\
+         https://例え.jp/パス</a>"
+    );
+    assert_eq!(
+        render("[url=https://bücher.example][b]Books[/b][/url]"),
+        "<a href=\"https://xn--bcher-kva.example/\" rel=\"nofollow\"><b>Books</b></a>"
+    );
+    // 正規化できない URL はリンクにしない
+    assert_eq!(render("[url=https://a\u{202E}b.example]x[/url]"), "x");
+}
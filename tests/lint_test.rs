@@ -0,0 +1,103 @@
+use bbcode_parser::{lint, BbCodeOptions, LintCode, Severity};
+
+fn codes(input: &str, opts: &BbCodeOptions) -> Vec<(&'static str, Severity, usize, usize)> {
+    lint(input, opts)
+        .iter()
+        .map(|l| (l.code.as_str(), l.severity, l.span.start, l.span.end))
+        .collect()
+}
+
+#[test]
+fn test_lint_codes() {
+    let input = "[b]x[/i] [zzz]y[/zzz] [color=red]z";
+    let opts = BbCodeOptions::default();
+    let lints = lint(input, &opts);
+    assert_eq!(
+        codes(input, &opts),
+        [
+            ("BB001", Severity::Warning, 0, 8),
+            ("BB002", Severity::Warning, 9, 21),
+            ("BB004", Severity::Warning, 22, 33),
+        ]
+    );
+    assert_eq!(
+        lints[0].message,
+        "Tag left as text (mismatched closing tag)"
+    );
+    assert_eq!(lints[1].message, "Unknown tag [zzz]");
+
+    // 入れ子の深さの超過はエラーで、それまでに見つけたものは報告しない
+    assert_eq!(
+        codes("[b][i][u][s]x[/s][/u][/i][/b] [zzz]", &opts),
+        [("BB003", Severity::Error, 9, 17)]
+    );
+    assert_eq!(lint("[b]ok[/b]", &opts), []);
+
+    assert_eq!(LintCode::from_code("bb002"), Some(LintCode::UnknownTag));
+    assert_eq!(LintCode::UnknownTag.to_string(), "BB002");
+    assert_eq!(LintCode::from_code("BB999"), None);
+}
+
+#[test]
+fn test_lint_severities() {
+    let input = "[b]x[/i] [zzz]y[/zzz]";
+    let opts = BbCodeOptions::default()
+        .with_lint_severity(LintCode::UnknownTag, Severity::Allow)
+        .with_lint_severity(LintCode::MismatchedClose, Severity::Info);
+    assert_eq!(codes(input, &opts), [("BB001", Severity::Info, 0, 8)]);
+
+    // strict ならテキストに戻すタグはすべてエラーとして報告する
+    let opts = BbCodeOptions::default().with_strict(true);
+    assert_eq!(
+        codes(input, &opts),
+        [
+            ("BB001", Severity::Error, 0, 8),
+            ("BB002", Severity::Error, 9, 21),
+        ]
+    );
+}
+
+#[test]
+fn test_lint_suggested_fixes() {
+    let opts = BbCodeOptions::default();
+    let fixed = |input: &str| -> Vec<String> {
+        lint(input, &opts)
+            .iter()
+            .filter_map(|l| l.suggested_fix.as_ref()?.apply(input))
+            .collect()
+    };
+    // 閉じていないタグには閉じタグを補う
+    assert_eq!(fixed("[b]bold"), ["[b]bold[/b]"]);
+    // `[/*]` の手前で閉じる
+    assert_eq!(
+        fixed("[list][*][i]x[/*][/list]"),
+        ["[list][*][i]x[/i][/*][/list]"]
+    );
+    // 閉じタグの名前を直す
+    assert_eq!(fixed("[b]x[/i]"), ["[b]x[/b]"]);
+    assert_eq!(fixed("[b]x[i]y[/b]"), ["[b]x[i]y[/b][/b]", "[b]x[i]y[/i]"]);
+    // 交差した閉じタグは内側から順に閉じ直し、閉じ損ねた内側のタグも知らせる
+    let lints = lint("[b][i]x[/b][/i]", &opts);
+    let found: Vec<_> = lints
+        .iter()
+        .map(|l| (l.code, l.span.start, l.span.end))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (LintCode::MismatchedClose, 0, 15),
+            (LintCode::MismatchedClose, 3, 11)
+        ]
+    );
+    assert_eq!(fixed("[b][i]x[/b][/i]"), ["[b][i]x[/i][/b]"]);
+    assert_eq!(fixed("[b][i]x[/b]y[/i]"), ["[b][i]x[/i][/b]y"]);
+    assert_eq!(fixed("[b][i][u]x[/b][/i][/u]"), ["[b][i][u]x[/u][/i][/b]"]);
+    // 未知のタグは近い名前に置き換える
+    let lints = lint("[colr=red]x[/colr]", &opts);
+    assert_eq!(lints[0].code, LintCode::UnknownTag);
+    assert_eq!(
+        lints[0].suggested_fix.as_ref().unwrap().replacement,
+        "[color=red]x[/color]"
+    );
+    assert_eq!(fixed("[qqqqq]x[/qqqqq]"), Vec::<String>::new());
+}
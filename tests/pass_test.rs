@@ -0,0 +1,72 @@
+use bbcode_parser::{
+    ast_to_html, parse_bbcode_with_diagnostics, AstPass, BbCodeOptions, Diagnostic, Node,
+};
+
+/// テキストの禁止語を伏せ字にする
+fn mask_words(nodes: Vec<Node>, _: &mut Vec<Diagnostic>) -> Vec<Node> {
+    nodes.into_iter().map(mask_node).collect()
+}
+
+fn mask_node(node: Node) -> Node {
+    match node {
+        Node::Text { span, text } => Node::Text {
+            span,
+            text: text.replace("darn", "****"),
+        },
+        Node::Element(mut el) => {
+            el.children = std::mem::take(&mut el.children)
+                .into_iter()
+                .map(mask_node)
+                .collect();
+            Node::Element(el)
+        }
+        other => other,
+    }
+}
+
+/// 特定のドメインへのリンクを診断として報告する
+struct FlagDomain(&'static str);
+
+impl AstPass for FlagDomain {
+    fn run(&self, nodes: Vec<Node>, diagnostics: &mut Vec<Diagnostic>) -> Vec<Node> {
+        for node in &nodes {
+            if let Node::Element(el) = node {
+                if el.attrs.iter().any(|(_, v)| v.contains(self.0)) {
+                    diagnostics.push(Diagnostic::LinkDomainNotAllowed {
+                        domain: self.0.to_string(),
+                        span: el.span,
+                    });
+                }
+            }
+        }
+        nodes
+    }
+}
+
+#[test]
+fn test_ast_passes_run_in_order() {
+    let opts = BbCodeOptions::default()
+        .with_ast_pass(mask_words)
+        .with_ast_pass(FlagDomain("bad.example"))
+        // 前のパスの結果を受け取る
+        .with_ast_pass(|nodes: Vec<Node>, _: &mut Vec<Diagnostic>| {
+            assert!(!ast_to_html(&nodes).contains("darn"));
+            nodes
+        });
+    let (ast, diagnostics) =
+        parse_bbcode_with_diagnostics("[b]darn[/b] it [url=https://bad.example/]x[/url]", &opts)
+            .unwrap();
+    assert_eq!(
+        ast_to_html(&ast),
+        "<b>****</b> it <a href=\"https://bad.example/\" rel=\"nofollow\">x</a>"
+    );
+    assert!(matches!(
+        &diagnostics[..],
+        [Diagnostic::LinkDomainNotAllowed { domain, .. }] if domain == "bad.example"
+    ));
+
+    // 複製した設定でも同じパスが動く
+    let cloned = opts.clone();
+    let (ast, _) = parse_bbcode_with_diagnostics("darn", &cloned).unwrap();
+    assert_eq!(ast_to_html(&ast), "****");
+}
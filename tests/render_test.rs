@@ -0,0 +1,885 @@
+use bbcode_parser::ast::{Element, Span};
+use bbcode_parser::escape::{escape_attr, escape_url_component};
+use bbcode_parser::render::{
+    ast_to_html_async, ast_to_html_cached, ast_to_html_with, ast_to_html_with_placeholders,
+    ast_to_html_with_resolvers, ast_to_html_with_source_map, ast_to_markdown_with,
+    fill_placeholders, walk, AsyncResolvers, CssProperty, HtmlRenderOptions, HtmlTarget,
+    LruRenderCache, MarkdownDialect, MissingAltPolicy, RenderCache, RenderContext, Renderer,
+    ResolverRequests, Resolvers, StyleBuilder, TagOutput, Visit,
+};
+use bbcode_parser::{
+    ast_to_asciidoc, ast_to_html, ast_to_markdown, ast_to_plain_text, ast_to_rst, ast_to_rtf,
+    parse_bbcode_to_ast, BbCodeOptions, Node, TagRegistry, TagSpec,
+};
+
+fn rtf(input: &str) -> String {
+    let opts = BbCodeOptions::default();
+    ast_to_rtf(&parse_bbcode_to_ast(input, &opts).unwrap())
+}
+
+#[test]
+fn test_rtf_basic_formatting() {
+    let out = rtf("[b]bold[/b] [i]it[/i]\n{x}");
+    assert!(out.starts_with("{\\rtf1\\ansi"));
+    assert!(out.ends_with('}'));
+    assert!(out.contains("{\\b bold} {\\i it}\\par\n\\{x\\}"));
+}
+
+#[test]
+fn test_rtf_color_table() {
+    let out = rtf("[color=red]a[/color][color=#00f]b[/color][color=red]c[/color]");
+    // 同じ色は色表で共有される
+    assert!(out.contains("{\\colortbl ;\\red255\\green0\\blue0;\\red0\\green0\\blue255;}"));
+    assert!(out.contains("{\\cf1 a}{\\cf2 b}{\\cf1 c}"));
+}
+
+#[test]
+fn test_rtf_list_and_unicode() {
+    let out = rtf("[list][*]あ[*]b[/list]");
+    assert!(out.contains("{\\pard\\li360\\fi-360 \\bullet\\tab \\u12354?\\par}"));
+    assert!(out.contains("\\bullet\\tab b\\par}"));
+}
+
+#[test]
+fn test_markdown_render() {
+    let opts = BbCodeOptions::default();
+    let md = |input: &str| ast_to_markdown(&parse_bbcode_to_ast(input, &opts).unwrap());
+
+    assert_eq!(
+        md("[b]bold[/b] [i]it[/i] [s]x[/s] 2*3"),
+        "**bold** *it* ~~x~~ 2\\*3"
+    );
+    assert_eq!(
+        md("[url=https://example.com]site[/url] [url]https://a.example[/url]"),
+        "[site](https://example.com) <https://a.example>"
+    );
+    assert_eq!(md("[list]\n[*]a\n[*]b\n[/list]"), "- a\n- b\n");
+    assert_eq!(md("[list=1][*]a[*]b[/list]"), "1. a\n2. b\n");
+    assert_eq!(
+        md("[quote=Alice]hi\nthere[/quote]"),
+        "> Alice:\n> hi\n> there\n"
+    );
+    assert_eq!(
+        md("x[code=rust]let a = 1;[/code]"),
+        "x\n```rust\nlet a = 1;\n```\n"
+    );
+}
+
+#[test]
+fn test_chat_markdown_dialects() {
+    let mut opts = BbCodeOptions::default();
+    opts.registry.insert("spoiler", TagSpec::simple());
+    let md = |input: &str, dialect| {
+        ast_to_markdown_with(&parse_bbcode_to_ast(input, &opts).unwrap(), dialect)
+    };
+
+    let input = "[b]b[/b] [i]i[/i] [u]u[/u] [s]s[/s] [spoiler]x[/spoiler] [color=red]c[/color]";
+    assert_eq!(
+        md(input, MarkdownDialect::CommonMark),
+        "**b** *i* u ~~s~~ x c"
+    );
+    assert_eq!(
+        md(input, MarkdownDialect::Discord),
+        "**b** *i* __u__ ~~s~~ ||x|| c"
+    );
+    assert_eq!(
+        md(input, MarkdownDialect::Telegram),
+        "*b* _i_ __u__ ~s~ ||x|| c"
+    );
+
+    // Discord は画像のURLをそのまま貼る
+    assert_eq!(
+        md("[img]https://e.com/a.png[/img]", MarkdownDialect::Discord),
+        "https://e.com/a.png"
+    );
+    // Telegram は記号をすべてエスケープし、コード・リンク先は別の規則になる
+    assert_eq!(
+        md("v1.0 (beta)! [tt]a`b[/tt]", MarkdownDialect::Telegram),
+        "v1\\.0 \\(beta\\)\\! `a\\`b`"
+    );
+    assert_eq!(
+        md(
+            "[url=https://e.com/a_(b)]go[/url]",
+            MarkdownDialect::Telegram
+        ),
+        "[go](https://e.com/a_(b\\))"
+    );
+    assert_eq!(
+        md(
+            "[list][*]a[*]b[/list][quote]q[/quote]",
+            MarkdownDialect::Telegram
+        ),
+        "• a\n• b\n>q\n"
+    );
+}
+
+#[test]
+fn test_rst_render() {
+    let opts = BbCodeOptions::default().with_max_depth(10);
+    let rst = |input: &str| ast_to_rst(&parse_bbcode_to_ast(input, &opts).unwrap());
+
+    assert_eq!(
+        rst("[b]bold[/b] [i]it[/i] 2*3_x"),
+        "**bold** *it* 2\\*3\\_x"
+    );
+    // 単語の途中の装飾はエスケープした空白で区切る。reST は装飾を入れ子にできない
+    assert_eq!(rst("a[b]b[/b]c [b][i]x[/i][/b]"), "a\\ **b**\\ c **x**");
+    assert_eq!(
+        rst("[url=https://example.com]my site[/url] [url]https://a.example[/url]"),
+        "`my site <https://example.com>`__ https://a.example"
+    );
+    assert_eq!(
+        rst("intro\n[list]\n[*]a\n[*]b\n[list=1][*]x[/list]\n[/list]\nafter"),
+        "intro\n\n- a\n- b\n\n  1. x\n\nafter"
+    );
+    assert_eq!(
+        rst("[quote=Alice]hi\nthere[/quote]\nreply"),
+        "    hi\n    there\n\n    -- Alice\n\nreply"
+    );
+    assert_eq!(
+        rst("x[code=rust]let a = 1;[/code]"),
+        "x\n\n.. code-block:: rust\n\n   let a = 1;\n"
+    );
+    // 行頭の記号・空白がブロックとして解釈されないようにする
+    assert_eq!(rst("- a\n  b"), "\\- a\nb");
+}
+
+#[test]
+fn test_asciidoc_render() {
+    let opts = BbCodeOptions::default().with_max_depth(10);
+    let adoc = |input: &str| ast_to_asciidoc(&parse_bbcode_to_ast(input, &opts).unwrap());
+
+    assert_eq!(
+        adoc("a[b]b[/b]c [i]it[/i] [u]u[/u] [color=red]r[/color]"),
+        "a**b**c __it__ [.underline]##u## [.red]##r##"
+    );
+    assert_eq!(adoc("2*3 {attr} [b][/b]"), "2&#42;3 &#123;attr&#125; ");
+    assert_eq!(
+        adoc("[url=https://example.com]my [b]site[/b][/url] [img=10x20]https://e.com/a.png[/img]"),
+        "link:https://example.com[my **site**] image:https://e.com/a.png[,10,20]"
+    );
+    assert_eq!(
+        adoc("[list]\n[*]a\n[list=1][*]x[/list]\n[/list]"),
+        "* a\n.. x\n"
+    );
+    assert_eq!(
+        adoc("[quote=Alice]hi[quote]in[/quote][/quote]"),
+        "[quote,\"Alice\"]\n____\nhi\n\n[quote]\n_____\nin\n_____\n____\n"
+    );
+    assert_eq!(
+        adoc("[code=rust]a\n----\nb[/code]"),
+        "[source,rust]\n-----\na\n----\nb\n-----\n"
+    );
+    assert_eq!(adoc(". not a title"), "&#46; not a title");
+}
+
+#[test]
+fn test_plain_text_render() {
+    let opts = BbCodeOptions::default();
+    let plain = |input: &str| ast_to_plain_text(&parse_bbcode_to_ast(input, &opts).unwrap());
+
+    assert_eq!(plain("[b]bold[/b] <tag>"), "bold <tag>");
+    assert_eq!(
+        plain("[url=https://example.com]site[/url][img]https://example.com/x.png[/img]"),
+        "site (https://example.com)"
+    );
+    assert_eq!(plain("[list]\n[*]a\n[*]b\n[/list]"), "- a\n- b\n");
+}
+
+/// 独自の出力形式も walk を使えば走査を書かずに済む
+#[test]
+fn test_custom_renderer() {
+    struct TagCounter {
+        names: Vec<String>,
+        text_len: usize,
+    }
+    impl Renderer for TagCounter {
+        fn text(&mut self, text: &str, _span: Span) {
+            self.text_len += text.len();
+        }
+        fn enter(&mut self, el: &Element) -> Visit {
+            self.names.push(el.name.to_string());
+            if el.name == "code" {
+                Visit::Skip
+            } else {
+                Visit::Children
+            }
+        }
+        fn exit(&mut self, _el: &Element) {}
+    }
+
+    let opts = BbCodeOptions::default();
+    let ast = parse_bbcode_to_ast("[b]ab[i]c[/i][/b][code]xyz[/code]", &opts).unwrap();
+    let mut counter = TagCounter {
+        names: vec![],
+        text_len: 0,
+    };
+    walk(&ast, &mut counter);
+    assert_eq!(counter.names, vec!["b", "i", "code"]);
+    assert_eq!(counter.text_len, 3);
+}
+
+#[test]
+fn test_html_source_map() {
+    let opts = BbCodeOptions::default();
+    let input = "ab [b]c<d[/b]";
+    let ast = parse_bbcode_to_ast(input, &opts).unwrap();
+    let (html, map) = ast_to_html_with_source_map(&ast);
+    assert_eq!(html, "ab <b>c&lt;d</b>");
+
+    // 出力位置順に並ぶ
+    let pairs: Vec<_> = map
+        .mappings
+        .iter()
+        .map(|m| (&html[m.output.clone()], &input[m.input.start..m.input.end]))
+        .collect();
+    assert_eq!(
+        pairs,
+        vec![
+            ("ab ", "ab "),
+            ("<b>c&lt;d</b>", "[b]c<d[/b]"),
+            ("c&lt;d", "c<d"),
+        ]
+    );
+
+    // "&lt;" の位置は太字内のテキストに対応する
+    let offset = html.find("&lt;").unwrap();
+    assert_eq!(map.lookup(offset), Some(Span { start: 6, end: 9 }));
+    // タグ部分は要素全体に対応する
+    assert_eq!(map.lookup(3), Some(Span { start: 3, end: 13 }));
+    assert_eq!(map.lookup(html.len()), None);
+}
+
+#[test]
+fn test_html_emit_source_spans() {
+    let opts = BbCodeOptions::default();
+    let ast = parse_bbcode_to_ast("x[b]y[/b][color=zz#]z[/color][img]/a.png[/img]", &opts).unwrap();
+    let render_opts = HtmlRenderOptions::default().with_emit_source_spans(true);
+    assert_eq!(
+        ast_to_html_with(&ast, &render_opts),
+        "x<b data-bb-start=\"1\" data-bb-end=\"9\">y</b>[color=zz#]z[/color]\
+         <img src=\"/a.png\" alt=\"\" data-bb-start=\"29\" data-bb-end=\"46\">"
+    );
+}
+
+#[test]
+fn test_kbd_and_tt_tags() {
+    let opts = BbCodeOptions::default();
+    let ast = |input: &str| parse_bbcode_to_ast(input, &opts).unwrap();
+
+    // [code] と違い中身の BBCode も解釈される
+    assert_eq!(
+        ast_to_html(&ast("[kbd]Ctrl[/kbd]+[mono][b]x[/b][/mono]")),
+        "<kbd>Ctrl</kbd>+<code><b>x</b></code>"
+    );
+    assert_eq!(
+        ast_to_markdown(&ast("[tt]a*b[/tt] [kbd]`[/kbd]")),
+        "`a*b` `` ` ``"
+    );
+}
+
+#[test]
+fn test_render_cache_reuses_subtrees() {
+    /// 呼び出し回数を数えるキャッシュ
+    struct Counting {
+        inner: LruRenderCache,
+        hits: usize,
+    }
+    impl RenderCache for Counting {
+        fn get(&mut self, key: u64) -> Option<String> {
+            let hit = self.inner.get(key);
+            self.hits += usize::from(hit.is_some());
+            hit
+        }
+        fn put(&mut self, key: u64, rendered: String) {
+            self.inner.put(key, rendered);
+        }
+    }
+
+    let opts = BbCodeOptions::default();
+    let quoted = "[quote=Alice][b]hi[/b][/quote]";
+    let first = parse_bbcode_to_ast(&format!("x {quoted}"), &opts).unwrap();
+    let second = parse_bbcode_to_ast(&format!("reply\n{quoted}"), &opts).unwrap();
+
+    let mut cache = Counting {
+        inner: LruRenderCache::new(16),
+        hits: 0,
+    };
+    let render_opts = HtmlRenderOptions::default();
+    let html = ast_to_html_cached(&first, &render_opts, &mut cache);
+    assert_eq!(html, ast_to_html(&first));
+    assert_eq!(cache.hits, 0);
+    // span が違っても同じ引用はキャッシュから出力される
+    let html = ast_to_html_cached(&second, &render_opts, &mut cache);
+    assert_eq!(html, ast_to_html(&second));
+    assert_eq!(cache.hits, 1);
+
+    // 祖先によって出力が変わる部分木は別々にキャッシュする
+    for input in [
+        "[pre][b]a\nb[/b][/pre][b]a\nb[/b]",
+        "[b]a\nb[/b][pre][b]a\nb[/b][/pre]",
+    ] {
+        let ast = parse_bbcode_to_ast(input, &opts).unwrap();
+        let mut cache = LruRenderCache::new(16);
+        assert_eq!(
+            ast_to_html_cached(&ast, &render_opts, &mut cache),
+            ast_to_html(&ast),
+            "{input}"
+        );
+    }
+
+    // 上限を超えたら古いものから捨てる
+    let mut lru = LruRenderCache::new(1);
+    lru.put(1, "a".to_string());
+    lru.put(2, "b".to_string());
+    assert_eq!(lru.len(), 1);
+    assert_eq!(lru.get(1), None);
+    assert_eq!(lru.get(2).as_deref(), Some("b"));
+
+    // 読んだもの・上書きしたものは新しくなり、使われていない順に捨てる
+    let mut lru = LruRenderCache::new(2);
+    lru.put(1, "a".to_string());
+    lru.put(2, "b".to_string());
+    for _ in 0..100 {
+        assert_eq!(lru.get(1).as_deref(), Some("a"));
+    }
+    lru.put(3, "c".to_string());
+    assert_eq!(lru.len(), 2);
+    assert_eq!(lru.get(2), None);
+    assert_eq!(lru.get(1).as_deref(), Some("a"));
+    lru.put(1, "a2".to_string());
+    lru.put(4, "d".to_string());
+    assert_eq!(lru.get(3), None);
+    assert_eq!(lru.get(1).as_deref(), Some("a2"));
+    assert_eq!(lru.get(4).as_deref(), Some("d"));
+}
+
+#[test]
+#[cfg(feature = "tags-embed")]
+fn test_raw_html_tag() {
+    use bbcode_parser::{parse_bbcode_with_diagnostics, Diagnostic, PermissionLevel};
+
+    let input = "[html]<b class=\"x\">[b]hi[/b]</b>[/html]";
+    // 既定では登録されていない
+    let ast = parse_bbcode_to_ast(input, &BbCodeOptions::default()).unwrap();
+    assert_eq!(ast_to_plain_text(&ast), input);
+
+    let mut registry = TagRegistry::builtin();
+    registry.enable_raw_html();
+    let admin = BbCodeOptions::default()
+        .with_registry(registry)
+        .with_author_level(PermissionLevel::Admin);
+    let (ast, diags) = parse_bbcode_with_diagnostics(input, &admin).unwrap();
+    assert_eq!(
+        diags,
+        vec![Diagnostic::RawHtml {
+            span: Span { start: 0, end: 39 },
+        }]
+    );
+
+    // 中身は BBCode として解釈しない。信頼しない限りエスケープして出力する
+    assert_eq!(
+        ast_to_html(&ast),
+        "&lt;b class=\"x\"&gt;[b]hi[/b]&lt;/b&gt;"
+    );
+    let trusted = HtmlRenderOptions::default().with_trust_raw_html(true);
+    assert_eq!(
+        ast_to_html_with(&ast, &trusted),
+        "<b class=\"x\">[b]hi[/b]</b>"
+    );
+    let sanitized = trusted.with_sanitize_html(|html| html.replace(" class=\"x\"", ""));
+    assert_eq!(ast_to_html_with(&ast, &sanitized), "<b>[b]hi[/b]</b>");
+
+    // 管理者以外は使えない
+    let member = admin.with_author_level(PermissionLevel::Moderator);
+    let (ast, diags) = parse_bbcode_with_diagnostics(input, &member).unwrap();
+    assert_eq!(ast_to_html_with(&ast, &sanitized), ast_to_html(&ast));
+    assert!(matches!(&diags[..], [Diagnostic::PermissionDenied { .. }]));
+}
+
+#[test]
+fn test_html_base_url() {
+    let ast = parse_bbcode_to_ast(
+        "[url=/thread/5]t[/url] [img]/uploads/x.png[/img] [url=#top]top[/url] \
+         [url]https://other.example/a[/url]",
+        &BbCodeOptions::default(),
+    )
+    .unwrap();
+    let opts = HtmlRenderOptions::default()
+        .with_base_url("https://forum.example:8443/community/index.php?x=1");
+    assert_eq!(
+        ast_to_html_with(&ast, &opts),
+        "<a href=\"https://forum.example:8443/thread/5\" rel=\"nofollow\">t</a> \
+         <img src=\"https://forum.example:8443/uploads/x.png\" alt=\"\"> \
+         <a href=\"#top\" rel=\"nofollow\">top</a> \
+         <a href=\"https://other.example/a\" rel=\"nofollow\">https://other.example/a</a>"
+    );
+    // メールでも同じように解決する
+    let email = opts.with_target(HtmlTarget::Email);
+    assert!(
+        ast_to_html_with(&ast, &email).contains("src=\"https://forum.example:8443/uploads/x.png\"")
+    );
+    // http(s) 以外の基準は使わない
+    let invalid = HtmlRenderOptions::default().with_base_url("ftp://files.example");
+    assert!(ast_to_html_with(&ast, &invalid).starts_with("<a href=\"/thread/5\""));
+}
+
+#[test]
+#[cfg(feature = "tags-embed")]
+fn test_html_feed_target() {
+    use bbcode_parser::PermissionLevel;
+
+    let mut registry = TagRegistry::builtin();
+    registry
+        .insert_template("yt", "<iframe src=\"{attr:src}\"></iframe>")
+        .unwrap();
+    registry.enable_raw_html();
+    let opts = BbCodeOptions::default()
+        .with_registry(registry.clone())
+        .with_author_level(PermissionLevel::Admin);
+    let ast = parse_bbcode_to_ast(
+        "[color=red][b]hi[/b][/color] [url=/t/1]rel[/url] [goto=top]up[/goto] \
+         [img]/a.png[/img][img]https://cdn.example/b.png[/img] [kbd]K[/kbd] \
+         [yt src=https://v.example/1]v[/yt] [html]<script>x</script>[/html]",
+        &opts,
+    )
+    .unwrap();
+    let feed = HtmlRenderOptions::default()
+        .with_target(HtmlTarget::Feed)
+        .with_templates(registry.templates().clone())
+        .with_trust_raw_html(true);
+    // サイト内パスは解決できないのでリンク・画像にしない
+    assert_eq!(
+        ast_to_html_with(&ast, &feed),
+        "<b>hi</b> rel up <img src=\"https://cdn.example/b.png\" alt=\"\"> <code>K</code> \
+         v &lt;script&gt;x&lt;/script&gt;"
+    );
+    let feed = feed.with_base_url("https://forum.example");
+    let html = ast_to_html_with(&ast, &feed);
+    assert!(html.contains("<a href=\"https://forum.example/t/1\">rel</a>"));
+    assert!(html.contains("<img src=\"https://forum.example/a.png\" alt=\"\">"));
+}
+
+#[test]
+fn test_html_accessible_mode() {
+    let mut registry = TagRegistry::builtin();
+    registry.insert("spoiler", TagSpec::simple());
+    let opts = BbCodeOptions::default().with_registry(registry);
+    let ast = parse_bbcode_to_ast(
+        "[b]B[/b][i]I[/i] [spoiler]S[/spoiler] [img alt=\"A \\\"cat\\\"\"]/cat.png[/img][img]/x.png[/img]",
+        &opts,
+    )
+    .unwrap();
+    assert_eq!(
+        ast_to_html(&ast),
+        "<b>B</b><i>I</i> <span class=\"spoiler\">S</span> \
+         <img src=\"/cat.png\" alt=\"A &quot;cat&quot;\"><img src=\"/x.png\" alt=\"\">"
+    );
+    let accessible = HtmlRenderOptions::default().with_accessible(true);
+    assert_eq!(
+        ast_to_html_with(&ast, &accessible),
+        "<strong>B</strong><em>I</em> \
+         <span class=\"spoiler\" role=\"note\" aria-label=\"Spoiler\">S</span> \
+         <img src=\"/cat.png\" alt=\"A &quot;cat&quot;\"><img src=\"/x.png\" alt=\"\" data-missing-alt>"
+    );
+    let drop = accessible.with_missing_alt(MissingAltPolicy::Drop);
+    assert!(ast_to_html_with(&ast, &drop).ends_with("alt=\"A &quot;cat&quot;\">"));
+}
+
+#[test]
+fn test_color_palette_and_variables() {
+    let mut registry = TagRegistry::builtin();
+    registry.restrict_color_palette(&["red", "Blue", "#336699"]);
+    let opts = BbCodeOptions::default().with_registry(registry);
+    let ast = parse_bbcode_to_ast(
+        "[color=RED]a[/color][color=#336699]b[/color][color=green]c[/color]",
+        &opts,
+    )
+    .unwrap();
+    assert_eq!(
+        ast_to_html(&ast),
+        "<span style=\"color:RED\">a</span><span style=\"color:#336699\">b</span>\
+         [color=green]c[/color]"
+    );
+    let vars = HtmlRenderOptions::default().with_color_variables(true);
+    assert_eq!(
+        ast_to_html_with(&ast, &vars),
+        "<span style=\"color:var(--bb-red, RED)\">a</span><span style=\"color:#336699\">b</span>\
+         [color=green]c[/color]"
+    );
+    let email = vars.with_target(HtmlTarget::Email);
+    assert!(ast_to_html_with(&ast, &email).starts_with("<span style=\"color:RED\">"));
+}
+
+#[test]
+fn test_html_email_target() {
+    let opts = BbCodeOptions::default();
+    let ast = parse_bbcode_to_ast(
+        "[quote=Alice][b]hi[/b][/quote][center]c[/center][mark]m[/mark]\
+         [size=7]big[/size][kbd]k[/kbd][code=rust]<x>[/code]",
+        &opts,
+    )
+    .unwrap();
+    let email = HtmlRenderOptions::default().with_target(HtmlTarget::Email);
+    let html = ast_to_html_with(&ast, &email);
+
+    assert_eq!(
+        html,
+        "<table role=\"presentation\" cellpadding=\"0\" cellspacing=\"0\" border=\"0\"><tr>\
+         <td style=\"border-left:3px solid #cccccc;padding:4px 0 4px 12px;color:#555555\">\
+         <div style=\"font-weight:bold\">Alice</div><b>hi</b></td></tr></table>\
+         <table role=\"presentation\" width=\"100%\" cellpadding=\"0\" cellspacing=\"0\" border=\"0\">\
+         <tr><td align=\"center\" style=\"text-align:center\">c</td></tr></table>\
+         <span style=\"background-color:#ffff00\">m</span>\
+         <span style=\"font-size:48px\">big</span>\
+         <span style=\"font-family:Consolas,'Courier New',monospace\">k</span>\
+         <pre style=\"font-family:Consolas,'Courier New',monospace;white-space:pre-wrap;\
+         background-color:#f5f5f5;padding:8px\">&lt;x&gt;</pre>"
+    );
+    for tag in [
+        "<blockquote",
+        "<mark",
+        "<kbd",
+        "<div style=\"text-align",
+        "class=",
+    ] {
+        assert!(!html.contains(tag), "{tag} in {html}");
+    }
+
+    // キャッシュは出力先ごとに分ける
+    let mut cache = LruRenderCache::new(16);
+    let web = ast_to_html_cached(&ast, &HtmlRenderOptions::default(), &mut cache);
+    assert_eq!(web, ast_to_html(&ast));
+    assert_eq!(ast_to_html_cached(&ast, &email, &mut cache), html);
+}
+
+#[test]
+fn test_reveal_link_hosts() {
+    let input = "[url=https://p\u{430}ypal.com][b]https://paypal.com[/b][/url] \
+                 [url=https://example.com]https://example.com[/url]";
+    let ast = parse_bbcode_to_ast(input, &BbCodeOptions::default()).unwrap();
+    let html = HtmlRenderOptions::default().with_reveal_link_hosts(true);
+    let expected_host = if cfg!(feature = "url") {
+        "xn--pypal-4ve.com"
+    } else {
+        "p\\u0430ypal.com"
+    };
+    assert_eq!(
+        ast_to_html_with(&ast, &html),
+        format!(
+            "<a href=\"https://p\u{430}ypal.com\" rel=\"nofollow\"><b>https://paypal.com</b></a> \
+             <span class=\"bb-link-host\">[{expected_host}]</span> \
+             <a href=\"https://example.com\" rel=\"nofollow\">https://example.com</a>"
+        )
+    );
+    // 既定では添えない
+    assert!(!ast_to_html(&ast).contains("bb-link-host"));
+}
+
+#[test]
+fn test_css_prefix() {
+    let input = "[anchor=intro]a[/anchor][goto=intro]b[/goto][tag]rust[/tag]\
+                 [code=rust]c[/code][img]https://img.example/a.png[/img]";
+    let ast = parse_bbcode_to_ast(input, &BbCodeOptions::default()).unwrap();
+    let html = HtmlRenderOptions::default()
+        .with_css_prefix("fx-\"><")
+        .with_accessible(true)
+        .with_emit_source_spans(true);
+    assert_eq!(
+        ast_to_html_with(&ast, &html),
+        "<a id=\"fx-intro\" data-fx-bb-start=\"0\" data-fx-bb-end=\"24\">a</a>\
+         <a href=\"#fx-intro\" data-fx-bb-start=\"24\" data-fx-bb-end=\"44\">b</a>\
+         <span class=\"fx-hashtag\" data-fx-bb-start=\"44\" data-fx-bb-end=\"59\">rust</span>\
+         <pre data-fx-bb-start=\"59\" data-fx-bb-end=\"78\"><code class=\"fx-language-rust\">c</code></pre>\
+         <img src=\"https://img.example/a.png\" alt=\"\" data-fx-missing-alt \
+         data-fx-bb-start=\"78\" data-fx-bb-end=\"114\">"
+    );
+    let ast = parse_bbcode_to_ast(":wave:", &BbCodeOptions::default()).unwrap();
+    let resolvers = Resolvers::default().with_emoji(|_| Some("/e.png".to_string()));
+    assert_eq!(
+        ast_to_html_with_resolvers(
+            &ast,
+            &HtmlRenderOptions::default().with_css_prefix("fx-"),
+            &resolvers
+        ),
+        "<img class=\"fx-bb-emoji\" src=\"/e.png\" alt=\":wave:\">"
+    );
+}
+
+#[test]
+fn test_style_builder() {
+    let mut style = StyleBuilder::default()
+        .with(CssProperty::Color, " #ff0000 ")
+        .with(CssProperty::FontSize, "150%");
+    assert!(!style.push(CssProperty::Color, "red;background:url(x)"));
+    assert!(!style.push(CssProperty::FontFamily, "Arial\"><script>"));
+    assert!(!style.push(CssProperty::FontSize, "1000px"));
+    assert!(!style.push(CssProperty::TextAlign, "middle"));
+    assert!(style.push(CssProperty::BackgroundColor, "var(--bb-red, red)"));
+    assert_eq!(
+        style.to_attr(),
+        " style=\"color:#ff0000;font-size:150%;background-color:var(--bb-red, red)\""
+    );
+    assert_eq!(StyleBuilder::default().to_attr(), "");
+    assert!(!CssProperty::Color.accepts("var(--bb-red, blue)"));
+    assert!(CssProperty::FontFamily.accepts("Noto Sans"));
+
+    // 組み込みのタグも同じ検証を通る
+    let ast = parse_bbcode_to_ast(
+        "[center][font=Noto Sans][size=150]x[/size][/font][/center]",
+        &BbCodeOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        ast_to_html(&ast),
+        "<div style=\"text-align:center\"><span style=\"font-family:Noto Sans\">\
+         <span style=\"font-size:150%\">x</span></span></div>"
+    );
+}
+
+#[test]
+fn test_pre_keeps_whitespace() {
+    let input = "[pre]  a  [b]b[/b]\n\n\n\n   c\n[/pre]\nd";
+    let opts = BbCodeOptions::default().with_max_consecutive_newlines(1);
+    let ast = parse_bbcode_to_ast(input, &opts).unwrap();
+    // 改行は <br> にせず、連続する改行も詰めない
+    assert_eq!(
+        ast_to_html(&ast),
+        "<pre>  a  <b>b</b>\n\n\n\n   c\n</pre><br>d"
+    );
+    assert_eq!(ast_to_plain_text(&ast), "  a  b\n\n\n\n   c\n\nd");
+}
+
+#[test]
+fn test_tag_renderer_context() {
+    // 引用の中の画像はリンクにする
+    fn img(el: &Element, ctx: &RenderContext<'_>) -> Option<TagOutput> {
+        let src = match el.children.first() {
+            Some(Node::Text { text, .. }) => escape_attr(text),
+            _ => return None,
+        };
+        if ctx.is_inside("quote") {
+            Some(TagOutput::replace(format!("<a href=\"{src}\">image</a>")))
+        } else {
+            Some(TagOutput::replace(format!("<img src=\"{src}\">")))
+        }
+    }
+    fn b(_: &Element, ctx: &RenderContext<'_>) -> Option<TagOutput> {
+        Some(TagOutput::wrap(
+            format!(
+                "<b data-depth=\"{}\" data-parent=\"{}\">",
+                ctx.depth,
+                ctx.parent_tag.unwrap_or("")
+            ),
+            "</b>",
+        ))
+    }
+    let html = HtmlRenderOptions::default()
+        .with_tag_renderer("img", img)
+        .with_tag_renderer("B", b);
+    let input = "[img]https://a.example/x.png[/img][quote][i][img]https://a.example/x.png[/img][/i][b]y[/b][/quote]";
+    let ast = parse_bbcode_to_ast(input, &BbCodeOptions::default()).unwrap();
+    let expected = "<img src=\"https://a.example/x.png\">\
+                    <blockquote><i><a href=\"https://a.example/x.png\">image</a></i>\
+                    <b data-depth=\"1\" data-parent=\"quote\">y</b></blockquote>";
+    assert_eq!(ast_to_html_with(&ast, &html), expected);
+    // 位置で出力が変わるので、キャッシュを渡しても要素ごとに描画する
+    let mut cache = LruRenderCache::new(16);
+    assert_eq!(ast_to_html_cached(&ast, &html, &mut cache), expected);
+    // フィード向けでは使わない
+    let feed = html.with_target(HtmlTarget::Feed);
+    assert!(!ast_to_html_with(&ast, &feed).contains("data-depth"));
+}
+
+#[test]
+fn test_resolvers() {
+    let mut registry = TagRegistry::builtin();
+    registry.insert("user", TagSpec::with_value(None));
+    registry.insert("attach", TagSpec::with_value(None));
+    let opts = BbCodeOptions::default().with_registry(registry);
+    let input = "[user=7]Alice[/user] [user]Bob[/user] [attach]12[/attach] :wave: :nope: 10:30 \
+                 [url=/t/1]x[/url] [img]https://img.example/a.png[/img]";
+    let ast = parse_bbcode_to_ast(input, &opts).unwrap();
+
+    // 描画ごとの情報（ここでは言語と CDN のホスト）を閉じ込めた関数を渡す
+    let locale = "ja".to_string();
+    let resolvers = Resolvers::default()
+        .with_user(move |id| (id == "7").then(|| format!("/{locale}/users/{id}")))
+        .with_attachment(|id| Some(format!("/files/{id}")))
+        .with_emoji(|name| (name == "wave").then(|| "/emoji/wave.png".to_string()))
+        .with_url_rewriter(|url| url.replace("https://img.example/", "https://cdn.example/"));
+    let html = HtmlRenderOptions::default().with_base_url("https://img.example");
+    assert_eq!(
+        ast_to_html_with_resolvers(&ast, &html, &resolvers),
+        "<a href=\"https://cdn.example/ja/users/7\" class=\"bb-user\">Alice</a> Bob \
+         <a href=\"https://cdn.example/files/12\" class=\"bb-attachment\">12</a> \
+         <img class=\"bb-emoji\" src=\"https://cdn.example/emoji/wave.png\" alt=\":wave:\"> \
+         :nope: 10:30 <a href=\"https://cdn.example/t/1\" rel=\"nofollow\">x</a> \
+         <img src=\"https://cdn.example/a.png\" alt=\"\">"
+    );
+    // 渡さなければ中身だけ・入力どおり
+    assert_eq!(
+        ast_to_html_with(&ast, &HtmlRenderOptions::default()),
+        "Alice Bob 12 :wave: :nope: 10:30 <a href=\"/t/1\" rel=\"nofollow\">x</a> \
+         <img src=\"https://img.example/a.png\" alt=\"\">"
+    );
+}
+
+#[test]
+fn test_quote_permalink() {
+    let input = "[quote=Alice;123]a[/quote][quote=Bob]b[/quote]";
+    let ast = parse_bbcode_to_ast(input, &BbCodeOptions::default()).unwrap();
+    let resolvers =
+        Resolvers::default().with_quote_permalink(|id| Some(format!("/posts/{id}#post-{id}")));
+    let html = HtmlRenderOptions::default().with_base_url("https://forum.example");
+    assert_eq!(
+        ast_to_html_with_resolvers(&ast, &html, &resolvers),
+        "<blockquote><cite>Alice</cite><a href=\"https://forum.example/posts/123#post-123\" \
+         class=\"bb-quote-link\" title=\"Go to quoted post\">&uarr;</a>a</blockquote>\
+         <blockquote><cite>Bob</cite>b</blockquote>"
+    );
+    // 渡さなければリンクは付けない
+    assert_eq!(
+        ast_to_html(&ast),
+        "<blockquote><cite>Alice</cite>a</blockquote><blockquote><cite>Bob</cite>b</blockquote>"
+    );
+    // メール向けでも同じ引用元・同じリンク先を使う
+    let email = html.with_target(HtmlTarget::Email);
+    let out = ast_to_html_with_resolvers(&ast, &email, &resolvers);
+    assert!(
+        out.contains(
+            "<div style=\"font-weight:bold\">Alice<a href=\"https://forum.example/posts/123#post-123\" \
+             class=\"bb-quote-link\" title=\"Go to quoted post\">&uarr;</a></div>a</td>"
+        ),
+        "{out}"
+    );
+    assert!(out.contains("<div style=\"font-weight:bold\">Bob</div>b</td>"));
+    // `[quote=Alice;123]` の値は名前と投稿 ID に分けて表示する
+    assert!(!ast_to_html_with(&ast, &email).contains("Alice;123"));
+}
+
+#[test]
+fn test_placeholders() {
+    let mut registry = TagRegistry::builtin();
+    registry.insert("user", TagSpec::with_value(None));
+    let opts = BbCodeOptions::default().with_registry(registry);
+    let input = "[user=a b]A[/user] [user]ghost[/user] :wave: :nope: [quote=Carol;5]q[/quote]";
+    let ast = parse_bbcode_to_ast(input, &opts).unwrap();
+
+    let html = HtmlRenderOptions::default();
+    let (template, requests) = ast_to_html_with_placeholders(&ast, &html);
+    assert_eq!(
+        template,
+        "<!--bb:user:a%20b-->A<!--/bb:user:a%20b--> <!--bb:user:ghost-->ghost<!--/bb:user:ghost--> \
+         <!--bb:emoji:wave--> <!--bb:emoji:nope--> <blockquote><cite>Carol</cite>\
+         <!--bb:quote:5-->q</blockquote>"
+    );
+    assert_eq!(requests.users, ["a b", "ghost"]);
+    assert_eq!(requests.emoji, ["nope", "wave"]);
+    assert_eq!(requests.quote_posts, ["5"]);
+
+    // 埋めた結果はその場で解決して描画したものと同じ
+    let resolvers = Resolvers::default()
+        .with_user(|id| (id != "ghost").then(|| format!("/users/{}", escape_url_component(id))))
+        .with_emoji(|name| (name == "wave").then(|| "/emoji/wave.png".to_string()))
+        .with_quote_permalink(|id| Some(format!("/posts/{id}")));
+    let filled = fill_placeholders(&template, &html, &resolvers);
+    assert_eq!(filled, ast_to_html_with_resolvers(&ast, &html, &resolvers));
+    assert_eq!(
+        filled,
+        "<a href=\"/users/a%20b\" class=\"bb-user\">A</a> ghost \
+         <img class=\"bb-emoji\" src=\"/emoji/wave.png\" alt=\":wave:\"> :nope: \
+         <blockquote><cite>Carol</cite><a href=\"/posts/5\" class=\"bb-quote-link\" \
+         title=\"Go to quoted post\">&uarr;</a>q</blockquote>"
+    );
+    // 何も解決できなければプレースホルダの無い描画と同じ
+    assert_eq!(
+        fill_placeholders(&template, &html, &Resolvers::default()),
+        ast_to_html_with(&ast, &html)
+    );
+    // プレースホルダ以外のコメントはそのまま残す
+    assert_eq!(
+        fill_placeholders("<!-- x --><!--bb:other:1-->", &html, &resolvers),
+        "<!-- x --><!--bb:other:1-->"
+    );
+
+    // 範囲の属性はプレースホルダの中に持ち、埋めたタグに付ける
+    let spans = html.with_emit_source_spans(true);
+    let (template, _) = ast_to_html_with_placeholders(&ast, &spans);
+    assert!(template.starts_with(
+        "<!--bb:user:a%20b data-bb-start=\"0\" data-bb-end=\"18\"-->A<!--/bb:user:a%20b-->"
+    ));
+    let filled = fill_placeholders(&template, &spans, &resolvers);
+    assert_eq!(filled, ast_to_html_with_resolvers(&ast, &spans, &resolvers));
+    assert!(filled.starts_with(
+        "<a href=\"/users/a%20b\" class=\"bb-user\" data-bb-start=\"0\" data-bb-end=\"18\">A</a>"
+    ));
+    assert_eq!(
+        fill_placeholders(&template, &spans, &Resolvers::default()),
+        ast_to_html_with(&ast, &spans)
+    );
+}
+
+/// 待たずに完了する Future を最後まで進める（テスト用の最小の実行器）
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    use std::task::{Context, Poll, Waker};
+    let mut future = std::pin::pin!(future);
+    let mut cx = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+    }
+}
+
+#[test]
+fn test_async_resolvers() {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    /// 問い合わせを記録し、データベースの代わりに固定の対応を返す
+    #[derive(Default)]
+    struct Db {
+        calls: Mutex<Vec<Vec<String>>>,
+    }
+    impl AsyncResolvers for Db {
+        async fn users(&self, keys: &[String]) -> HashMap<String, String> {
+            self.calls.lock().unwrap().push(keys.to_vec());
+            keys.iter()
+                .filter(|k| *k != "ghost")
+                .map(|k| (k.clone(), format!("/u/{k}")))
+                .collect()
+        }
+
+        async fn emoji(&self, names: &[String]) -> HashMap<String, String> {
+            self.calls.lock().unwrap().push(names.to_vec());
+            HashMap::from([("ok".to_string(), "/e/ok.png".to_string())])
+        }
+    }
+
+    let mut registry = TagRegistry::builtin();
+    registry.insert("user", TagSpec::with_value(None));
+    let opts = BbCodeOptions::default().with_registry(registry);
+    let input =
+        "[user]bob[/user] [user=alice]A[/user] [user]ghost[/user] [b][user]bob[/user] :ok:[/b] \
+                 [code]:skip:[/code] [quote=Carol;5]q[/quote]";
+    let ast = parse_bbcode_to_ast(input, &opts).unwrap();
+
+    let requests = ResolverRequests::collect(&ast);
+    assert_eq!(requests.users, ["alice", "bob", "ghost"]);
+    assert!(requests.attachments.is_empty());
+    assert_eq!(requests.emoji, ["ok"]);
+    assert_eq!(requests.quote_posts, ["5"]);
+
+    let db = Db::default();
+    let html = block_on(ast_to_html_async(&ast, &HtmlRenderOptions::default(), &db));
+    assert_eq!(
+        html,
+        "<a href=\"/u/bob\" class=\"bb-user\">bob</a> <a href=\"/u/alice\" class=\"bb-user\">A</a> \
+         ghost <b><a href=\"/u/bob\" class=\"bb-user\">bob</a> \
+         <img class=\"bb-emoji\" src=\"/e/ok.png\" alt=\":ok:\"></b> \
+         <pre><code>:skip:</code></pre> <blockquote><cite>Carol</cite>q</blockquote>"
+    );
+    // 種類ごとに1回だけ問い合わせる
+    assert_eq!(db.calls.lock().unwrap().len(), 2);
+}
@@ -0,0 +1,86 @@
+use bbcode_parser::{ast_to_html, lint, parse_bbcode_to_ast, repair, BbCodeOptions, LintCode};
+
+#[test]
+fn test_repair() {
+    let opts = BbCodeOptions::default();
+    let fixed = |input: &str| repair(input, &opts).0;
+
+    assert_eq!(fixed("[b]bold"), "[b]bold[/b]");
+    assert_eq!(fixed("[b][i]x"), "[b][i]x[/i][/b]");
+    assert_eq!(fixed("[b]x[i]y[/b]"), "[b]x[i]y[/i][/b]");
+    // 交差した閉じタグは内側のタグから閉じる
+    assert_eq!(fixed("[b][i]x[/b][/i]"), "[b][i]x[/i][/b]");
+    assert_eq!(fixed("[b]a[i]x[/b]y[/i]z"), "[b]a[i]x[/i][/b]yz");
+    assert!(lint(&fixed("[b][i]x[/b][/i]"), &opts).is_empty());
+    assert_eq!(fixed("[b]x[/b][/i] y[/*]"), "[b]x[/b] y");
+    // `[*]` も1段と数えるので、既定の深さでは閉じられない
+    assert_eq!(
+        fixed("[list][*][i]x[/*][/list]"),
+        "[list][*][i]x[/*][/list]"
+    );
+    let deeper = BbCodeOptions::default().with_max_depth(4);
+    assert_eq!(
+        repair("[list][*][i]x[/*][/list]", &deeper).0,
+        "[list][*][i]x[/i][/*][/list]"
+    );
+    // 本文中の括弧はエスケープするだけ（表示は変わらない）で、未知のタグの名前は変えない
+    let prose = "see [1] and [colr]x[/colr]";
+    assert_eq!(fixed(prose), "see \\[1] and [colr]x[/colr]");
+    let html = |input: &str| ast_to_html(&parse_bbcode_to_ast(input, &opts).unwrap());
+    assert_eq!(html(&fixed(prose)), html(prose));
+    // 閉じタグを横取りする本文中の括弧はエスケープしてから閉じる
+    assert_eq!(fixed("[b]see [1] here[/b]"), "[b]see \\[1] here[/b]");
+    assert_eq!(fixed("[b][td]x"), "[b]\\[td]x[/b]");
+    // 閉じると深さの制限を超えるタグは閉じずに残す
+    let nested = fixed("[b][b][b][b]x");
+    assert_eq!(nested, "[b][b][b][b]x[/b][/b][/b]");
+    assert!(parse_bbcode_to_ast(&nested, &opts).is_ok());
+
+    let input = "[u]x[/s][/b]";
+    let (output, applied) = repair(input, &opts);
+    assert_eq!(output, "[u]x[/u]");
+    assert!(lint(&output, &opts).is_empty());
+    let codes: Vec<_> = applied.iter().map(|f| f.code).collect();
+    assert_eq!(codes, [LintCode::Syntax, LintCode::MismatchedClose]);
+    // 返した順に適用すると結果になる
+    let replayed = applied
+        .iter()
+        .try_fold(input.to_string(), |text, fix| fix.edit.apply(&text));
+    assert_eq!(replayed.as_deref(), Some(output.as_str()));
+
+    assert_eq!(
+        repair("[b]ok[/b]", &opts),
+        ("[b]ok[/b]".to_string(), vec![])
+    );
+}
+
+#[test]
+fn test_repair_keeps_parseable_posts_parseable() {
+    let opts = BbCodeOptions::default();
+    let pieces = [
+        "[b]", "[/b]", "[i]", "[/i]", "[1]", "[td]", "[/td]", "[quote]", "[/quote]", "[list]",
+        "[*]", "[/list]", "[/*]", "x", " ", "\n",
+    ];
+    let mut seed = 12345u64;
+    for _ in 0..3000 {
+        let mut input = String::new();
+        for _ in 0..8 {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            input.push_str(pieces[(seed >> 33) as usize % pieces.len()]);
+        }
+        let before = parse_bbcode_to_ast(&input, &opts).is_ok();
+        let (output, _) = repair(&input, &opts);
+        if before {
+            assert!(
+                parse_bbcode_to_ast(&output, &opts).is_ok(),
+                "{input:?} -> {output:?}"
+            );
+            assert!(
+                lint(&output, &opts).len() <= lint(&input, &opts).len(),
+                "{input:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use bbcode_parser::{parse_bbcode_to_ast, spam_score, BbCodeOptions, SpamHeuristics};
+
+fn score(input: &str, heuristics: &SpamHeuristics) -> f32 {
+    let nodes = parse_bbcode_to_ast(input, &BbCodeOptions::default()).unwrap();
+    spam_score(&nodes, heuristics)
+}
+
+#[test]
+fn test_normal_post_scores_low() {
+    let h = SpamHeuristics::default();
+    let s = score(
+        "Thanks for the write-up! I tried the [b]second[/b] approach and it worked, \
+         see the docs at [url=https://example.com/docs]the manual[/url] for details.",
+        &h,
+    );
+    assert!(s < 0.15, "{s}");
+    assert_eq!(score("", &h), 0.0);
+}
+
+#[test]
+fn test_spam_features_raise_score() {
+    let h = SpamHeuristics::default();
+    let normal = score("hello there, nice weather today", &h);
+
+    // リンクだらけ（本文の URL と [url] の両方を数える）
+    let links = score(
+        "buy https://a.example [url]https://b.example[/url] [url=https://c.example]now[/url]",
+        &h,
+    );
+    // 同じタグの連続・入れ子
+    let repeated = score("[b][b][b]x[/b][/b][/b][i]a[/i][i]b[/i][i]c[/i]", &h);
+    // 巨大な文字と大文字だらけ
+    let loud = score("[size=7]FREE MONEY CLICK HERE RIGHT NOW[/size]", &h);
+
+    assert!(links > 0.3, "{links}");
+    assert!(repeated > normal, "{repeated}");
+    assert!(loud > 0.25, "{loud}");
+    assert!(loud > normal && links > normal);
+}
+
+#[test]
+fn test_blocked_words_and_weights() {
+    let h = SpamHeuristics {
+        blocked_words: ["casino".to_string()].into_iter().collect(),
+        ..Default::default()
+    };
+    assert!(score("best Casino!", &h) > score("best game!", &h));
+
+    // 重みをすべて 0 にすれば常に 0
+    let zero = SpamHeuristics {
+        link_weight: 0.0,
+        repeat_weight: 0.0,
+        size_weight: 0.0,
+        caps_weight: 0.0,
+        blocked_word_weight: 0.0,
+        ..Default::default()
+    };
+    assert_eq!(score("[size=7]https://spam.example[/size]", &zero), 0.0);
+}
@@ -0,0 +1,55 @@
+use bbcode_parser::ast::Span;
+use bbcode_parser::{parse_bbcode_to_ast, BbCodeOptions, Node};
+
+#[test]
+fn test_span_conversions() {
+    // "é" は2バイト・1文字、"😀" は4バイト・UTF-16 で2単位、"e\u{301}" は2文字で1書記素
+    let input = "é[b]😀e\u{301}[/b]!";
+    let ast = parse_bbcode_to_ast(input, &BbCodeOptions::default()).unwrap();
+    let Node::Element(b) = &ast[1] else {
+        panic!("expected [b]");
+    };
+    let span = b.span;
+    assert_eq!(span, Span { start: 2, end: 16 });
+    assert_eq!(span.slice(input), Some("[b]😀e\u{301}[/b]"));
+    assert_eq!(span.to_char_range(input), Some(1..11));
+    assert_eq!(span.to_utf16_range(input), Some(1..12));
+    assert_eq!(span.to_grapheme_range(input), Some(1..10));
+
+    assert_eq!(Span::from_char_range(input, 1..11), Some(span));
+    assert_eq!(Span::from_utf16_range(input, 1..12), Some(span));
+    // サロゲートペアの途中・範囲外
+    assert_eq!(Span::from_utf16_range(input, 5..6), None);
+    assert_eq!(Span::from_char_range(input, 0..100), None);
+}
+
+#[test]
+fn test_span_conversion_never_panics() {
+    let input = "aé😀";
+    // 文字の途中・範囲外・逆順
+    for span in [
+        Span { start: 2, end: 3 },
+        Span { start: 0, end: 99 },
+        Span { start: 3, end: 1 },
+    ] {
+        assert_eq!(span.slice(input), None);
+        assert_eq!(span.to_char_range(input), None);
+        assert_eq!(span.to_utf16_range(input), None);
+        assert_eq!(span.to_grapheme_range(input), None);
+    }
+
+    // 書記素の途中（基底文字と結合文字の間）は書記素全体に広げる
+    let input = "xe\u{301}y";
+    assert_eq!(
+        Span { start: 2, end: 2 }.to_grapheme_range(input),
+        Some(1..2)
+    );
+    assert_eq!(
+        Span { start: 2, end: 5 }.to_grapheme_range(input),
+        Some(1..3)
+    );
+    assert_eq!(
+        Span { start: 5, end: 5 }.to_grapheme_range(input),
+        Some(3..3)
+    );
+}
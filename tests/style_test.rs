@@ -0,0 +1,36 @@
+use bbcode_parser::{parse_bbcode_to_ast, resolve_styles, BbCodeOptions};
+
+#[test]
+fn test_resolve_nested_styles() {
+    let opts = BbCodeOptions::default().with_max_depth(4);
+    let input = "a[b]b[color=red]c[color=blue][i]d[/i][/color][/color][/b]";
+    let ast = parse_bbcode_to_ast(input, &opts).unwrap();
+    let runs = resolve_styles(&ast);
+
+    let texts: Vec<_> = runs.iter().map(|r| r.text.as_str()).collect();
+    assert_eq!(texts, vec!["a", "b", "c", "d"]);
+
+    assert_eq!(runs[0].style, Default::default());
+    assert!(runs[1].style.bold);
+    assert_eq!(runs[1].style.color, None);
+    assert_eq!(runs[2].style.color.as_deref(), Some("red"));
+
+    // 内側の color が優先され、外側の bold は引き継がれる
+    let d = &runs[3].style;
+    assert!(d.bold && d.italic);
+    assert_eq!(d.color.as_deref(), Some("blue"));
+    assert_eq!(&input[runs[3].span.start..runs[3].span.end], "d");
+}
+
+#[test]
+fn test_resolve_size_and_font() {
+    let opts = BbCodeOptions::default();
+    let ast = parse_bbcode_to_ast("[font=Arial][size=5]x[/size]y[/font]", &opts).unwrap();
+    let runs = resolve_styles(&ast);
+
+    assert_eq!(runs.len(), 2);
+    assert_eq!(runs[0].style.size.as_deref(), Some("5"));
+    assert_eq!(runs[0].style.font.as_deref(), Some("Arial"));
+    assert_eq!(runs[1].style.size, None);
+    assert_eq!(runs[1].style.font.as_deref(), Some("Arial"));
+}
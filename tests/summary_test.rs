@@ -0,0 +1,52 @@
+use bbcode_parser::ast::Node;
+use bbcode_parser::{
+    ast_to_html, parse_bbcode_to_ast, summarize, BbCodeOptions, Summary, SummaryOptions,
+};
+
+fn parse(input: &str) -> Vec<Node> {
+    parse_bbcode_to_ast(input, &BbCodeOptions::default()).unwrap()
+}
+
+#[test]
+fn test_summarize_truncates_keeping_markup() {
+    let nodes = parse("one [b]two three[/b] four [img]https://a.example/x.png[/img] five");
+    let summary = summarize(
+        &nodes,
+        SummaryOptions::default()
+            .with_max_words(2)
+            .with_words_per_minute(2),
+    );
+
+    assert_eq!(ast_to_html(&summary.excerpt_ast), "one <b>two</b>");
+    assert!(summary.truncated);
+    assert_eq!(summary.word_count, 5);
+    assert_eq!(summary.reading_minutes, 3);
+    assert!(summary.has_media);
+}
+
+#[test]
+fn test_summarize_short_text() {
+    let nodes = parse("[i]short[/i] post");
+    let summary = summarize(&nodes, SummaryOptions::default());
+
+    assert_eq!(
+        summary,
+        Summary {
+            excerpt_ast: nodes.clone(),
+            truncated: false,
+            word_count: 2,
+            reading_minutes: 1,
+            has_media: false,
+        }
+    );
+    assert_eq!(summarize(&[], SummaryOptions::default()).reading_minutes, 0);
+}
+
+#[test]
+fn test_summarize_counts_cjk_characters_as_words() {
+    let nodes = parse("日本語の[b]本文[/b]です");
+    let summary = summarize(&nodes, SummaryOptions::default().with_max_words(5));
+
+    assert_eq!(summary.word_count, 8);
+    assert_eq!(ast_to_html(&summary.excerpt_ast), "日本語の<b>本</b>");
+}
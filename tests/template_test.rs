@@ -0,0 +1,126 @@
+use bbcode_parser::render::{ast_to_html_with, HtmlRenderOptions};
+use bbcode_parser::{
+    ast_to_html, parse_bbcode_to_ast, BbCodeOptions, DisplayKind, TagRegistry, TagSpec,
+    TagTemplate, TemplateError,
+};
+
+fn render(registry: &TagRegistry, input: &str) -> String {
+    let opts = BbCodeOptions::default().with_registry(registry.clone());
+    let ast = parse_bbcode_to_ast(input, &opts).unwrap();
+    let html = HtmlRenderOptions::default().with_templates(registry.templates().clone());
+    ast_to_html_with(&ast, &html)
+}
+
+#[test]
+fn test_template_tag_placeholders() {
+    let mut registry = TagRegistry::builtin();
+    registry
+        .insert_template("spoiler", "<span class=\"spoiler\">{content}</span>")
+        .unwrap();
+    registry
+        .insert_template(
+            "abbr",
+            "<abbr title=\"{value}\" data-lang=\"{attr:lang}\">{content}</abbr>",
+        )
+        .unwrap();
+    registry
+        .insert_template("yt", "<iframe src=\"{attr:src}\"></iframe>")
+        .unwrap();
+
+    assert_eq!(
+        render(&registry, "[spoiler][b]x[/b][/spoiler]"),
+        "<span class=\"spoiler\"><b>x</b></span>"
+    );
+    // 属性値・中身はそれぞれの位置に合わせてエスケープする
+    assert_eq!(
+        render(&registry, "[abbr=\"a\\\"<b>\" lang=ja]HTML & co[/abbr]"),
+        "<abbr title=\"a&quot;&lt;b&gt;\" data-lang=\"ja\">HTML &amp; co</abbr>"
+    );
+    // {content} が無ければ中身は出力しない
+    assert_eq!(
+        render(&registry, "[yt src=https://example.com/v]ignored[/yt]"),
+        "<iframe src=\"https://example.com/v\"></iframe>"
+    );
+    // リンク先の属性に使えない値なら中身だけを出力する
+    assert_eq!(render(&registry, "[yt src=javascript:alert(1)]x[/yt]"), "x");
+    // テンプレートで使わない属性は受け付けない
+    assert_eq!(
+        render(&registry, "[spoiler=x]y[/spoiler]"),
+        "[spoiler=x]y[/spoiler]"
+    );
+    // テンプレートを渡さなければ中身だけになる
+    let ast = parse_bbcode_to_ast(
+        "[abbr=x]z[/abbr]",
+        &BbCodeOptions::default().with_registry(registry.clone()),
+    )
+    .unwrap();
+    assert_eq!(ast_to_html(&ast), "z");
+
+    // 通常の登録で置き換えるとテンプレートも外れる
+    registry.insert("spoiler", TagSpec::simple());
+    assert!(!registry.templates().contains_key("spoiler"));
+}
+
+#[test]
+fn test_template_display_and_errors() {
+    let block = TagTemplate::parse("<div class=\"note\">{content}</div>").unwrap();
+    assert_eq!(block.display(), DisplayKind::Block);
+    assert!(!block.uses_value());
+    let inline = TagTemplate::parse("<em>{{{content}}}</em>").unwrap();
+    assert_eq!(inline.display(), DisplayKind::Inline);
+
+    let err = |t: &str| TagTemplate::parse(t).unwrap_err();
+    assert_eq!(
+        err("<a href={value}>{content}</a>"),
+        TemplateError::UnquotedAttribute("value".to_string())
+    );
+    assert_eq!(
+        err("<b onclick=\"{value}\">{content}</b>"),
+        TemplateError::UnsafeContext("value".to_string())
+    );
+    assert_eq!(
+        err("<script>var x = \"{value}\";</script>"),
+        TemplateError::UnsafeContext("value".to_string())
+    );
+    assert_eq!(
+        err("<b title=\"{content}\"></b>"),
+        TemplateError::MisplacedContent
+    );
+    assert_eq!(
+        err("<b>{content}{content}</b>"),
+        TemplateError::MisplacedContent
+    );
+    assert_eq!(
+        err("<b>{name}</b>"),
+        TemplateError::UnknownPlaceholder("name".to_string())
+    );
+    assert_eq!(err("<b>{value</b>"), TemplateError::UnclosedPlaceholder);
+    // <script> を閉じた後なら置いてよい
+    assert!(TagTemplate::parse("<script></script><b>{value}</b>").is_ok());
+}
+
+#[test]
+fn test_template_url_and_document_attributes() {
+    let mut registry = TagRegistry::builtin();
+    // srcdoc の値は文書として読まれるので置けない
+    assert_eq!(
+        registry.insert_template("frame", "<iframe srcdoc=\"{value}\"></iframe>"),
+        Err(TemplateError::UnsafeContext("value".to_string()))
+    );
+    // data / xlink:href などはリンク先として検証する
+    registry
+        .insert_template("obj", "<object data=\"{value}\">{content}</object>")
+        .unwrap();
+    registry
+        .insert_template("svglink", "<a xlink:href=\"{value}\">{content}</a>")
+        .unwrap();
+    assert_eq!(render(&registry, "[obj=javascript:alert(1)]x[/obj]"), "x");
+    assert_eq!(
+        render(&registry, "[obj=https://example.com/a.swf]x[/obj]"),
+        "<object data=\"https://example.com/a.swf\">x</object>"
+    );
+    assert_eq!(
+        render(&registry, "[svglink=javascript:alert(1)]x[/svglink]"),
+        "x"
+    );
+}
@@ -0,0 +1,132 @@
+use bbcode_parser::ast::{Node, Span};
+use bbcode_parser::{
+    append_signature, ast_to_html, ast_to_plain_text, parse_bbcode_to_ast, strip_quotes, truncate,
+    BbCodeError, BbCodeOptions, LimitError, SignaturePolicy, TruncateOptions,
+};
+
+fn parse(input: &str) -> Vec<Node> {
+    parse_bbcode_to_ast(input, &BbCodeOptions::default()).unwrap()
+}
+
+#[test]
+fn test_strip_quotes() {
+    let input = "[quote=Alice]old [quote]older[/quote][/quote]\nMy [b]reply[/b]\
+                 [center][quote]x[/quote]done[/center]";
+    let stripped = strip_quotes(&parse(input));
+
+    assert_eq!(ast_to_plain_text(&stripped), "My replydone");
+    // 引用直後の改行は取り除き、span もそれに合わせる
+    assert_eq!(
+        stripped[0],
+        Node::Text {
+            span: Span { start: 46, end: 49 },
+            text: "My ".to_string(),
+        }
+    );
+}
+
+#[test]
+fn test_strip_quotes_only_quote() {
+    assert!(strip_quotes(&parse("[quote]x[/quote]\n")).is_empty());
+}
+
+#[test]
+fn test_append_signature_strips_disallowed_tags() {
+    let post = parse("Hello [b]all[/b]");
+    let sig = parse("[size=7][i]Bob[/i][/size] [img]https://a.example/x.png[/img]");
+
+    let merged = append_signature(&post, &sig, &SignaturePolicy::default()).unwrap();
+
+    assert_eq!(
+        ast_to_html(&merged),
+        "Hello <b>all</b><br>--<br><i>Bob</i> https://a.example/x.png"
+    );
+}
+
+#[test]
+fn test_append_signature_enforces_its_own_limits() {
+    let post = parse("x");
+    let policy = SignaturePolicy::default()
+        .with_max_tags(1)
+        .with_max_text_len(10);
+
+    let too_many = parse("[b]a[/b][i]b[/i]");
+    assert!(matches!(
+        append_signature(&post, &too_many, &policy),
+        Err(BbCodeError::Limit(LimitError::TagCountExceeded {
+            max_tags: 1
+        }))
+    ));
+
+    let too_long = parse("[b]0123456789abc[/b]");
+    assert!(matches!(
+        append_signature(&post, &too_long, &policy),
+        Err(BbCodeError::Limit(LimitError::TextLengthExceeded {
+            max_len: 10,
+            actual_len: 13
+        }))
+    ));
+
+    // 空の署名なら本文のまま
+    assert_eq!(append_signature(&post, &[], &policy).unwrap(), post);
+}
+
+fn truncated_html(input: &str, max_graphemes: usize, preserve_words: bool) -> String {
+    let opts = TruncateOptions::default()
+        .with_max_graphemes(max_graphemes)
+        .with_ellipsis("…")
+        .with_preserve_words(preserve_words);
+    ast_to_html(&truncate(&parse(input), &opts))
+}
+
+#[test]
+fn test_truncate_keeps_tags_balanced() {
+    // 収まるならそのまま
+    assert_eq!(truncated_html("[b]short[/b]", 5, true), "<b>short</b>");
+    assert_eq!(
+        truncated_html("Hello [b]wonderful world[/b] again", 18, true),
+        "Hello <b>wonderful</b>…"
+    );
+    assert_eq!(
+        truncated_html("Hello [b]wonderful world[/b] again", 18, false),
+        "Hello <b>wonderful w</b>…"
+    );
+}
+
+#[test]
+fn test_truncate_graphemes_and_cjk() {
+    // 結合文字・絵文字の ZWJ 列は1文字として数え、途中で切らない
+    assert_eq!(
+        truncated_html("e\u{301}e\u{301}e\u{301}x", 3, false),
+        "e\u{301}e\u{301}…"
+    );
+    assert_eq!(truncated_html("👨‍👩‍👧👨‍👩‍👧👨‍👩‍👧", 2, false), "👨‍👩‍👧…");
+    // 空白で区切らない日本語は単語を保つ設定でも文字単位で切れる
+    assert_eq!(truncated_html("今日は良い天気です", 5, true), "今日は良…");
+}
+
+#[test]
+fn test_truncate_never_cuts_urls_or_code() {
+    assert_eq!(
+        truncated_html("see https://example.com/very/long/path now", 20, false),
+        "see…"
+    );
+    assert_eq!(
+        truncated_html("a [url=https://example.com]link text[/url] b", 8, false),
+        "a…"
+    );
+    assert_eq!(truncated_html("a [code]let x = 1;[/code] b", 8, true), "a…");
+    // 画像は文字数に数えない
+    let nodes = truncate(
+        &parse("[img]https://e.com/a.png[/img]abc def"),
+        &TruncateOptions::default().with_max_graphemes(4),
+    );
+    assert_eq!(ast_to_plain_text(&nodes), "abc…");
+    assert_eq!(
+        nodes.last(),
+        Some(&Node::Text {
+            span: Span { start: 33, end: 33 },
+            text: "…".to_string(),
+        })
+    );
+}
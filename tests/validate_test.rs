@@ -0,0 +1,28 @@
+use bbcode_parser::{validate, BbCodeError, BbCodeOptions, Diagnostic, LimitError};
+
+#[test]
+fn test_validate_report() {
+    let opts = BbCodeOptions::default();
+    let report = validate("[b]x[/b] [zzz]y[/zzz] [i]z", &opts);
+    assert!(report.ok);
+    assert!(report.errors.is_empty());
+    assert!(matches!(
+        &report.warnings[..],
+        [Diagnostic::UnknownTag { tag, .. }] if tag == "zzz"
+    ));
+    assert_eq!(report.stats.input_len, 26);
+    assert_eq!(report.stats.metrics.element_count, 1);
+    assert_eq!(report.stats.metrics.max_depth, 1);
+    assert_eq!(report.stats.fallbacks, 2);
+
+    let report = validate("[b][i][u][s]x[/s][/u][/i][/b]", &opts);
+    assert!(!report.ok);
+    assert!(matches!(
+        report.errors[..],
+        [BbCodeError::Limit(LimitError::NestDepthExceeded {
+            max_depth: 3,
+            ..
+        })]
+    ));
+    assert_eq!(report.stats.metrics.element_count, 0);
+}